@@ -0,0 +1,129 @@
+//! Per-path attributes from `.snapsafeattributes`, in the spirit of `.gitattributes`.
+//!
+//! Each non-empty, non-comment line holds a glob pattern followed by attributes, e.g.
+//! `*.csv compress` or `data/*.db no-dedup -compress`. A name sets the attribute and a name
+//! prefixed with `-` unsets it; when several lines match a path, the last one mentioning an
+//! attribute decides. Patterns without a `/` match file names anywhere in the tree, and patterns
+//! with one match whole relative paths.
+
+use glob::{MatchOptions, Pattern};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::constants::ATTRIBUTES_FILE;
+
+/// Store the file gzip-compressed whatever its size; unset, never compress it.
+pub const COMPRESS: &str = "compress";
+/// Store the file as its own copy: never hard-link it to the baseline snapshot's copy or to
+/// identical files.
+pub const NO_DEDUP: &str = "no-dedup";
+/// Treat the file as binary, so no line or word diff is shown for it; unset, treat it as text
+/// whatever its extension.
+pub const BINARY: &str = "binary";
+
+/// Attributes understood by snapsafe.
+const KNOWN_ATTRIBUTES: &[&str] = &[COMPRESS, NO_DEDUP, BINARY];
+
+/// One line of the attributes file.
+#[derive(Debug, Clone)]
+struct Rule {
+    pattern: Pattern,
+    /// Match the whole relative path rather than the file name.
+    full_path: bool,
+    /// Attribute names, each set (true) or unset (false).
+    attributes: Vec<(String, bool)>,
+}
+
+/// The rules of a `.snapsafeattributes` file; empty when there is none.
+#[derive(Debug, Clone, Default)]
+pub struct Attributes {
+    rules: Vec<Rule>,
+}
+
+impl Attributes {
+    /// Reads `.snapsafeattributes` from the base directory. Lines with an invalid pattern and
+    /// unknown attributes are skipped with a warning.
+    pub fn load(base: &Path) -> io::Result<Self> {
+        let path = base.join(ATTRIBUTES_FILE);
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e),
+        };
+
+        let mut rules = Vec::new();
+        for (number, line) in content.lines().enumerate() {
+            let mut words = line.split_whitespace();
+            let Some(glob) = words.next().filter(|glob| !glob.starts_with('#')) else {
+                continue;
+            };
+            let pattern = match Pattern::new(glob.trim_start_matches('/')) {
+                Ok(pattern) => pattern,
+                Err(e) => {
+                    eprintln!(
+                        "Warning: ignoring line {} of {}: invalid pattern '{}': {}",
+                        number + 1,
+                        ATTRIBUTES_FILE,
+                        glob,
+                        e
+                    );
+                    continue;
+                }
+            };
+            let mut attributes = Vec::new();
+            for word in words {
+                let (name, set) = match word.strip_prefix('-') {
+                    Some(name) => (name, false),
+                    None => (word, true),
+                };
+                if KNOWN_ATTRIBUTES.contains(&name) {
+                    attributes.push((name.to_string(), set));
+                } else {
+                    eprintln!(
+                        "Warning: ignoring unknown attribute '{}' on line {} of {}",
+                        word,
+                        number + 1,
+                        ATTRIBUTES_FILE
+                    );
+                }
+            }
+            rules.push(Rule {
+                pattern,
+                full_path: glob.contains('/'),
+                attributes,
+            });
+        }
+        Ok(Attributes { rules })
+    }
+
+    /// Returns whether the attribute is set (true) or unset (false) for a relative path, or None
+    /// if no line mentions it for the path.
+    pub fn get(&self, relative_path: &str, attribute: &str) -> Option<bool> {
+        let path = relative_path.replace('\\', "/");
+        let name = path.rsplit('/').next().unwrap_or(&path);
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::new()
+        };
+        self.rules
+            .iter()
+            .rev()
+            .filter(|rule| {
+                let subject = if rule.full_path { path.as_str() } else { name };
+                rule.pattern.matches_with(subject, options)
+            })
+            .find_map(|rule| {
+                rule.attributes
+                    .iter()
+                    .rev()
+                    .find(|(name, _)| name == attribute)
+                    .map(|&(_, set)| set)
+            })
+    }
+
+    /// Returns true if the attribute is set for a relative path.
+    pub fn is_set(&self, relative_path: &str, attribute: &str) -> bool {
+        self.get(relative_path, attribute) == Some(true)
+    }
+}
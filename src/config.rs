@@ -0,0 +1,279 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::constants::{
+    CONFIG_ENV_PREFIX, CONFIG_FILE, DEFAULT_CONFIRM_OVERWRITE_THRESHOLD, DEFAULT_COPY_BUFFER_SIZE,
+    DEFAULT_PARALLEL_HASH_THRESHOLD, GLOBAL_CONFIG_FOLDER,
+};
+use crate::info::get_repo_dir;
+
+/// Raw key/value settings as stored in a config file.
+pub type ConfigFile = BTreeMap<String, String>;
+
+/// Configuration keys that can be set with `snapsafe config --set`.
+pub const CONFIG_KEYS: &[&str] = &[
+    "exclude_vcs",
+    "parallel_hash_threshold",
+    "jobs",
+    "confirm_overwrite_threshold",
+    "disable_backup",
+    "snapshot_concurrency_safe",
+    "copy_buffer_size",
+    "outlier_factor",
+    "outlier_percentile",
+    "compress_threshold",
+];
+
+/// Repository config key holding the external snapshot store set by `snapsafe init --store`.
+/// It isn't one of the `CONFIG_KEYS`, since moving the store would orphan existing snapshots.
+pub const STORE_KEY: &str = "store";
+
+/// Value of the global `--jobs` flag, which overrides the `jobs` config key.
+static JOBS_OVERRIDE: OnceLock<usize> = OnceLock::new();
+
+/// Value of the global `--buffer-size` flag, which overrides the `copy_buffer_size` config key.
+static BUFFER_SIZE_OVERRIDE: OnceLock<usize> = OnceLock::new();
+
+/// Resolved configuration: built-in defaults, overridden by the global config file,
+/// overridden by the repository config file, overridden by `SNAPSAFE_<KEY>` environment
+/// variables.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Always exclude version control metadata from snapshots.
+    pub exclude_vcs: bool,
+    /// Files of at least this many bytes are hashed on multiple threads.
+    pub parallel_hash_threshold: u64,
+    /// Number of threads parallel operations may use; 1 makes them sequential.
+    pub jobs: usize,
+    /// Restores overwriting or deleting more files than this need `--force` or a typed confirmation.
+    pub confirm_overwrite_threshold: usize,
+    /// Never create a backup snapshot before a restore, whatever flags are given.
+    pub disable_backup: bool,
+    /// Compare the contents of files that look unchanged with the baseline snapshot before
+    /// hard-linking them, so files rewritten during a snapshot are copied instead.
+    pub snapshot_concurrency_safe: bool,
+    /// Size in bytes of the buffer files are copied through by `snapshot` and `restore`.
+    pub copy_buffer_size: usize,
+    /// Warn about snapshotted files larger than this multiple of the previous snapshot's typical
+    /// file size; None (or 0) turns the check off.
+    pub outlier_factor: Option<f64>,
+    /// Percentile of the previous snapshot's file sizes used as the typical size (50 = median).
+    pub outlier_percentile: f64,
+    /// Files larger than this many bytes are stored gzip-compressed in snapshots; None stores
+    /// every file as is.
+    pub compress_threshold: Option<u64>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            exclude_vcs: false,
+            parallel_hash_threshold: DEFAULT_PARALLEL_HASH_THRESHOLD,
+            jobs: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            confirm_overwrite_threshold: DEFAULT_CONFIRM_OVERWRITE_THRESHOLD,
+            disable_backup: false,
+            snapshot_concurrency_safe: false,
+            copy_buffer_size: DEFAULT_COPY_BUFFER_SIZE,
+            outlier_factor: None,
+            outlier_percentile: 50.0,
+            compress_threshold: None,
+        }
+    }
+}
+
+impl Config {
+    /// Applies the settings of a config file on top of the current values.
+    /// Invalid values are skipped with a warning.
+    fn apply(&mut self, file: &ConfigFile, source: &Path) {
+        for (key, value) in file {
+            if key == STORE_KEY {
+                continue;
+            }
+            if !is_valid_config_key(key) || !is_valid_config_value(key, value) {
+                eprintln!(
+                    "Warning: ignoring invalid config entry {} = {} in {:?}",
+                    key, value, source
+                );
+                continue;
+            }
+            match key.as_str() {
+                "exclude_vcs" => self.exclude_vcs = value == "true",
+                "parallel_hash_threshold" => {
+                    self.parallel_hash_threshold = value.parse().unwrap_or_default()
+                }
+                "jobs" => self.jobs = value.parse().unwrap_or(1),
+                "disable_backup" => self.disable_backup = value == "true",
+                "snapshot_concurrency_safe" => self.snapshot_concurrency_safe = value == "true",
+                "confirm_overwrite_threshold" => {
+                    self.confirm_overwrite_threshold = value.parse().unwrap_or_default()
+                }
+                "copy_buffer_size" => {
+                    self.copy_buffer_size = value.parse().unwrap_or(DEFAULT_COPY_BUFFER_SIZE)
+                }
+                "outlier_factor" => self.outlier_factor = value.parse().ok(),
+                "outlier_percentile" => {
+                    self.outlier_percentile = value.parse().unwrap_or(self.outlier_percentile)
+                }
+                "compress_threshold" => self.compress_threshold = value.parse().ok(),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Returns true if the key is a known configuration key.
+pub fn is_valid_config_key(key: &str) -> bool {
+    CONFIG_KEYS.contains(&key)
+}
+
+/// Returns true if the value is acceptable for the given configuration key.
+pub fn is_valid_config_value(key: &str, value: &str) -> bool {
+    match key {
+        "exclude_vcs" | "disable_backup" | "snapshot_concurrency_safe" => {
+            value == "true" || value == "false"
+        }
+        "parallel_hash_threshold" | "compress_threshold" => value.parse::<u64>().is_ok(),
+        "jobs" => value.parse::<usize>().is_ok_and(|jobs| jobs > 0),
+        "copy_buffer_size" => value.parse::<usize>().is_ok_and(|size| size > 0),
+        "outlier_factor" => value
+            .parse::<f64>()
+            .is_ok_and(|factor| factor.is_finite() && factor >= 0.0),
+        "outlier_percentile" => value
+            .parse::<f64>()
+            .is_ok_and(|percentile| (0.0..=100.0).contains(&percentile)),
+        "confirm_overwrite_threshold" => value.parse::<usize>().is_ok(),
+        _ => false,
+    }
+}
+
+/// Checks a key/value pair against the known keys and their accepted values.
+pub fn validate_config_entry(key: &str, value: &str) -> io::Result<()> {
+    if key == STORE_KEY {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "The snapshot store can only be chosen with 'snapsafe init --store'",
+        ));
+    }
+    if !is_valid_config_key(key) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Unknown config key '{}'. Valid keys: {}",
+                key,
+                CONFIG_KEYS.join(", ")
+            ),
+        ));
+    }
+    if !is_valid_config_value(key, value) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid value '{}' for config key '{}'", value, key),
+        ));
+    }
+    Ok(())
+}
+
+/// Returns the path of the repository config file (`.snapsafe/config.json`).
+pub fn repo_config_path(base_path: &Path) -> PathBuf {
+    get_repo_dir(base_path).join(CONFIG_FILE)
+}
+
+/// Returns the path of the global config file in the user's config directory, if there is one.
+pub fn global_config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join(GLOBAL_CONFIG_FOLDER).join(CONFIG_FILE))
+}
+
+/// Loads a config file, returning an empty config if it doesn't exist.
+pub fn load_config_file(path: &Path) -> io::Result<ConfigFile> {
+    if path.exists() {
+        let content = fs::read_to_string(path)?;
+        serde_json::from_str(&content).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid config file {:?}: {}", path, e),
+            )
+        })
+    } else {
+        Ok(ConfigFile::new())
+    }
+}
+
+/// Saves a config file, creating its parent directory if needed.
+pub fn save_config_file(path: &Path, config: &ConfigFile) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let json = serde_json::to_string_pretty(config).map_err(io::Error::other)?;
+    fs::write(path, json)
+}
+
+/// Returns the external snapshot store recorded in the repository config, if any.
+/// An unreadable config file counts as no store.
+pub fn configured_store(base_path: &Path) -> Option<PathBuf> {
+    load_config_file(&repo_config_path(base_path))
+        .ok()?
+        .get(STORE_KEY)
+        .map(PathBuf::from)
+}
+
+/// Returns the name of the environment variable overriding a config key, e.g. `SNAPSAFE_JOBS`.
+pub fn config_env_var(key: &str) -> String {
+    format!("{}{}", CONFIG_ENV_PREFIX, key.to_uppercase())
+}
+
+/// Returns the config keys set through `SNAPSAFE_<KEY>` environment variables, so settings can
+/// be tuned (e.g. on CI runners) without writing a config file. Empty variables are ignored, and
+/// invalid values are skipped with a warning.
+pub fn env_overrides() -> ConfigFile {
+    let mut overrides = ConfigFile::new();
+    for key in CONFIG_KEYS {
+        let var = config_env_var(key);
+        let Some(value) = std::env::var(&var).ok().filter(|value| !value.is_empty()) else {
+            continue;
+        };
+        if is_valid_config_value(key, &value) {
+            overrides.insert(key.to_string(), value);
+        } else {
+            eprintln!("Warning: ignoring invalid value '{}' of {}", value, var);
+        }
+    }
+    overrides
+}
+
+/// Sets the number of jobs for this run, taking precedence over the `jobs` config key.
+pub fn set_jobs_override(jobs: usize) {
+    let _ = JOBS_OVERRIDE.set(jobs);
+}
+
+/// Sets the copy buffer size for this run, taking precedence over the `copy_buffer_size` config key.
+pub fn set_buffer_size_override(size: usize) {
+    let _ = BUFFER_SIZE_OVERRIDE.set(size);
+}
+
+/// Loads the resolved configuration for the repository at base_path.
+/// Unreadable config files are reported and skipped, falling back to defaults.
+/// `SNAPSAFE_<KEY>` environment variables override both config files, and the global `--jobs`
+/// and `--buffer-size` flags, if given, override everything else.
+pub fn load_config(base_path: &Path) -> Config {
+    let mut config = Config::default();
+    let paths = global_config_path()
+        .into_iter()
+        .chain(std::iter::once(repo_config_path(base_path)));
+    for path in paths {
+        match load_config_file(&path) {
+            Ok(file) => config.apply(&file, &path),
+            Err(e) => eprintln!("Warning: {}. Using defaults.", e),
+        }
+    }
+    config.apply(&env_overrides(), Path::new("the environment"));
+    if let Some(&jobs) = JOBS_OVERRIDE.get() {
+        config.jobs = jobs;
+    }
+    if let Some(&size) = BUFFER_SIZE_OVERRIDE.get() {
+        config.copy_buffer_size = size;
+    }
+    config
+}
@@ -1,8 +1,44 @@
 pub const REPO_FOLDER: &str = ".snapsafe";
+/// Environment variable overriding the repository folder name.
+pub const REPO_DIR_ENV: &str = "SNAPSAFE_DIR";
+/// Debug builds only: fail this many file copies with a transient error, to test `--retries`.
+pub const INJECT_TRANSIENT_ERRORS_ENV: &str = "SNAPSAFE_INJECT_TRANSIENT_ERRORS";
+/// Debug builds only: wait this many milliseconds before each file copy, to simulate slow
+/// storage when testing `--timeout`.
+pub const INJECT_DELAY_ENV: &str = "SNAPSAFE_INJECT_DELAY_MS";
+/// Prefix of the environment variables overriding config keys, e.g. `SNAPSAFE_JOBS` for `jobs`.
+pub const CONFIG_ENV_PREFIX: &str = "SNAPSAFE_";
 pub const SNAPSHOTS_FOLDER: &str = "snapshots";
+/// Folder inside the repository folder that `export` writes archives to when no output path is
+/// given, so they aren't picked up by the next snapshot.
+pub const EXPORTS_FOLDER: &str = "exports";
 pub const HEAD_MANIFEST_FILE: &str = "head_manifest.json";
 pub const MANIFEST_FILE: &str = "manifest.json";
+pub const INDEX_FILE: &str = "index.json";
+/// Copy of a snapshot's tags and metadata kept in its folder, so they survive the loss of the
+/// head manifest and travel with exported archives.
+pub const SNAPSHOT_META_FILE: &str = ".snapsafe_meta.json";
+/// Note attached to a snapshot with `snapshot --note-file`, shown by `info --note`.
+pub const NOTE_FILE: &str = ".snapsafe_note.md";
+/// Files snapsafe keeps in the root of a snapshot folder next to the stored files. A working
+/// directory with a root file of one of these names can't be snapshotted, since the two would
+/// overwrite each other.
+pub const RESERVED_FILES: &[&str] = &[SNAPSHOT_META_FILE, NOTE_FILE];
+/// Describes an incremental archive written by `export --since`; stored next to its manifest.
+pub const INCREMENTAL_FILE: &str = "incremental.json";
+/// Head manifest entry (timestamp, message, label, tags, and metadata) of the snapshot in an
+/// archive written by `export`, from which `import` recreates the entry.
+pub const SNAPSHOT_INDEX_FILE: &str = ".snapsafe_index.json";
+/// `sha256sum`-format list of a snapshot's stored files written by `snapshot --checksum-manifest`,
+/// so `sha256sum -c SHA256SUMS` can check the snapshot folder without snapsafe.
+pub const CHECKSUMS_FILE: &str = "SHA256SUMS";
 pub const IGNORE_FILE: &str = ".snapsafeignore";
+/// Per-path attributes (`compress`, `no-dedup`, `binary`) read by `snapshot` and `diff`.
+pub const ATTRIBUTES_FILE: &str = ".snapsafeattributes";
+pub const CONFIG_FILE: &str = "config.json";
+/// Name and description of the repository set with `init --name/--description`.
+pub const DESCRIPTION_FILE: &str = "description.json";
+pub const GLOBAL_CONFIG_FOLDER: &str = "snapsafe";
 
 pub const DEFAULT_IGNORE_ITEMS: &[&str] = &[
     ".git",
@@ -11,3 +47,58 @@ pub const DEFAULT_IGNORE_ITEMS: &[&str] = &[
     ".DS_Store",
     ".snapsafeignore",
 ];
+
+/// Version control metadata excluded by `snapshot --exclude-vcs` (mirrors `tar --exclude-vcs`).
+pub const VCS_IGNORE_ITEMS: &[&str] = &[
+    "CVS",
+    ".cvsignore",
+    "RCS",
+    "SCCS",
+    ".git",
+    ".gitignore",
+    ".gitattributes",
+    ".gitmodules",
+    ".svn",
+    ".hg",
+    ".hgignore",
+    ".hgtags",
+    ".bzr",
+    ".bzrignore",
+    ".bzrtags",
+    "_darcs",
+    ".fslckout",
+    "_FOSSIL_",
+];
+
+/// Size of the buffer files are read through when hashing, which keeps memory use bounded.
+pub const HASH_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Delay before the first retry of a failed copy or link; it doubles on every further retry.
+pub const RETRY_BASE_DELAY_MS: u64 = 100;
+
+/// Longest delay between two retries of a copy or link.
+pub const RETRY_MAX_DELAY_MS: u64 = 5000;
+
+/// Files at least this large (in bytes) are hashed on multiple threads unless configured otherwise.
+pub const DEFAULT_PARALLEL_HASH_THRESHOLD: u64 = 64 * 1024 * 1024;
+
+/// Size (in bytes) of the buffer used to copy files unless configured otherwise.
+pub const DEFAULT_COPY_BUFFER_SIZE: usize = 128 * 1024;
+
+/// A restore that would overwrite or delete more files than this needs `--force` (or a typed
+/// confirmation) unless configured otherwise.
+pub const DEFAULT_CONFIRM_OVERWRITE_THRESHOLD: usize = 1000;
+
+/// Exit code returned by `snapshot --if-changed` when nothing changed and no snapshot was created.
+pub const EXIT_NO_CHANGES: i32 = 10;
+
+/// Exit code returned by `snapshot --only-if-idle` when files were modified too recently and no
+/// snapshot was created.
+pub const EXIT_NOT_IDLE: i32 = 12;
+
+/// Exit code returned by `verify` when at least one snapshot failed verification.
+/// Errors that prevent verification altogether (e.g. an unreadable repository) exit with 1.
+pub const EXIT_VERIFY_FAILED: i32 = 11;
+
+/// Exit code returned when a command runs longer than its `--timeout` and is aborted.
+pub const EXIT_TIMED_OUT: i32 = 13;
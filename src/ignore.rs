@@ -0,0 +1,81 @@
+//! Ignore rules shared by everything that walks or filters the working tree, so `snapshot`,
+//! `diff ... working`, `restore --clean` and `snapshot --only-if-idle` agree on which files
+//! belong in a snapshot.
+//!
+//! Two kinds of rules exist: the names in `.snapsafeignore` (plus the version control folders
+//! with `exclude_vcs`), which skip any file or directory with that name while walking a tree,
+//! and the glob patterns given on the command line (`diff --ignore`, `restore --exclude`),
+//! which match whole relative paths.
+
+use glob::{MatchOptions, Pattern};
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::Path;
+
+use crate::constants::{IGNORE_FILE, VCS_IGNORE_ITEMS};
+
+/// Returns the names excluded from snapshots of base: the .snapsafeignore entries, plus the
+/// version control metadata folders when exclude_vcs is set.
+pub fn build_ignore_list(base: &Path, exclude_vcs: bool) -> io::Result<Vec<String>> {
+    let mut ignore_list = read_ignore_list(base)?;
+    if exclude_vcs {
+        ignore_list.extend(VCS_IGNORE_ITEMS.iter().map(|item| item.to_string()));
+    }
+    Ok(ignore_list)
+}
+
+/// Reads the ignore list from the .snapsafeignore file in the base directory.
+/// Each non-empty, non-comment line is treated as a literal file or directory name to ignore.
+fn read_ignore_list(base: &Path) -> io::Result<Vec<String>> {
+    let ignore_path = base.join(IGNORE_FILE);
+    let mut ignore_list = Vec::new();
+
+    if ignore_path.exists() {
+        let file = fs::File::open(ignore_path)?;
+        let reader = io::BufReader::new(file);
+        for line_result in reader.lines() {
+            let line = line_result?;
+            let trimmed = line.trim();
+            if !trimmed.is_empty() && !trimmed.starts_with('#') {
+                ignore_list.push(trimmed.to_string());
+            }
+        }
+    }
+    Ok(ignore_list)
+}
+
+/// Returns true if a file or directory with this name is skipped by the ignore list, wherever
+/// it appears in the tree.
+pub fn is_ignored(file_name: &str, ignore_list: &[String]) -> bool {
+    ignore_list.iter().any(|name| name == file_name)
+}
+
+/// Returns a predicate telling whether a path matches any of the given glob patterns (`diff
+/// --ignore`, `restore --exclude`).
+pub fn ignore_matcher(patterns: &[String]) -> io::Result<impl Fn(&String) -> bool> {
+    let ignore_patterns = compile_patterns(patterns)?;
+    Ok(move |path: &String| {
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::new()
+        };
+        ignore_patterns
+            .iter()
+            .any(|pattern| pattern.matches_path_with(Path::new(path), options))
+    })
+}
+
+/// Parses glob patterns, reporting the first invalid one.
+fn compile_patterns(patterns: &[String]) -> io::Result<Vec<Pattern>> {
+    patterns
+        .iter()
+        .map(|pattern| {
+            Pattern::new(pattern).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Invalid glob pattern '{}': {}", pattern, e),
+                )
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,77 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::constants::INDEX_FILE;
+use crate::info::get_repo_dir;
+use crate::models::SnapshotIndex;
+
+/// Lookup tables from tags and metadata keys to the snapshot versions carrying them,
+/// stored in `.snapsafe/index.json` so queries don't have to scan every snapshot.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct SearchIndex {
+    /// Tag -> versions having that tag
+    pub tags: BTreeMap<String, Vec<String>>,
+    /// Metadata key -> versions having that key
+    pub meta_keys: BTreeMap<String, Vec<String>>,
+}
+
+/// Builds the search index from the head manifest.
+pub fn build_index(head_manifest: &[SnapshotIndex]) -> SearchIndex {
+    let mut index = SearchIndex::default();
+    for snapshot in head_manifest {
+        if let Some(ref metadata) = snapshot.metadata {
+            for tag in &metadata.tags {
+                index
+                    .tags
+                    .entry(tag.clone())
+                    .or_default()
+                    .push(snapshot.version.clone());
+            }
+            let mut keys: Vec<&String> = metadata.custom.keys().collect();
+            keys.sort();
+            for key in keys {
+                index
+                    .meta_keys
+                    .entry(key.clone())
+                    .or_default()
+                    .push(snapshot.version.clone());
+            }
+        }
+    }
+    index
+}
+
+/// Loads the search index, returning None if it doesn't exist or can't be read or parsed.
+/// The index is only a cache of the head manifest, so read-only commands rebuild it in memory
+/// rather than fail (e.g. on an archived repository with restrictive permissions).
+pub fn load_index(base_path: &Path) -> io::Result<Option<SearchIndex>> {
+    let index_path = get_repo_dir(base_path).join(INDEX_FILE);
+    if !index_path.exists() {
+        return Ok(None);
+    }
+    Ok(fs::read_to_string(&index_path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok()))
+}
+
+/// Saves the search index to `.snapsafe/index.json`.
+pub fn save_index(base_path: &Path, index: &SearchIndex) -> io::Result<()> {
+    let index_path = get_repo_dir(base_path).join(INDEX_FILE);
+    let json = serde_json::to_string_pretty(index).map_err(io::Error::other)?;
+    fs::write(&index_path, json)
+}
+
+/// Returns the search index for querying, falling back to building it in memory from the
+/// head manifest when the index file is missing or unreadable.
+pub fn load_or_build_index(
+    base_path: &Path,
+    head_manifest: &[SnapshotIndex],
+) -> io::Result<SearchIndex> {
+    match load_index(base_path)? {
+        Some(index) => Ok(index),
+        None => Ok(build_index(head_manifest)),
+    }
+}
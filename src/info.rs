@@ -1,12 +1,99 @@
+use crate::config;
+use crate::constants::{REPO_DIR_ENV, REPO_FOLDER, SNAPSHOTS_FOLDER};
 use crate::models::SnapshotIndex;
+use chrono::{Local, NaiveDateTime, TimeZone};
 use std::io;
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
+
+/// Turns a user-supplied path such as `./src/main.rs` into the form manifests record.
+pub fn normalize_relative_path(path: &str) -> String {
+    Path::new(path)
+        .components()
+        .filter(|c| matches!(c, Component::Normal(_)))
+        .collect::<PathBuf>()
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Formats a byte count in binary units with one decimal (e.g. "512 B", "1.5 KiB", "2.0 MiB").
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Formats a snapshot timestamp (`%Y-%m-%d %H:%M:%S`, local time) as the time elapsed since then,
+/// e.g. "just now", "1 hour ago", "3 days ago". Unparseable timestamps are returned unchanged.
+pub fn format_age(timestamp: &str) -> String {
+    let then = match NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M:%S")
+        .ok()
+        .and_then(|t| Local.from_local_datetime(&t).earliest())
+    {
+        Some(then) => then,
+        None => return timestamp.to_string(),
+    };
+    let elapsed = Local::now().signed_duration_since(then);
+    if elapsed.num_seconds() < 0 {
+        return "in the future".to_string();
+    }
+
+    let (count, unit) = if elapsed.num_minutes() < 1 {
+        return "just now".to_string();
+    } else if elapsed.num_hours() < 1 {
+        (elapsed.num_minutes(), "minute")
+    } else if elapsed.num_days() < 1 {
+        (elapsed.num_hours(), "hour")
+    } else if elapsed.num_days() < 30 {
+        (elapsed.num_days(), "day")
+    } else if elapsed.num_days() < 365 {
+        (elapsed.num_days() / 30, "month")
+    } else {
+        (elapsed.num_days() / 365, "year")
+    };
+    format!(
+        "{} {}{} ago",
+        count,
+        unit,
+        if count == 1 { "" } else { "s" }
+    )
+}
 
 /// Returns the base directory (current working directory).
 pub fn get_base_dir() -> io::Result<PathBuf> {
     std::env::current_dir()
 }
 
+/// Returns the name of the repository folder: the value of `SNAPSAFE_DIR` if set,
+/// otherwise `.snapsafe`.
+pub fn repo_folder_name() -> String {
+    match std::env::var(REPO_DIR_ENV) {
+        Ok(name) if !name.trim().is_empty() => name,
+        _ => REPO_FOLDER.to_string(),
+    }
+}
+
+/// Returns the path of the repository folder inside the base directory.
+pub fn get_repo_dir(base_path: &Path) -> PathBuf {
+    base_path.join(repo_folder_name())
+}
+
+/// Returns the path of the folder holding the snapshot directories: the external store chosen
+/// with `snapsafe init --store` if there is one, otherwise `snapshots` inside the repository folder.
+pub fn get_snapshots_dir(base_path: &Path) -> PathBuf {
+    match config::configured_store(base_path) {
+        Some(store) => store,
+        None => get_repo_dir(base_path).join(SNAPSHOTS_FOLDER),
+    }
+}
+
 /// Given the current head manifest and an optional user-provided version,
 /// returns the next snapshot version string.
 pub fn get_next_version(head: &[SnapshotIndex], version: Option<String>) -> String {
@@ -46,26 +133,115 @@ pub fn get_next_version(head: &[SnapshotIndex], version: Option<String>) -> Stri
             }
         }
     } else {
-        // No version provided, use the auto-incrementing logic
-        if head.is_empty() {
-            "v1.0.0.0".to_string()
-        } else {
-            let last_version = &head.last().unwrap().version;
-            // Assume the version is in the format vX.Y.Z.B
-            let numeric_part = last_version.trim_start_matches('v');
-            let parts: Vec<&str> = numeric_part.split('.').collect();
-            if parts.len() != 4 {
-                // Fallback if not in expected format
-                "v1.0.0.0".to_string()
+        // No version provided, use the auto-incrementing logic. Snapshots named with
+        // `--label-as-version` are skipped, so numbering continues from the last numeric version.
+        let last_numeric = head.iter().rev().find(|s| {
+            let parts: Vec<&str> = s.version.trim_start_matches('v').split('.').collect();
+            parts.len() == 4 && parts.iter().all(|p| p.parse::<u32>().is_ok())
+        });
+        last_numeric
+            .and_then(|last| next_build_version(&last.version))
+            .unwrap_or_else(|| "v1.0.0.0".to_string())
+    }
+}
+
+/// Returns the version following `version` by build number (`vX.Y.Z.B` becomes `vX.Y.Z.B+1`),
+/// or None if it isn't in that format.
+pub fn next_build_version(version: &str) -> Option<String> {
+    let parts: Vec<&str> = version.trim_start_matches('v').split('.').collect();
+    if parts.len() != 4 {
+        return None;
+    }
+    let build: u32 = parts[3].parse().ok()?;
+    Some(format!(
+        "v{}.{}.{}.{}",
+        parts[0],
+        parts[1],
+        parts[2],
+        build + 1
+    ))
+}
+
+/// Turns a friendly name given with `snapshot --label-as-version` into a version that is safe
+/// to use as a folder name: characters other than ASCII letters, digits, `.`, `_`, and `-` are
+/// replaced with `-`, and leading or trailing dots and dashes are dropped.
+pub fn sanitize_version(name: &str) -> io::Result<String> {
+    let replaced: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-') {
+                c
             } else {
-                let major = parts[0];
-                let minor = parts[1];
-                let patch = parts[2];
-                let build: u32 = parts[3].parse().unwrap_or(0);
-                let new_build = build + 1;
-                format!("v{}.{}.{}.{}", major, minor, patch, new_build)
+                '-'
             }
+        })
+        .collect();
+    let sanitized = replaced.trim_matches(|c| c == '.' || c == '-').to_string();
+    if sanitized.is_empty()
+        || sanitized.eq_ignore_ascii_case("latest")
+        || sanitized.eq_ignore_ascii_case("working")
+        || sanitized.eq_ignore_ascii_case(ALL_SNAPSHOTS_ID)
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("'{}' can't be used as a snapshot version", name),
+        ));
+    }
+    Ok(sanitized)
+}
+
+/// Snapshot ID that makes `tag` and `meta` changes apply to every snapshot in the repository.
+pub const ALL_SNAPSHOTS_ID: &str = "all";
+
+/// Resolves the snapshots a `tag` or `meta` command applies to and returns their positions in
+/// the head manifest: every snapshot for `all`, otherwise the one the ID resolves to.
+pub fn resolve_snapshot_positions(
+    snapshot_id: Option<String>,
+    head_manifest: &[SnapshotIndex],
+) -> io::Result<Vec<usize>> {
+    if snapshot_id
+        .as_deref()
+        .is_some_and(|id| id.eq_ignore_ascii_case(ALL_SNAPSHOTS_ID))
+    {
+        if head_manifest.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "No snapshots available.",
+            ));
         }
+        return Ok((0..head_manifest.len()).collect());
+    }
+
+    let actual_id = resolve_snapshot_id(snapshot_id, head_manifest)?;
+    let position = head_manifest
+        .iter()
+        .position(|s| s.version == actual_id || s.version.starts_with(&actual_id))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Snapshot {} not found", actual_id),
+            )
+        })?;
+    Ok(vec![position])
+}
+
+/// Asks the user to confirm a change to every snapshot, unless `yes` is set. Returns false if
+/// the user declined.
+pub fn confirm_bulk_change(change: &str, count: usize, yes: bool) -> io::Result<bool> {
+    if yes {
+        return Ok(true);
+    }
+    println!(
+        "This will {} on all {} snapshot(s). Continue? (y/n)",
+        change, count
+    );
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    if input.trim().eq_ignore_ascii_case("y") {
+        Ok(true)
+    } else {
+        println!("Cancelled; no snapshots were changed.");
+        Ok(false)
     }
 }
 
@@ -73,6 +249,7 @@ pub fn get_next_version(head: &[SnapshotIndex], version: Option<String>) -> Stri
 /// - None (returns the latest snapshot)
 /// - "latest" (returns the latest snapshot)
 /// - Exact version match
+/// - Snapshot label
 /// - Prefix version match
 pub fn resolve_snapshot_id(
     snapshot_id: Option<String>,
@@ -95,10 +272,15 @@ pub fn resolve_snapshot_id(
             if id.to_lowercase() == "latest" {
                 Ok(head_manifest.last().unwrap().version.clone())
             } else {
-                // Try exact match first
+                // Try exact match first, then labels
                 let exact_match = head_manifest
                     .iter()
                     .find(|s| s.version == id)
+                    .or_else(|| {
+                        head_manifest
+                            .iter()
+                            .find(|s| s.label.as_deref() == Some(id.as_str()))
+                    })
                     .map(|s| s.version.clone());
 
                 // If no exact match, try prefix match
@@ -111,7 +293,11 @@ pub fn resolve_snapshot_id(
                         .ok_or_else(|| {
                             io::Error::new(
                                 io::ErrorKind::NotFound,
-                                format!("Snapshot {} not found", id),
+                                format!(
+                                    "Snapshot {} not found. {}",
+                                    id,
+                                    available_snapshots_hint(&id, head_manifest)
+                                ),
                             )
                         }),
                 }
@@ -119,3 +305,53 @@ pub fn resolve_snapshot_id(
         }
     }
 }
+
+/// Number of snapshots listed when an ID can't be resolved; larger repositories list the
+/// closest matches instead.
+const SNAPSHOT_HINT_LIMIT: usize = 10;
+
+/// Describes the snapshots that exist, for an error about an ID that matched none of them:
+/// every version (with its label) in small repositories, otherwise the versions and labels
+/// closest to the ID.
+fn available_snapshots_hint(id: &str, head_manifest: &[SnapshotIndex]) -> String {
+    let describe = |s: &SnapshotIndex| match s.label {
+        Some(ref label) => format!("{} ({})", s.version, label),
+        None => s.version.clone(),
+    };
+    if head_manifest.len() <= SNAPSHOT_HINT_LIMIT {
+        let all: Vec<String> = head_manifest.iter().map(describe).collect();
+        return format!("Available snapshots: {}", all.join(", "));
+    }
+
+    let distance = |s: &SnapshotIndex| {
+        let by_label = s.label.as_deref().map(|label| edit_distance(id, label));
+        edit_distance(id, &s.version).min(by_label.unwrap_or(usize::MAX))
+    };
+    let mut closest: Vec<&SnapshotIndex> = head_manifest.iter().collect();
+    closest.sort_by_key(|s| distance(s));
+    let closest: Vec<String> = closest
+        .into_iter()
+        .take(SNAPSHOT_HINT_LIMIT / 2)
+        .map(describe)
+        .collect();
+    format!(
+        "Closest matches: {} ({} snapshots in total; run 'snapsafe list' to see them all)",
+        closest.join(", "),
+        head_manifest.len()
+    )
+}
+
+/// Number of single-character insertions, deletions, or substitutions turning a into b.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
@@ -12,21 +12,57 @@
 //!
 
 use clap::{Parser, Subcommand};
+use pager::Paging;
+use std::path::PathBuf;
 use std::process;
+use subcommands::list::ListColumn;
+use subcommands::meta::MetaType;
+use subcommands::restore::ConflictPolicy;
+use subcommands::snapshot::CompareBy;
+use subcommands::verify::ColorChoice;
+mod attributes;
+mod config;
 mod constants;
+mod ignore;
+mod index;
 mod info;
 mod manifest;
 mod models;
+mod pager;
+mod profile;
+mod storage;
 mod subcommands;
+mod watchdog;
 
 #[derive(Parser)]
 #[command(name = "snapsafe")]
 #[command(about = "Snap Safe: A CLI tool for efficient snapshots management", long_about = None)]
 struct Cli {
+    /// Number of threads for parallel operations (defaults to the `jobs` config key,
+    /// or the number of logical CPUs); 1 runs everything sequentially
+    #[arg(short, long, global = true, value_parser = clap::value_parser!(u64).range(1..))]
+    jobs: Option<u64>,
+
+    /// Size in bytes of the buffer files are copied through (defaults to the
+    /// `copy_buffer_size` config key, or 131072); tune it for the storage in use
+    #[arg(long, global = true, value_name = "BYTES", value_parser = clap::value_parser!(u64).range(1..))]
+    buffer_size: Option<u64>,
+
+    /// Print how long each phase of `snapshot`, `verify`, or `restore` took
+    #[arg(long, global = true)]
+    profile: bool,
+
+    /// Abort `snapshot`, `restore`, or `verify` if it runs longer than this many seconds, e.g.
+    /// on hung network storage; a partial snapshot is removed and the exit code is 13
+    #[arg(long, global = true, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+    timeout: Option<u64>,
+
     #[command(subcommand)]
     command: Commands,
 }
 
+// Parsed once per run, so the size of the largest variant doesn't matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 enum Commands {
     /// Initializes a new Snap Safe repository in the current directory
@@ -35,8 +71,28 @@ enum Commands {
     /// snapshots and metadata. It's the first command you should run before
     /// using other Snap Safe features.
     ///
-    /// Example: snapsafe init
-    Init,
+    /// Examples:
+    ///   snapsafe init
+    ///   snapsafe init --store /mnt/backup/my-project
+    ///   snapsafe init --force
+    ///   snapsafe init --name web-frontend --description "Nightly builds of the web app"
+    Init {
+        /// Keep snapshots in this directory instead of .snapsafe/snapshots
+        #[arg(long, value_name = "PATH")]
+        store: Option<PathBuf>,
+
+        /// Name the repository, shown by `about` and `info` (an empty name removes it)
+        #[arg(long)]
+        name: Option<String>,
+
+        /// Describe the repository, shown by `about` (an empty description removes it)
+        #[arg(long)]
+        description: Option<String>,
+
+        /// Reset a corrupt head manifest to empty (after confirmation); snapshot folders are kept
+        #[arg(long)]
+        force: bool,
+    },
 
     /// Create a new snapshot of the current directory state
     ///
@@ -48,6 +104,27 @@ enum Commands {
     ///   snapsafe snapshot -m "Initial snapshot"
     ///   snapsafe snapshot -v "2.0.0.0" -m "Release candidate"
     ///   snapsafe snapshot --tags production release --meta ran_by SCM
+    ///   snapsafe snapshot --inherit-tags --tags build-42
+    ///   snapsafe snapshot --meta-json '{"commit":"a1b2c3","runner":"ci-7"}'
+    ///   snapsafe snapshot --if-changed -m "Nightly"
+    ///   snapsafe snapshot --exclude-vcs
+    ///   snapsafe snapshot --no-ignore -m "Full backup"
+    ///   snapsafe snapshot --retag stable
+    ///   snapsafe snapshot --from v1.0.0.0 --compare-by hash
+    ///   snapsafe snapshot --label release-candidate
+    ///   snapsafe snapshot --prune-after-keep-last 10 --yes
+    ///   snapsafe snapshot --retries 5 --verbose
+    ///   snapsafe snapshot --only-if-idle 60
+    ///   snapsafe snapshot --outlier-factor 100 --block-outliers
+    ///   snapsafe snapshot -m "Release 2.1" --note-file CHANGELOG.md
+    ///   snapsafe snapshot --content-only -m "Build output"
+    ///   snapsafe snapshot --timeout 600 -m "Nightly to NAS"
+    ///   snapsafe snapshot --incremental -m "Hourly"
+    ///   snapsafe snapshot --checksum-manifest
+    ///   snapsafe snapshot --print-version -q
+    ///
+    /// Exit codes: 0 when a snapshot was created, 10 when --if-changed found
+    /// no changes and nothing was created, 13 when --timeout passed, 1 on error.
     Snapshot {
         /// Optional custom version for the snapshot (e.g., "v1.2.3.4", "2", "3.0", etc.)
         /// If not provided, the version will auto-increment from the last snapshot
@@ -59,13 +136,240 @@ enum Commands {
         /// Add tags to the snapshot
         #[arg(long, num_args = 1..)]
         tags: Option<Vec<String>>,
-        /// Add custom metadata to the snapshot (key and value pair)
+        /// Copy the previous snapshot's tags onto the new snapshot (combined with --tags)
+        /// Useful for snapshot lines like "nightly" where every snapshot carries the same tags
+        #[arg(long)]
+        inherit_tags: bool,
+        /// Add custom metadata to the snapshot (key and value pair); repeat for more pairs
         /// This can store arbitrary information like build IDs, environment details, etc.
         #[arg(long, num_args = 2, value_names = &["KEY", "VALUE"])]
         meta: Option<Vec<String>>,
+        /// Add every key/value pair of a flat JSON object of strings as custom metadata
+        /// e.g. '{"commit":"a1b2c3","runner":"ci-7"}'; combined with --meta
+        #[arg(long, value_name = "JSON")]
+        meta_json: Option<String>,
+        /// Only create a snapshot if files changed since the latest snapshot
+        /// Exits with code 10 when there is nothing to snapshot
+        #[arg(long)]
+        if_changed: bool,
+        /// Don't print a notice when --if-changed finds no changes (the exit code still signals it)
+        #[arg(long)]
+        quiet_on_no_change: bool,
+        /// Exclude version control metadata (.git, .svn, .hg, .bzr, CVS, ...) from the snapshot
+        /// Can be enabled permanently with `snapsafe config --set exclude_vcs true`
+        #[arg(long)]
+        exclude_vcs: bool,
+        /// Include every file, bypassing .snapsafeignore and --exclude-vcs for this run
+        /// The .snapsafe repository folder is always skipped
+        #[arg(long, conflicts_with = "exclude_vcs")]
+        no_ignore: bool,
+        /// Move tags to the new snapshot, removing them from any snapshot that has them
+        /// Useful for pointer tags like "stable" that should follow the newest snapshot
+        #[arg(long, num_args = 1.., value_name = "TAG")]
+        retag: Option<Vec<String>>,
+        /// Hard-link unchanged files against this snapshot instead of the latest one
+        /// Useful after resetting the working tree to an older snapshot
+        #[arg(long, value_name = "SNAPSHOT_ID")]
+        from: Option<String>,
+        /// How files are compared with the baseline snapshot to decide whether to hard-link them
+        /// "mtime" still links a file whose modification time changed if its SHA-256 hash matches
+        /// the one the baseline recorded; "hash" compares every file by BLAKE3 hash, even against
+        /// baselines that recorded none
+        #[arg(long, value_enum, default_value_t = CompareBy::Mtime)]
+        compare_by: CompareBy,
+        /// Give the snapshot a human-friendly name that can be used in place of its version
+        /// Labels must be unique within the repository
+        #[arg(long)]
+        label: Option<String>,
+        /// Fail instead of warning when file paths differ only by case (e.g. ReadMe.txt and
+        /// readme.txt), which would collide when restored on macOS or Windows
+        #[arg(long)]
+        strict_case: bool,
+        /// After creating the snapshot, prune so only the N most recent snapshots remain
+        #[arg(long, value_name = "N")]
+        prune_after_keep_last: Option<usize>,
+        /// After creating the snapshot, prune snapshots older than this duration (e.g. "30d")
+        #[arg(long, value_name = "DURATION")]
+        prune_after_older_than: Option<String>,
+        /// Don't ask for confirmation before pruning with --prune-after-*
+        #[arg(short, long)]
+        yes: bool,
+        /// Retry a file copy or link up to N times, with backoff, when it fails with a
+        /// transient error (useful on NFS/SMB mounts)
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        retries: u32,
+        /// Report each retry on stderr
+        #[arg(long)]
+        verbose: bool,
+        /// Store files that are hard links to each other in the working tree, or identical
+        /// copies, as independent copies instead of linking them (one copy) in the snapshot
+        #[arg(long)]
+        dereference_hardlinks: bool,
+        /// Leave zero-byte files (e.g. marker files) out of the snapshot
+        #[arg(long)]
+        exclude_empty_files: bool,
+
+        /// Warn about files more than FACTOR times larger than the previous snapshot's typical
+        /// file size (the median, or the outlier_percentile config key); overrides the
+        /// outlier_factor config key, and 0 turns the check off
+        #[arg(long, value_name = "FACTOR")]
+        outlier_factor: Option<f64>,
+
+        /// Leave the files flagged by the size outlier check out of the snapshot
+        #[arg(long)]
+        block_outliers: bool,
+
+        /// Store files larger than BYTES gzip-compressed, leaving small files as is so they
+        /// can still be hard-linked cheaply; overrides the compress_threshold config key
+        #[arg(long, value_name = "BYTES")]
+        compress_threshold: Option<u64>,
+
+        /// Record each file's precise access, modification and creation times (for forensic
+        /// snapshots); restore reapplies the access and modification times. Creation and
+        /// inode change (ctime) times are set by the OS and can't be restored
+        #[arg(long)]
+        preserve_times: bool,
+
+        /// Use the name given with -v verbatim as the version and folder name (e.g.
+        /// "release-2024-q1") instead of normalizing it to vX.Y.Z.B; unsafe characters
+        /// are replaced with "-"
+        #[arg(long, requires = "version")]
+        label_as_version: bool,
+
+        /// Skip the snapshot (exit code 12) if any file was modified in the last SECS seconds,
+        /// e.g. while a build is still writing its output
+        #[arg(long, value_name = "SECS")]
+        only_if_idle: Option<u64>,
+
+        /// Attach the contents of FILE (e.g. release notes or a changelog) to the snapshot as
+        /// its note, shown by `info --note`
+        #[arg(long, value_name = "FILE")]
+        note_file: Option<PathBuf>,
+
+        /// Identify files by size and content hash only (implies --compare-by hash): modification
+        /// times are left out of the manifest, so snapshots of identical content have identical
+        /// manifests and diffs, e.g. to check that a build is reproducible
+        #[arg(long, conflicts_with = "preserve_times")]
+        content_only: bool,
+
+        /// Leave files unchanged since the previous (or --from) snapshot out of the new
+        /// snapshot's folder instead of hard-linking them; restore reads them from the
+        /// snapshots it builds on, so those can't be pruned while it exists
+        #[arg(long, visible_alias = "exclude-from-previous")]
+        incremental: bool,
+
+        /// Also write a SHA256SUMS file into the snapshot folder, so it can be checked with
+        /// `sha256sum -c SHA256SUMS` without snapsafe
+        #[arg(long)]
+        checksum_manifest: bool,
+
+        /// Print the new snapshot's version on the last line of output, for scripts
+        #[arg(long)]
+        print_version: bool,
+
+        /// Don't print progress messages (warnings and errors still go to stderr);
+        /// with --print-version, only the new version is printed
+        #[arg(short, long)]
+        quiet: bool,
+    },
+    /// Re-capture the working directory into the latest snapshot
+    ///
+    /// Like `git commit --amend`: the snapshot keeps its version, creation time,
+    /// label, tags, metadata, and note, and records when it was amended. Only the
+    /// latest snapshot can be amended.
+    ///
+    /// Examples:
+    ///   snapsafe amend
+    ///   snapsafe amend -m "Release 2.1, with the missing config file"
+    Amend {
+        /// Snapshot to amend, which must be the latest one (default: the latest)
+        snapshot_id: Option<String>,
+        /// Replace the snapshot's message
+        #[arg(short, long)]
+        message: Option<String>,
     },
     /// List all snapshots
-    List,
+    ///
+    /// Examples:
+    ///   snapsafe list
+    ///   snapsafe list --show-size
+    ///   snapsafe list --tag production
+    ///   snapsafe list --age
+    ///   snapsafe list --columns version,tags,size
+    ///   snapsafe list --no-page
+    List {
+        /// Show the total size of each snapshot in human-readable units
+        #[arg(long)]
+        show_size: bool,
+
+        /// Only list snapshots with this tag
+        #[arg(long)]
+        tag: Option<String>,
+
+        /// Show timestamps relative to now (e.g. "3 days ago")
+        #[arg(long, visible_alias = "relative-time")]
+        age: bool,
+
+        /// Comma-separated columns to show, in order
+        /// (version, label, timestamp, size, message, tags, metadata)
+        #[arg(long, value_enum, value_delimiter = ',', conflicts_with = "show_size")]
+        columns: Option<Vec<ListColumn>>,
+
+        /// Send the output through the pager ($PAGER or less) even when not printing to a
+        /// terminal; by default it is paged only on a terminal
+        #[arg(long)]
+        page: bool,
+
+        /// Never page the output
+        #[arg(long, conflicts_with = "page")]
+        no_page: bool,
+    },
+    /// Show the repository's name, description, and location
+    ///
+    /// The name and description are set with `init --name` and `init --description`.
+    ///
+    /// Examples:
+    ///   snapsafe about
+    About,
+    /// Show the snapshot history with each snapshot's parent
+    ///
+    /// A snapshot's parent is the snapshot it was hard-linked against when it was
+    /// created: the latest one, or the one given with `snapshot --from`.
+    ///
+    /// Examples:
+    ///   snapsafe log
+    ///   snapsafe log --graph
+    ///   snapsafe log --no-page
+    Log {
+        /// Draw the history as a tree, showing where snapshots branched off
+        #[arg(long)]
+        graph: bool,
+
+        /// Send the output through the pager ($PAGER or less) even when not printing to a
+        /// terminal; by default it is paged only on a terminal
+        #[arg(long)]
+        page: bool,
+
+        /// Never page the output
+        #[arg(long, conflicts_with = "page")]
+        no_page: bool,
+    },
+    /// Show how a single file changed across snapshots
+    ///
+    /// Prints a timeline of the file's size and content hash in every snapshot,
+    /// marking each as added, modified, unchanged, or removed.
+    ///
+    /// Examples:
+    ///   snapsafe history src/main.rs
+    ///   snapsafe history config/app.yaml --text
+    History {
+        /// Path of the file, relative to the repository root
+        path: String,
+
+        /// Also print the lines changed between consecutive versions of a text file
+        #[arg(long)]
+        text: bool,
+    },
     /// Show differences between two snapshots
     ///
     /// Compares two snapshots and displays files that were added, removed,
@@ -75,12 +379,40 @@ enum Commands {
     /// Examples:
     ///   snapsafe diff v1.0.0.0 v1.0.0.1
     ///   snapsafe diff v1.0.0.0  # Compares with latest snapshot
+    ///   snapsafe diff v1.0.0.0 v1.0.0.1 --meta
+    ///   snapsafe diff v1.0.0.0 v1.0.0.1 --ignore "**/*.log"
+    ///   snapsafe diff --self v1.0.0.0
+    ///   snapsafe diff v1.0.0.0 working
+    ///   snapsafe diff v1.0.0.0 v1.0.0.1 --word-diff
     Diff {
         /// First snapshot ID
         snapshot1: String,
-        /// Optional Second snapshot ID
+        /// Optional Second snapshot ID, or "working" for the working directory
         /// If not provided, defaults to the latest snapshot
         snapshot2: Option<String>,
+
+        /// Compare tags and custom metadata instead of files
+        #[arg(long)]
+        meta: bool,
+
+        /// Leave paths matching this glob pattern out of the diff (repeatable)
+        /// e.g. --ignore "**/*.log" --ignore "cache/**"
+        #[arg(long, value_name = "GLOB")]
+        ignore: Vec<String>,
+
+        /// Only report files whose size changed, ignoring modification times
+        /// The fastest way to spot large changes in huge snapshots
+        #[arg(long, conflicts_with = "meta")]
+        size_only: bool,
+
+        /// Compare the snapshot's manifest with the files stored in it (tamper check)
+        #[arg(long = "self", conflicts_with_all = ["snapshot2", "meta"])]
+        self_check: bool,
+
+        /// Also show the words that changed in each updated text file, marked as
+        /// [-removed-] and {+added+}
+        #[arg(long, conflicts_with_all = ["meta", "self_check"])]
+        word_diff: bool,
     },
     /// Restore the working directory to a snapshot state
     ///
@@ -92,15 +424,104 @@ enum Commands {
     ///   snapsafe restore v1.0.0.0
     ///   snapsafe restore latest
     ///   snapsafe restore v1.0.0.0 --no-backup
+    ///   snapsafe restore v1.0.0.0 --rollback-on-error
+    ///   snapsafe restore v1.0.0.0 --keep-newer
+    ///   snapsafe restore v1.0.0.0 --target ../checkout --clean
+    ///   snapsafe restore v1.0.0.0 --clean --dry-run
+    ///   snapsafe restore v1.0.0.0 --exclude config/local.toml
+    ///   snapsafe restore v1.0.0.0 --on-conflict backup
+    ///   snapsafe restore v1.0.0.0 assets/
+    ///   snapsafe restore --from-archive v1.0.0.0.tar.gz --target ../checkout
     Restore {
         /// Snapshot ID to restore (version, prefix, or "latest")
         /// If not provided, restores the latest snapshot
         snapshot_id: Option<String>,
 
+        /// Only restore this file or directory (e.g. "assets/"); with --clean, only files
+        /// under it are deleted
+        #[arg(requires = "snapshot_id")]
+        path: Option<String>,
+
         /// Skip creating a backup snapshot before restoring
         /// Note: Without a backup, you can't easily undo the restoration
+        /// Backups are never created when the disable_backup config key is true
         #[arg(long, action = clap::ArgAction::SetTrue)]
         no_backup: bool,
+
+        /// Automatically roll back to the backup snapshot if the restore fails partway through
+        /// Without this flag you are asked whether to roll back
+        #[arg(long)]
+        rollback_on_error: bool,
+
+        /// Don't overwrite working files modified after the snapshot recorded them
+        #[arg(long)]
+        keep_newer: bool,
+
+        /// Restore into the directory the snapshot was originally taken from
+        /// instead of the current working directory
+        #[arg(long, conflicts_with = "target")]
+        original_location: bool,
+
+        /// Restore into this directory instead of the current working directory
+        #[arg(long, value_name = "DIR")]
+        target: Option<PathBuf>,
+
+        /// Delete files that aren't part of the snapshot (ignored files are kept)
+        #[arg(long)]
+        clean: bool,
+
+        /// Only add and overwrite files, keeping files that aren't part of the snapshot (default)
+        #[arg(long, conflicts_with = "clean")]
+        merge: bool,
+
+        /// Restore the contents of an archive written by `export` instead of a stored snapshot
+        /// Existing files are overwritten without the overwrite threshold or conflict policies
+        #[arg(
+            long,
+            value_name = "FILE",
+            conflicts_with_all = [
+                "snapshot_id",
+                "original_location",
+                "keep_newer",
+                "rollback_on_error",
+                "on_conflict",
+                "force",
+                "confirm_overwrite_threshold",
+                "preserve_xattrs",
+            ]
+        )]
+        from_archive: Option<PathBuf>,
+
+        /// Restore without asking for confirmation
+        #[arg(short, long)]
+        yes: bool,
+
+        /// Allow restores that overwrite or delete more files than the confirmation threshold
+        #[arg(long)]
+        force: bool,
+
+        /// Number of files a restore may overwrite or delete before it needs --force
+        /// Defaults to the confirm_overwrite_threshold config key (1000)
+        #[arg(long, value_name = "N")]
+        confirm_overwrite_threshold: Option<usize>,
+
+        /// Reapply the extended attributes recorded in the snapshot (Unix only)
+        #[arg(long)]
+        preserve_xattrs: bool,
+
+        /// List the files that would be created, overwritten or deleted without changing anything
+        #[arg(long, conflicts_with = "from_archive")]
+        dry_run: bool,
+
+        /// Leave paths matching this glob (e.g. "config/local.toml" or "**/*.log") untouched:
+        /// they are neither restored nor deleted by --clean; repeatable
+        #[arg(long, value_name = "GLOB")]
+        exclude: Vec<String>,
+
+        /// What to do with working files that differ from the snapshot: overwrite them (default),
+        /// skip them, back them up to a .bak file first, or prompt for each one
+        #[arg(long, value_enum, value_name = "POLICY", default_value_t = ConflictPolicy::Overwrite)]
+        on_conflict: ConflictPolicy,
     },
     /// Remove old snapshots based on specified criteria
     ///
@@ -112,20 +533,46 @@ enum Commands {
     ///   snapsafe prune --keep-last 5
     ///   snapsafe prune --older-than 7d
     ///   snapsafe prune --older-than 30d --dry-run
+    ///   snapsafe prune --before 2024-01-01 --dry-run
+    ///   snapsafe prune --keep-last 5 --dry-run --json
+    ///   snapsafe prune --keep-last 2 --dry-run --show-kept
+    ///   snapsafe prune --keep-last-per-tag 3
     Prune {
         /// Keep only the N most recent snapshots and remove older ones
         #[arg(long)]
         keep_last: Option<usize>,
 
+        /// Keep the N most recent snapshots of each tag; tagged snapshots outside every tag's
+        /// newest N are removed, and the other criteria then apply to untagged snapshots
+        #[arg(long, value_name = "N")]
+        keep_last_per_tag: Option<usize>,
+
         /// Remove snapshots older than the specified duration
         /// Supports formats: "7d" (days), "24h" (hours), "30m" (minutes), "60s" (seconds)
         #[arg(long)]
         older_than: Option<String>,
 
+        /// Remove snapshots taken before this date, in local time
+        /// Accepts "2024-01-01" (midnight) or "2024-01-01 12:00:00"
+        #[arg(long, value_name = "DATE")]
+        before: Option<String>,
+
         /// Simulate pruning without actually deleting snapshots
         /// Shows what would be removed without making changes
         #[arg(long)]
         dry_run: bool,
+
+        /// With --dry-run, print the plan as JSON (versions, timestamps, reclaimable bytes)
+        #[arg(long, requires = "dry_run")]
+        json: bool,
+
+        /// With --dry-run, also list the snapshots that would survive, with their ages
+        #[arg(long, requires = "dry_run")]
+        show_kept: bool,
+
+        /// Delete without asking for confirmation (for scheduled jobs)
+        #[arg(short, long)]
+        yes: bool,
     },
 
     /// Verify the integrity of snapshots
@@ -137,10 +584,72 @@ enum Commands {
     /// Examples:
     ///   snapsafe verify
     ///   snapsafe verify v1.0.0.0
+    ///   snapsafe verify --check-links
+    ///   snapsafe verify --fail-fast
+    ///   snapsafe verify --check-mtime --strict
+    ///   snapsafe verify --json
+    ///   snapsafe verify --oneline
+    ///   snapsafe verify --show-all --color always
+    ///   snapsafe verify --skip-recent 1h
+    ///   snapsafe verify --manifest-hash
+    ///   snapsafe verify --jobs 8 --io-limit 1
+    ///
+    /// Exit codes: 0 when every snapshot passed, 11 when at least one failed,
+    /// 1 when the repository couldn't be read.
     Verify {
         /// Verify only the specified snapshot ID
         /// If not provided, verifies all snapshots
         snapshot_id: Option<String>,
+
+        /// Check that unchanged files still share an inode with the snapshot they were linked against
+        /// Reports files whose hard link was broken (e.g. by a copy tool), which wastes space
+        #[arg(long)]
+        check_links: bool,
+
+        /// Stop at the first snapshot that fails verification and skip the rest
+        #[arg(long)]
+        fail_fast: bool,
+
+        /// Compare each stored file's modification time with the manifest
+        /// Mismatches are reported as warnings unless --strict is given
+        #[arg(long)]
+        check_mtime: bool,
+
+        /// Treat modification time mismatches found by --check-mtime as failures
+        #[arg(long, requires = "check_mtime")]
+        strict: bool,
+
+        /// Print a JSON report with the status of each snapshot
+        #[arg(long)]
+        json: bool,
+
+        /// Print only one status line, e.g. "OK verified=3 failed=0", for health checks
+        #[arg(long, conflicts_with = "json")]
+        oneline: bool,
+
+        /// List every problem file in the failure details instead of the first few
+        #[arg(long)]
+        show_all: bool,
+
+        /// Color the results: auto (when printing to a terminal), always, or never
+        #[arg(long, value_enum, value_name = "WHEN", default_value_t = ColorChoice::Auto)]
+        color: ColorChoice,
+
+        /// Skip snapshots that passed verification within this duration (e.g. "1h", "7d")
+        /// and whose files and manifest haven't been modified since
+        /// Snapshots verified with this flag are recorded for the next run
+        #[arg(long, value_name = "DURATION")]
+        skip_recent: Option<String>,
+
+        /// Check that each snapshot's manifest still matches the checksum recorded when the
+        /// snapshot was created, failing snapshots whose manifest was modified since
+        #[arg(long)]
+        manifest_hash: bool,
+
+        /// Read at most N stored files at once, while --jobs files are checked in parallel;
+        /// defaults to 1 when the snapshots are on a spinning disk, otherwise to --jobs
+        #[arg(long, visible_alias = "parallel-io-limit", value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+        io_limit: Option<u64>,
     },
     /// Show detailed information about a snapshot
     ///
@@ -149,11 +658,32 @@ enum Commands {
     ///
     /// Examples:
     ///   snapsafe info v1.0.0.0
+    ///   snapsafe info v1.0.0.0 --compare latest
     ///   snapsafe info
+    ///   snapsafe info --age
+    ///   snapsafe info --churn
+    ///   snapsafe info v1.0.0.0 --note
     Info {
         /// Snapshot ID to show information
         /// If not provided, shows information for the latest snapshot
         snapshot_id: Option<String>,
+
+        /// Also show how long ago the snapshot was created (e.g. "3 days ago")
+        #[arg(long, visible_alias = "relative-time")]
+        age: bool,
+
+        /// Show the statistics of this snapshot and another one side by side, with the changes
+        #[arg(long, value_name = "OTHER_ID")]
+        compare: Option<String>,
+
+        /// Rank files by how often they changed and how long they stayed unchanged,
+        /// across all snapshots
+        #[arg(long, conflicts_with_all = ["snapshot_id", "age", "compare"])]
+        churn: bool,
+
+        /// Print the note attached with `snapshot --note-file` instead of the information
+        #[arg(long, conflicts_with_all = ["age", "compare", "churn"])]
+        note: bool,
     },
     /// Manage tags for snapshots
     ///
@@ -164,8 +694,10 @@ enum Commands {
     ///   snapsafe tag v1.0.0.0 --add production stable
     ///   snapsafe tag v1.0.0.0 --remove unstable
     ///   snapsafe tag v1.0.0.0 --list
+    ///   snapsafe tag latest --list --raw
+    ///   snapsafe tag all --add migrated
     Tag {
-        /// Snapshot ID to manage tags
+        /// Snapshot ID to manage tags, or "all" to change every snapshot
         /// If not provided, defaults to the latest snapshot
         snapshot_id: Option<String>,
 
@@ -180,6 +712,14 @@ enum Commands {
         /// List all tags for the snapshot (default if no other options provided)
         #[arg(short, long)]
         list: bool,
+
+        /// Print the list as bare tags, one per line, for use in scripts
+        #[arg(long, conflicts_with_all = ["add", "remove"])]
+        raw: bool,
+
+        /// Don't ask for confirmation before changing all snapshots
+        #[arg(short, long)]
+        yes: bool,
     },
 
     /// Manage custom metadata for snapshots
@@ -190,9 +730,12 @@ enum Commands {
     /// Examples:
     ///   snapsafe meta v1.0.0.0 --set build_id 12345
     ///   snapsafe meta v1.0.0.0 --remove build_id
+    ///   snapsafe meta v1.0.0.0 --set build_number 42 --type int
     ///   snapsafe meta v1.0.0.0 --list
+    ///   snapsafe meta latest --list --raw
+    ///   snapsafe meta all --set migrated_by ops --yes
     Meta {
-        /// Snapshot ID to manage metadata
+        /// Snapshot ID to manage metadata, or "all" to change every snapshot
         /// If not provided, defaults to the latest snapshot
         snapshot_id: Option<String>,
 
@@ -200,6 +743,10 @@ enum Commands {
         #[arg(short, long, num_args = 2)]
         set: Option<Vec<String>>,
 
+        /// Store the value set with --set as a typed value so it can be queried by range
+        #[arg(short = 't', long = "type", value_enum, default_value_t = MetaType::String)]
+        value_type: MetaType,
+
         /// Remove a metadata key and its associated value
         #[arg(short, long)]
         remove: Option<String>,
@@ -207,15 +754,187 @@ enum Commands {
         /// List all metadata for the snapshot (default if no other options provided)
         #[arg(short, long)]
         list: bool,
+
+        /// Print the list as bare key=value lines, for use in scripts
+        #[arg(long, conflicts_with_all = ["set", "remove"])]
+        raw: bool,
+
+        /// Don't ask for confirmation before changing all snapshots
+        #[arg(short, long)]
+        yes: bool,
+    },
+
+    /// Find snapshots by their tags and metadata
+    ///
+    /// Numeric comparisons use values stored with `meta --set ... --type int|float`
+    /// and fall back to parsing plain string values.
+    ///
+    /// Examples:
+    ///   snapsafe find --tag production
+    ///   snapsafe find --meta-gt build_number 40
+    ///   snapsafe find --meta-gt build_number 40 --meta-lt build_number 50
+    ///   snapsafe find --meta-eq env production
+    Find {
+        /// Match snapshots carrying this tag
+        #[arg(long)]
+        tag: Option<String>,
+
+        /// Match snapshots whose numeric metadata value is greater than NUMBER
+        #[arg(long, num_args = 2, value_names = &["KEY", "NUMBER"])]
+        meta_gt: Option<Vec<String>>,
+
+        /// Match snapshots whose numeric metadata value is less than NUMBER
+        #[arg(long, num_args = 2, value_names = &["KEY", "NUMBER"])]
+        meta_lt: Option<Vec<String>>,
+
+        /// Match snapshots whose metadata value equals VALUE
+        #[arg(long, num_args = 2, value_names = &["KEY", "VALUE"])]
+        meta_eq: Option<Vec<String>>,
+    },
+
+    /// Check the repository for consistency and repair problems
+    ///
+    /// Reports snapshots whose directory or manifest is missing and snapshot
+    /// directories that aren't listed in the head manifest. A stale search
+    /// index is rebuilt.
+    ///
+    /// Examples:
+    ///   snapsafe check
+    ///   snapsafe check --fix-manifest v1.0.0.0
+    ///   snapsafe check --repair-head
+    #[command(alias = "fsck")]
+    Check {
+        /// Rebuild a snapshot's manifest from the files stored in its directory
+        /// The snapshot is added back to the head manifest if it is missing there
+        #[arg(long, value_name = "SNAPSHOT_ID")]
+        fix_manifest: Option<String>,
+
+        /// Rebuild a lost or corrupted head manifest from the snapshot directories
+        /// Snapshots that are still listed keep their entry; the others are recovered
+        /// from their manifests, dated by when the manifest was written
+        #[arg(long)]
+        repair_head: bool,
+    },
+
+    /// Export a snapshot to a compressed archive
+    ///
+    /// Writes the snapshot's files and manifest to a .tar.gz archive that can
+    /// be copied elsewhere. Symbolic links are stored as links unless
+    /// --dereference is given.
+    ///
+    /// Examples:
+    ///   snapsafe export v1.0.0.0
+    ///   snapsafe export v1.0.0.0 -o backup.tar.gz
+    ///   snapsafe export --dereference
+    ///   snapsafe export v1.0.0.5 --since v1.0.0.4 -o incremental.tar.gz
+    Export {
+        /// Snapshot ID to export
+        /// If not provided, exports the latest snapshot
+        snapshot_id: Option<String>,
+
+        /// Path of the archive to write (defaults to .snapsafe/exports/<version>.tar.gz)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Store the contents of symbolic link targets instead of the links themselves
+        #[arg(long)]
+        dereference: bool,
+
+        /// Only store the files added or modified since this snapshot (an incremental archive)
+        /// Add it to another repository that has that snapshot with `snapsafe import`
+        #[arg(long, value_name = "BASE_ID")]
+        since: Option<String>,
+    },
+
+    /// Add a snapshot from an archive written by `export` to the repository
+    ///
+    /// Incremental archives (written with `export --since`) need their base
+    /// snapshot in the repository; unchanged files are hard-linked from it.
+    ///
+    /// Examples:
+    ///   snapsafe import v1.0.0.0.tar.gz
+    ///   snapsafe import incremental.tar.gz
+    Import {
+        /// Path of the archive to import
+        archive: PathBuf,
+    },
+
+    /// View or change configuration settings
+    ///
+    /// Settings are read from the global config file first and then from the
+    /// repository's .snapsafe/config.json, which takes precedence.
+    ///
+    /// Examples:
+    ///   snapsafe config --set exclude_vcs true
+    ///   snapsafe config --get exclude_vcs
+    ///   snapsafe config --list --global
+    ///   snapsafe config --edit
+    ///   snapsafe config --validate
+    ///   snapsafe config --paths
+    Config {
+        /// Set a configuration key and value
+        #[arg(short, long, num_args = 2, value_names = &["KEY", "VALUE"])]
+        set: Option<Vec<String>>,
+
+        /// Print the value of a configuration key
+        #[arg(short, long)]
+        get: Option<String>,
+
+        /// List all configuration settings (default if no other options provided)
+        #[arg(short, long)]
+        list: bool,
+
+        /// Open the config file in $EDITOR; the result is validated and reverted if invalid
+        #[arg(short, long, conflicts_with_all = ["set", "get", "list"])]
+        edit: bool,
+
+        /// Check the config file for unknown keys and invalid values without changing it
+        #[arg(long, conflicts_with_all = ["set", "get", "list", "edit"])]
+        validate: bool,
+
+        /// Operate on the global config file instead of the repository's
+        #[arg(long)]
+        global: bool,
+
+        /// Print where the global and repository config files are and whether each exists
+        #[arg(long, conflicts_with_all = ["set", "get", "list", "edit", "validate", "global"])]
+        paths: bool,
     },
 }
 
 fn main() {
     let cli = Cli::parse();
+    if let Some(jobs) = cli.jobs {
+        config::set_jobs_override(jobs as usize);
+    }
+    if let Some(size) = cli.buffer_size {
+        config::set_buffer_size_override(size as usize);
+    }
+    if cli.profile {
+        profile::enable();
+    }
+    if let Some(secs) = cli.timeout {
+        match cli.command {
+            Commands::Snapshot { .. } => watchdog::start("snapshot", secs),
+            Commands::Restore { .. } => watchdog::start("restore", secs),
+            Commands::Verify { .. } => watchdog::start("verify", secs),
+            _ => {}
+        }
+    }
 
     match &cli.command {
-        Commands::Init => {
-            if let Err(e) = subcommands::init::init_repository() {
+        Commands::Init {
+            store,
+            name,
+            description,
+            force,
+        } => {
+            if let Err(e) = subcommands::init::init_repository(
+                store.clone(),
+                *force,
+                name.clone(),
+                description.clone(),
+            ) {
                 eprintln!("Error initializing repository: {}", e);
                 process::exit(1);
             }
@@ -224,61 +943,274 @@ fn main() {
             version,
             message,
             tags,
+            inherit_tags,
             meta,
+            meta_json,
+            if_changed,
+            quiet_on_no_change,
+            exclude_vcs,
+            no_ignore,
+            retag,
+            from,
+            compare_by,
+            label,
+            strict_case,
+            prune_after_keep_last,
+            prune_after_older_than,
+            yes,
+            retries,
+            verbose,
+            dereference_hardlinks,
+            exclude_empty_files,
+            outlier_factor,
+            block_outliers,
+            compress_threshold,
+            preserve_times,
+            label_as_version,
+            only_if_idle,
+            note_file,
+            content_only,
+            incremental,
+            checksum_manifest,
+            print_version,
+            quiet,
         } => {
-            // Create the snapshot first
-            if let Err(e) = subcommands::snapshot::create_snapshot(message.clone(), version.clone())
+            let options = subcommands::snapshot::SnapshotOptions {
+                if_changed: *if_changed,
+                exclude_vcs: *exclude_vcs,
+                no_ignore: *no_ignore,
+                from: from.clone(),
+                compare_by: *compare_by,
+                label: label.clone(),
+                strict_case: *strict_case,
+                retries: *retries,
+                verbose: *verbose,
+                dereference_hardlinks: *dereference_hardlinks,
+                exclude_empty_files: *exclude_empty_files,
+                outlier_factor: *outlier_factor,
+                block_outliers: *block_outliers,
+                compress_threshold: *compress_threshold,
+                preserve_times: *preserve_times,
+                label_as_version: *label_as_version,
+                note_file: note_file.clone(),
+                content_only: *content_only,
+                incremental: *incremental,
+                checksum_manifest: *checksum_manifest,
+                quiet: *quiet,
+            };
+            // Reject malformed tags and metadata before anything is created. Clap gives every
+            // --meta exactly two values, so repeated flags form whole key/value pairs.
+            let mut metadata: Vec<(String, String)> = meta
+                .as_deref()
+                .unwrap_or_default()
+                .chunks(2)
+                .map(|pair| (pair[0].clone(), pair[1].clone()))
+                .collect();
+            match meta_json
+                .as_deref()
+                .map(subcommands::meta::parse_meta_json)
+                .transpose()
             {
-                eprintln!("Error creating snapshot: {}", e);
+                Ok(pairs) => metadata.extend(pairs.unwrap_or_default()),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    process::exit(1);
+                }
+            }
+            if metadata.iter().any(|(key, _)| key.trim().is_empty()) {
+                eprintln!("Error: Metadata keys can't be empty. No snapshot was created.");
                 process::exit(1);
             }
-
-            // Get the created snapshot version (likely the latest one)
-            let base_path = info::get_base_dir().unwrap();
-            let head_manifest = manifest::load_head_manifest(&base_path).unwrap();
-            if let Some(last_snapshot) = head_manifest.last() {
-                let snapshot_id = last_snapshot.version.clone();
-
-                // Add tags if provided
-                if let Some(tag_list) = tags {
-                    if let Err(e) = subcommands::tag::manage_tags(
-                        Some(snapshot_id.clone()),
-                        Some(tag_list.to_vec()),
-                        None,
-                        false,
-                    ) {
-                        eprintln!("Error adding tags: {}", e);
+            let mut all_tags = tags.iter().flatten().chain(retag.iter().flatten());
+            if all_tags.any(|tag| tag.trim().is_empty()) {
+                eprintln!("Error: Tags can't be empty. No snapshot was created.");
+                process::exit(1);
+            }
+            if let Some(idle_secs) = only_if_idle {
+                match subcommands::snapshot::find_recent_change(*idle_secs, &options) {
+                    Ok(None) => {}
+                    Ok(Some((path, age))) => {
+                        println!(
+                            "Skipping snapshot: {} was modified {}s ago, within the last {}s.",
+                            path,
+                            age.max(0),
+                            idle_secs
+                        );
+                        process::exit(constants::EXIT_NOT_IDLE);
+                    }
+                    Err(e) => {
+                        eprintln!("Error checking for recent changes: {}", e);
+                        process::exit(1);
+                    }
+                }
+            }
+            // Create the snapshot first
+            let snapshot = match subcommands::snapshot::create_snapshot(
+                message.clone(),
+                version.clone(),
+                &options,
+            ) {
+                Ok(Some(snapshot)) => snapshot,
+                Ok(None) => {
+                    if !quiet_on_no_change {
+                        println!("No changes since the latest snapshot. Nothing to snapshot.");
                     }
+                    process::exit(constants::EXIT_NO_CHANGES);
+                }
+                Err(e) => {
+                    eprintln!("Error creating snapshot: {}", e);
+                    process::exit(1);
                 }
+            };
+            let snapshot_id = snapshot.version;
 
-                // Add metadata if provided
-                if let Some(metadata) = meta {
-                    if metadata.len() == 2 {
-                        if let Err(e) = subcommands::meta::manage_metadata(
-                            Some(snapshot_id.clone()),
-                            Some(metadata.to_vec()),
-                            None,
-                            false,
-                        ) {
-                            eprintln!("Error adding metadata: {}", e);
-                        }
-                    } else {
-                        eprintln!("Error: Please provide exactly two values for --meta: a key and a value.");
+            // Add tags if provided, along with the previous snapshot's tags when inheriting
+            let mut tag_list = tags.clone().unwrap_or_default();
+            if *inherit_tags {
+                // The snapshot recorded just before this one, which may not be the parent
+                let head_manifest = info::get_base_dir()
+                    .and_then(|base_path| manifest::load_head_manifest(&base_path))
+                    .unwrap_or_default();
+                let previous = head_manifest
+                    .iter()
+                    .position(|s| s.version == snapshot_id)
+                    .and_then(|position| position.checked_sub(1))
+                    .map(|position| &head_manifest[position]);
+                for tag in previous
+                    .and_then(|s| s.metadata.as_ref())
+                    .map(|m| m.tags.as_slice())
+                    .unwrap_or_default()
+                {
+                    if !tag_list.contains(tag) {
+                        tag_list.push(tag.clone());
                     }
                 }
             }
+            if !tag_list.is_empty() {
+                if let Err(e) = subcommands::tag::manage_tags(
+                    Some(snapshot_id.clone()),
+                    Some(tag_list),
+                    None,
+                    false,
+                    false,
+                    false,
+                ) {
+                    eprintln!("Error adding tags: {}", e);
+                }
+            }
+
+            // Move pointer tags if provided
+            if let Some(tag_list) = retag {
+                if let Err(e) = subcommands::tag::move_tags(Some(snapshot_id.clone()), tag_list) {
+                    eprintln!("Error moving tags: {}", e);
+                }
+            }
+
+            // Add metadata if provided
+            for (key, value) in metadata {
+                if let Err(e) = subcommands::meta::manage_metadata(
+                    Some(snapshot_id.clone()),
+                    Some(vec![key, value]),
+                    MetaType::String,
+                    None,
+                    false,
+                    false,
+                    false,
+                ) {
+                    eprintln!("Error adding metadata: {}", e);
+                }
+            }
+
+            // Apply retention now that the new snapshot exists
+            if prune_after_keep_last.is_some() || prune_after_older_than.is_some() {
+                let options = subcommands::prune::PruneOptions {
+                    yes: *yes,
+                    ..Default::default()
+                };
+                if let Err(e) = subcommands::prune::prune_snapshots(
+                    *prune_after_keep_last,
+                    prune_after_older_than.clone(),
+                    &options,
+                ) {
+                    eprintln!("Error pruning snapshots: {}", e);
+                    process::exit(1);
+                }
+            }
+
+            if *print_version {
+                println!("{}", snapshot_id);
+            }
         }
-        Commands::List => {
-            if let Err(e) = subcommands::list::list_snapshots() {
+        Commands::Amend {
+            snapshot_id,
+            message,
+        } => {
+            if let Err(e) = subcommands::amend::amend_snapshot(snapshot_id.clone(), message.clone())
+            {
+                eprintln!("Error amending snapshot: {}", e);
+                process::exit(1);
+            }
+        }
+        Commands::List {
+            show_size,
+            tag,
+            age,
+            columns,
+            page,
+            no_page,
+        } => {
+            if let Err(e) = subcommands::list::list_snapshots(
+                *show_size,
+                tag.clone(),
+                *age,
+                columns.clone(),
+                Paging::from_flags(*page, *no_page),
+            ) {
                 eprintln!("Error listing snapshots: {}", e);
                 process::exit(1);
             }
         }
+        Commands::About => {
+            if let Err(e) = subcommands::about::show_about() {
+                eprintln!("Error showing repository information: {}", e);
+                process::exit(1);
+            }
+        }
+        Commands::Log {
+            graph,
+            page,
+            no_page,
+        } => {
+            if let Err(e) = subcommands::log::show_log(*graph, Paging::from_flags(*page, *no_page))
+            {
+                eprintln!("Error showing log: {}", e);
+                process::exit(1);
+            }
+        }
+        Commands::History { path, text } => {
+            if let Err(e) = subcommands::history::show_history(path, *text) {
+                eprintln!("Error showing file history: {}", e);
+                process::exit(1);
+            }
+        }
         Commands::Diff {
             snapshot1,
             snapshot2,
+            meta,
+            ignore,
+            size_only,
+            self_check,
+            word_diff,
         } => {
-            if let Err(e) = subcommands::diff::diff_snapshots(snapshot1.clone(), snapshot2.clone())
+            let options = subcommands::diff::DiffOptions {
+                meta: *meta,
+                ignore: ignore.clone(),
+                size_only: *size_only,
+                self_check: *self_check,
+                word_diff: *word_diff,
+            };
+            if let Err(e) =
+                subcommands::diff::diff_snapshots(snapshot1.clone(), snapshot2.clone(), &options)
             {
                 eprintln!("Error diffing snapshots: {}", e);
                 process::exit(1);
@@ -286,34 +1218,129 @@ fn main() {
         }
         Commands::Restore {
             snapshot_id,
+            path,
             no_backup,
+            rollback_on_error,
+            keep_newer,
+            original_location,
+            target,
+            clean,
+            merge: _,
+            from_archive,
+            yes,
+            force,
+            confirm_overwrite_threshold,
+            preserve_xattrs,
+            dry_run,
+            exclude,
+            on_conflict,
         } => {
             let backup = !no_backup; // Invert the flag since we want backup by default
-            if let Err(e) = subcommands::restore::restore_snapshot(snapshot_id.clone(), backup) {
+            let options = subcommands::restore::RestoreOptions {
+                rollback_on_error: *rollback_on_error,
+                keep_newer: *keep_newer,
+                original_location: *original_location,
+                target: target.clone(),
+                clean: *clean,
+                from_archive: from_archive.clone(),
+                yes: *yes,
+                force: *force,
+                confirm_overwrite_threshold: *confirm_overwrite_threshold,
+                preserve_xattrs: *preserve_xattrs,
+                dry_run: *dry_run,
+                exclude: exclude.clone(),
+                on_conflict: *on_conflict,
+                path: path.clone(),
+            };
+            if let Err(e) =
+                subcommands::restore::restore_snapshot(snapshot_id.clone(), backup, &options)
+            {
                 eprintln!("Error restoring snapshot: {}", e);
                 process::exit(1);
             }
         }
         Commands::Prune {
             keep_last,
+            keep_last_per_tag,
             older_than,
+            before,
             dry_run,
+            json,
+            show_kept,
+            yes,
         } => {
+            let options = subcommands::prune::PruneOptions {
+                dry_run: *dry_run,
+                json: *json,
+                yes: *yes,
+                keep_last_per_tag: *keep_last_per_tag,
+                before: before.clone(),
+                show_kept: *show_kept,
+            };
             if let Err(e) =
-                subcommands::prune::prune_snapshots(*keep_last, older_than.clone(), *dry_run)
+                subcommands::prune::prune_snapshots(*keep_last, older_than.clone(), &options)
             {
                 eprintln!("Error pruning snapshots: {}", e);
                 process::exit(1);
             }
         }
-        Commands::Verify { snapshot_id } => {
-            if let Err(e) = subcommands::verify::verify_snapshots(snapshot_id.clone()) {
-                eprintln!("Error verifying snapshots: {}", e);
-                process::exit(1);
+        Commands::Verify {
+            snapshot_id,
+            check_links,
+            fail_fast,
+            check_mtime,
+            strict,
+            json,
+            oneline,
+            show_all,
+            color,
+            skip_recent,
+            manifest_hash,
+            io_limit,
+        } => {
+            let options = subcommands::verify::VerifyOptions {
+                check_links: *check_links,
+                fail_fast: *fail_fast,
+                check_mtime: *check_mtime,
+                strict: *strict,
+                json: *json,
+                oneline: *oneline,
+                show_all: *show_all,
+                color: color.enabled(),
+                skip_recent: skip_recent.clone(),
+                manifest_hash: *manifest_hash,
+                io_limit: io_limit.map(|limit| limit as usize),
+            };
+            match subcommands::verify::verify_snapshots(snapshot_id.clone(), &options) {
+                Ok(true) => {}
+                Ok(false) => {
+                    profile::report();
+                    process::exit(constants::EXIT_VERIFY_FAILED)
+                }
+                Err(e) => {
+                    eprintln!("Error verifying snapshots: {}", e);
+                    process::exit(1);
+                }
             }
         }
-        Commands::Info { snapshot_id } => {
-            if let Err(e) = subcommands::info::show_snapshot_info(snapshot_id.clone()) {
+        Commands::Info {
+            snapshot_id,
+            age,
+            compare,
+            churn,
+            note,
+        } => {
+            let result = if *churn {
+                subcommands::info::show_churn()
+            } else {
+                subcommands::info::show_snapshot_info(
+                    snapshot_id.clone(),
+                    *age,
+                    compare.clone(),
+                    *note,
+                )
+            };
+            if let Err(e) = result {
                 eprintln!("Error showing snapshot info: {}", e);
                 process::exit(1);
             }
@@ -323,12 +1350,16 @@ fn main() {
             add,
             remove,
             list,
+            raw,
+            yes,
         } => {
             if let Err(e) = subcommands::tag::manage_tags(
                 snapshot_id.clone(),
                 add.clone(),
                 remove.clone(),
                 *list,
+                *raw,
+                *yes,
             ) {
                 eprintln!("Error managing tags: {}", e);
                 process::exit(1);
@@ -337,18 +1368,104 @@ fn main() {
         Commands::Meta {
             snapshot_id,
             set,
+            value_type,
             remove,
             list,
+            raw,
+            yes,
         } => {
             if let Err(e) = subcommands::meta::manage_metadata(
                 snapshot_id.clone(),
                 set.clone(),
+                *value_type,
                 remove.clone(),
                 *list,
+                *raw,
+                *yes,
             ) {
                 eprintln!("Error managing metadata: {}", e);
                 process::exit(1);
             }
         }
+        Commands::Find {
+            tag,
+            meta_gt,
+            meta_lt,
+            meta_eq,
+        } => {
+            let query = subcommands::find::FindQuery {
+                tag: tag.clone(),
+                meta_gt: meta_gt.clone(),
+                meta_lt: meta_lt.clone(),
+                meta_eq: meta_eq.clone(),
+            };
+            if let Err(e) = subcommands::find::find_snapshots(&query) {
+                eprintln!("Error finding snapshots: {}", e);
+                process::exit(1);
+            }
+        }
+        Commands::Check {
+            fix_manifest,
+            repair_head,
+        } => {
+            let options = subcommands::check::CheckOptions {
+                fix_manifest: fix_manifest.clone(),
+                repair_head: *repair_head,
+            };
+            if let Err(e) = subcommands::check::check_repository(&options) {
+                eprintln!("Error checking repository: {}", e);
+                process::exit(1);
+            }
+        }
+        Commands::Export {
+            snapshot_id,
+            output,
+            dereference,
+            since,
+        } => {
+            let options = subcommands::export::ExportOptions {
+                dereference: *dereference,
+                since: since.clone(),
+            };
+            if let Err(e) =
+                subcommands::export::export_snapshot(snapshot_id.clone(), output.clone(), &options)
+            {
+                eprintln!("Error exporting snapshot: {}", e);
+                process::exit(1);
+            }
+        }
+        Commands::Import { archive } => {
+            if let Err(e) = subcommands::import::import_archive(archive) {
+                eprintln!("Error importing archive: {}", e);
+                process::exit(1);
+            }
+        }
+        Commands::Config {
+            set,
+            get,
+            list,
+            edit,
+            validate,
+            global,
+            paths,
+        } => {
+            let result = if *paths {
+                subcommands::config::show_config_paths()
+            } else {
+                subcommands::config::manage_config(
+                    set.clone(),
+                    get.clone(),
+                    *list,
+                    *edit,
+                    *validate,
+                    *global,
+                )
+            };
+            if let Err(e) = result {
+                eprintln!("Error managing config: {}", e);
+                process::exit(1);
+            }
+        }
     }
+    profile::report();
 }
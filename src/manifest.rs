@@ -1,20 +1,24 @@
+use flate2::read::GzDecoder;
+use serde::de::DeserializeOwned;
 use std::{
     collections::HashMap,
-    fs, io,
+    fs,
+    io::{self, Read},
     path::{Path, PathBuf},
 };
 
 use crate::{
-    constants::{HEAD_MANIFEST_FILE, MANIFEST_FILE, REPO_FOLDER, SNAPSHOTS_FOLDER},
-    models::{FileMetadata, SnapshotIndex},
+    constants::{DESCRIPTION_FILE, HEAD_MANIFEST_FILE, MANIFEST_FILE, SNAPSHOT_META_FILE},
+    index,
+    info::{get_repo_dir, get_snapshots_dir},
+    models::{FileMetadata, RepoDescription, SnapshotIndex, SnapshotMetadata},
 };
 
 pub fn initialize_head_manifest(base_path: &Path) -> io::Result<()> {
-    let head_manifest_path = base_path.join(REPO_FOLDER).join(HEAD_MANIFEST_FILE);
+    let head_manifest_path = get_repo_dir(base_path).join(HEAD_MANIFEST_FILE);
     if !head_manifest_path.exists() {
         let empty: Vec<SnapshotIndex> = Vec::new();
-        let manifest_json = serde_json::to_string_pretty(&empty)
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        let manifest_json = serde_json::to_string_pretty(&empty).map_err(io::Error::other)?;
         fs::write(&head_manifest_path, manifest_json)?;
         println!("Initialized head manifest at {:?}", head_manifest_path);
     } else {
@@ -23,44 +27,144 @@ pub fn initialize_head_manifest(base_path: &Path) -> io::Result<()> {
     Ok(())
 }
 
-/// Loads the head manifest from `.snapsafe/head_manifest.json`.
+/// Loads the head manifest from `.snapsafe/head_manifest.json`, in any format
+/// `read_manifest_entries` recognizes.
 pub fn load_head_manifest(base_path: &Path) -> io::Result<Vec<SnapshotIndex>> {
-    let head_manifest_path = base_path.join(REPO_FOLDER).join(HEAD_MANIFEST_FILE);
+    let head_manifest_path = get_repo_dir(base_path).join(HEAD_MANIFEST_FILE);
     if head_manifest_path.exists() {
-        let content = fs::read_to_string(&head_manifest_path)?;
-        let indices: Vec<SnapshotIndex> =
-            serde_json::from_str(&content).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-        Ok(indices)
+        read_manifest_entries(&head_manifest_path)
     } else {
         Ok(Vec::new())
     }
 }
 
-/// Saves the head manifest to `.snapsafe/head_manifest.json`.
+/// Reads the entries of a manifest file, detecting its format from its first bytes so
+/// repositories mixing formats can be read transparently:
+/// - a JSON array, pretty-printed or compact (the format snapsafe writes);
+/// - JSON Lines, one JSON object per line;
+/// - either of those compressed with gzip.
+pub fn read_manifest_entries<T: DeserializeOwned>(path: &Path) -> io::Result<Vec<T>> {
+    let bytes = fs::read(path)?;
+    decode_manifest(&bytes).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Can't read manifest {:?}: {}", path, e),
+        )
+    })
+}
+
+/// Decodes manifest contents in one of the formats listed on `read_manifest_entries`.
+fn decode_manifest<T: DeserializeOwned>(bytes: &[u8]) -> io::Result<Vec<T>> {
+    const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+    if bytes.starts_with(&GZIP_MAGIC) {
+        let mut decoded = Vec::new();
+        GzDecoder::new(bytes).read_to_end(&mut decoded)?;
+        return decode_manifest(&decoded);
+    }
+    let invalid = |e: serde_json::Error| io::Error::new(io::ErrorKind::InvalidData, e);
+    match bytes.iter().find(|b| !b.is_ascii_whitespace()) {
+        Some(b'[') => serde_json::from_slice(bytes).map_err(invalid),
+        Some(b'{') => bytes
+            .split(|&b| b == b'\n')
+            .filter(|line| line.iter().any(|b| !b.is_ascii_whitespace()))
+            .map(|line| serde_json::from_slice(line).map_err(invalid))
+            .collect(),
+        // An empty file holds no entries
+        None => Ok(Vec::new()),
+        Some(_) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "unrecognized manifest format (expected JSON, JSON Lines, or gzip)",
+        )),
+    }
+}
+
+/// Saves the head manifest to `.snapsafe/head_manifest.json` and refreshes the search index
+/// so tag and metadata lookups stay in sync with it.
 pub fn save_head_manifest(base_path: &Path, indices: &[SnapshotIndex]) -> io::Result<()> {
-    let head_manifest_path = base_path.join(REPO_FOLDER).join(HEAD_MANIFEST_FILE);
-    let json = serde_json::to_string_pretty(&indices)
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    let head_manifest_path = get_repo_dir(base_path).join(HEAD_MANIFEST_FILE);
+    let json = serde_json::to_string_pretty(&indices).map_err(io::Error::other)?;
     fs::write(&head_manifest_path, json)?;
+    index::save_index(base_path, &index::build_index(indices))?;
     Ok(())
 }
 
-/// Loads the detailed manifest for the given snapshot version from its snapshot folder.
+/// Writes the detailed manifest (`manifest.json`) into the given snapshot folder. Entries are
+/// sorted by path, so the same tree always produces the same manifest, whatever order the
+/// filesystem listed its files in. Returns the checksum of the written manifest, to be recorded
+/// in the snapshot's head manifest entry.
+pub fn save_snapshot_manifest(
+    snapshot_dir: &Path,
+    metadata: &[FileMetadata],
+) -> io::Result<String> {
+    let mut sorted: Vec<&FileMetadata> = metadata.iter().collect();
+    sorted.sort_by(|a, b| a.relative_path.cmp(&b.relative_path));
+    let json = serde_json::to_string_pretty(&sorted).map_err(io::Error::other)?;
+    fs::write(snapshot_dir.join(MANIFEST_FILE), &json)?;
+    Ok(blake3::hash(json.as_bytes()).to_hex().to_string())
+}
+
+/// Returns the BLAKE3 hash of a snapshot's `manifest.json` as it is on disk.
+pub fn manifest_checksum(snapshot_dir: &Path) -> io::Result<String> {
+    let bytes = fs::read(snapshot_dir.join(MANIFEST_FILE))?;
+    Ok(blake3::hash(&bytes).to_hex().to_string())
+}
+
+/// Writes a snapshot's tags and metadata to `.snapsafe_meta.json` in its snapshot folder, if the
+/// folder exists. The file is replaced rather than written in place, so a stored file that
+/// happens to share its name and inode with other snapshots is never modified.
+pub fn save_snapshot_meta(base_path: &Path, snapshot: &SnapshotIndex) -> io::Result<()> {
+    let snapshot_dir = get_snapshots_dir(base_path).join(&snapshot.version);
+    if !snapshot_dir.is_dir() {
+        return Ok(());
+    }
+    let metadata = snapshot.metadata.clone().unwrap_or_default();
+    let json = serde_json::to_string_pretty(&metadata).map_err(io::Error::other)?;
+    let temp_path = snapshot_dir.join(format!("{}.tmp", SNAPSHOT_META_FILE));
+    fs::write(&temp_path, json)?;
+    fs::rename(&temp_path, snapshot_dir.join(SNAPSHOT_META_FILE))
+}
+
+/// Loads the tags and metadata stored in a snapshot folder, if it has any.
+pub fn load_snapshot_meta(snapshot_dir: &Path) -> io::Result<Option<SnapshotMetadata>> {
+    let path = snapshot_dir.join(SNAPSHOT_META_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(&path)?;
+    serde_json::from_str(&content)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Loads the repository's name and description from `.snapsafe/description.json`; both are
+/// unset if the file doesn't exist.
+pub fn load_repo_description(base_path: &Path) -> io::Result<RepoDescription> {
+    let path = get_repo_dir(base_path).join(DESCRIPTION_FILE);
+    if !path.exists() {
+        return Ok(RepoDescription::default());
+    }
+    let content = fs::read_to_string(&path)?;
+    serde_json::from_str(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Writes the repository's name and description to `.snapsafe/description.json`.
+pub fn save_repo_description(base_path: &Path, description: &RepoDescription) -> io::Result<()> {
+    let json = serde_json::to_string_pretty(description).map_err(io::Error::other)?;
+    fs::write(get_repo_dir(base_path).join(DESCRIPTION_FILE), json)
+}
+
+/// Loads the detailed manifest for the given snapshot version from its snapshot folder, in any
+/// format `read_manifest_entries` recognizes.
 /// Returns an Option with a tuple containing the snapshot folder path and a HashMap
 /// mapping each file's relative path to its FileMetadata.
 pub fn load_snapshot_manifest(
     base_path: &Path,
     version: &str,
 ) -> io::Result<Option<(PathBuf, HashMap<String, FileMetadata>)>> {
-    let snapshot_folder = base_path
-        .join(REPO_FOLDER)
-        .join(SNAPSHOTS_FOLDER)
-        .join(version);
+    let snapshot_folder = get_snapshots_dir(base_path).join(version);
     let manifest_path = snapshot_folder.join(MANIFEST_FILE);
     if manifest_path.exists() {
-        let manifest_content = fs::read_to_string(&manifest_path)?;
-        let metadata_vec: Vec<FileMetadata> = serde_json::from_str(&manifest_content)
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        let metadata_vec: Vec<FileMetadata> = read_manifest_entries(&manifest_path)?;
         let mut metadata_map = HashMap::new();
         for meta in metadata_vec {
             metadata_map.insert(meta.relative_path.clone(), meta);
@@ -71,6 +175,45 @@ pub fn load_snapshot_manifest(
     }
 }
 
+/// Returns the versions an incremental snapshot reads its unchanged files from, nearest first:
+/// its parent, and the parent's parent while that is incremental too. Empty for a snapshot
+/// that stores all of its files.
+pub fn incremental_bases(head: &[SnapshotIndex], version: &str) -> Vec<String> {
+    let mut bases: Vec<String> = Vec::new();
+    let mut current = head.iter().find(|s| s.version == version);
+    while let Some(parent) = current
+        .filter(|s| s.incremental)
+        .and_then(|s| s.parent.as_deref())
+    {
+        // A parent that is already in the chain would loop forever
+        if parent == version || bases.iter().any(|base| base == parent) {
+            break;
+        }
+        bases.push(parent.to_string());
+        current = head.iter().find(|s| s.version == parent);
+    }
+    bases
+}
+
+/// Returns the folders a snapshot's stored files are found in: its own folder followed by the
+/// folders of its incremental bases.
+pub fn snapshot_chain(base_path: &Path, head: &[SnapshotIndex], version: &str) -> Vec<PathBuf> {
+    let snapshots_dir = get_snapshots_dir(base_path);
+    std::iter::once(version.to_string())
+        .chain(incremental_bases(head, version))
+        .map(|version| snapshots_dir.join(version))
+        .collect()
+}
+
+/// Returns the first folder in a snapshot chain that stores the file, or the snapshot's own
+/// folder if none does.
+pub fn stored_file_dir<'a>(chain: &'a [PathBuf], relative_path: &str) -> &'a Path {
+    chain
+        .iter()
+        .find(|dir| fs::symlink_metadata(dir.join(relative_path)).is_ok())
+        .unwrap_or(&chain[0])
+}
+
 /// Loads the previous snapshot's detailed manifest (if any) from the head manifest.
 /// Returns an Option with a tuple containing the snapshot folder path and a HashMap
 /// mapping each file's relative path to its FileMetadata.
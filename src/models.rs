@@ -1,6 +1,20 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+/// Timestamps of a file captured for forensic snapshots, as RFC 3339 strings with nanoseconds.
+/// Restore reapplies the access and modification times; the creation time (and the inode change
+/// time, which isn't captured) are assigned by the operating system and can't be restored.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FileTimes {
+    /// Last access time.
+    pub accessed: String,
+    /// Last modification time.
+    pub modified: String,
+    /// Creation (birth) time, where the platform and filesystem report it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<String>,
+}
+
 /// Structure to hold metadata for a single file.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct FileMetadata {
@@ -8,8 +22,43 @@ pub struct FileMetadata {
     pub relative_path: String,
     /// File size in bytes.
     pub file_size: u64,
-    /// Last modification time as a formatted string.
+    /// Last modification time as a formatted string. Empty (and left out of the manifest) in
+    /// snapshots taken with `--content-only`, which identify files by size and hash alone.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub modified: String,
+    /// BLAKE3 hash of the file contents, computed when the file was copied or hashed and carried
+    /// over from the baseline when it was hard-linked. Manifests written by older versions lack
+    /// it for most files.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+    /// SHA-256 hash of the file contents, computed when the file was copied and carried over from
+    /// the baseline when it was hard-linked. A file whose modification time changed is still
+    /// linked when its SHA-256 matches the baseline's. Manifests written by older versions lack it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// Target of a symbolic link, which is stored as a link rather than a copy of its target.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link_target: Option<String>,
+    /// Extended attributes of the file (Unix only). Attributes whose name or value isn't valid
+    /// UTF-8 aren't recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub xattrs: Option<HashMap<String, String>>,
+    /// Permission bits of the file (Unix only, e.g. 0o755). None for symbolic links, on other
+    /// platforms, and in snapshots taken before modes were recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+    /// Precise access, modification, and creation times, captured with
+    /// `snapshot --preserve-times`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub times: Option<FileTimes>,
+    /// Size of the gzip-compressed copy stored in the snapshot, for files stored compressed
+    /// (see the `compress_threshold` setting). None means the file is stored as is.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compressed_size: Option<u64>,
+    /// Modification time of the stored copy, when it is a hard link to a baseline's copy taken
+    /// before the file was rewritten with the same contents. None when it matches `modified`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stored_modified: Option<String>,
 }
 
 /// Structure for custom metadata attached to a snapshot
@@ -19,10 +68,55 @@ pub struct SnapshotMetadata {
     pub tags: Vec<String>,
     /// Custom key-value properties
     pub custom: HashMap<String, String>,
+    /// Typed values for custom properties set with an explicit type (e.g. `--type int`).
+    /// The string form is still kept in `custom` so older readers see every property.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub typed: HashMap<String, serde_json::Value>,
+    /// The last verification the snapshot passed, used by `verify --skip-recent`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_verified: Option<VerificationRecord>,
 }
 
-/// Structure to represent a snapshot entry in the head manifest.
+/// Human-readable name and description of a repository, shown by `about` and `info` to tell
+/// repositories apart.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct RepoDescription {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// A successful run of `verify` over one snapshot.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct VerificationRecord {
+    /// When the verification started, in `%Y-%m-%d %H:%M:%S` format.
+    pub verified_at: String,
+    /// Whether hard-link sharing was checked (`--check-links`).
+    pub check_links: bool,
+    /// Whether modification times were checked and all matched (`--check-mtime`).
+    pub check_mtime: bool,
+    /// Whether the manifest was checked against its recorded checksum (`--manifest-hash`).
+    #[serde(default)]
+    pub manifest_hash: bool,
+    /// Hash of the paths, sizes, and modification times of the snapshot's stored files.
+    #[serde(default)]
+    pub fingerprint: String,
+}
+
+/// Contents of `incremental.json` in an archive written by `export --since`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IncrementalExport {
+    /// Version of the snapshot the archive is relative to; it must exist where it is imported.
+    pub base: String,
+    /// Head manifest entry of the exported snapshot.
+    pub snapshot: SnapshotIndex,
+    /// Files stored in the archive: those added or modified since the base snapshot.
+    pub files: Vec<String>,
+}
+
+/// Structure to represent a snapshot entry in the head manifest.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
 pub struct SnapshotIndex {
     /// The version string (e.g., "v1.0.0.0" or "vrelease" if provided).
     pub version: String,
@@ -33,4 +127,28 @@ pub struct SnapshotIndex {
     /// Optional metadata for the snapshot
     #[serde(default)]
     pub metadata: Option<SnapshotMetadata>,
+    /// Total size in bytes of the files in the snapshot, cached at creation
+    #[serde(default)]
+    pub total_size: Option<u64>,
+    /// Absolute path of the directory the snapshot was taken from
+    #[serde(default)]
+    pub base_dir: Option<String>,
+    /// Human-friendly name for the snapshot, unique within the repository
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Version of the snapshot this one was hard-linked against when it was created
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    /// Files unchanged since the parent aren't stored in the snapshot's folder but read from
+    /// the parent's (`snapshot --incremental`)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub incremental: bool,
+    /// BLAKE3 hash of the snapshot's `manifest.json` when it was written, checked by
+    /// `verify --manifest-hash`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub manifest_checksum: Option<String>,
+    /// When the snapshot was last re-captured with `amend`; `timestamp` keeps the original
+    /// creation time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub amended_at: Option<String>,
 }
@@ -0,0 +1,72 @@
+//! Paging of long output for `list` and `log`, similar to git.
+//!
+//! Output goes through `$PAGER` (or `less`, `more` on Windows) when stdout is a terminal, so it
+//! is never paged when piped. `less` is started with `LESS=FRX` unless `LESS` is set, so output
+//! that fits on one screen is printed directly and left on the terminal.
+
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::process::{Child, Command, Stdio};
+
+/// When output goes through the pager.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Paging {
+    /// When printing to a terminal (default)
+    #[default]
+    Auto,
+    /// Even when stdout isn't a terminal (`--page`)
+    Always,
+    /// Never (`--no-page`)
+    Never,
+}
+
+impl Paging {
+    /// Returns the paging mode selected by the `--page` and `--no-page` flags.
+    pub fn from_flags(page: bool, no_page: bool) -> Self {
+        if no_page {
+            Paging::Never
+        } else if page {
+            Paging::Always
+        } else {
+            Paging::Auto
+        }
+    }
+}
+
+/// Prints lines of output, through the pager when paging applies. If the pager can't be
+/// started, the lines are printed directly.
+pub fn print(lines: &[String], paging: Paging) -> io::Result<()> {
+    let enabled = match paging {
+        Paging::Always => true,
+        Paging::Never => false,
+        Paging::Auto => io::stdout().is_terminal(),
+    };
+    let mut output = lines.join("\n");
+    output.push('\n');
+
+    if let Some(mut pager) = enabled.then(spawn_pager).flatten() {
+        if let Some(mut stdin) = pager.stdin.take() {
+            // The pipe closes if the user quits the pager before reading everything
+            let _ = stdin.write_all(output.as_bytes());
+        }
+        pager.wait()?;
+        return Ok(());
+    }
+    io::stdout().write_all(output.as_bytes())
+}
+
+/// Starts the pager named by `$PAGER` (which may include arguments, e.g. "less -S"), or the
+/// platform's default pager, reading from a pipe.
+fn spawn_pager() -> Option<Child> {
+    let pager = env::var("PAGER")
+        .ok()
+        .filter(|pager| !pager.trim().is_empty())
+        .unwrap_or_else(|| if cfg!(windows) { "more" } else { "less" }.to_string());
+    let mut parts = pager.split_whitespace();
+    let mut command = Command::new(parts.next()?);
+    command.args(parts).stdin(Stdio::piped());
+    if env::var_os("LESS").is_none() {
+        command.env("LESS", "FRX");
+    }
+    command.spawn().ok()
+}
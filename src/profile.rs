@@ -0,0 +1,68 @@
+//! Phase timings for the global `--profile` flag.
+//!
+//! Commands time their phases (e.g. traversal, copying, manifest write) with `start` or `time`;
+//! the totals are printed once the command finishes. Nothing is recorded unless profiling is
+//! enabled.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Total time per phase, in the order the phases first ran.
+static PHASES: Mutex<Vec<(&'static str, Duration)>> = Mutex::new(Vec::new());
+
+/// Turns on recording of phase timings for this run.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Measures one run of a phase; the time is added to the phase when the timer is dropped.
+pub struct Timer {
+    phase: &'static str,
+    start: Option<Instant>,
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        if let Some(start) = self.start {
+            record(self.phase, start.elapsed());
+        }
+    }
+}
+
+/// Starts timing a phase until the returned timer is dropped. A phase that runs several times
+/// (or on several threads at once) accumulates, so its total can exceed the wall-clock time.
+pub fn start(phase: &'static str) -> Timer {
+    Timer {
+        phase,
+        start: ENABLED.load(Ordering::Relaxed).then(Instant::now),
+    }
+}
+
+/// Runs f, adding the time it took to the given phase.
+pub fn time<T>(phase: &'static str, f: impl FnOnce() -> T) -> T {
+    let _timer = start(phase);
+    f()
+}
+
+fn record(phase: &'static str, elapsed: Duration) {
+    let mut phases = PHASES.lock().unwrap_or_else(|e| e.into_inner());
+    match phases.iter_mut().find(|(name, _)| *name == phase) {
+        Some((_, total)) => *total += elapsed,
+        None => phases.push((phase, elapsed)),
+    }
+}
+
+/// Prints the recorded phase timings, if any.
+pub fn report() {
+    let phases = PHASES.lock().unwrap_or_else(|e| e.into_inner());
+    if phases.is_empty() {
+        return;
+    }
+    println!("\nProfile:");
+    for (phase, total) in phases.iter() {
+        println!("  {:<16} {:>10.3} ms", phase, total.as_secs_f64() * 1000.0);
+    }
+}
@@ -0,0 +1,87 @@
+//! Limits on concurrent storage access, separate from the number of worker threads.
+//!
+//! Parallel workers speed up checks on solid-state and network storage, but on a spinning disk
+//! concurrent reads turn into random seeks and make things slower. An `IoLimiter` caps how many
+//! workers touch the disk at once while the rest of their work stays parallel.
+
+use std::path::Path;
+use std::sync::{Condvar, Mutex};
+
+/// Counting semaphore bounding the number of storage accesses in flight.
+pub struct IoLimiter {
+    available: Mutex<usize>,
+    freed: Condvar,
+}
+
+/// Permission to access storage, returned to the limiter when dropped.
+pub struct IoPermit<'a> {
+    limiter: &'a IoLimiter,
+}
+
+impl IoLimiter {
+    /// Creates a limiter allowing `limit` concurrent accesses (at least one).
+    pub fn new(limit: usize) -> Self {
+        IoLimiter {
+            available: Mutex::new(limit.max(1)),
+            freed: Condvar::new(),
+        }
+    }
+
+    /// Waits until an access is allowed and returns the permit for it.
+    pub fn acquire(&self) -> IoPermit<'_> {
+        let mut available = self.available.lock().unwrap_or_else(|e| e.into_inner());
+        while *available == 0 {
+            available = self
+                .freed
+                .wait(available)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+        *available -= 1;
+        IoPermit { limiter: self }
+    }
+}
+
+impl Drop for IoPermit<'_> {
+    fn drop(&mut self) {
+        let mut available = self
+            .limiter
+            .available
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        *available += 1;
+        self.limiter.freed.notify_one();
+    }
+}
+
+/// Returns the number of concurrent storage accesses to allow by default for `jobs` workers
+/// reading from `path`: one on a spinning disk, otherwise one per worker.
+pub fn default_io_limit(path: &Path, jobs: usize) -> usize {
+    if is_rotational(path) == Some(true) {
+        1
+    } else {
+        jobs
+    }
+}
+
+/// Returns whether the disk holding `path` is a spinning disk, as reported by the kernel in
+/// `/sys/dev/block/<major>:<minor>/queue/rotational`, or None if that can't be told.
+#[cfg(target_os = "linux")]
+pub fn is_rotational(path: &Path) -> Option<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    let dev = std::fs::metadata(path).ok()?.dev();
+    let major = ((dev >> 8) & 0xfff) | ((dev >> 32) & !0xfff);
+    let minor = (dev & 0xff) | ((dev >> 12) & !0xff);
+    let device = format!("/sys/dev/block/{}:{}", major, minor);
+    // Partitions report through the whole disk, one level up
+    ["queue/rotational", "../queue/rotational"]
+        .iter()
+        .find_map(|file| std::fs::read_to_string(format!("{}/{}", device, file)).ok())
+        .map(|value| value.trim() == "1")
+}
+
+/// Disk types aren't detected on this platform.
+#[cfg(not(target_os = "linux"))]
+pub fn is_rotational(_path: &Path) -> Option<bool> {
+    None
+}
@@ -0,0 +1,38 @@
+use std::io;
+
+use crate::info;
+use crate::manifest::{load_head_manifest, load_repo_description};
+
+/// Prints the repository's name and description (set with `init --name/--description`), where
+/// it is, and how many snapshots it holds.
+pub fn show_about() -> io::Result<()> {
+    let base_path = info::get_base_dir()?;
+    let description = load_repo_description(&base_path)?;
+    let head_manifest = load_head_manifest(&base_path)?;
+
+    println!(
+        "Name:        {}",
+        description
+            .name
+            .as_deref()
+            .unwrap_or("(none; set one with 'snapsafe init --name')")
+    );
+    if let Some(ref text) = description.description {
+        println!("Description: {}", text);
+    }
+    println!("Directory:   {}", base_path.display());
+    println!(
+        "Snapshots:   {}",
+        info::get_snapshots_dir(&base_path).display()
+    );
+    match head_manifest.last() {
+        Some(latest) => println!(
+            "Count:       {} (latest {}, {})",
+            head_manifest.len(),
+            latest.version,
+            latest.timestamp
+        ),
+        None => println!("Count:       0"),
+    }
+    Ok(())
+}
@@ -0,0 +1,114 @@
+use std::fs;
+use std::io::{self, ErrorKind};
+
+use chrono::Local;
+
+use crate::constants::NOTE_FILE;
+use crate::info;
+use crate::manifest::{load_head_manifest, save_head_manifest, save_snapshot_meta};
+use crate::subcommands::snapshot::{self, SnapshotOptions};
+
+/// Hidden folder the snapshot being amended is moved to while the working directory is
+/// captured again, so it can be put back if that fails.
+const AMEND_FOLDER: &str = ".amend";
+
+/// Re-captures the working directory into the latest snapshot, like `git commit --amend`:
+/// the snapshot keeps its version, creation timestamp, label, tags, metadata, and note (and its
+/// message unless a new one is given), and records when it was amended. Only the latest
+/// snapshot can be amended, since later snapshots are hard-linked against or built on it.
+pub fn amend_snapshot(snapshot_id: Option<String>, message: Option<String>) -> io::Result<()> {
+    let base_path = info::get_base_dir()?;
+    let mut head_manifest = load_head_manifest(&base_path)?;
+    let Some(latest) = head_manifest.last() else {
+        return Err(io::Error::new(
+            ErrorKind::NotFound,
+            "No snapshots to amend. Create one with 'snapsafe snapshot' first.",
+        ));
+    };
+    if let Some(id) = snapshot_id {
+        let version = info::resolve_snapshot_id(Some(id), &head_manifest)?;
+        if version != latest.version {
+            let position = head_manifest.iter().position(|s| s.version == version);
+            let later = head_manifest.len() - 1 - position.unwrap_or_default();
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Snapshot {} can't be amended: {} later snapshot(s) were created after it. Only the latest snapshot can be amended.",
+                    version, later
+                ),
+            ));
+        }
+    }
+
+    let original = head_manifest.pop().unwrap();
+    let snapshots_path = info::get_snapshots_dir(&base_path);
+    let snapshot_dir = snapshots_path.join(&original.version);
+    let amend_dir = snapshots_path.join(AMEND_FOLDER);
+    if amend_dir.exists() {
+        fs::remove_dir_all(&amend_dir)?;
+    }
+
+    // Take the snapshot out of the repository while it's captured again under its version
+    fs::rename(&snapshot_dir, &amend_dir)?;
+    save_head_manifest(&base_path, &head_manifest)?;
+    let options = SnapshotOptions {
+        // Hard-link against the snapshot the original was created against
+        from: original
+            .parent
+            .clone()
+            .filter(|parent| head_manifest.last().map(|s| &s.version) != Some(parent)),
+        label: original.label.clone(),
+        label_as_version: true,
+        incremental: original.incremental,
+        quiet: true,
+        ..Default::default()
+    };
+    let result = snapshot::create_snapshot(
+        message.or_else(|| original.message.clone()),
+        Some(original.version.clone()),
+        &options,
+    );
+    let amended = match result {
+        Ok(Some(amended)) => amended,
+        Ok(None) => unreachable!("amend always captures the working directory"),
+        Err(e) => {
+            // Put the original snapshot back
+            if snapshot_dir.exists() {
+                fs::remove_dir_all(&snapshot_dir)?;
+            }
+            fs::rename(&amend_dir, &snapshot_dir)?;
+            let mut head_manifest = load_head_manifest(&base_path)?;
+            head_manifest.push(original);
+            save_head_manifest(&base_path, &head_manifest)?;
+            return Err(e);
+        }
+    };
+
+    let note = amend_dir.join(NOTE_FILE);
+    if note.is_file() {
+        fs::copy(&note, snapshot_dir.join(NOTE_FILE))?;
+    }
+    let mut head_manifest = load_head_manifest(&base_path)?;
+    let entry = head_manifest
+        .iter_mut()
+        .find(|s| s.version == amended.version)
+        .ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::NotFound,
+                format!("Snapshot {} not found", amended.version),
+            )
+        })?;
+    entry.timestamp = original.timestamp.clone();
+    entry.metadata = original.metadata;
+    entry.amended_at = Some(Local::now().format("%Y-%m-%d %H:%M:%S").to_string());
+    let entry = entry.clone();
+    save_head_manifest(&base_path, &head_manifest)?;
+    save_snapshot_meta(&base_path, &entry)?;
+    fs::remove_dir_all(&amend_dir)?;
+
+    println!(
+        "Amended snapshot {} (created {}).",
+        entry.version, original.timestamp
+    );
+    Ok(())
+}
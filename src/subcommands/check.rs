@@ -0,0 +1,343 @@
+use chrono::{DateTime, Local};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::config;
+use crate::constants::{CHECKSUMS_FILE, MANIFEST_FILE, NOTE_FILE, SNAPSHOT_META_FILE};
+use crate::index;
+use crate::info;
+use crate::manifest::{self, load_head_manifest, save_head_manifest};
+use crate::models::{FileMetadata, SnapshotIndex, SnapshotMetadata};
+use crate::subcommands::snapshot;
+
+/// Optional repairs performed by `check_repository`.
+#[derive(Debug, Default, Clone)]
+pub struct CheckOptions {
+    /// Rebuild the manifest of this snapshot from the files in its directory.
+    pub fix_manifest: Option<String>,
+    /// Rebuild the head manifest from the snapshot directories.
+    pub repair_head: bool,
+}
+
+/// Checks the repository structure for consistency between the head manifest and the
+/// snapshot directories, optionally repairing problems first.
+pub fn check_repository(options: &CheckOptions) -> io::Result<()> {
+    let base_path = info::get_base_dir()?;
+    let repo_path = info::get_repo_dir(&base_path);
+    if !repo_path.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "Repository not initialized. Please run the init command first.",
+        ));
+    }
+
+    if options.repair_head {
+        repair_head_manifest(&base_path)?;
+    }
+    if let Some(ref id) = options.fix_manifest {
+        rebuild_manifest(&base_path, id)?;
+    }
+
+    reconcile_snapshot_meta(&base_path)?;
+    let head_manifest = load_head_manifest(&base_path)?;
+    let snapshots_path = info::get_snapshots_dir(&base_path);
+    let mut problems = 0;
+
+    println!("Checking {} snapshot(s)...", head_manifest.len());
+    for snapshot in &head_manifest {
+        let snapshot_dir = snapshots_path.join(&snapshot.version);
+        if !snapshot_dir.is_dir() {
+            println!("❌ {}: snapshot directory is missing", snapshot.version);
+            problems += 1;
+        } else if !snapshot_dir.join(MANIFEST_FILE).exists() {
+            println!(
+                "❌ {}: manifest is missing (run 'snapsafe check --fix-manifest {}')",
+                snapshot.version, snapshot.version
+            );
+            problems += 1;
+        } else if let Some((_, files)) =
+            manifest::load_snapshot_manifest(&base_path, &snapshot.version)?
+        {
+            let files: Vec<_> = files.into_values().collect();
+            for group in snapshot::find_case_collisions(&files) {
+                println!(
+                    "⚠️  {}: paths differ only by case: {}",
+                    snapshot.version,
+                    group.join(", ")
+                );
+            }
+        }
+    }
+
+    if snapshots_path.is_dir() {
+        for entry in fs::read_dir(&snapshots_path)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if entry.path().is_dir() && !head_manifest.iter().any(|s| s.version == name) {
+                println!(
+                    "❌ {}: snapshot directory is not in the head manifest",
+                    name
+                );
+                problems += 1;
+            }
+        }
+    }
+
+    let expected_index = index::build_index(&head_manifest);
+    if index::load_index(&base_path)?.as_ref() != Some(&expected_index) {
+        index::save_index(&base_path, &expected_index)?;
+        println!("Rebuilt the stale search index.");
+    }
+
+    if problems > 0 {
+        return Err(io::Error::other(format!("{} problem(s) found", problems)));
+    }
+    println!("✅ Repository is consistent.");
+    Ok(())
+}
+
+/// Rewrites the head manifest from the snapshot directories, for when it was lost or corrupted.
+/// Listed snapshots whose directory still exists keep their entry; every other directory with a
+/// manifest gets a new entry, dated by when its manifest was written, with its tags and metadata
+/// taken from its folder. Entries of snapshots whose directory is gone are dropped.
+fn repair_head_manifest(base_path: &Path) -> io::Result<()> {
+    let snapshots_path = info::get_snapshots_dir(base_path);
+    let listed = load_head_manifest(base_path).unwrap_or_else(|e| {
+        println!("The head manifest is unreadable ({}); rebuilding it.", e);
+        Vec::new()
+    });
+
+    let mut head_manifest = Vec::new();
+    for snapshot in listed {
+        if snapshots_path
+            .join(&snapshot.version)
+            .join(MANIFEST_FILE)
+            .is_file()
+        {
+            head_manifest.push(snapshot);
+        } else {
+            println!(
+                "Dropped snapshot {} from the head manifest: its directory or manifest is missing.",
+                snapshot.version
+            );
+        }
+    }
+
+    let mut recovered = 0;
+    let mut entries = match fs::read_dir(&snapshots_path) {
+        Ok(entries) => entries.collect::<io::Result<Vec<_>>>()?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e),
+    };
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let version = entry.file_name().to_string_lossy().to_string();
+        let snapshot_dir = entry.path();
+        let manifest_path = snapshot_dir.join(MANIFEST_FILE);
+        // Hidden folders (e.g. an interrupted import) aren't snapshots
+        if version.starts_with('.')
+            || !manifest_path.is_file()
+            || head_manifest.iter().any(|s| s.version == version)
+        {
+            continue;
+        }
+        let files: Vec<FileMetadata> = match manifest::read_manifest_entries(&manifest_path) {
+            Ok(files) => files,
+            Err(e) => {
+                println!("⚠️  {}: skipped, {}", version, e);
+                continue;
+            }
+        };
+        let created: DateTime<Local> = fs::metadata(&manifest_path)?
+            .modified()
+            .map(DateTime::<Local>::from)
+            .unwrap_or_else(|_| Local::now());
+        head_manifest.push(SnapshotIndex {
+            version,
+            timestamp: created.format("%Y-%m-%d %H:%M:%S").to_string(),
+            message: Some("Recovered by check --repair-head".to_string()),
+            total_size: Some(files.iter().map(|m| m.file_size).sum()),
+            metadata: manifest::load_snapshot_meta(&snapshot_dir)?,
+            ..Default::default()
+        });
+        recovered += 1;
+    }
+
+    // Keep the head manifest in creation order; the sort is stable, so listed snapshots
+    // created within the same second stay in their order.
+    head_manifest.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+    save_head_manifest(base_path, &head_manifest)?;
+    println!(
+        "Rebuilt the head manifest: {} snapshot(s), {} recovered from their directories.",
+        head_manifest.len(),
+        recovered
+    );
+    Ok(())
+}
+
+/// Brings the tags and metadata in the head manifest and in each snapshot folder back in sync.
+/// Snapshots that lost theirs from the head manifest get them back from their folder; otherwise
+/// the head manifest wins and the folder's copy is rewritten.
+fn reconcile_snapshot_meta(base_path: &Path) -> io::Result<()> {
+    let mut head_manifest = load_head_manifest(base_path)?;
+    let snapshots_path = info::get_snapshots_dir(base_path);
+    let mut restored = 0;
+    for snapshot in head_manifest.iter_mut() {
+        let snapshot_dir = snapshots_path.join(&snapshot.version);
+        if !snapshot_dir.is_dir() {
+            continue;
+        }
+        let stored = manifest::load_snapshot_meta(&snapshot_dir).unwrap_or(None);
+        let listed = snapshot.metadata.clone().unwrap_or_default();
+        match stored {
+            Some(ref stored) if *stored == listed => {}
+            None if listed == SnapshotMetadata::default() => {}
+            Some(stored) if listed == SnapshotMetadata::default() => {
+                println!(
+                    "Restored the tags and metadata of {} from its snapshot folder.",
+                    snapshot.version
+                );
+                snapshot.metadata = Some(stored);
+                restored += 1;
+            }
+            _ => manifest::save_snapshot_meta(base_path, snapshot)?,
+        }
+    }
+    if restored > 0 {
+        save_head_manifest(base_path, &head_manifest)?;
+    }
+    Ok(())
+}
+
+/// Regenerates a snapshot's manifest from the files stored in its directory and adds the
+/// snapshot back to the head manifest if it is no longer listed there. Files stored compressed
+/// are recognized by their gzip header and recorded with their original size, and each file's
+/// content hash is recorded so later snapshots and `diff` can compare against it.
+///
+/// Incremental snapshots can't be rebuilt: the files they left to their parent can't be told
+/// apart from files they removed. Neither can snapshots holding gzip streams written before
+/// compressed files were marked, which may or may not have been stored compressed.
+fn rebuild_manifest(base_path: &Path, id: &str) -> io::Result<()> {
+    let snapshots_path = info::get_snapshots_dir(base_path);
+    let mut head_manifest = load_head_manifest(base_path)?;
+
+    // The snapshot may be missing from the head manifest, so look for its folder first.
+    let version = if snapshots_path.join(id).is_dir() {
+        id.to_string()
+    } else {
+        info::resolve_snapshot_id(Some(id.to_string()), &head_manifest)?
+    };
+    let snapshot_dir = snapshots_path.join(&version);
+    if !snapshot_dir.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Snapshot directory for {} not found", version),
+        ));
+    }
+    let listed = head_manifest.iter().find(|s| s.version == version);
+    if let Some(parent) = listed
+        .filter(|s| s.incremental)
+        .and_then(|s| s.parent.as_deref())
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Snapshot {} is incremental: its unchanged files are stored in {} and the files it removed weren't recorded anywhere else, so its manifest can't be rebuilt from the stored files",
+                version, parent
+            ),
+        ));
+    }
+
+    let mut metadata_vec = Vec::new();
+    snapshot::collect_metadata_recursive(
+        &snapshot_dir,
+        "",
+        &snapshot_dir,
+        &Vec::new(),
+        &mut metadata_vec,
+    )?;
+    metadata_vec.retain(|meta| {
+        meta.relative_path != MANIFEST_FILE
+            && meta.relative_path != SNAPSHOT_META_FILE
+            && meta.relative_path != NOTE_FILE
+            && meta.relative_path != CHECKSUMS_FILE
+    });
+    let hash_threshold = config::load_config(base_path).parallel_hash_threshold;
+    let mut unmarked = Vec::new();
+    for meta in metadata_vec
+        .iter_mut()
+        .filter(|meta| meta.link_target.is_none())
+    {
+        let stored = snapshot_dir.join(&meta.relative_path);
+        match snapshot::stored_compression(&stored)? {
+            Some(true) => {
+                meta.compressed_size = Some(meta.file_size);
+                meta.file_size = io::copy(
+                    &mut snapshot::open_stored_file(&stored, meta)?,
+                    &mut io::sink(),
+                )?;
+            }
+            Some(false) => {}
+            None => {
+                unmarked.push(meta.relative_path.clone());
+                continue;
+            }
+        }
+        meta.checksum = Some(snapshot::stored_checksum(&stored, meta, hash_threshold)?);
+    }
+    if let Some(first) = unmarked.first() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Snapshot {} holds {} gzip file(s) (e.g. {}) that an older version may have stored compressed, or that may be .gz files stored as they are; the manifest can't be rebuilt without telling them apart",
+                version,
+                unmarked.len(),
+                first
+            ),
+        ));
+    }
+    let manifest_checksum = manifest::save_snapshot_manifest(&snapshot_dir, &metadata_vec)?;
+    println!(
+        "Rebuilt manifest for snapshot {} ({} files).",
+        version,
+        metadata_vec.len()
+    );
+
+    if let Some(snapshot) = head_manifest.iter_mut().find(|s| s.version == version) {
+        snapshot.manifest_checksum = Some(manifest_checksum);
+        save_head_manifest(base_path, &head_manifest)?;
+    } else {
+        let created: DateTime<Local> = fs::metadata(&snapshot_dir)?
+            .modified()
+            .map(DateTime::<Local>::from)
+            .unwrap_or_else(|_| Local::now());
+        let timestamp = created.format("%Y-%m-%d %H:%M:%S").to_string();
+        // Keep the head manifest in creation order.
+        let position = head_manifest
+            .iter()
+            .position(|s| s.timestamp > timestamp)
+            .unwrap_or(head_manifest.len());
+        head_manifest.insert(
+            position,
+            SnapshotIndex {
+                version: version.clone(),
+                timestamp,
+                message: Some("Recovered by check --fix-manifest".to_string()),
+                total_size: Some(metadata_vec.iter().map(|m| m.file_size).sum()),
+                metadata: manifest::load_snapshot_meta(&snapshot_dir)?,
+                manifest_checksum: Some(manifest_checksum),
+                ..Default::default()
+            },
+        );
+        save_head_manifest(base_path, &head_manifest)?;
+        println!("Added snapshot {} back to the head manifest.", version);
+        if head_manifest.iter().any(|s| s.incremental) {
+            println!(
+                "Note: if {} was an incremental snapshot, the files it left to its parent are missing from the rebuilt manifest.",
+                version
+            );
+        }
+    }
+    Ok(())
+}
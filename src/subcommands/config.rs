@@ -0,0 +1,189 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+use crate::config::{
+    config_env_var, env_overrides, global_config_path, load_config_file, repo_config_path,
+    save_config_file, validate_config_entry, ConfigFile, STORE_KEY,
+};
+use crate::info;
+
+/// Set, get, list, edit, or validate configuration settings in the repository (or global)
+/// config file
+pub fn manage_config(
+    set: Option<Vec<String>>,
+    get: Option<String>,
+    list: bool,
+    edit: bool,
+    validate: bool,
+    global: bool,
+) -> io::Result<()> {
+    let path = if global {
+        global_config_path().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "Could not determine the global config directory.",
+            )
+        })?
+    } else {
+        repo_config_path(&info::get_base_dir()?)
+    };
+    if edit {
+        return edit_config(&path);
+    }
+    if validate {
+        return validate_config(&path, global);
+    }
+    let mut config = load_config_file(&path)?;
+
+    if let Some(ref values) = set {
+        let key = &values[0];
+        let value = &values[1];
+        validate_config_entry(key, value)?;
+        config.insert(key.clone(), value.clone());
+        save_config_file(&path, &config)?;
+        println!("Set {} = {}", key, value);
+    } else if let Some(ref key) = get {
+        match config.get(key) {
+            Some(value) => println!("{}", value),
+            None => println!("{} is not set", key),
+        }
+    } else if list || (set.is_none() && get.is_none()) {
+        println!("Configuration ({:?}):", path);
+        if config.is_empty() {
+            println!("  No settings");
+        } else {
+            for (key, value) in &config {
+                println!("  {} = {}", key, value);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints where the global and repository config files are and whether each exists, in the
+/// order they are applied. Outside a repository only the global file is shown.
+pub fn show_config_paths() -> io::Result<()> {
+    let describe = |path: &Path| {
+        format!(
+            "{:?} ({})",
+            path,
+            if path.exists() { "exists" } else { "not found" }
+        )
+    };
+    match global_config_path() {
+        Some(path) => println!("Global config:     {}", describe(&path)),
+        None => println!("Global config:     (no config directory on this system)"),
+    }
+    match info::get_base_dir() {
+        Ok(base_path) => println!(
+            "Repository config: {}",
+            describe(&repo_config_path(&base_path))
+        ),
+        Err(_) => println!("Repository config: (not in a snapsafe repository)"),
+    }
+    println!("Settings in the repository config take precedence over the global config.");
+    let overrides = env_overrides();
+    if !overrides.is_empty() {
+        println!("Overridden by the environment:");
+        for key in overrides.keys() {
+            println!("  {} (from {})", key, config_env_var(key));
+        }
+    }
+    Ok(())
+}
+
+/// Opens the config file in `$EDITOR` and validates it once the editor exits.
+/// If the edited file is invalid, the previous contents are put back.
+fn edit_config(path: &Path) -> io::Result<()> {
+    let original = if path.exists() {
+        Some(fs::read(path)?)
+    } else {
+        save_config_file(path, &ConfigFile::new())?;
+        None
+    };
+
+    let editor = std::env::var("EDITOR")
+        .ok()
+        .filter(|e| !e.trim().is_empty())
+        .unwrap_or_else(|| default_editor().to_string());
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().unwrap_or_default();
+    let status = Command::new(program)
+        .args(parts)
+        .arg(path)
+        .status()
+        .map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Failed to run editor '{}': {}", editor, e),
+            )
+        })?;
+
+    let result = if status.success() {
+        load_config_file(path).and_then(|config| {
+            config
+                .iter()
+                .filter(|(key, _)| key.as_str() != STORE_KEY)
+                .try_for_each(|(key, value)| validate_config_entry(key, value))
+        })
+    } else {
+        Err(io::Error::other(format!("Editor exited with {}", status)))
+    };
+
+    if let Err(e) = result {
+        match original {
+            Some(ref contents) => fs::write(path, contents)?,
+            None => fs::remove_file(path)?,
+        }
+        return Err(io::Error::new(
+            e.kind(),
+            format!("{}. The config file was not changed.", e),
+        ));
+    }
+
+    println!("Saved {:?}", path);
+    Ok(())
+}
+
+/// Checks every entry of a config file without changing it, reporting each unknown key and
+/// invalid value. Unlike loading the config, which skips bad entries with a warning, any problem
+/// makes this fail. The snapshot store is only accepted in the repository config.
+fn validate_config(path: &Path, global: bool) -> io::Result<()> {
+    if !path.exists() {
+        println!("No config file at {:?}; the defaults are used.", path);
+        return Ok(());
+    }
+    let config = load_config_file(path)?;
+
+    let problems: Vec<String> = config
+        .iter()
+        .filter(|(key, _)| global || key.as_str() != STORE_KEY)
+        .filter_map(|(key, value)| validate_config_entry(key, value).err())
+        .map(|e| e.to_string())
+        .collect();
+    if problems.is_empty() {
+        println!("✅ {:?} is valid ({} setting(s)).", path, config.len());
+        return Ok(());
+    }
+
+    println!("Problems in {:?}:", path);
+    for problem in &problems {
+        println!("  - {}", problem);
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{} invalid setting(s) in the config file", problems.len()),
+    ))
+}
+
+/// Editor used when `$EDITOR` isn't set.
+fn default_editor() -> &'static str {
+    if cfg!(windows) {
+        "notepad"
+    } else {
+        "vi"
+    }
+}
@@ -1,47 +1,124 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
 use std::io;
+use std::path::Path;
 
 use crate::{
-    info::get_base_dir,
+    attributes::Attributes,
+    config,
+    constants::{CHECKSUMS_FILE, MANIFEST_FILE, NOTE_FILE, SNAPSHOT_META_FILE},
+    ignore,
+    info::{self, get_base_dir},
     manifest::{self, load_head_manifest},
+    models::{FileMetadata, SnapshotMetadata},
+    subcommands::{history, snapshot},
 };
+use similar::{ChangeTag, DiffTag, TextDiff};
+
+/// Optional modes for `diff_snapshots`.
+#[derive(Debug, Default, Clone)]
+pub struct DiffOptions {
+    /// Compare the tags and custom metadata of the snapshots instead of their files.
+    pub meta: bool,
+    /// Glob patterns (e.g. `**/*.log`) for paths left out of the comparison.
+    pub ignore: Vec<String>,
+    /// Treat a file as updated only when its size changed, ignoring its modification time.
+    pub size_only: bool,
+    /// Compare a snapshot's manifest with the files actually stored in its folder.
+    pub self_check: bool,
+    /// Also print the words that changed in each updated text file.
+    pub word_diff: bool,
+}
+
+/// Snapshot ID that stands for the current working directory in `diff <id> working`.
+pub const WORKING_TREE_ID: &str = "working";
 
 /// Diffs two snapshots identified by their version strings.
 /// It prints the added, removed, and updated files in tabular form.
 /// Only files that have differences (or are new/removed) are shown.
-pub fn diff_snapshots(version1: String, version2: Option<String>) -> io::Result<()> {
-    let (v1, v2) = get_snapshots_to_diff(version1, version2)?;
+/// A second version of `working` compares the first snapshot with the working directory.
+pub fn diff_snapshots(
+    version1: String,
+    version2: Option<String>,
+    options: &DiffOptions,
+) -> io::Result<()> {
     let base_path = get_base_dir()?;
+    if options.self_check {
+        return diff_self(&base_path, version1, options);
+    }
+    let (v1, v2) = get_snapshots_to_diff(version1, version2)?;
+    let working = v2 == WORKING_TREE_ID;
+
+    if options.meta {
+        if working {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The working directory has no tags or metadata to compare with --meta",
+            ));
+        }
+        return diff_metadata(&base_path, v1, v2);
+    }
 
     // Load the detailed manifest for snapshot v1.
     let snap1_option = manifest::load_snapshot_manifest(&base_path, &v1)?;
-    // Load the detailed manifest for snapshot v2.
-    let snap2_option = manifest::load_snapshot_manifest(&base_path, &v2)?;
 
-    // If either manifest is missing, return an error.
-    let (_, manifest1) = snap1_option.ok_or_else(|| {
+    // If the manifest is missing, return an error.
+    let (_, mut manifest1) = snap1_option.ok_or_else(|| {
         io::Error::new(
             io::ErrorKind::NotFound,
             format!("Manifest for snapshot {} not found", v1),
         )
     })?;
-    let (_, manifest2) = snap2_option.ok_or_else(|| {
-        io::Error::new(
-            io::ErrorKind::NotFound,
-            format!("Manifest for snapshot {} not found", v2),
-        )
-    })?;
+    // The other side is either snapshot v2 or what a snapshot would record right now.
+    let mut manifest2 = if working {
+        working_tree_files(&base_path)?
+    } else {
+        manifest::load_snapshot_manifest(&base_path, &v2)?
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("Manifest for snapshot {} not found", v2),
+                )
+            })?
+            .1
+    };
+
+    // Leave out the paths matching any --ignore pattern.
+    let is_ignored = ignore::ignore_matcher(&options.ignore)?;
+    manifest1.retain(|path, _| !is_ignored(path));
+    manifest2.retain(|path, _| !is_ignored(path));
+
     // Determine added files: present in manifest2 but not in manifest1.
     let mut added: Vec<String> = Vec::new();
     // Determine removed files: present in manifest1 but not in manifest2.
     let mut removed: Vec<String> = Vec::new();
     // Determine updated files: present in both but with differences.
     let mut updated: Vec<String> = Vec::new();
+    // Updated text files, whose changed words are printed with --word-diff.
+    let mut updated_text: Vec<&String> = Vec::new();
+    let attributes = if options.word_diff {
+        Attributes::load(&base_path)?
+    } else {
+        Attributes::default()
+    };
 
     for (path, meta2) in &manifest2 {
         match manifest1.get(path.as_str()) {
             Some(meta1) => {
-                if meta1.file_size != meta2.file_size || meta1.modified != meta2.modified {
-                    updated.push(path.clone());
+                if !is_modified(meta1, meta2, options) {
+                    continue;
+                }
+                if options.word_diff
+                    && history::is_text_file(path, &attributes)
+                    && content_modified(meta1, meta2, options)
+                {
+                    updated_text.push(path);
+                }
+                match (meta1.mode, meta2.mode) {
+                    (Some(mode1), Some(mode2)) if !content_modified(meta1, meta2, options) => {
+                        updated.push(format!("{} (mode {:o} -> {:o})", path, mode1, mode2))
+                    }
+                    _ => updated.push(path.clone()),
                 }
             }
             None => {
@@ -54,6 +131,9 @@ pub fn diff_snapshots(version1: String, version2: Option<String>) -> io::Result<
             removed.push(path.clone());
         }
     }
+    added.sort();
+    removed.sort();
+    updated.sort();
 
     // Print the diff in tabular form.
     if !added.is_empty() {
@@ -83,34 +163,349 @@ pub fn diff_snapshots(version1: String, version2: Option<String>) -> io::Result<
         println!();
     }
 
+    if !updated_text.is_empty() {
+        updated_text.sort();
+        let head_manifest = load_head_manifest(&base_path)?;
+        let chain1 = manifest::snapshot_chain(&base_path, &head_manifest, &v1);
+        let chain2 = if working {
+            vec![base_path.clone()]
+        } else {
+            manifest::snapshot_chain(&base_path, &head_manifest, &v2)
+        };
+        for path in updated_text {
+            let stored1 = manifest::stored_file_dir(&chain1, path).join(path);
+            let stored2 = manifest::stored_file_dir(&chain2, path).join(path);
+            println!("Word Diff: {}", path);
+            println!("{:-<50}", "");
+            let old = history::read_text(&(stored1, manifest1[path].clone()))?;
+            let new = history::read_text(&(stored2, manifest2[path].clone()))?;
+            match (old, new) {
+                (Some(old), Some(new)) => print_word_diff(&old, &new),
+                _ => println!("(binary contents, no diff shown)"),
+            }
+            println!();
+        }
+    }
+
     if added.is_empty() && removed.is_empty() && updated.is_empty() {
-        println!("No differences found between snapshots {} and {}.", v1, v2);
+        if working {
+            println!(
+                "No differences found between snapshot {} and the working directory.",
+                v1
+            );
+        } else {
+            println!("No differences found between snapshots {} and {}.", v1, v2);
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints each group of changed lines with the words removed shown as `[-old-]` and the words
+/// added shown as `{+new+}`, like `git diff --word-diff`, after the number of its first line
+/// in the new version.
+fn print_word_diff(old: &str, new: &str) {
+    let lines = TextDiff::from_lines(old, new);
+    for op in lines.ops() {
+        if op.tag() == DiffTag::Equal {
+            continue;
+        }
+        let old_text = lines.old_slices()[op.old_range()].concat();
+        let new_text = lines.new_slices()[op.new_range()].concat();
+        let words = TextDiff::from_words(old_text.as_str(), new_text.as_str());
+
+        // Consecutive words with the same change are marked together
+        let mut rendered = String::new();
+        let mut run: Option<(ChangeTag, String)> = None;
+        for change in words.iter_all_changes() {
+            match run {
+                Some((tag, ref mut text)) if tag == change.tag() => text.push_str(change.value()),
+                _ => {
+                    if let Some((tag, text)) = run.take() {
+                        push_word_run(&mut rendered, tag, &text);
+                    }
+                    run = Some((change.tag(), change.value().to_string()));
+                }
+            }
+        }
+        if let Some((tag, text)) = run {
+            push_word_run(&mut rendered, tag, &text);
+        }
+
+        println!("@@ line {} @@", op.new_range().start + 1);
+        for line in rendered.lines() {
+            println!("{}", line);
+        }
+    }
+}
+
+/// Appends a run of words to a word diff, marked as removed or added if it changed. Runs
+/// spanning several lines are marked on each line, so every marker closes on its own line.
+fn push_word_run(rendered: &mut String, tag: ChangeTag, text: &str) {
+    let (open, close) = match tag {
+        ChangeTag::Equal => return rendered.push_str(text),
+        ChangeTag::Delete => ("[-", "-]"),
+        ChangeTag::Insert => ("{+", "+}"),
+    };
+    for line in text.split_inclusive('\n') {
+        let words = line.trim_end_matches('\n');
+        if !words.is_empty() {
+            rendered.push_str(open);
+            rendered.push_str(words);
+            rendered.push_str(close);
+        }
+        rendered.push_str(&line[words.len()..]);
+    }
+}
+
+/// Returns the manifest entries a snapshot of the working directory would record right now,
+/// leaving out the same ignored files as `snapshot`.
+fn working_tree_files(base_path: &Path) -> io::Result<HashMap<String, FileMetadata>> {
+    let config = config::load_config(base_path);
+    let ignore_list = ignore::build_ignore_list(base_path, config.exclude_vcs)?;
+    let mut files = Vec::new();
+    snapshot::collect_metadata_recursive(
+        base_path,
+        &info::repo_folder_name(),
+        base_path,
+        &ignore_list,
+        &mut files,
+    )?;
+    Ok(files
+        .into_iter()
+        .map(|meta| (meta.relative_path.clone(), meta))
+        .collect())
+}
+
+/// Returns true if a file differs between two snapshots, in its contents or its permissions.
+pub fn is_modified(meta1: &FileMetadata, meta2: &FileMetadata, options: &DiffOptions) -> bool {
+    content_modified(meta1, meta2, options) || (!options.size_only && mode_changed(meta1, meta2))
+}
+
+/// Returns true if both snapshots recorded the file's permission bits and they differ, e.g. the
+/// file gained the executable bit. Snapshots taken before modes were recorded never differ.
+pub fn mode_changed(meta1: &FileMetadata, meta2: &FileMetadata) -> bool {
+    matches!((meta1.mode, meta2.mode), (Some(mode1), Some(mode2)) if mode1 != mode2)
+}
+
+/// Returns true if a file's contents differ between two snapshots. Content hashes are only
+/// compared when both manifests recorded one; files recorded without a hash (by older versions,
+/// or from the working directory) fall back to size and modification time, so mixing them
+/// doesn't report spurious changes.
+fn content_modified(meta1: &FileMetadata, meta2: &FileMetadata, options: &DiffOptions) -> bool {
+    if meta1.file_size != meta2.file_size || meta1.link_target != meta2.link_target {
+        return true;
+    }
+    if options.size_only {
+        return false;
+    }
+    match (&meta1.checksum, &meta2.checksum) {
+        (Some(hash1), Some(hash2)) => hash1 != hash2,
+        _ => meta1.modified != meta2.modified,
+    }
+}
+
+/// Compares the sizes, hashes, and link targets recorded in a snapshot's manifest with the
+/// files currently stored in its folder, listing each file that is missing, differs, or isn't
+/// in the manifest. Files without a recorded hash are compared by modification time instead.
+fn diff_self(base_path: &Path, id: String, options: &DiffOptions) -> io::Result<()> {
+    let head_manifest = load_head_manifest(base_path)?;
+    let version = info::resolve_snapshot_id(Some(id), &head_manifest)?;
+    let (snapshot_dir, mut files) = manifest::load_snapshot_manifest(base_path, &version)?
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Manifest for snapshot {} not found", version),
+            )
+        })?;
+    let is_ignored = ignore::ignore_matcher(&options.ignore)?;
+    files.retain(|path, _| !is_ignored(path));
+    let hash_threshold = config::load_config(base_path).parallel_hash_threshold;
+    let chain = manifest::snapshot_chain(base_path, &head_manifest, &version);
+
+    let mut missing = Vec::new();
+    let mut changed = Vec::new();
+    for (path, meta) in &files {
+        let stored_dir = manifest::stored_file_dir(&chain, path);
+        let stored_path = stored_dir.join(path);
+        let stored = match fs::symlink_metadata(&stored_path) {
+            Ok(stored) => stored,
+            Err(_) => {
+                missing.push(path.clone());
+                continue;
+            }
+        };
+        let stored_meta = snapshot::build_file_metadata(&stored_path, stored_dir)?;
+        let reason = if meta.link_target.is_some() || stored.file_type().is_symlink() {
+            (meta.link_target != stored_meta.link_target).then(|| {
+                format!(
+                    "link target: recorded {}, found {}",
+                    meta.link_target.as_deref().unwrap_or("(not a link)"),
+                    stored_meta.link_target.as_deref().unwrap_or("(not a link)")
+                )
+            })
+        } else if meta.compressed_size.unwrap_or(meta.file_size) != stored_meta.file_size {
+            Some(format!(
+                "size: recorded {} bytes, found {} bytes",
+                meta.compressed_size.unwrap_or(meta.file_size),
+                stored_meta.file_size
+            ))
+        } else if options.size_only {
+            None
+        } else if let Some(ref recorded) = meta.checksum {
+            let actual = snapshot::stored_checksum(&stored_path, meta, hash_threshold)?;
+            (*recorded != actual).then(|| "content: hash doesn't match the manifest".to_string())
+        } else {
+            (meta.modified != stored_meta.modified).then(|| {
+                format!(
+                    "modified: recorded {}, found {}",
+                    meta.modified, stored_meta.modified
+                )
+            })
+        };
+        if let Some(reason) = reason {
+            changed.push(format!("{} ({})", path, reason));
+        }
     }
 
+    let mut stored_files = Vec::new();
+    snapshot::collect_metadata_recursive(
+        &snapshot_dir,
+        "",
+        &snapshot_dir,
+        &Vec::new(),
+        &mut stored_files,
+    )?;
+    let mut untracked: Vec<String> = stored_files
+        .into_iter()
+        .map(|meta| meta.relative_path)
+        .filter(|path| {
+            path != MANIFEST_FILE
+                && path != SNAPSHOT_META_FILE
+                && path != NOTE_FILE
+                && path != CHECKSUMS_FILE
+                && !files.contains_key(path)
+                && !is_ignored(path)
+        })
+        .collect();
+    missing.sort();
+    changed.sort();
+    untracked.sort();
+
+    for (title, list) in [
+        ("Missing Files:", &missing),
+        ("Changed Files:", &changed),
+        ("Untracked Files:", &untracked),
+    ] {
+        if !list.is_empty() {
+            println!("{}", title);
+            println!("{:-<50}", "");
+            for file in list {
+                println!("{}", file);
+            }
+            println!();
+        }
+    }
+
+    if missing.is_empty() && changed.is_empty() && untracked.is_empty() {
+        println!(
+            "No differences found between snapshot {}'s manifest and its stored files.",
+            version
+        );
+    }
     Ok(())
 }
 
-/// Given a required snapshot version (version1) and an optional snapshot version (version2),
-/// returns a tuple of snapshot versions to compare. If version2 is not provided,
-/// it retrieves the latest snapshot version from the head manifest.
+/// Compares the tags and custom metadata between two snapshots.
+fn diff_metadata(base_path: &Path, version1: String, version2: String) -> io::Result<()> {
+    let head_manifest = load_head_manifest(base_path)?;
+    let v1 = info::resolve_snapshot_id(Some(version1), &head_manifest)?;
+    let v2 = info::resolve_snapshot_id(Some(version2), &head_manifest)?;
+    let metadata_of = |version: &str| {
+        head_manifest
+            .iter()
+            .find(|s| s.version == version)
+            .and_then(|s| s.metadata.clone())
+            .unwrap_or_default()
+    };
+    let meta1: SnapshotMetadata = metadata_of(&v1);
+    let meta2: SnapshotMetadata = metadata_of(&v2);
+
+    let added_tags: Vec<&String> = meta2
+        .tags
+        .iter()
+        .filter(|t| !meta1.tags.contains(t))
+        .collect();
+    let removed_tags: Vec<&String> = meta1
+        .tags
+        .iter()
+        .filter(|t| !meta2.tags.contains(t))
+        .collect();
+
+    let keys: BTreeSet<&String> = meta1.custom.keys().chain(meta2.custom.keys()).collect();
+    let mut changed_meta = Vec::new();
+    for key in keys {
+        match (meta1.custom.get(key), meta2.custom.get(key)) {
+            (Some(old), Some(new)) if old != new => {
+                changed_meta.push(format!("~ {}: {} -> {}", key, old, new))
+            }
+            (None, Some(new)) => changed_meta.push(format!("+ {} = {}", key, new)),
+            (Some(old), None) => changed_meta.push(format!("- {} = {}", key, old)),
+            _ => {}
+        }
+    }
+
+    if !added_tags.is_empty() {
+        println!("Added Tags:");
+        println!("{:-<50}", "");
+        for tag in &added_tags {
+            println!("{}", tag);
+        }
+        println!();
+    }
+
+    if !removed_tags.is_empty() {
+        println!("Removed Tags:");
+        println!("{:-<50}", "");
+        for tag in &removed_tags {
+            println!("{}", tag);
+        }
+        println!();
+    }
+
+    if !changed_meta.is_empty() {
+        println!("Changed Metadata:");
+        println!("{:-<50}", "");
+        for change in &changed_meta {
+            println!("{}", change);
+        }
+        println!();
+    }
+
+    if added_tags.is_empty() && removed_tags.is_empty() && changed_meta.is_empty() {
+        println!(
+            "No metadata differences found between snapshots {} and {}.",
+            v1, v2
+        );
+    }
+
+    Ok(())
+}
+
+/// Given a required snapshot ID (version1) and an optional snapshot ID (version2), returns a
+/// tuple of snapshot versions to compare. Both are resolved like other commands' snapshot IDs
+/// (`latest`, labels, and version prefixes); if version2 is not provided, the latest snapshot
+/// is used.
 fn get_snapshots_to_diff(
     version1: String,
     version2: Option<String>,
 ) -> io::Result<(String, String)> {
     let base_path = get_base_dir()?;
     let head_manifest = load_head_manifest(&base_path)?;
+    let v1 = info::resolve_snapshot_id(Some(version1), &head_manifest)?;
     let v2 = match version2 {
-        Some(v) => v,
-        None => {
-            if head_manifest.is_empty() {
-                return Err(io::Error::new(
-                    io::ErrorKind::NotFound,
-                    "No snapshots available for diff.",
-                ));
-            } else {
-                head_manifest.last().unwrap().version.clone()
-            }
-        }
+        Some(v) if v == WORKING_TREE_ID => v,
+        v => info::resolve_snapshot_id(v, &head_manifest)?,
     };
-    Ok((version1, v2))
+    Ok((v1, v2))
 }
@@ -0,0 +1,260 @@
+use chrono::Local;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use crate::constants::{
+    EXPORTS_FOLDER, INCREMENTAL_FILE, MANIFEST_FILE, NOTE_FILE, SNAPSHOT_INDEX_FILE,
+    SNAPSHOT_META_FILE,
+};
+use crate::info;
+use crate::manifest::{self, load_head_manifest};
+use crate::models::{FileMetadata, IncrementalExport, SnapshotIndex};
+use crate::subcommands::diff::{self, DiffOptions};
+use crate::subcommands::snapshot;
+
+/// Optional behaviour switches for `export_snapshot`.
+#[derive(Debug, Default, Clone)]
+pub struct ExportOptions {
+    /// Store the contents of symbolic link targets instead of the links themselves.
+    pub dereference: bool,
+    /// Only store the files added or modified since this snapshot (an incremental archive).
+    pub since: Option<String>,
+}
+
+/// Writes a snapshot to a gzip-compressed tar archive. Files are stored under a top-level
+/// folder named after the snapshot version, together with the snapshot's manifest and its
+/// head manifest entry, which includes its tags and metadata. Files stored compressed in the
+/// snapshot are written with their original contents, so the archive can be extracted without
+/// snapsafe. If no output path is given, the archive is written to `<version>.tar.gz` in the
+/// repository's `exports` folder. With `since`, only the files that changed since that snapshot
+/// are stored.
+pub fn export_snapshot(
+    snapshot_id: Option<String>,
+    output: Option<PathBuf>,
+    options: &ExportOptions,
+) -> io::Result<()> {
+    let base_path = info::get_base_dir()?;
+    let head_manifest = load_head_manifest(&base_path)?;
+    let version = info::resolve_snapshot_id(snapshot_id, &head_manifest)?;
+
+    let snapshot_path = info::get_snapshots_dir(&base_path).join(&version);
+    if !snapshot_path.exists() {
+        return Err(io::Error::new(
+            ErrorKind::NotFound,
+            format!("Snapshot directory for {} not found", version),
+        ));
+    }
+
+    let output = match output {
+        Some(output) => output,
+        None => {
+            let exports = info::get_repo_dir(&base_path).join(EXPORTS_FOLDER);
+            fs::create_dir_all(&exports)?;
+            exports.join(format!("{}.tar.gz", version))
+        }
+    };
+    let file = fs::File::create(&output)?;
+    let mut archive = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+    archive.follow_symlinks(options.dereference);
+    let snapshot = head_manifest
+        .iter()
+        .find(|s| s.version == version)
+        .cloned()
+        .unwrap_or_default();
+    let result = match options.since {
+        Some(ref since) => {
+            let base_version = info::resolve_snapshot_id(Some(since.clone()), &head_manifest)?;
+            append_incremental(
+                &mut archive,
+                &base_path,
+                &head_manifest,
+                snapshot,
+                &base_version,
+            )
+            .map(|count| {
+                println!(
+                    "Exported {} file(s) of snapshot {} changed since {} to {:?}",
+                    count, version, base_version, output
+                )
+            })
+        }
+        None => append_full(&mut archive, &base_path, &head_manifest, snapshot)
+            .map(|_| println!("Exported snapshot {} to {:?}", version, output)),
+    };
+    result
+        .map_err(|e| io::Error::new(e.kind(), format!("Failed to archive {}: {}", version, e)))?;
+    archive.into_inner()?.finish()?;
+    Ok(())
+}
+
+/// Adds the whole snapshot folder to the archive. Snapshots holding compressed files, and
+/// incremental snapshots, are added file by file from the manifest instead, so those files can
+/// be decompressed, or taken from the snapshots they build on, on the way. The snapshot's head
+/// manifest entry is written first.
+fn append_full<W: Write>(
+    archive: &mut tar::Builder<W>,
+    base_path: &Path,
+    head_manifest: &[SnapshotIndex],
+    snapshot: SnapshotIndex,
+) -> io::Result<()> {
+    let version = snapshot.version.clone();
+    let folder = Path::new(&version);
+    // The parent and incremental chain only make sense in this repository
+    let entry = SnapshotIndex {
+        parent: None,
+        incremental: false,
+        manifest_checksum: None,
+        ..snapshot
+    };
+    append_json(archive, &folder.join(SNAPSHOT_INDEX_FILE), &entry)?;
+
+    let (snapshot_path, files) = load_manifest(base_path, &version)?;
+    let chain = manifest::snapshot_chain(base_path, head_manifest, &version);
+    if chain.len() == 1 && files.values().all(|meta| meta.compressed_size.is_none()) {
+        return archive.append_dir_all(&version, &snapshot_path);
+    }
+
+    append_manifest(archive, &snapshot_path, folder, &files)?;
+    let mut paths: Vec<&String> = files.keys().collect();
+    paths.sort();
+    for path in paths {
+        append_file(archive, &chain, folder, &files[path])?;
+    }
+    Ok(())
+}
+
+/// Adds the files of a snapshot that were added or modified since the base snapshot to the
+/// archive, along with the snapshot's full manifest and an `incremental.json` describing the
+/// archive, which is written first so readers can tell it apart from a full export.
+/// Returns the number of files stored.
+fn append_incremental<W: Write>(
+    archive: &mut tar::Builder<W>,
+    base_path: &Path,
+    head_manifest: &[SnapshotIndex],
+    snapshot: SnapshotIndex,
+    base_version: &str,
+) -> io::Result<usize> {
+    let (snapshot_path, files) = load_manifest(base_path, &snapshot.version)?;
+    let chain = manifest::snapshot_chain(base_path, head_manifest, &snapshot.version);
+    let (_, base_files) = load_manifest(base_path, base_version)?;
+
+    let mut changed: Vec<String> = files
+        .iter()
+        .filter(|(path, meta)| match base_files.get(*path) {
+            Some(base_meta) => diff::is_modified(base_meta, meta, &DiffOptions::default()),
+            None => true,
+        })
+        .map(|(path, _)| path.clone())
+        .collect();
+    changed.sort();
+
+    let folder = Path::new(&snapshot.version).to_path_buf();
+    let description = IncrementalExport {
+        base: base_version.to_string(),
+        // The archive's own base replaces any incremental chain in this repository
+        snapshot: SnapshotIndex {
+            incremental: false,
+            ..snapshot
+        },
+        files: changed,
+    };
+    append_json(archive, &folder.join(INCREMENTAL_FILE), &description)?;
+
+    append_manifest(archive, &snapshot_path, &folder, &files)?;
+    for path in &description.files {
+        append_file(archive, &chain, &folder, &files[path])?;
+    }
+    Ok(description.files.len())
+}
+
+/// Adds a file holding the value as pretty-printed JSON to the archive.
+fn append_json<W: Write, T: Serialize>(
+    archive: &mut tar::Builder<W>,
+    path: &Path,
+    value: &T,
+) -> io::Result<()> {
+    let json = serde_json::to_vec_pretty(value).map_err(io::Error::other)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(json.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(Local::now().timestamp().max(0) as u64);
+    header.set_cksum();
+    archive.append_data(&mut header, path, json.as_slice())
+}
+
+/// Loads the manifest of a snapshot, failing if the snapshot has none.
+fn load_manifest(
+    base_path: &Path,
+    version: &str,
+) -> io::Result<(PathBuf, HashMap<String, FileMetadata>)> {
+    manifest::load_snapshot_manifest(base_path, version)?.ok_or_else(|| {
+        io::Error::new(
+            ErrorKind::NotFound,
+            format!("Manifest for snapshot {} not found", version),
+        )
+    })
+}
+
+/// Adds the snapshot's manifest and metadata file to the archive. Since files are archived with
+/// their original contents, the manifest's record of compressed files is left out.
+fn append_manifest<W: Write>(
+    archive: &mut tar::Builder<W>,
+    snapshot_path: &Path,
+    folder: &Path,
+    files: &HashMap<String, FileMetadata>,
+) -> io::Result<()> {
+    if files.values().all(|meta| meta.compressed_size.is_none()) {
+        archive.append_path_with_name(
+            snapshot_path.join(MANIFEST_FILE),
+            folder.join(MANIFEST_FILE),
+        )?;
+    } else {
+        let mut entries: Vec<FileMetadata> = files
+            .values()
+            .map(|meta| FileMetadata {
+                compressed_size: None,
+                ..meta.clone()
+            })
+            .collect();
+        entries.sort_by(|a, b| a.relative_path.cmp(&b.relative_path));
+        let json = serde_json::to_vec_pretty(&entries).map_err(io::Error::other)?;
+        let mut header = tar::Header::new_gnu();
+        header.set_metadata(&fs::metadata(snapshot_path.join(MANIFEST_FILE))?);
+        header.set_size(json.len() as u64);
+        header.set_cksum();
+        archive.append_data(&mut header, folder.join(MANIFEST_FILE), json.as_slice())?;
+    }
+    for name in [SNAPSHOT_META_FILE, NOTE_FILE] {
+        if snapshot_path.join(name).exists() {
+            archive.append_path_with_name(snapshot_path.join(name), folder.join(name))?;
+        }
+    }
+    Ok(())
+}
+
+/// Adds one file of the snapshot to the archive, decompressing it if it is stored compressed.
+fn append_file<W: Write>(
+    archive: &mut tar::Builder<W>,
+    chain: &[PathBuf],
+    folder: &Path,
+    meta: &FileMetadata,
+) -> io::Result<()> {
+    let stored = manifest::stored_file_dir(chain, &meta.relative_path).join(&meta.relative_path);
+    if meta.compressed_size.is_none() {
+        return archive.append_path_with_name(&stored, folder.join(&meta.relative_path));
+    }
+    let mut header = tar::Header::new_gnu();
+    header.set_metadata(&fs::metadata(&stored)?);
+    header.set_size(meta.file_size);
+    header.set_cksum();
+    archive.append_data(
+        &mut header,
+        folder.join(&meta.relative_path),
+        snapshot::open_stored_file(&stored, meta)?,
+    )
+}
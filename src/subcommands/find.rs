@@ -0,0 +1,106 @@
+use std::io;
+
+use crate::index::load_or_build_index;
+use crate::info::get_base_dir;
+use crate::manifest::load_head_manifest;
+use crate::models::SnapshotMetadata;
+
+/// Criteria for `find_snapshots`. A snapshot must satisfy every criterion that is set.
+#[derive(Debug, Default, Clone)]
+pub struct FindQuery {
+    /// Tag the snapshot must carry (looked up in the search index)
+    pub tag: Option<String>,
+    /// Metadata key whose numeric value must be greater than the given number
+    pub meta_gt: Option<Vec<String>>,
+    /// Metadata key whose numeric value must be less than the given number
+    pub meta_lt: Option<Vec<String>>,
+    /// Metadata key whose value must equal the given value
+    pub meta_eq: Option<Vec<String>>,
+}
+
+/// Finds snapshots whose tags and metadata match the query and prints them.
+pub fn find_snapshots(query: &FindQuery) -> io::Result<()> {
+    let meta_gt = parse_numeric_criterion(&query.meta_gt, "--meta-gt")?;
+    let meta_lt = parse_numeric_criterion(&query.meta_lt, "--meta-lt")?;
+
+    let base_path = get_base_dir()?;
+    let head_manifest = load_head_manifest(&base_path)?;
+    let empty = SnapshotMetadata::default();
+    let tagged = match query.tag {
+        Some(ref tag) => Some(
+            load_or_build_index(&base_path, &head_manifest)?
+                .tags
+                .remove(tag)
+                .unwrap_or_default(),
+        ),
+        None => None,
+    };
+
+    let matches: Vec<_> = head_manifest
+        .iter()
+        .filter(|snapshot| {
+            tagged
+                .as_ref()
+                .is_none_or(|versions| versions.contains(&snapshot.version))
+        })
+        .filter(|snapshot| {
+            let metadata = snapshot.metadata.as_ref().unwrap_or(&empty);
+            let gt_ok = meta_gt.as_ref().is_none_or(|(key, bound)| {
+                numeric_value(metadata, key).is_some_and(|v| v > *bound)
+            });
+            let lt_ok = meta_lt.as_ref().is_none_or(|(key, bound)| {
+                numeric_value(metadata, key).is_some_and(|v| v < *bound)
+            });
+            let eq_ok = query
+                .meta_eq
+                .as_ref()
+                .is_none_or(|values| metadata.custom.get(&values[0]) == Some(&values[1]));
+            gt_ok && lt_ok && eq_ok
+        })
+        .collect();
+
+    if matches.is_empty() {
+        println!("No snapshots match the given criteria.");
+        return Ok(());
+    }
+
+    println!("{:<10} {:<20} {:<30}", "Version", "Timestamp", "Message");
+    println!("{:-<10} {:-<20} {:-<30}", "", "", "");
+    for snapshot in matches {
+        println!(
+            "{:<10} {:<20} {:<30}",
+            snapshot.version,
+            snapshot.timestamp,
+            snapshot.message.clone().unwrap_or_default()
+        );
+    }
+    Ok(())
+}
+
+/// Parses a `KEY NUMBER` pair given to a numeric comparison option.
+fn parse_numeric_criterion(
+    values: &Option<Vec<String>>,
+    option: &str,
+) -> io::Result<Option<(String, f64)>> {
+    match values {
+        None => Ok(None),
+        Some(values) => {
+            let number = values[1].parse::<f64>().map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} expects a number, got '{}'", option, values[1]),
+                )
+            })?;
+            Ok(Some((values[0].clone(), number)))
+        }
+    }
+}
+
+/// Returns the numeric value of a metadata key, preferring the typed value and falling back
+/// to parsing the string form for properties stored before typed metadata existed.
+fn numeric_value(metadata: &SnapshotMetadata, key: &str) -> Option<f64> {
+    match metadata.typed.get(key) {
+        Some(value) => value.as_f64(),
+        None => metadata.custom.get(key)?.parse().ok(),
+    }
+}
@@ -0,0 +1,205 @@
+use std::io::{self, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+
+use crate::attributes::{self, Attributes};
+use crate::config;
+use crate::info::{self, format_size};
+use crate::manifest::{self, load_head_manifest};
+use crate::models::FileMetadata;
+use crate::subcommands::diff::{self, DiffOptions};
+use crate::subcommands::snapshot;
+
+/// Extensions of files `history --text` shows line diffs for.
+const TEXT_EXTENSIONS: &[&str] = &[
+    "c", "cfg", "conf", "cpp", "css", "csv", "go", "h", "html", "ini", "java", "js", "json", "log",
+    "md", "py", "rs", "sh", "toml", "ts", "txt", "xml", "yaml", "yml",
+];
+
+/// Largest number of line pairs compared when diffing two versions; bigger files are only
+/// listed in the timeline.
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+/// How a file changed in a snapshot compared with the previous snapshot.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Status {
+    Added,
+    Modified,
+    Unchanged,
+    Removed,
+}
+
+impl Status {
+    fn as_str(self) -> &'static str {
+        match self {
+            Status::Added => "added",
+            Status::Modified => "modified",
+            Status::Unchanged => "unchanged",
+            Status::Removed => "removed",
+        }
+    }
+}
+
+/// Prints the timeline of one file across all snapshots, oldest first: its size and content
+/// hash in each snapshot and whether it was added, modified, unchanged, or removed compared
+/// with the previous snapshot. With text, the lines that changed between consecutive versions
+/// of a text file are printed below each modification.
+pub fn show_history(path: &str, text: bool) -> io::Result<()> {
+    let base_path = info::get_base_dir()?;
+    let head_manifest = load_head_manifest(&base_path)?;
+    let hash_threshold = config::load_config(&base_path).parallel_hash_threshold;
+    let relative_path = info::normalize_relative_path(path);
+
+    let diffable = is_text_file(&relative_path, &Attributes::load(&base_path)?);
+    if text && !diffable {
+        eprintln!(
+            "Note: {} isn't a text file (by its extension or .snapsafeattributes), so only the timeline is shown.",
+            relative_path
+        );
+    }
+
+    let mut rows = Vec::new();
+    let mut previous: Option<(PathBuf, FileMetadata)> = None;
+    for snapshot in &head_manifest {
+        let Some((_, mut files)) = manifest::load_snapshot_manifest(&base_path, &snapshot.version)?
+        else {
+            continue;
+        };
+        let current = files.remove(&relative_path);
+        let status = match (&previous, &current) {
+            (None, None) => continue,
+            (None, Some(_)) => Status::Added,
+            (Some(_), None) => Status::Removed,
+            (Some((_, prev)), Some(meta)) => {
+                if diff::is_modified(prev, meta, &DiffOptions::default()) {
+                    Status::Modified
+                } else {
+                    Status::Unchanged
+                }
+            }
+        };
+        let chain = manifest::snapshot_chain(&base_path, &head_manifest, &snapshot.version);
+        let stored_dir = manifest::stored_file_dir(&chain, &relative_path);
+        let current = current.map(|meta| (stored_dir.join(&relative_path), meta));
+        rows.push((snapshot, status, previous.clone(), current.clone()));
+        previous = current;
+    }
+    if rows.is_empty() {
+        return Err(io::Error::new(
+            ErrorKind::NotFound,
+            format!("{} isn't in any snapshot", relative_path),
+        ));
+    }
+
+    println!(
+        "History of {} across {} snapshot(s):",
+        relative_path,
+        head_manifest.len()
+    );
+    println!(
+        "{:<16} {:<19} {:>10}  {:<12}  STATUS",
+        "VERSION", "TIMESTAMP", "SIZE", "HASH"
+    );
+    for (snapshot, status, before, after) in rows {
+        let (size, hash) = match after {
+            Some((ref stored, ref meta)) => (
+                format_size(meta.file_size),
+                content_hash(stored, meta, hash_threshold),
+            ),
+            None => ("-".to_string(), "-".to_string()),
+        };
+        println!(
+            "{:<16} {:<19} {:>10}  {:<12}  {}",
+            snapshot.version,
+            snapshot.timestamp,
+            size,
+            hash,
+            status.as_str()
+        );
+        if let (true, true, Status::Modified, Some(before), Some(after)) =
+            (text, diffable, status, before, after)
+        {
+            print_line_diff(&before, &after)?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns whether line and word diffs are shown for a file: `binary` in `.snapsafeattributes`
+/// turns them off and `-binary` on, and otherwise the file needs a text file extension.
+pub fn is_text_file(path: &str, attributes: &Attributes) -> bool {
+    attributes.get(path, attributes::BINARY).map_or_else(
+        || {
+            Path::new(path)
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| TEXT_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+        },
+        |binary| !binary,
+    )
+}
+
+/// Returns the first 12 characters of the file's content hash, using the hash recorded in the
+/// manifest when there is one.
+fn content_hash(stored: &Path, meta: &FileMetadata, parallel_threshold: u64) -> String {
+    if meta.link_target.is_some() {
+        return "(link)".to_string();
+    }
+    let hash = match meta.checksum {
+        Some(ref checksum) => checksum.clone(),
+        None => match snapshot::stored_checksum(stored, meta, parallel_threshold) {
+            Ok(checksum) => checksum,
+            Err(_) => return "(unreadable)".to_string(),
+        },
+    };
+    hash.chars().take(12).collect()
+}
+
+/// Prints the lines removed from and added to a text file between two stored versions.
+fn print_line_diff(
+    before: &(PathBuf, FileMetadata),
+    after: &(PathBuf, FileMetadata),
+) -> io::Result<()> {
+    let (Some(old), Some(new)) = (read_text(before)?, read_text(after)?) else {
+        println!("    (binary contents, no diff shown)");
+        return Ok(());
+    };
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    if old.len().saturating_mul(new.len()) > MAX_DIFF_CELLS {
+        println!("    (too large to diff)");
+        return Ok(());
+    }
+
+    // Longest common subsequence table, filled from the end so the walk below runs forwards
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || common[i + 1][j] >= common[i][j + 1]) {
+            println!("    - {}", old[i]);
+            i += 1;
+        } else {
+            println!("    + {}", new[j]);
+            j += 1;
+        }
+    }
+    Ok(())
+}
+
+/// Reads a stored version of a file as text, or returns None if it isn't valid UTF-8.
+pub fn read_text((stored, meta): &(PathBuf, FileMetadata)) -> io::Result<Option<String>> {
+    let mut bytes = Vec::new();
+    snapshot::open_stored_file(stored, meta)?.read_to_end(&mut bytes)?;
+    Ok(String::from_utf8(bytes).ok())
+}
@@ -0,0 +1,273 @@
+use chrono::Local;
+use flate2::read::GzDecoder;
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+
+use crate::constants::{INCREMENTAL_FILE, MANIFEST_FILE, SNAPSHOT_INDEX_FILE};
+use crate::info;
+use crate::manifest::{self, load_head_manifest, save_head_manifest};
+use crate::models::{FileMetadata, IncrementalExport, SnapshotIndex};
+use crate::subcommands::restore::check_relative_path;
+use crate::subcommands::snapshot;
+
+/// Name of the folder inside the snapshots directory that an archive is unpacked into before
+/// it becomes a snapshot, so a failed import never leaves a partial snapshot behind.
+const STAGING_FOLDER: &str = ".import";
+
+/// Adds the snapshot stored in an archive written by `export` to the repository.
+/// Incremental archives (`export --since`) only contain the files that changed since their base
+/// snapshot, which must already be in the repository; the other files are hard-linked from it.
+pub fn import_archive(archive_path: &Path) -> io::Result<()> {
+    let base_path = info::get_base_dir()?;
+    if !info::get_repo_dir(&base_path).exists() {
+        return Err(io::Error::new(
+            ErrorKind::NotFound,
+            "Repository not initialized. Please run the init command first.",
+        ));
+    }
+    let mut head_manifest = load_head_manifest(&base_path)?;
+    let snapshots_path = info::get_snapshots_dir(&base_path);
+    let staging = snapshots_path.join(STAGING_FOLDER);
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+
+    let result = unpack_archive(archive_path, &staging).and_then(|version| {
+        let entry = build_snapshot(
+            &base_path,
+            &staging.join(&version),
+            &version,
+            &head_manifest,
+        )?;
+        fs::rename(staging.join(&version), snapshots_path.join(&version))?;
+        Ok(entry)
+    });
+    let _ = fs::remove_dir_all(&staging);
+    let (entry, stored) = result?;
+
+    println!(
+        "Imported snapshot {} from {:?} ({} file(s) from the archive).",
+        entry.version, archive_path, stored
+    );
+    head_manifest.push(entry);
+    save_head_manifest(&base_path, &head_manifest)
+}
+
+/// Unpacks an archive into the staging folder and returns the snapshot version, which is the
+/// name of the archive's top-level folder. Entries that would be written outside the staging
+/// folder, through a link unpacked earlier or as a hard link to a file elsewhere, are refused.
+fn unpack_archive(archive_path: &Path, staging: &Path) -> io::Result<String> {
+    let file = fs::File::open(archive_path).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("Failed to open archive {:?}: {}", archive_path, e),
+        )
+    })?;
+    let mut archive = tar::Archive::new(GzDecoder::new(file));
+    fs::create_dir_all(staging)?;
+
+    let mut version: Option<String> = None;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        check_relative_path(&path)?;
+        let top = path
+            .components()
+            .next()
+            .map(|c| c.as_os_str().to_string_lossy().to_string())
+            .unwrap_or_default();
+        match version {
+            Some(ref v) if *v != top => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "Archive {:?} holds more than one snapshot folder",
+                        archive_path
+                    ),
+                ))
+            }
+            Some(_) => {}
+            None => version = Some(top),
+        }
+
+        if entry.header().entry_type().is_hard_link() {
+            if let Some(target) = entry.link_name()? {
+                check_relative_path(&target)?;
+            }
+        }
+        let unpacked = entry.unpack_in(staging).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Failed to import {}: {}", path.display(), e),
+            )
+        })?;
+        if !unpacked {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Refusing to import {:?}: the path escapes the staging folder",
+                    path
+                ),
+            ));
+        }
+    }
+
+    version.ok_or_else(|| {
+        io::Error::new(
+            ErrorKind::InvalidData,
+            format!("Archive {:?} is empty", archive_path),
+        )
+    })
+}
+
+/// Completes an unpacked snapshot folder and returns its head manifest entry along with the
+/// number of files that came from the archive. For incremental archives, the files missing from
+/// the folder are hard-linked (or copied) from the base snapshot.
+fn build_snapshot(
+    base_path: &Path,
+    snapshot_dir: &Path,
+    version: &str,
+    head_manifest: &[SnapshotIndex],
+) -> io::Result<(SnapshotIndex, usize)> {
+    if head_manifest.iter().any(|s| s.version == version) {
+        return Err(io::Error::new(
+            ErrorKind::AlreadyExists,
+            format!("Snapshot {} already exists in this repository", version),
+        ));
+    }
+
+    let manifest_path = snapshot_dir.join(MANIFEST_FILE);
+    if !manifest_path.is_file() {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("The archive has no manifest for snapshot {}", version),
+        ));
+    }
+    let mut files: Vec<FileMetadata> = manifest::read_manifest_entries(&manifest_path)?;
+
+    let incremental_path = snapshot_dir.join(INCREMENTAL_FILE);
+    if !incremental_path.exists() {
+        let stored = files.len();
+        // Archives from older versions don't carry the snapshot's head manifest entry
+        let exported = read_exported_entry(snapshot_dir)?;
+        let label = exported.as_ref().and_then(|e| e.label.clone());
+        let label = match label {
+            Some(label)
+                if head_manifest
+                    .iter()
+                    .any(|s| s.label.as_ref() == Some(&label)) =>
+            {
+                println!(
+                    "Dropping label '{}': another snapshot in this repository already has it.",
+                    label
+                );
+                None
+            }
+            label => label,
+        };
+        let entry = SnapshotIndex {
+            version: version.to_string(),
+            total_size: Some(files.iter().map(|m| m.file_size).sum()),
+            metadata: manifest::load_snapshot_meta(snapshot_dir)?
+                .or_else(|| exported.as_ref().and_then(|e| e.metadata.clone())),
+            manifest_checksum: Some(manifest::manifest_checksum(snapshot_dir)?),
+            label,
+            ..exported.unwrap_or_else(|| SnapshotIndex {
+                timestamp: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+                message: Some("Imported from archive".to_string()),
+                ..Default::default()
+            })
+        };
+        return Ok((entry, stored));
+    }
+
+    let incremental: IncrementalExport =
+        serde_json::from_str(&fs::read_to_string(&incremental_path)?)
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+    fs::remove_file(&incremental_path)?;
+    if !head_manifest.iter().any(|s| s.version == incremental.base) {
+        return Err(io::Error::new(
+            ErrorKind::NotFound,
+            format!(
+                "Snapshot {} is incremental on {}, which isn't in this repository. Import {} first.",
+                version, incremental.base, incremental.base
+            ),
+        ));
+    }
+    let (_, base_files) = manifest::load_snapshot_manifest(base_path, &incremental.base)?
+        .ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::NotFound,
+                format!("Manifest for snapshot {} not found", incremental.base),
+            )
+        })?;
+
+    let base_chain = manifest::snapshot_chain(base_path, head_manifest, &incremental.base);
+    let stored: HashSet<&String> = incremental.files.iter().collect();
+    for meta in files
+        .iter_mut()
+        .filter(|m| !stored.contains(&m.relative_path))
+    {
+        check_relative_path(Path::new(&meta.relative_path))?;
+        let base_dir = manifest::stored_file_dir(&base_chain, &meta.relative_path);
+        link_from_base(base_dir, snapshot_dir, &meta.relative_path)?;
+        // Linked files keep the stored form of the base snapshot, which may be compressed
+        meta.compressed_size = base_files
+            .get(&meta.relative_path)
+            .and_then(|base_meta| base_meta.compressed_size);
+    }
+    let manifest_checksum = manifest::save_snapshot_manifest(snapshot_dir, &files)?;
+
+    let metadata = manifest::load_snapshot_meta(snapshot_dir)?.or(incremental.snapshot.metadata);
+    let entry = SnapshotIndex {
+        parent: Some(incremental.base.clone()),
+        incremental: false,
+        metadata,
+        manifest_checksum: Some(manifest_checksum),
+        ..incremental.snapshot
+    };
+    Ok((entry, stored.len()))
+}
+
+/// Reads and removes the head manifest entry `export` stores in the snapshot folder, if any.
+fn read_exported_entry(snapshot_dir: &Path) -> io::Result<Option<SnapshotIndex>> {
+    let path = snapshot_dir.join(SNAPSHOT_INDEX_FILE);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let entry = serde_json::from_str(&fs::read_to_string(&path)?).map_err(|e| {
+        io::Error::new(
+            ErrorKind::InvalidData,
+            format!("Invalid {} in the archive: {}", SNAPSHOT_INDEX_FILE, e),
+        )
+    })?;
+    fs::remove_file(&path)?;
+    Ok(Some(entry))
+}
+
+/// Hard-links a file of the base snapshot into the new snapshot, copying it if linking fails.
+/// Symbolic links are recreated as links.
+fn link_from_base(base_dir: &Path, snapshot_dir: &Path, relative_path: &str) -> io::Result<()> {
+    let source = base_dir.join(relative_path);
+    let dest = snapshot_dir.join(relative_path);
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if fs::symlink_metadata(&source).is_ok_and(|m| m.file_type().is_symlink()) {
+        return snapshot::copy_symlink(&source, &dest);
+    }
+    if fs::hard_link(&source, &dest).is_err() {
+        fs::copy(&source, &dest).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!(
+                    "Failed to take {} from the base snapshot: {}",
+                    relative_path, e
+                ),
+            )
+        })?;
+    }
+    Ok(())
+}
@@ -1,13 +1,27 @@
 use std::collections::HashMap;
-use std::io;
+use std::fs;
+use std::io::{self, Write};
 use std::path::Path;
 
+use crate::constants::NOTE_FILE;
 use crate::info;
-use crate::manifest::{self, load_head_manifest};
-use crate::models::FileMetadata;
+use crate::manifest::{self, load_head_manifest, load_repo_description};
+use crate::models::{FileMetadata, SnapshotIndex};
+use crate::subcommands::diff::{self, DiffOptions};
+
+/// Number of files listed in each ranking of `info --churn`.
+const CHURN_LIST_LIMIT: usize = 10;
 
 /// Display detailed information about a specific snapshot
-pub fn show_snapshot_info(snapshot_id: Option<String>) -> io::Result<()> {
+/// With age, the creation time is also shown relative to now.
+/// With compare, the statistics of both snapshots are shown side by side instead.
+/// With note, only the note attached with `snapshot --note-file` is printed.
+pub fn show_snapshot_info(
+    snapshot_id: Option<String>,
+    age: bool,
+    compare: Option<String>,
+    note: bool,
+) -> io::Result<()> {
     let base_path = info::get_base_dir()?;
     let head_manifest = load_head_manifest(&base_path)?;
 
@@ -24,47 +38,114 @@ pub fn show_snapshot_info(snapshot_id: Option<String>) -> io::Result<()> {
             )
         })?;
 
-    // Load the snapshot manifest
-    let snap_option = manifest::load_snapshot_manifest(&base_path, &snapshot.version)?;
-    let (_snapshot_dir, manifest) = snap_option.ok_or_else(|| {
-        io::Error::new(
-            io::ErrorKind::NotFound,
-            format!("Manifest for snapshot {} not found", actual_id),
-        )
-    })?;
+    if let Some(other_id) = compare {
+        let other = info::resolve_snapshot_id(Some(other_id), &head_manifest)?;
+        return compare_snapshot_stats(&base_path, &snapshot.version, &other);
+    }
+
+    let note_path = info::get_snapshots_dir(&base_path)
+        .join(&snapshot.version)
+        .join(NOTE_FILE);
+    if note {
+        if !note_path.is_file() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "Snapshot {} has no note (attach one with snapshot --note-file)",
+                    snapshot.version
+                ),
+            ));
+        }
+        io::stdout().write_all(&fs::read(&note_path)?)?;
+        return Ok(());
+    }
 
     // Calculate statistics
+    let manifest = load_manifest(&base_path, &snapshot.version)?;
+    let sharing = calculate_sharing(&base_path, &head_manifest, snapshot, &manifest)?;
     let stats = calculate_snapshot_stats(&manifest);
 
     // Display the information
     println!("Snapshot Information");
     println!("===================");
+    if let Some(name) = load_repo_description(&base_path)?.name {
+        println!("Repository: {}", name);
+    }
     println!("Version:    {}", snapshot.version);
-    println!("Created:    {}", snapshot.timestamp);
+    if let Some(ref label) = snapshot.label {
+        println!("Label:      {}", label);
+    }
+    if age {
+        println!(
+            "Created:    {} ({})",
+            info::format_age(&snapshot.timestamp),
+            snapshot.timestamp
+        );
+    } else {
+        println!("Created:    {}", snapshot.timestamp);
+    }
+    if let Some(ref amended_at) = snapshot.amended_at {
+        println!("Amended:    {}", amended_at);
+    }
     if let Some(ref msg) = snapshot.message {
         println!("Message:    {}", msg);
     }
+    if let Some(ref base_dir) = snapshot.base_dir {
+        println!("Location:   {}", base_dir);
+    }
+    if let (true, Some(ref parent)) = (snapshot.incremental, &snapshot.parent) {
+        println!(
+            "Stored:     incremental; unchanged files are read from {}",
+            parent
+        );
+    }
+    if note_path.is_file() {
+        println!("Note:       attached (show it with info --note)");
+    }
     println!();
 
     println!("Statistics");
     println!("==========");
     println!("Total files:       {}", stats.total_files);
     println!(
-        "Total size:        {} bytes ({} MB)",
-        stats.total_size,
-        stats.total_size / 1024 / 1024
+        "Total size:        {} ({} bytes)",
+        info::format_size(stats.total_size),
+        stats.total_size
+    );
+    println!(
+        "Largest file:      {} ({})",
+        info::format_size(stats.largest_file_size),
+        stats.largest_file_path
     );
     println!(
-        "Largest file:      {} bytes ({})",
-        stats.largest_file_size, stats.largest_file_path
+        "Average file size: {}",
+        info::format_size(stats.average_file_size)
+    );
+    let stored_files = sharing.shared_files + sharing.unique_files;
+    match snapshot.parent {
+        Some(ref parent) if stored_files > 0 => println!(
+            "Shared files:      {} of {} ({}%) shared with {}",
+            sharing.shared_files,
+            stored_files,
+            sharing.shared_files * 100 / stored_files,
+            parent
+        ),
+        _ => println!(
+            "Shared files:      {} of {}",
+            sharing.shared_files, stored_files
+        ),
+    }
+    println!(
+        "Unique data:       {} added by this snapshot ({} file(s))",
+        info::format_size(sharing.unique_bytes),
+        sharing.unique_files
     );
-    println!("Average file size: {} bytes", stats.average_file_size);
     println!();
 
     println!("File Types");
     println!("==========");
     let mut file_types: Vec<(String, usize)> = stats.file_types.into_iter().collect();
-    file_types.sort_by(|a, b| b.1.cmp(&a.1)); // Sort by count (descending)
+    file_types.sort_by_key(|b| std::cmp::Reverse(b.1)); // Sort by count (descending)
 
     for (ext, count) in file_types.iter().take(10) {
         // Show top 10
@@ -74,6 +155,255 @@ pub fn show_snapshot_info(snapshot_id: Option<String>) -> io::Result<()> {
     Ok(())
 }
 
+/// How a file's contents evolved across the snapshot history, for `info --churn`.
+#[derive(Default)]
+struct FileChurn {
+    /// Snapshots in which the file differed from the previous snapshot.
+    changes: usize,
+    /// Consecutive snapshots, up to the latest one seen, holding the same contents.
+    run: usize,
+    /// Longest run of consecutive snapshots holding the same contents.
+    longest_run: usize,
+}
+
+/// Reports which files change most often and which stayed identical across the most consecutive
+/// snapshots. A file counts as changed when it differs (by size and hash or modification time)
+/// from the previous snapshot; a file missing from a snapshot starts a new run when it returns.
+pub fn show_churn() -> io::Result<()> {
+    let base_path = info::get_base_dir()?;
+    let head_manifest = load_head_manifest(&base_path)?;
+    if head_manifest.is_empty() {
+        println!("No snapshots found.");
+        return Ok(());
+    }
+
+    let options = DiffOptions::default();
+    let mut files: HashMap<String, FileChurn> = HashMap::new();
+    let mut previous: HashMap<String, FileMetadata> = HashMap::new();
+    for snapshot in &head_manifest {
+        let manifest = load_manifest(&base_path, &snapshot.version)?;
+        for (path, meta) in &manifest {
+            let churn = files.entry(path.clone()).or_default();
+            match previous.get(path) {
+                Some(prev) if !diff::is_modified(prev, meta, &options) => churn.run += 1,
+                Some(_) => {
+                    churn.changes += 1;
+                    churn.run = 1;
+                }
+                None => churn.run = 1,
+            }
+            churn.longest_run = churn.longest_run.max(churn.run);
+        }
+        previous = manifest;
+    }
+
+    let title = format!(
+        "File churn across {} snapshot(s) ({} to {})",
+        head_manifest.len(),
+        head_manifest[0].version,
+        head_manifest[head_manifest.len() - 1].version
+    );
+    println!("{}", title);
+    println!("{:=<1$}", "", title.len());
+
+    let mut ranked: Vec<(&String, &FileChurn)> = files.iter().collect();
+    println!("Most churned files:");
+    ranked.sort_by(|a, b| b.1.changes.cmp(&a.1.changes).then(a.0.cmp(b.0)));
+    let churned: Vec<_> = ranked.iter().filter(|(_, c)| c.changes > 0).collect();
+    if churned.is_empty() {
+        println!("  No file changed between snapshots.");
+    }
+    for (path, churn) in churned.iter().take(CHURN_LIST_LIMIT) {
+        println!("  {:>5} change(s)  {}", churn.changes, path);
+    }
+    println!();
+
+    println!("Most stable files:");
+    ranked.sort_by(|a, b| {
+        (b.1.longest_run, a.1.changes, a.0).cmp(&(a.1.longest_run, b.1.changes, b.0))
+    });
+    for (path, churn) in ranked.iter().take(CHURN_LIST_LIMIT) {
+        println!("  {:>5} snapshot(s) unchanged  {}", churn.longest_run, path);
+    }
+
+    Ok(())
+}
+
+/// Prints the statistics of two snapshots side by side, with the change from the first to the
+/// second in a third column.
+fn compare_snapshot_stats(base_path: &Path, version1: &str, version2: &str) -> io::Result<()> {
+    let stats1 = calculate_snapshot_stats(&load_manifest(base_path, version1)?);
+    let stats2 = calculate_snapshot_stats(&load_manifest(base_path, version2)?);
+
+    let title = format!("Comparing {} with {}", version1, version2);
+    println!("{}", title);
+    println!("{:=<1$}", "", title.len());
+    println!("{:<19}{:<14}{:<14}Change", "", version1, version2);
+    let sizes = [
+        ("Total size", stats1.total_size, stats2.total_size),
+        (
+            "Average file size",
+            stats1.average_file_size,
+            stats2.average_file_size,
+        ),
+        (
+            "Largest file",
+            stats1.largest_file_size,
+            stats2.largest_file_size,
+        ),
+    ];
+    println!(
+        "{:<19}{:<14}{:<14}{}",
+        "Total files",
+        stats1.total_files,
+        stats2.total_files,
+        format_delta(
+            stats1.total_files as i128,
+            stats2.total_files as i128,
+            |n| { n.to_string() }
+        )
+    );
+    for (name, size1, size2) in sizes {
+        println!(
+            "{:<19}{:<14}{:<14}{}",
+            name,
+            info::format_size(size1),
+            info::format_size(size2),
+            format_delta(size1 as i128, size2 as i128, |n| info::format_size(
+                n as u64
+            ))
+        );
+    }
+    println!();
+
+    println!("File Types");
+    println!("==========");
+    let mut extensions: Vec<&String> = stats1
+        .file_types
+        .keys()
+        .chain(stats2.file_types.keys())
+        .collect();
+    extensions.sort();
+    extensions.dedup();
+    // Largest changes first, then by name
+    let count = |stats: &SnapshotStats, ext: &str| *stats.file_types.get(ext).unwrap_or(&0) as i128;
+    extensions
+        .sort_by_key(|ext| std::cmp::Reverse((count(&stats2, ext) - count(&stats1, ext)).abs()));
+    for ext in extensions.iter().take(10) {
+        let (count1, count2) = (count(&stats1, ext), count(&stats2, ext));
+        println!(
+            "{:<19}{:<14}{:<14}{}",
+            ext,
+            count1,
+            count2,
+            format_delta(count1, count2, |n| n.to_string())
+        );
+    }
+
+    Ok(())
+}
+
+/// Formats the change from `before` to `after` with an explicit sign, e.g. "+3", "-1.5 KiB",
+/// or "0" when nothing changed. `format` renders the magnitude.
+fn format_delta(before: i128, after: i128, format: impl Fn(u128) -> String) -> String {
+    let delta = after - before;
+    match delta.signum() {
+        1 => format!("+{}", format(delta.unsigned_abs())),
+        -1 => format!("-{}", format(delta.unsigned_abs())),
+        _ => "0".to_string(),
+    }
+}
+
+/// Loads the manifest of a snapshot, failing if it is missing.
+fn load_manifest(base_path: &Path, version: &str) -> io::Result<HashMap<String, FileMetadata>> {
+    let snap_option = manifest::load_snapshot_manifest(base_path, version)?;
+    let (_snapshot_dir, manifest) = snap_option.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Manifest for snapshot {} not found", version),
+        )
+    })?;
+    Ok(manifest)
+}
+
+/// How much of a snapshot's stored data it shares with the snapshot it was created against.
+/// Symbolic links aren't counted.
+struct Sharing {
+    /// Files hard-linked to (or, for incremental snapshots, read from) earlier snapshots.
+    shared_files: usize,
+    /// Files stored only by this snapshot.
+    unique_files: usize,
+    /// Bytes on disk taken by the unique files.
+    unique_bytes: u64,
+}
+
+/// Counts the files a snapshot shares with its parent and the bytes it added. A file is shared
+/// when it's the same inode as the parent's stored copy, or when an incremental snapshot left it
+/// out of its folder.
+fn calculate_sharing(
+    base_path: &Path,
+    head_manifest: &[SnapshotIndex],
+    snapshot: &SnapshotIndex,
+    manifest: &HashMap<String, FileMetadata>,
+) -> io::Result<Sharing> {
+    let snapshot_dir = info::get_snapshots_dir(base_path).join(&snapshot.version);
+    let parent = match snapshot.parent {
+        Some(ref parent) => {
+            manifest::load_snapshot_manifest(base_path, parent)?.map(|(_, files)| {
+                (
+                    manifest::snapshot_chain(base_path, head_manifest, parent),
+                    files,
+                )
+            })
+        }
+        None => None,
+    };
+
+    let mut sharing = Sharing {
+        shared_files: 0,
+        unique_files: 0,
+        unique_bytes: 0,
+    };
+    for (path, meta) in manifest.iter().filter(|(_, m)| m.link_target.is_none()) {
+        let Ok(stored) = fs::symlink_metadata(snapshot_dir.join(path)) else {
+            sharing.shared_files += 1;
+            continue;
+        };
+        let shared = parent.as_ref().is_some_and(|(chain, files)| {
+            let parent_copy = manifest::stored_file_dir(chain, path).join(path);
+            is_same_file(&stored, &parent_copy, || {
+                files
+                    .get(path)
+                    .is_some_and(|prev| !diff::is_modified(prev, meta, &DiffOptions::default()))
+            })
+        });
+        if shared {
+            sharing.shared_files += 1;
+        } else {
+            sharing.unique_files += 1;
+            sharing.unique_bytes += stored.len();
+        }
+    }
+    Ok(sharing)
+}
+
+/// Returns whether the stored file is the same inode as `other`.
+#[cfg(unix)]
+fn is_same_file(stored: &fs::Metadata, other: &Path, _unchanged: impl FnOnce() -> bool) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    stored.nlink() > 1
+        && fs::symlink_metadata(other)
+            .is_ok_and(|other| other.dev() == stored.dev() && other.ino() == stored.ino())
+}
+
+/// Inode information isn't available on this platform, so a file counts as shared when it is
+/// unchanged from the parent's manifest, which is when snapshots hard-link it.
+#[cfg(not(unix))]
+fn is_same_file(_stored: &fs::Metadata, _other: &Path, unchanged: impl FnOnce() -> bool) -> bool {
+    unchanged()
+}
+
 /// Statistics about a snapshot
 struct SnapshotStats {
     total_files: usize,
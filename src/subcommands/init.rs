@@ -1,17 +1,31 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 use std::{fs, io};
 
 use crate::{
-    constants::{DEFAULT_IGNORE_ITEMS, IGNORE_FILE, REPO_FOLDER, SNAPSHOTS_FOLDER},
+    config::{self, STORE_KEY},
+    constants::{DEFAULT_IGNORE_ITEMS, HEAD_MANIFEST_FILE, IGNORE_FILE},
     info, manifest,
 };
 
 /// Initializes the Snap Safe repository in the current directory.
 /// This creates the hidden `.snapsafe` folder (and its subfolder for snapshots)
-/// and initializes an empty head manifest.
-pub fn init_repository() -> io::Result<()> {
+/// and initializes an empty head manifest. With `store`, snapshots are kept in that
+/// directory instead, which is recorded in the repository config.
+///
+/// Running it in an existing repository recreates any missing pieces. A corrupt head manifest
+/// is only reset with `force` (after confirmation); snapshot folders are never deleted.
+///
+/// A `name` or `description` given is recorded for `about` and `info`, replacing the previous
+/// one; an empty string removes it.
+pub fn init_repository(
+    store: Option<PathBuf>,
+    force: bool,
+    name: Option<String>,
+    description: Option<String>,
+) -> io::Result<()> {
     let base_path = info::get_base_dir()?;
-    let repo_path = base_path.join(REPO_FOLDER);
-    let snapshots_path = repo_path.join(SNAPSHOTS_FOLDER);
+    let repo_path = info::get_repo_dir(&base_path);
 
     if repo_path.exists() {
         println!("Repository already exists at {:?}", repo_path);
@@ -20,6 +34,11 @@ pub fn init_repository() -> io::Result<()> {
         println!("Created repository directory at {:?}", repo_path);
     }
 
+    if let Some(store) = store {
+        set_store(&base_path, &store)?;
+    }
+    let snapshots_path = info::get_snapshots_dir(&base_path);
+
     if snapshots_path.exists() {
         println!("Snapshots directory already exists at {:?}", snapshots_path);
     } else {
@@ -47,10 +66,141 @@ pub fn init_repository() -> io::Result<()> {
         println!("Format: One filename or directory per line (similar to .gitignore)");
     }
 
+    check_head_manifest(&base_path, force)?;
     manifest::initialize_head_manifest(&base_path)?;
+    report_unlisted_snapshots(&base_path)?;
+    if name.is_some() || description.is_some() {
+        set_description(&base_path, name, description)?;
+    }
 
     println!("\nRepository initialized successfully!");
     println!("Run 'snapsafe snapshot -m \"Initial snapshot\"' to create your first snapshot");
 
     Ok(())
 }
+
+/// Checks that an existing head manifest can be read. With `force`, a corrupt one is moved aside
+/// (to `head_manifest.json.bak`) after confirmation so an empty one can take its place.
+fn check_head_manifest(base_path: &Path, force: bool) -> io::Result<()> {
+    let path = info::get_repo_dir(base_path).join(HEAD_MANIFEST_FILE);
+    let error = match manifest::load_head_manifest(base_path) {
+        Ok(_) => return Ok(()),
+        Err(e) => e,
+    };
+    println!("Head manifest at {:?} is corrupt: {}", path, error);
+    if !force {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Run 'snapsafe init --force' to reset the head manifest to empty (snapshot folders are kept)",
+        ));
+    }
+
+    println!("Reset the head manifest to empty? Snapshots will no longer be listed. (y/n)");
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    if !input.trim().eq_ignore_ascii_case("y") {
+        return Err(io::Error::new(
+            io::ErrorKind::Interrupted,
+            "Reset cancelled; the head manifest was not changed.",
+        ));
+    }
+
+    let backup = path.with_extension("json.bak");
+    fs::rename(&path, &backup)?;
+    println!("Moved the corrupt head manifest to {:?}", backup);
+    Ok(())
+}
+
+/// Lists the snapshot folders that the head manifest doesn't know about, e.g. after it was lost
+/// or reset. They are left in place.
+fn report_unlisted_snapshots(base_path: &Path) -> io::Result<()> {
+    let listed: HashSet<String> = manifest::load_head_manifest(base_path)?
+        .into_iter()
+        .map(|s| s.version)
+        .collect();
+    let mut unlisted: Vec<String> = fs::read_dir(info::get_snapshots_dir(base_path))?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .filter(|name| !name.starts_with('.') && !listed.contains(name))
+        .collect();
+    if unlisted.is_empty() {
+        return Ok(());
+    }
+
+    unlisted.sort();
+    println!(
+        "Found {} snapshot folder(s) not listed in the head manifest (they were kept):",
+        unlisted.len()
+    );
+    for name in &unlisted {
+        println!("  - {}", name);
+    }
+    Ok(())
+}
+
+/// Records the repository's name and/or description, keeping whichever isn't given.
+fn set_description(
+    base_path: &Path,
+    name: Option<String>,
+    description: Option<String>,
+) -> io::Result<()> {
+    let mut current = manifest::load_repo_description(base_path)?;
+    if let Some(name) = name {
+        current.name = Some(name.trim().to_string()).filter(|name| !name.is_empty());
+        match current.name {
+            Some(ref name) => println!("Set the repository name to '{}'", name),
+            None => println!("Removed the repository name"),
+        }
+    }
+    if let Some(description) = description {
+        current.description =
+            Some(description.trim().to_string()).filter(|description| !description.is_empty());
+        match current.description {
+            Some(_) => println!("Set the repository description"),
+            None => println!("Removed the repository description"),
+        }
+    }
+    manifest::save_repo_description(base_path, &current)
+}
+
+/// Records an external snapshot store in the repository config, creating the directory if needed.
+/// The store can't be changed once the repository has snapshots, since they would be left behind.
+fn set_store(base_path: &Path, store: &Path) -> io::Result<()> {
+    fs::create_dir_all(base_path.join(store))?;
+    let store = fs::canonicalize(base_path.join(store))?;
+
+    // A store inside the working directory would end up in its own snapshots
+    let base = fs::canonicalize(base_path)?;
+    let repo = fs::canonicalize(info::get_repo_dir(base_path))?;
+    if store.starts_with(&base) && !store.starts_with(&repo) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "The snapshot store {:?} must be outside the directory being snapshotted",
+                store
+            ),
+        ));
+    }
+
+    let current = info::get_snapshots_dir(base_path);
+    let has_snapshots = !manifest::load_head_manifest(base_path)
+        .unwrap_or_default()
+        .is_empty();
+    if has_snapshots && fs::canonicalize(&current).ok().as_ref() != Some(&store) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "The repository already has snapshots in {:?}; the snapshot store can't be changed",
+                current
+            ),
+        ));
+    }
+
+    let path = config::repo_config_path(base_path);
+    let mut file = config::load_config_file(&path)?;
+    file.insert(STORE_KEY.to_string(), store.to_string_lossy().into_owned());
+    config::save_config_file(&path, &file)?;
+    println!("Snapshots will be stored in {:?}", store);
+    Ok(())
+}
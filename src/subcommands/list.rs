@@ -1,73 +1,173 @@
+use clap::ValueEnum;
 use std::io;
+use std::path::Path;
 
-use crate::{info::get_base_dir, manifest::load_head_manifest};
+use crate::{
+    index::load_or_build_index,
+    info::{format_age, format_size, get_base_dir},
+    manifest::{self, load_head_manifest},
+    models::SnapshotIndex,
+    pager::{self, Paging},
+};
+
+/// A column of the `list` table, as selected with `list --columns`.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ListColumn {
+    Version,
+    Label,
+    Timestamp,
+    Size,
+    Message,
+    Tags,
+    Metadata,
+}
+
+impl ListColumn {
+    fn header(self) -> &'static str {
+        match self {
+            ListColumn::Version => "Version",
+            ListColumn::Label => "Label",
+            ListColumn::Timestamp => "Timestamp",
+            ListColumn::Size => "Size",
+            ListColumn::Message => "Message",
+            ListColumn::Tags => "Tags",
+            ListColumn::Metadata => "Metadata",
+        }
+    }
+
+    /// Width the column is padded to.
+    fn width(self) -> usize {
+        match self {
+            ListColumn::Version => 10,
+            ListColumn::Size => 10,
+            ListColumn::Metadata => 30,
+            _ => 20,
+        }
+    }
+
+    /// Values longer than this are cut to the column width, ending in "...".
+    fn max_len(self) -> Option<usize> {
+        match self {
+            ListColumn::Label => Some(self.width()),
+            ListColumn::Message | ListColumn::Tags | ListColumn::Metadata => Some(self.width() - 3),
+            _ => None,
+        }
+    }
+}
 
 /// Lists all snapshots by reading the head manifest and printing each entry.
-pub fn list_snapshots() -> io::Result<()> {
+/// With show_size, a column with each snapshot's total size is added.
+/// With tag, only snapshots carrying that tag (according to the search index) are listed.
+/// With age, timestamps are shown relative to now (e.g. "3 days ago").
+/// With columns, exactly those columns are shown, in that order.
+/// The table goes through the pager as paging says.
+pub fn list_snapshots(
+    show_size: bool,
+    tag: Option<String>,
+    age: bool,
+    columns: Option<Vec<ListColumn>>,
+    paging: Paging,
+) -> io::Result<()> {
     let base_path = get_base_dir()?;
-    let head_manifest = load_head_manifest(&base_path)?;
+    let mut head_manifest = load_head_manifest(&base_path)?;
+    if let Some(ref tag) = tag {
+        let tagged = load_or_build_index(&base_path, &head_manifest)?
+            .tags
+            .remove(tag)
+            .unwrap_or_default();
+        head_manifest.retain(|s| tagged.contains(&s.version));
+    }
     if head_manifest.is_empty() {
         println!("No snapshots found.");
-    } else {
-        println!(
-            "{:<10} {:<20} {:<20} {:<20} {:<30}",
-            "Version", "Timestamp", "Message", "Tags", "Metadata"
-        );
-        println!(
-            "{:-<10} {:-<20} {:-<20} {:-<20} {:-<30}",
-            "", "", "", "", ""
-        );
-        for snapshot in head_manifest {
-            let msg = snapshot.message.unwrap_or_default();
+        return Ok(());
+    }
 
-            // Format tags as a comma-separated list
-            let tags = if let Some(ref metadata) = snapshot.metadata {
-                if metadata.tags.is_empty() {
-                    "-".to_string()
-                } else {
-                    metadata.tags.join(", ")
-                }
-            } else {
-                "-".to_string()
-            };
+    // By default, the label column only appears once a snapshot has a label
+    let columns = columns.unwrap_or_else(|| {
+        let show_label = head_manifest.iter().any(|s| s.label.is_some());
+        [
+            Some(ListColumn::Version),
+            show_label.then_some(ListColumn::Label),
+            Some(ListColumn::Timestamp),
+            show_size.then_some(ListColumn::Size),
+            Some(ListColumn::Message),
+            Some(ListColumn::Tags),
+            Some(ListColumn::Metadata),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    });
 
-            // Format metadata as key=value pairs
-            let meta_str = if let Some(ref metadata) = snapshot.metadata {
-                if metadata.custom.is_empty() {
-                    "-".to_string()
-                } else {
-                    metadata
-                        .custom
-                        .iter()
-                        .map(|(k, v)| format!("{}={}", k, v))
-                        .collect::<Vec<String>>()
-                        .join(", ")
-                }
-            } else {
-                "-".to_string()
-            };
+    let headers: Vec<String> = columns
+        .iter()
+        .map(|c| format!("{:<1$}", c.header(), c.width()))
+        .collect();
+    let rules: Vec<String> = columns.iter().map(|c| "-".repeat(c.width())).collect();
+    let mut lines = vec![headers.join(" "), rules.join(" ")];
+    for snapshot in &head_manifest {
+        let cells = columns
+            .iter()
+            .map(|&column| {
+                column_value(&base_path, snapshot, column, age).map(|value| cell(column, &value))
+            })
+            .collect::<io::Result<Vec<String>>>()?;
+        lines.push(cells.join(" "));
+    }
+    pager::print(&lines, paging)
+}
 
-            println!(
-                "{:<10} {:<20} {:<20} {:<20} {:<30}",
-                snapshot.version,
-                snapshot.timestamp,
-                if msg.len() > 17 {
-                    format!("{}...", &msg[..17])
-                } else {
-                    msg
-                },
-                if tags.len() > 17 {
-                    format!("{}...", &tags[..17])
-                } else {
-                    tags
-                },
-                if meta_str.len() > 27 {
-                    format!("{}...", &meta_str[..27])
-                } else {
-                    meta_str
-                }
-            );
-        }
+/// Pads a value to its column's width, cutting it short (ending in "...") if it is too long.
+fn cell(column: ListColumn, value: &str) -> String {
+    let width = column.width();
+    if column.max_len().is_some_and(|max| value.len() > max) {
+        format!("{:<width$}", format!("{}...", &value[..width - 3]))
+    } else {
+        format!("{:<width$}", value)
+    }
+}
+
+/// Returns the text shown for a snapshot in the given column.
+fn column_value(
+    base_path: &Path,
+    snapshot: &SnapshotIndex,
+    column: ListColumn,
+    age: bool,
+) -> io::Result<String> {
+    let value = match column {
+        ListColumn::Version => snapshot.version.clone(),
+        ListColumn::Label => snapshot.label.clone().unwrap_or_else(|| "-".to_string()),
+        ListColumn::Timestamp if age => format_age(&snapshot.timestamp),
+        ListColumn::Timestamp => snapshot.timestamp.clone(),
+        ListColumn::Size => snapshot_size(base_path, snapshot)?,
+        ListColumn::Message => snapshot.message.clone().unwrap_or_default(),
+        // Tags as a comma-separated list
+        ListColumn::Tags => match snapshot.metadata {
+            Some(ref metadata) if !metadata.tags.is_empty() => metadata.tags.join(", "),
+            _ => "-".to_string(),
+        },
+        // Metadata as key=value pairs
+        ListColumn::Metadata => match snapshot.metadata {
+            Some(ref metadata) if !metadata.custom.is_empty() => metadata
+                .custom
+                .iter()
+                .map(|(k, v)| format!("{}={}", k, v))
+                .collect::<Vec<String>>()
+                .join(", "),
+            _ => "-".to_string(),
+        },
+    };
+    Ok(value)
+}
+
+/// Returns the human-readable total size of a snapshot, using the size cached in the head
+/// manifest or computing it from the snapshot manifest for older snapshots.
+fn snapshot_size(base_path: &Path, snapshot: &SnapshotIndex) -> io::Result<String> {
+    if let Some(size) = snapshot.total_size {
+        return Ok(format_size(size));
+    }
+    match manifest::load_snapshot_manifest(base_path, &snapshot.version)? {
+        Some((_, files)) => Ok(format_size(files.values().map(|m| m.file_size).sum())),
+        None => Ok("-".to_string()),
     }
-    Ok(())
 }
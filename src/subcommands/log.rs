@@ -0,0 +1,93 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+
+use crate::{
+    info::get_base_dir,
+    manifest::load_head_manifest,
+    models::SnapshotIndex,
+    pager::{self, Paging},
+};
+
+/// Prints the snapshot history, oldest first, with each snapshot's parent: the snapshot it was
+/// hard-linked against when it was created. With graph, the history is drawn as a tree so
+/// snapshots branched off an older one with `snapshot --from` stand out. The history goes
+/// through the pager as paging says.
+pub fn show_log(graph: bool, paging: Paging) -> io::Result<()> {
+    let base_path = get_base_dir()?;
+    let head_manifest = load_head_manifest(&base_path)?;
+    if head_manifest.is_empty() {
+        println!("No snapshots found.");
+        return Ok(());
+    }
+
+    let mut lines = Vec::new();
+    if !graph {
+        for snapshot in &head_manifest {
+            let parent = match snapshot.parent {
+                Some(ref parent) => format!("(parent {})", parent),
+                None => "(root)".to_string(),
+            };
+            lines.push(format!("{}  {}", describe(snapshot), parent));
+        }
+        return pager::print(&lines, paging);
+    }
+
+    // Snapshots whose parent was pruned (or that predate parent tracking) start a new tree
+    let versions: HashSet<&str> = head_manifest.iter().map(|s| s.version.as_str()).collect();
+    let mut children: HashMap<&str, Vec<&SnapshotIndex>> = HashMap::new();
+    let mut roots = Vec::new();
+    for snapshot in &head_manifest {
+        match snapshot.parent.as_deref() {
+            Some(parent) if versions.contains(parent) => {
+                children.entry(parent).or_default().push(snapshot)
+            }
+            _ => roots.push(snapshot),
+        }
+    }
+    for root in roots {
+        print_tree(root, &children, "", "", &mut lines);
+    }
+    pager::print(&lines, paging)
+}
+
+/// Adds the lines for a snapshot and its descendants. The newest child continues the current line;
+/// older children are drawn as branches, indented under a `├─` connector.
+fn print_tree(
+    snapshot: &SnapshotIndex,
+    children: &HashMap<&str, Vec<&SnapshotIndex>>,
+    head_prefix: &str,
+    body_prefix: &str,
+    lines: &mut Vec<String>,
+) {
+    lines.push(format!("{}* {}", head_prefix, describe(snapshot)));
+    let kids = match children.get(snapshot.version.as_str()) {
+        Some(kids) => kids,
+        None => return,
+    };
+    for (i, child) in kids.iter().enumerate() {
+        if i + 1 < kids.len() {
+            print_tree(
+                child,
+                children,
+                &format!("{}├─", body_prefix),
+                &format!("{}│ ", body_prefix),
+                lines,
+            );
+        } else {
+            print_tree(child, children, body_prefix, body_prefix, lines);
+        }
+    }
+}
+
+/// One-line summary of a snapshot: version, label, timestamp, and message.
+fn describe(snapshot: &SnapshotIndex) -> String {
+    let mut line = snapshot.version.clone();
+    if let Some(ref label) = snapshot.label {
+        line.push_str(&format!(" [{}]", label));
+    }
+    line.push_str(&format!("  {}", snapshot.timestamp));
+    if let Some(ref message) = snapshot.message {
+        line.push_str(&format!("  {}", message));
+    }
+    line
+}
@@ -1,28 +1,106 @@
+use clap::ValueEnum;
+use serde_json::Value;
 use std::io;
 
 use crate::info;
-use crate::manifest::{load_head_manifest, save_head_manifest};
+use crate::manifest::{load_head_manifest, save_head_manifest, save_snapshot_meta};
+
+/// Type used to store a metadata value.
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum MetaType {
+    /// Plain string (default)
+    #[default]
+    String,
+    /// Whole number
+    Int,
+    /// Floating point number
+    Float,
+    /// true or false
+    Bool,
+}
+
+impl MetaType {
+    /// Parses a raw value into a typed JSON value, or returns `None` for plain strings.
+    fn parse(self, value: &str) -> io::Result<Option<Value>> {
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("'{}' is not a valid {:?} value", value, self).to_lowercase(),
+            )
+        };
+        match self {
+            MetaType::String => Ok(None),
+            MetaType::Int => value
+                .parse::<i64>()
+                .map(|v| Some(Value::from(v)))
+                .map_err(|_| invalid()),
+            MetaType::Float => value
+                .parse::<f64>()
+                .ok()
+                .and_then(serde_json::Number::from_f64)
+                .map(|v| Some(Value::Number(v)))
+                .ok_or_else(invalid),
+            MetaType::Bool => value
+                .parse::<bool>()
+                .map(|v| Some(Value::Bool(v)))
+                .map_err(|_| invalid()),
+        }
+    }
+}
 
-/// Add, update, remove, or list custom metadata for a snapshot
+/// Returns a short name for the type of a stored typed value.
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Bool(_) => "bool",
+        Value::Number(n) if n.is_f64() => "float",
+        Value::Number(_) => "int",
+        _ => "string",
+    }
+}
+
+/// Parses the `--meta-json` argument of `snapshot`: a flat JSON object whose values are all
+/// strings. Returns its key/value pairs, or an error describing why the JSON isn't accepted.
+pub fn parse_meta_json(json: &str) -> io::Result<Vec<(String, String)>> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+    let value: Value =
+        serde_json::from_str(json).map_err(|e| invalid(format!("Invalid --meta-json: {}", e)))?;
+    let Value::Object(object) = value else {
+        return Err(invalid(
+            "--meta-json must be a JSON object of string values".to_string(),
+        ));
+    };
+    object
+        .into_iter()
+        .map(|(key, value)| match value {
+            Value::String(value) => Ok((key, value)),
+            other => Err(invalid(format!(
+                "--meta-json value for '{}' must be a string, not {}",
+                key, other
+            ))),
+        })
+        .collect()
+}
+
+/// Add, update, remove, or list custom metadata for a snapshot.
+/// With `raw`, the list is printed as bare `key=value` lines for scripts.
+/// The snapshot ID `all` applies the change to every snapshot, after a confirmation unless
+/// `yes` is set.
 pub fn manage_metadata(
     snapshot_id: Option<String>,
     set: Option<Vec<String>>,
+    value_type: MetaType,
     remove: Option<String>,
     list: bool,
+    raw: bool,
+    yes: bool,
 ) -> io::Result<()> {
     let base_path = info::get_base_dir()?;
     let mut head_manifest = load_head_manifest(&base_path)?;
-    let actual_id = info::resolve_snapshot_id(snapshot_id, &head_manifest)?;
-    // Find the snapshot in the head manifest
-    let snapshot_index = head_manifest
-        .iter()
-        .position(|s| s.version == actual_id || s.version.starts_with(&actual_id))
-        .ok_or_else(|| {
-            io::Error::new(
-                io::ErrorKind::NotFound,
-                format!("Snapshot {} not found", actual_id),
-            )
-        })?;
+    // Find the snapshot(s) in the head manifest
+    let all = snapshot_id
+        .as_deref()
+        .is_some_and(|id| id.eq_ignore_ascii_case(info::ALL_SNAPSHOTS_ID));
+    let positions = info::resolve_snapshot_positions(snapshot_id, &head_manifest)?;
 
     // Set custom metadata
     if let Some(ref values) = set {
@@ -36,71 +114,116 @@ pub fn manage_metadata(
 
         let key = &values[0];
         let value = &values[1];
+        let typed_value = value_type.parse(value)?;
 
-        // Reference to the snapshot (move after all pattern matches to avoid borrow conflicts)
-        let snapshot = &mut head_manifest[snapshot_index];
-
-        // Initialize metadata if it doesn't exist
-        if snapshot.metadata.is_none() {
-            snapshot.metadata = Some(crate::models::SnapshotMetadata::default());
+        let change = format!("set {} = {}", key, value);
+        if all && !info::confirm_bulk_change(&change, positions.len(), yes)? {
+            return Ok(());
         }
 
-        let metadata = snapshot.metadata.as_mut().unwrap();
-
-        metadata.custom.insert(key.clone(), value.clone());
-        println!(
-            "Set metadata for snapshot {}: {} = {}",
-            snapshot.version, key, value
-        );
+        for &position in &positions {
+            let snapshot = &mut head_manifest[position];
+            let metadata = snapshot
+                .metadata
+                .get_or_insert_with(crate::models::SnapshotMetadata::default);
+
+            metadata.custom.insert(key.clone(), value.clone());
+            match typed_value {
+                Some(ref typed) => {
+                    println!(
+                        "Set metadata for snapshot {}: {} = {} ({})",
+                        snapshot.version,
+                        key,
+                        value,
+                        type_name(typed)
+                    );
+                    metadata.typed.insert(key.clone(), typed.clone());
+                }
+                None => {
+                    metadata.typed.remove(key);
+                    println!(
+                        "Set metadata for snapshot {}: {} = {}",
+                        snapshot.version, key, value
+                    );
+                }
+            }
+        }
 
         // Save the updated manifest
         save_head_manifest(&base_path, &head_manifest)?;
+        for &position in &positions {
+            save_snapshot_meta(&base_path, &head_manifest[position])?;
+        }
     }
     // Remove custom metadata
     else if let Some(ref key) = remove {
-        // Use ref to avoid moving key
-        // Reference to the snapshot
-        let snapshot = &mut head_manifest[snapshot_index];
-
-        // Initialize metadata if it doesn't exist
-        if snapshot.metadata.is_none() {
-            snapshot.metadata = Some(crate::models::SnapshotMetadata::default());
+        let change = format!("remove metadata key '{}'", key);
+        if all && !info::confirm_bulk_change(&change, positions.len(), yes)? {
+            return Ok(());
         }
 
-        let metadata = snapshot.metadata.as_mut().unwrap();
-
-        if metadata.custom.remove(key).is_some() {
-            println!(
-                "Removed metadata key '{}' from snapshot {}",
-                key, snapshot.version
-            );
-        } else {
-            println!(
-                "Metadata key '{}' not found for snapshot {}",
-                key, snapshot.version
-            );
+        for &position in &positions {
+            let snapshot = &mut head_manifest[position];
+            let metadata = snapshot
+                .metadata
+                .get_or_insert_with(crate::models::SnapshotMetadata::default);
+
+            metadata.typed.remove(key);
+            if metadata.custom.remove(key).is_some() {
+                println!(
+                    "Removed metadata key '{}' from snapshot {}",
+                    key, snapshot.version
+                );
+            } else {
+                println!(
+                    "Metadata key '{}' not found for snapshot {}",
+                    key, snapshot.version
+                );
+            }
         }
 
         // Save the updated manifest
         save_head_manifest(&base_path, &head_manifest)?;
+        for &position in &positions {
+            save_snapshot_meta(&base_path, &head_manifest[position])?;
+        }
     }
     // List custom metadata
     else if list || (set.is_none() && remove.is_none()) {
-        // Reference to the snapshot - using a separate binding to avoid borrow conflicts
-        let snapshot = &head_manifest[snapshot_index];
-
-        println!("Custom metadata for snapshot {}:", snapshot.version);
+        for &position in &positions {
+            let snapshot = &head_manifest[position];
+
+            if raw {
+                let mut pairs: Vec<_> = snapshot
+                    .metadata
+                    .iter()
+                    .flat_map(|metadata| &metadata.custom)
+                    .collect();
+                pairs.sort();
+                for (key, value) in pairs {
+                    println!("{}={}", key, value);
+                }
+                continue;
+            }
 
-        if let Some(ref metadata) = snapshot.metadata {
-            if metadata.custom.is_empty() {
-                println!("  No custom metadata");
-            } else {
-                for (key, value) in &metadata.custom {
-                    println!("  {} = {}", key, value);
+            println!("Custom metadata for snapshot {}:", snapshot.version);
+
+            if let Some(ref metadata) = snapshot.metadata {
+                if metadata.custom.is_empty() {
+                    println!("  No custom metadata");
+                } else {
+                    for (key, value) in &metadata.custom {
+                        match metadata.typed.get(key) {
+                            Some(typed) => {
+                                println!("  {} = {} ({})", key, value, type_name(typed))
+                            }
+                            None => println!("  {} = {}", key, value),
+                        }
+                    }
                 }
+            } else {
+                println!("  No metadata available");
             }
-        } else {
-            println!("  No metadata available");
         }
     }
 
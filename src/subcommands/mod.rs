@@ -1,7 +1,16 @@
+pub mod about;
+pub mod amend;
+pub mod check;
+pub mod config;
 pub mod diff;
+pub mod export;
+pub mod find;
+pub mod history;
+pub mod import;
 pub mod info;
 pub mod init;
 pub mod list;
+pub mod log;
 pub mod meta;
 pub mod prune;
 pub mod restore;
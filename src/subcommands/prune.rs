@@ -1,23 +1,78 @@
-use chrono::{Duration, Local, NaiveDateTime, TimeZone};
+use chrono::{Duration, Local, NaiveDate, NaiveDateTime, TimeZone};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
 use std::fs;
 use std::io;
+use std::path::Path;
 
-use crate::constants::{REPO_FOLDER, SNAPSHOTS_FOLDER};
 use crate::info;
-use crate::manifest::{load_head_manifest, save_head_manifest};
+use crate::manifest::{self, load_head_manifest, save_head_manifest};
+use crate::models::SnapshotIndex;
+
+/// Optional behaviour switches for `prune_snapshots`.
+#[derive(Debug, Default, Clone)]
+pub struct PruneOptions {
+    /// Show what would be pruned without deleting anything.
+    pub dry_run: bool,
+    /// Print the dry-run plan as JSON instead of a human-readable list.
+    pub json: bool,
+    /// Delete without asking for confirmation.
+    pub yes: bool,
+    /// Keep the N most recent snapshots of each tag. Tagged snapshots are then only kept by
+    /// their tag groups, and the other criteria apply to untagged snapshots.
+    pub keep_last_per_tag: Option<usize>,
+    /// Delete snapshots taken before this date or date and time (e.g. "2024-01-01" or
+    /// "2024-01-01 12:00:00"), interpreted in local time like snapshot timestamps.
+    pub before: Option<String>,
+    /// With `dry_run`, also list the snapshots that would survive.
+    pub show_kept: bool,
+}
+
+/// A snapshot that would be removed, as reported by `prune --dry-run --json`.
+#[derive(Serialize)]
+struct PlannedDeletion {
+    version: String,
+    timestamp: String,
+    /// Bytes freed by deleting the snapshot, excluding files hard-linked from kept snapshots.
+    reclaimable_bytes: u64,
+}
+
+/// A snapshot that would survive, as reported by `prune --dry-run --json --show-kept`.
+#[derive(Serialize)]
+struct PlannedSurvivor {
+    version: String,
+    timestamp: String,
+}
+
+/// The full dry-run plan printed by `prune --dry-run --json`.
+#[derive(Serialize)]
+struct PrunePlan {
+    snapshots: Vec<PlannedDeletion>,
+    total_reclaimable_bytes: u64,
+    /// The snapshots that would survive, with `--show-kept`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kept: Option<Vec<PlannedSurvivor>>,
+}
 
 /// Prune snapshots based on age or count
 pub fn prune_snapshots(
     keep_last: Option<usize>,
     older_than: Option<String>,
-    dry_run: bool,
+    options: &PruneOptions,
 ) -> io::Result<()> {
     let base_path = info::get_base_dir()?;
     let mut head_manifest = load_head_manifest(&base_path)?;
+    let dry_run = options.dry_run;
+    // Human-readable progress is suppressed when printing the JSON plan
+    let say = |message: String| {
+        if !options.json {
+            println!("{}", message);
+        }
+    };
 
     if head_manifest.is_empty() {
-        println!("No snapshots to prune.");
-        return Ok(());
+        say("No snapshots to prune.".to_string());
+        return print_json_plan(&base_path, &[], &head_manifest, options);
     }
 
     // Sort snapshots by timestamp (oldest first)
@@ -26,17 +81,37 @@ pub fn prune_snapshots(
     // Create a list of snapshots to delete
     let mut to_delete = Vec::new();
 
-    // If keep_last is specified, keep the N most recent snapshots
-    if let Some(keep) = keep_last {
-        if keep >= head_manifest.len() {
-            println!("Keeping all {} snapshots.", head_manifest.len());
-            return Ok(());
+    // With keep_last_per_tag, tagged snapshots are decided by their tag groups alone
+    let candidates: Vec<SnapshotIndex> = match options.keep_last_per_tag {
+        Some(keep) => {
+            to_delete.extend(prune_per_tag(&head_manifest, keep));
+            say(format!(
+                "Will keep the {} most recent snapshots of each tag.",
+                keep
+            ));
+            head_manifest
+                .iter()
+                .filter(|s| tags_of(s).is_empty())
+                .cloned()
+                .collect()
         }
+        None => head_manifest.clone(),
+    };
 
-        let to_keep = head_manifest.len() - keep;
-        to_delete.extend(head_manifest.iter().take(to_keep).cloned());
+    // If keep_last is specified, keep the N most recent snapshots
+    if let Some(keep) = keep_last {
+        if keep >= candidates.len() {
+            say(format!("Keeping all {} snapshots.", candidates.len()));
+            if to_delete.is_empty() {
+                print_kept(&head_manifest, &to_delete, options);
+                return print_json_plan(&base_path, &[], &head_manifest, options);
+            }
+        } else {
+            let to_keep = candidates.len() - keep;
+            to_delete.extend(candidates.iter().take(to_keep).cloned());
 
-        println!("Will keep {} most recent snapshots.", keep);
+            say(format!("Will keep {} most recent snapshots.", keep));
+        }
     }
 
     // If older_than is specified, delete snapshots older than the specified duration
@@ -47,9 +122,9 @@ pub fn prune_snapshots(
         let cutoff_time = Local::now() - duration;
         let cutoff_str = cutoff_time.format("%Y-%m-%d %H:%M:%S").to_string();
 
-        println!("Will delete snapshots older than {}", cutoff_str);
+        say(format!("Will delete snapshots older than {}", cutoff_str));
 
-        for snapshot in &head_manifest {
+        for snapshot in &candidates {
             // Parse the snapshot timestamp
             if let Ok(snapshot_time) =
                 NaiveDateTime::parse_from_str(&snapshot.timestamp, "%Y-%m-%d %H:%M:%S")
@@ -63,14 +138,57 @@ pub fn prune_snapshots(
         }
     }
 
-    // If neither option is specified, do nothing
-    if keep_last.is_none() && older_than.is_none() {
-        println!("No pruning criteria specified. Use --keep-last or --older-than.");
-        return Ok(());
+    // If before is specified, delete snapshots taken before that date
+    if let Some(ref date) = options.before {
+        let cutoff =
+            parse_date(date).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        say(format!(
+            "Will delete snapshots taken before {}",
+            cutoff.format("%Y-%m-%d %H:%M:%S")
+        ));
+        for snapshot in &candidates {
+            let taken = NaiveDateTime::parse_from_str(&snapshot.timestamp, "%Y-%m-%d %H:%M:%S");
+            if taken.is_ok_and(|taken| taken < cutoff) && !to_delete.contains(snapshot) {
+                to_delete.push(snapshot.clone());
+            }
+        }
+    }
+
+    // If no option is specified, do nothing
+    if keep_last.is_none()
+        && older_than.is_none()
+        && options.before.is_none()
+        && options.keep_last_per_tag.is_none()
+    {
+        say(
+            "No pruning criteria specified. Use --keep-last, --keep-last-per-tag, --older-than, or --before."
+                .to_string(),
+        );
+        return print_json_plan(&base_path, &[], &head_manifest, options);
+    }
+
+    // Incremental snapshots read their unchanged files from the snapshots they build on
+    let needed: HashSet<String> = head_manifest
+        .iter()
+        .filter(|s| !to_delete.contains(s))
+        .flat_map(|s| manifest::incremental_bases(&head_manifest, &s.version))
+        .collect();
+    let before = to_delete.len();
+    to_delete.retain(|s| !needed.contains(&s.version));
+    if to_delete.len() < before {
+        say(format!(
+            "Keeping {} snapshot(s) that incremental snapshots build on.",
+            before - to_delete.len()
+        ));
+    }
+
+    if options.json {
+        return print_json_plan(&base_path, &to_delete, &head_manifest, options);
     }
 
     if to_delete.is_empty() {
         println!("No snapshots to prune based on the specified criteria.");
+        print_kept(&head_manifest, &to_delete, options);
         return Ok(());
     }
 
@@ -88,25 +206,30 @@ pub fn prune_snapshots(
     }
 
     if dry_run {
+        print_kept(&head_manifest, &to_delete, options);
+        let reclaimable = reclaimable_bytes(&base_path, &to_delete, &head_manifest)?;
+        println!(
+            "Estimated space reclaimed: {}",
+            info::format_size(reclaimable.iter().sum())
+        );
         println!("Dry run - no snapshots were deleted.");
         return Ok(());
     }
 
     // Confirm deletion
-    println!("Are you sure you want to delete these snapshots? (y/n)");
-    let mut input = String::new();
-    io::stdin().read_line(&mut input)?;
-    if !input.trim().eq_ignore_ascii_case("y") {
-        println!("Pruning cancelled.");
-        return Ok(());
+    if !options.yes {
+        println!("Are you sure you want to delete these snapshots? (y/n)");
+        let mut input = String::new();
+        io::stdin().read_line(&mut input)?;
+        if !input.trim().eq_ignore_ascii_case("y") {
+            println!("Pruning cancelled.");
+            return Ok(());
+        }
     }
 
     // Delete the snapshots
     for snapshot in &to_delete {
-        let snapshot_dir = base_path
-            .join(REPO_FOLDER)
-            .join(SNAPSHOTS_FOLDER)
-            .join(&snapshot.version);
+        let snapshot_dir = info::get_snapshots_dir(&base_path).join(&snapshot.version);
 
         if snapshot_dir.exists() {
             fs::remove_dir_all(&snapshot_dir)?;
@@ -122,28 +245,200 @@ pub fn prune_snapshots(
     Ok(())
 }
 
-/// Parse a duration string into a chrono::Duration
-/// Supports formats like "7d", "24h", "30m"
-fn parse_duration(duration_str: &str) -> Result<Duration, String> {
-    let mut chars = duration_str.chars();
-    let mut num_str = String::new();
-
-    // Extract the numeric part
-    for c in chars.by_ref() {
-        if c.is_ascii_digit() {
-            num_str.push(c);
-        } else {
-            break;
+/// Returns the tags of a snapshot.
+fn tags_of(snapshot: &SnapshotIndex) -> &[String] {
+    snapshot
+        .metadata
+        .as_ref()
+        .map(|m| m.tags.as_slice())
+        .unwrap_or_default()
+}
+
+/// Returns the tagged snapshots that no tag group keeps, where each group keeps the `keep` most
+/// recent snapshots carrying its tag. A snapshot with several tags survives if any group keeps
+/// it. Expects the snapshots sorted oldest first.
+fn prune_per_tag(snapshots: &[SnapshotIndex], keep: usize) -> Vec<SnapshotIndex> {
+    let mut groups: BTreeMap<&str, Vec<&SnapshotIndex>> = BTreeMap::new();
+    for snapshot in snapshots {
+        for tag in tags_of(snapshot) {
+            groups.entry(tag.as_str()).or_default().push(snapshot);
         }
     }
+    let kept: HashSet<&str> = groups
+        .values()
+        .flat_map(|group| group.iter().rev().take(keep))
+        .map(|s| s.version.as_str())
+        .collect();
 
-    // Extract the unit part
-    let unit: String = chars.collect();
+    snapshots
+        .iter()
+        .filter(|s| !tags_of(s).is_empty() && !kept.contains(s.version.as_str()))
+        .cloned()
+        .collect()
+}
+
+/// Lists the snapshots that survive pruning, oldest first with their ages, for a dry run with
+/// `show_kept` printed as text.
+fn print_kept(
+    head_manifest: &[SnapshotIndex],
+    to_delete: &[SnapshotIndex],
+    options: &PruneOptions,
+) {
+    if !options.dry_run || !options.show_kept || options.json {
+        return;
+    }
+    let kept: Vec<&SnapshotIndex> = head_manifest
+        .iter()
+        .filter(|s| !to_delete.contains(s))
+        .collect();
+    println!("The following {} snapshot(s) will be kept:", kept.len());
+    for snapshot in kept {
+        println!(
+            "  + {} ({}, {})",
+            snapshot.version,
+            snapshot.timestamp,
+            info::format_age(&snapshot.timestamp)
+        );
+    }
+}
+
+/// Prints the dry-run plan as JSON when requested; otherwise does nothing.
+fn print_json_plan(
+    base_path: &Path,
+    to_delete: &[SnapshotIndex],
+    head_manifest: &[SnapshotIndex],
+    options: &PruneOptions,
+) -> io::Result<()> {
+    if !options.json {
+        return Ok(());
+    }
+    let reclaimable = reclaimable_bytes(base_path, to_delete, head_manifest)?;
+    let plan = PrunePlan {
+        total_reclaimable_bytes: reclaimable.iter().sum(),
+        snapshots: to_delete
+            .iter()
+            .zip(reclaimable)
+            .map(|(snapshot, bytes)| PlannedDeletion {
+                version: snapshot.version.clone(),
+                timestamp: snapshot.timestamp.clone(),
+                reclaimable_bytes: bytes,
+            })
+            .collect(),
+        kept: options.show_kept.then(|| {
+            head_manifest
+                .iter()
+                .filter(|s| !to_delete.contains(s))
+                .map(|s| PlannedSurvivor {
+                    version: s.version.clone(),
+                    timestamp: s.timestamp.clone(),
+                })
+                .collect()
+        }),
+    };
+    let json = serde_json::to_string_pretty(&plan).map_err(io::Error::other)?;
+    println!("{}", json);
+    Ok(())
+}
+
+/// Estimates the bytes freed by deleting each snapshot in to_delete. Files hard-linked from a
+/// snapshot that is kept, or already counted for an earlier deletion, free nothing.
+#[cfg(unix)]
+fn reclaimable_bytes(
+    base_path: &Path,
+    to_delete: &[SnapshotIndex],
+    head_manifest: &[SnapshotIndex],
+) -> io::Result<Vec<u64>> {
+    use std::collections::HashSet;
+    use std::os::unix::fs::MetadataExt;
+
+    // Inodes of every file stored by a snapshot, with its size
+    let stored_files = |version: &str| -> io::Result<Vec<((u64, u64), u64)>> {
+        let mut files = Vec::new();
+        if let Some((dir, manifest)) = manifest::load_snapshot_manifest(base_path, version)? {
+            for relative_path in manifest.keys() {
+                if let Ok(meta) = fs::symlink_metadata(dir.join(relative_path)) {
+                    files.push(((meta.dev(), meta.ino()), meta.len()));
+                }
+            }
+        }
+        Ok(files)
+    };
+
+    let mut counted = HashSet::new();
+    for snapshot in head_manifest.iter().filter(|s| !to_delete.contains(s)) {
+        counted.extend(
+            stored_files(&snapshot.version)?
+                .into_iter()
+                .map(|(id, _)| id),
+        );
+    }
+
+    let mut reclaimable = Vec::new();
+    for snapshot in to_delete {
+        let mut bytes = 0;
+        for (id, size) in stored_files(&snapshot.version)? {
+            if counted.insert(id) {
+                bytes += size;
+            }
+        }
+        reclaimable.push(bytes);
+    }
+    Ok(reclaimable)
+}
+
+/// Hard links can't be detected on this platform, so each snapshot's full size is counted.
+#[cfg(not(unix))]
+fn reclaimable_bytes(
+    base_path: &Path,
+    to_delete: &[SnapshotIndex],
+    _head_manifest: &[SnapshotIndex],
+) -> io::Result<Vec<u64>> {
+    let mut reclaimable = Vec::new();
+    for snapshot in to_delete {
+        let bytes = match snapshot.total_size {
+            Some(size) => size,
+            None => manifest::load_snapshot_manifest(base_path, &snapshot.version)?
+                .map(|(_, files)| files.values().map(|m| m.file_size).sum())
+                .unwrap_or(0),
+        };
+        reclaimable.push(bytes);
+    }
+    Ok(reclaimable)
+}
+
+/// Parses the absolute cutoff of `prune --before`: a date ("2024-01-01", meaning midnight) or a
+/// date and time ("2024-01-01 12:00:00" or "2024-01-01T12:00:00").
+pub fn parse_date(date_str: &str) -> Result<NaiveDateTime, String> {
+    let date_str = date_str.trim();
+    ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(date_str, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(date_str, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })
+        .ok_or_else(|| {
+            format!(
+                "Invalid date: {}. Use YYYY-MM-DD or YYYY-MM-DD HH:MM:SS.",
+                date_str
+            )
+        })
+}
+
+/// Parse a duration string into a chrono::Duration
+/// Supports formats like "7d", "24h", "30m"
+pub fn parse_duration(duration_str: &str) -> Result<Duration, String> {
+    // Split into the numeric part and the unit that follows it
+    let unit_start = duration_str
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(duration_str.len());
+    let (num_str, unit) = duration_str.split_at(unit_start);
     let value: i64 = num_str
         .parse()
         .map_err(|_| format!("Invalid duration: {}", duration_str))?;
 
-    match unit.as_str() {
+    match unit {
         "d" | "days" | "day" => Ok(Duration::days(value)),
         "h" | "hours" | "hour" => Ok(Duration::hours(value)),
         "m" | "minutes" | "min" => Ok(Duration::minutes(value)),
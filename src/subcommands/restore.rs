@@ -1,16 +1,105 @@
+use chrono::{DateTime, Local};
+use clap::ValueEnum;
+use flate2::read::GzDecoder;
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::io::{self, ErrorKind};
+use std::io::{self, ErrorKind, Write};
+use std::path::{Component, Path, PathBuf};
 
-use crate::constants::{REPO_FOLDER, SNAPSHOTS_FOLDER};
+use crate::config;
+use crate::constants::{
+    CHECKSUMS_FILE, INCREMENTAL_FILE, MANIFEST_FILE, NOTE_FILE, SNAPSHOT_INDEX_FILE,
+    SNAPSHOT_META_FILE,
+};
+use crate::ignore;
 use crate::info;
 use crate::manifest::{self, load_head_manifest};
+use crate::models::FileMetadata;
+use crate::profile;
 use crate::subcommands::snapshot;
+use crate::watchdog;
+
+/// Optional behaviour switches for `restore_snapshot`.
+#[derive(Debug, Default, Clone)]
+pub struct RestoreOptions {
+    /// Roll back to the backup snapshot without asking if the restore fails partway through.
+    pub rollback_on_error: bool,
+    /// Leave working files alone if they were modified after the snapshot recorded them.
+    pub keep_newer: bool,
+    /// Restore into the directory the snapshot was originally taken from.
+    pub original_location: bool,
+    /// Restore into this directory instead of the working directory.
+    pub target: Option<PathBuf>,
+    /// Delete files in the target that aren't part of the snapshot (ignored files are kept).
+    pub clean: bool,
+    /// Restore the contents of an archive written by `export` instead of a stored snapshot.
+    pub from_archive: Option<PathBuf>,
+    /// Skip the confirmation prompt.
+    pub yes: bool,
+    /// Proceed even if the restore overwrites or deletes more files than the threshold.
+    pub force: bool,
+    /// Overrides the `confirm_overwrite_threshold` config key for this restore.
+    pub confirm_overwrite_threshold: Option<usize>,
+    /// Reapply the extended attributes recorded in the snapshot to the restored files.
+    pub preserve_xattrs: bool,
+    /// List the files the restore would create, overwrite and delete without changing anything.
+    pub dry_run: bool,
+    /// Glob patterns (e.g. `config/local.toml`) for paths the restore leaves alone: they are
+    /// neither written nor deleted by `clean`.
+    pub exclude: Vec<String>,
+    /// What to do with working files that differ from the snapshot's copy.
+    pub on_conflict: ConflictPolicy,
+    /// Only restore the file or directory at this path (e.g. `assets/`); `clean` then only
+    /// deletes files under it.
+    pub path: Option<String>,
+}
+
+/// How `restore` handles a file in the target that differs from the snapshot's copy.
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum ConflictPolicy {
+    /// Replace the file with the snapshot's copy (default)
+    #[default]
+    Overwrite,
+    /// Leave the file as it is
+    Skip,
+    /// Rename the file with a `.bak` suffix, then restore the snapshot's copy
+    Backup,
+    /// Ask for each file whether to overwrite it
+    Prompt,
+}
+
+/// A file written to the working directory during a restore.
+struct RestoredFile {
+    relative_path: String,
+    /// Whether the file existed before it was overwritten by the restore.
+    existed: bool,
+}
+
+/// Changes a restore made to the working directory, which a rollback undoes.
+#[derive(Default)]
+struct RestoreJournal {
+    /// Files written, in order.
+    written: Vec<RestoredFile>,
+    /// Files renamed by `--on-conflict backup`, with the `.bak` path each was renamed to.
+    backed_up: Vec<(String, PathBuf)>,
+}
 
 /// Restores the contents of a snapshot to the working directory.
 /// If no snapshot ID is provided, restores the latest snapshot.
 /// If backup flag is true, creates a snapshot of the current state before restoring.
-pub fn restore_snapshot(snapshot_id: Option<String>, backup: bool) -> io::Result<()> {
+/// If the restore fails partway through, the files written so far are reported and, when a
+/// backup was taken, the working directory can be rolled back to it.
+pub fn restore_snapshot(
+    snapshot_id: Option<String>,
+    backup: bool,
+    options: &RestoreOptions,
+) -> io::Result<()> {
     let base_path = info::get_base_dir()?;
+    let is_excluded = ignore::ignore_matcher(&options.exclude)?;
+    if let Some(ref archive_path) = options.from_archive {
+        let target_path = options.target.clone().unwrap_or_else(|| base_path.clone());
+        return restore_from_archive(archive_path, &target_path, options);
+    }
     let head_manifest = load_head_manifest(&base_path)?;
 
     if head_manifest.is_empty() {
@@ -20,60 +109,35 @@ pub fn restore_snapshot(snapshot_id: Option<String>, backup: bool) -> io::Result
         ));
     }
 
-    // Determine which snapshot to restore (similar to diff.rs approach)
-    let version = match snapshot_id {
-        Some(id) => {
-            // Check if the ID is "latest"
-            if id.to_lowercase() == "latest" {
-                head_manifest.last().unwrap().version.clone()
-            } else {
-                // Try exact match first
-                let exact_match = head_manifest
-                    .iter()
-                    .find(|s| s.version == id)
-                    .map(|s| s.version.clone());
-
-                // If no exact match, try prefix match
-                match exact_match {
-                    Some(v) => v,
-                    None => head_manifest
-                        .iter()
-                        .find(|s| s.version.starts_with(&id))
-                        .map(|s| s.version.clone())
-                        .ok_or_else(|| {
-                            io::Error::new(
-                                ErrorKind::NotFound,
-                                format!("Snapshot {} not found", id),
-                            )
-                        })?,
-                }
-            }
-        }
-        None => {
-            // If no ID provided, use the latest snapshot
-            head_manifest.last().unwrap().version.clone()
-        }
-    };
+    // Determine which snapshot to restore
+    let version = info::resolve_snapshot_id(snapshot_id, &head_manifest)?;
 
-    // If backup flag is set, take a snapshot of the current state
-    if backup {
-        println!("Creating backup snapshot before restoring...");
-        if let Err(e) =
-            snapshot::create_snapshot(Some("Auto-backup before restore".to_string()), None)
-        {
-            return Err(io::Error::new(
-                ErrorKind::Other,
-                format!("Failed to create backup snapshot: {}", e),
-            ));
+    // Get the snapshot info from head manifest for display
+    let snapshot_info = head_manifest.iter().find(|s| s.version == version).unwrap();
+
+    // Restore into the working directory unless the original location was requested
+    let target_path = if options.original_location {
+        let original = snapshot_info.base_dir.as_ref().ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::NotFound,
+                format!(
+                    "Snapshot {} has no recorded original location (it predates location tracking)",
+                    version
+                ),
+            )
+        })?;
+        PathBuf::from(original)
+    } else if let Some(ref target) = options.target {
+        if !options.dry_run {
+            fs::create_dir_all(target)?;
         }
-        println!("Backup snapshot created successfully.");
-    }
+        target.clone()
+    } else {
+        base_path.clone()
+    };
 
     // Get the path to the snapshot directory
-    let snapshot_path = base_path
-        .join(REPO_FOLDER)
-        .join(SNAPSHOTS_FOLDER)
-        .join(&version);
+    let snapshot_path = info::get_snapshots_dir(&base_path).join(&version);
 
     if !snapshot_path.exists() {
         return Err(io::Error::new(
@@ -82,43 +146,852 @@ pub fn restore_snapshot(snapshot_id: Option<String>, backup: bool) -> io::Result
         ));
     }
 
+    // Files an incremental snapshot left out are read from the snapshots it builds on
+    let chain = manifest::snapshot_chain(&base_path, &head_manifest, &version);
+
     // Load the snapshot manifest to get the file list
     let snap_option = manifest::load_snapshot_manifest(&base_path, &version)?;
-    let (_, manifest) = snap_option.ok_or_else(|| {
+    let (_, mut manifest) = snap_option.ok_or_else(|| {
         io::Error::new(
             ErrorKind::NotFound,
             format!("Manifest for snapshot {} not found", version),
         )
     })?;
 
-    // Get the snapshot info from head manifest for display
-    let snapshot_info = head_manifest.iter().find(|s| s.version == version).unwrap();
+    // A tampered manifest must not write outside the target
+    for relative_path in manifest.keys() {
+        check_relative_path(Path::new(relative_path))?;
+    }
+    let subtree = options
+        .path
+        .as_deref()
+        .map(info::normalize_relative_path)
+        .filter(|path| !path.is_empty());
+    let in_subtree = |path: &str| {
+        subtree
+            .as_ref()
+            .is_none_or(|subtree| Path::new(path).starts_with(subtree))
+    };
+    if let Some(ref subtree) = subtree {
+        manifest.retain(|path, _| in_subtree(path));
+        if manifest.is_empty() {
+            return Err(io::Error::new(
+                ErrorKind::NotFound,
+                format!("Snapshot {} has no files under {}", version, subtree),
+            ));
+        }
+    }
+    let before = manifest.len();
+    manifest.retain(|path, _| !is_excluded(path));
+    let excluded = before - manifest.len();
 
     println!("Restoring snapshot: {}", snapshot_info.version);
     println!("Created on: {}", snapshot_info.timestamp);
     if let Some(ref msg) = snapshot_info.message {
         println!("Message: {}", msg);
     }
-    println!("This will overwrite files in your working directory. Press Enter to continue or Ctrl+C to abort...");
+    if target_path != base_path {
+        println!("Restoring into: {:?}", target_path);
+    }
+    if let Some(ref subtree) = subtree {
+        println!("Restoring only {} ({} file(s)).", subtree, manifest.len());
+    }
+    if excluded > 0 {
+        println!("Excluding {} file(s) matching --exclude.", excluded);
+    }
+
+    // Work out which files to restore, keeping working copies edited after the snapshot and
+    // handling the other conflicting files as the --on-conflict policy says
+    let planning = profile::start("planning");
+    let config = config::load_config(&base_path);
+    let mut to_restore = Vec::new();
+    let mut kept_newer = Vec::new();
+    let mut skipped = Vec::new();
+    let mut to_back_up = Vec::new();
+    let mut paths: Vec<&String> = manifest.keys().collect();
+    paths.sort();
+    for relative_path in paths {
+        let meta = &manifest[relative_path];
+        let path = target_path.join(relative_path);
+        if options.keep_newer && modified_after(&path, &meta.modified) {
+            kept_newer.push(relative_path.clone());
+            continue;
+        }
+        if options.on_conflict != ConflictPolicy::Overwrite
+            && conflicts_with_snapshot(
+                &path,
+                &manifest::stored_file_dir(&chain, relative_path).join(relative_path),
+                meta,
+                config.parallel_hash_threshold,
+            )
+        {
+            let overwrite = match options.on_conflict {
+                ConflictPolicy::Overwrite => true,
+                ConflictPolicy::Skip => false,
+                ConflictPolicy::Backup => {
+                    to_back_up.push(relative_path.clone());
+                    true
+                }
+                ConflictPolicy::Prompt => options.dry_run || ask_overwrite(relative_path)?,
+            };
+            if !overwrite {
+                skipped.push(relative_path.clone());
+                continue;
+            }
+        }
+        to_restore.push(relative_path);
+    }
+    let overwritten = to_restore
+        .iter()
+        .filter(|path| {
+            differs_from(
+                &target_path.join(path),
+                &manifest[**path],
+                config.parallel_hash_threshold,
+            )
+        })
+        .count();
+    let to_clean = if options.clean && target_path.exists() {
+        let mut to_clean = files_to_clean(&target_path, &manifest.keys().cloned().collect())?;
+        to_clean.retain(|path| !is_excluded(path) && in_subtree(path));
+        to_clean
+    } else {
+        Vec::new()
+    };
+    drop(planning);
+
+    if options.dry_run {
+        let kept: Vec<String> = kept_newer.iter().chain(&skipped).cloned().collect();
+        print_restore_plan(
+            &target_path,
+            &manifest,
+            &to_restore,
+            &to_clean,
+            &kept,
+            config.parallel_hash_threshold,
+        );
+        return Ok(());
+    }
+
+    confirm_restore(options)?;
+    let threshold = options
+        .confirm_overwrite_threshold
+        .unwrap_or(config.confirm_overwrite_threshold);
+    confirm_large_restore(overwritten, to_clean.len(), threshold, options)?;
+
+    // If backup flag is set, take a snapshot of the current state
+    let mut backup_version = None;
+    if backup && config.disable_backup {
+        println!("Skipping backup snapshot: backups are disabled by the disable_backup setting.");
+    } else if backup && target_path != base_path {
+        println!(
+            "Skipping backup snapshot: the restore target {:?} is not this repository's working directory.",
+            target_path
+        );
+    } else if backup {
+        let _backup = profile::start("backup");
+        println!("Creating backup snapshot before restoring...");
+        backup_version = snapshot::create_snapshot(
+            Some("Auto-backup before restore".to_string()),
+            None,
+            &snapshot::SnapshotOptions::default(),
+        )
+        .map_err(|e| io::Error::other(format!("Failed to create backup snapshot: {}", e)))?
+        .map(|s| s.version);
+        println!("Backup snapshot created successfully.");
+    }
+
+    let mut journal = RestoreJournal::default();
+    if let Err(e) = back_up_conflicts(&target_path, &to_back_up, &mut journal) {
+        // Put back the files already renamed
+        undo_backups(&target_path, &journal)?;
+        return Err(e);
+    }
+
+    watchdog::set_note(match backup_version {
+        Some(ref backup) => format!(
+            "The working directory may be partly restored; run 'snapsafe restore {} --yes' to return to the state before the restore.",
+            backup
+        ),
+        None => "The working directory may be partly restored; run the restore again once the storage responds.".to_string(),
+    });
+
+    // Restore each file from the snapshot to the target directory
+    let copying = profile::start("restoring files");
+    if let Err(e) = restore_files(
+        &target_path,
+        &chain,
+        &manifest,
+        to_restore.into_iter(),
+        config.copy_buffer_size,
+        &mut journal.written,
+    ) {
+        return Err(handle_restore_failure(
+            &base_path,
+            &target_path,
+            e,
+            &journal,
+            backup_version.as_deref(),
+            config.copy_buffer_size,
+            options,
+        ));
+    }
+
+    if options.preserve_xattrs {
+        restore_xattrs(&target_path, &manifest, &journal.written);
+    }
+    restore_times(&target_path, &manifest, &journal.written);
+    drop(copying);
+    profile::time("cleanup", || clean_target(&target_path, &to_clean))?;
+
+    if !kept_newer.is_empty() {
+        kept_newer.sort();
+        println!(
+            "Kept {} file(s) modified after the snapshot was taken:",
+            kept_newer.len()
+        );
+        for path in &kept_newer {
+            println!("  - {}", path);
+        }
+    }
+    if !skipped.is_empty() {
+        println!(
+            "Skipped {} file(s) that differ from the snapshot:",
+            skipped.len()
+        );
+        for path in &skipped {
+            println!("  - {}", path);
+        }
+    }
+
+    println!("Snapshot {} restored successfully.", version);
+    Ok(())
+}
+
+/// Extracts an archive written by `export` into the target directory. The archive's top-level
+/// snapshot folder is stripped and its manifest and metadata are skipped, so the target ends up with the same
+/// files the snapshot recorded.
+fn restore_from_archive(
+    archive_path: &Path,
+    target_path: &Path,
+    options: &RestoreOptions,
+) -> io::Result<()> {
+    let file = fs::File::open(archive_path).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("Failed to open archive {:?}: {}", archive_path, e),
+        )
+    })?;
+    let mut archive = tar::Archive::new(GzDecoder::new(file));
+
+    println!("Restoring archive: {:?}", archive_path);
+    println!("Restoring into: {:?}", target_path);
+    confirm_restore(options)?;
+    fs::create_dir_all(target_path)?;
+    let canonical_target = fs::canonicalize(target_path)?;
+    let repo_folder = info::repo_folder_name();
+
+    let is_excluded = ignore::ignore_matcher(&options.exclude)?;
+    let mut restored = HashSet::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let relative: PathBuf = path.components().skip(1).collect();
+        if relative == Path::new(INCREMENTAL_FILE) {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "{:?} is an incremental archive and only holds the files changed since its base snapshot. Add it to a repository with 'snapsafe import' instead.",
+                    archive_path
+                ),
+            ));
+        }
+        if relative.as_os_str().is_empty()
+            || relative == Path::new(MANIFEST_FILE)
+            || relative == Path::new(SNAPSHOT_META_FILE)
+            || relative == Path::new(NOTE_FILE)
+            || relative == Path::new(SNAPSHOT_INDEX_FILE)
+            || relative == Path::new(CHECKSUMS_FILE)
+        {
+            continue;
+        }
+        check_relative_path(&relative)?;
+        let relative_str = relative.to_string_lossy().to_string();
+        if relative.starts_with(&repo_folder) {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Refusing to restore {:?}: the path is inside the {} folder",
+                    relative_str, repo_folder
+                ),
+            ));
+        }
+        if is_excluded(&relative_str) {
+            continue;
+        }
+
+        // A link unpacked earlier from the archive must not redirect later entries elsewhere
+        let dest = target_path.join(&relative);
+        if entry.header().entry_type().is_dir() {
+            check_within_target(&canonical_target, &dest, &relative_str)?;
+            fs::create_dir_all(&dest)?;
+            continue;
+        }
+        if let Some(parent) = dest.parent() {
+            check_within_target(&canonical_target, parent, &relative_str)?;
+            fs::create_dir_all(parent)?;
+        }
+        // Replace existing files and links instead of writing through them
+        if fs::symlink_metadata(&dest).is_ok_and(|m| !m.is_dir()) {
+            fs::remove_file(&dest)?;
+        }
+        entry.unpack(&dest).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Failed to restore {}: {}", relative.display(), e),
+            )
+        })?;
+        restored.insert(relative_str);
+    }
+
+    if options.clean {
+        let mut to_clean = files_to_clean(target_path, &restored)?;
+        to_clean.retain(|path| !is_excluded(path));
+        clean_target(target_path, &to_clean)?;
+    }
+
+    println!(
+        "Restored {} file(s) from archive {:?}.",
+        restored.len(),
+        archive_path
+    );
+    Ok(())
+}
+
+/// Returns an error if a path from a manifest or archive could resolve outside the directory it
+/// is restored into, e.g. `../../etc/passwd` or an absolute path.
+pub fn check_relative_path(relative_path: &Path) -> io::Result<()> {
+    if relative_path
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)))
+    {
+        return Ok(());
+    }
+    Err(io::Error::new(
+        ErrorKind::InvalidData,
+        format!(
+            "Refusing to restore {:?}: the path escapes the target directory",
+            relative_path
+        ),
+    ))
+}
+
+/// Returns an error if dir, once symbolic links are resolved, isn't inside the (canonical) target
+/// directory. Only the part of dir that already exists is resolved, so this can run before
+/// creating it. Catches entries that would be written through a restored link pointing elsewhere.
+fn check_within_target(canonical_target: &Path, dir: &Path, relative_path: &str) -> io::Result<()> {
+    let existing = dir.ancestors().find(|a| a.exists()).unwrap_or(dir);
+    if fs::canonicalize(existing)?.starts_with(canonical_target) {
+        return Ok(());
+    }
+    Err(io::Error::new(
+        ErrorKind::InvalidData,
+        format!(
+            "Refusing to restore {:?}: the path escapes the target directory",
+            relative_path
+        ),
+    ))
+}
+
+/// Asks the user to confirm before any files are overwritten (or deleted with `--clean`),
+/// unless `--yes` was given.
+fn confirm_restore(options: &RestoreOptions) -> io::Result<()> {
+    if options.yes {
+        return Ok(());
+    }
+    if options.clean {
+        println!("This will overwrite files in your working directory and delete files that aren't in the snapshot. Press Enter to continue or Ctrl+C to abort...");
+    } else {
+        println!("This will overwrite files in your working directory. Press Enter to continue or Ctrl+C to abort...");
+    }
     let mut input = String::new();
     io::stdin().read_line(&mut input)?;
+    Ok(())
+}
 
-    // Restore each file from the snapshot to the working directory
-    for relative_path in manifest.keys() {
-        let target_path = base_path.join(relative_path);
-        let source_path = snapshot_path.join(relative_path);
+/// Guards against restoring the wrong snapshot over a large tree: when more files than the
+/// threshold would be overwritten or deleted, the restore needs `--force`, or the user typing
+/// "yes" when running interactively. `--yes` alone isn't enough.
+fn confirm_large_restore(
+    overwritten: usize,
+    deleted: usize,
+    threshold: usize,
+    options: &RestoreOptions,
+) -> io::Result<()> {
+    let changed = overwritten + deleted;
+    if changed <= threshold || options.force {
+        return Ok(());
+    }
+
+    let summary = format!(
+        "This restore would overwrite {} and delete {} file(s), more than the limit of {}",
+        overwritten, deleted, threshold
+    );
+    if options.yes {
+        return Err(io::Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "{}. Re-run with --force if this is the right snapshot.",
+                summary
+            ),
+        ));
+    }
+
+    println!("{}. Type 'yes' to continue:", summary);
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    if input.trim() != "yes" {
+        return Err(io::Error::new(
+            ErrorKind::Interrupted,
+            "Restore cancelled; no files were changed.",
+        ));
+    }
+    Ok(())
+}
+
+/// Prints what a restore would do to each file, for `--dry-run`: files that would be created,
+/// overwritten or (with `--clean`) deleted. Files that already match the snapshot are only counted.
+fn print_restore_plan(
+    target_path: &Path,
+    manifest: &HashMap<String, FileMetadata>,
+    to_restore: &[&String],
+    to_clean: &[String],
+    kept_newer: &[String],
+    parallel_threshold: u64,
+) {
+    let mut created = Vec::new();
+    let mut overwritten = Vec::new();
+    let mut unchanged = 0;
+    for relative_path in to_restore {
+        let path = target_path.join(relative_path);
+        if fs::symlink_metadata(&path).is_err() {
+            created.push(relative_path.as_str());
+        } else if differs_from(&path, &manifest[*relative_path], parallel_threshold) {
+            overwritten.push(relative_path.as_str());
+        } else {
+            unchanged += 1;
+        }
+    }
+    created.sort();
+    overwritten.sort();
+    let mut kept_newer = kept_newer.to_vec();
+    kept_newer.sort();
+
+    for path in &created {
+        println!("  create     {}", path);
+    }
+    for path in &overwritten {
+        println!("  overwrite  {}", path);
+    }
+    for path in to_clean {
+        println!("  delete     {}", path);
+    }
+    for path in &kept_newer {
+        println!("  keep       {}", path);
+    }
+    println!(
+        "Dry run - would create {}, overwrite {} and delete {} file(s); {} already match the snapshot. No files were changed.",
+        created.len(),
+        overwritten.len(),
+        to_clean.len(),
+        unchanged
+    );
+}
+
+/// Returns true if a file exists at the path and differs from the snapshot's copy, i.e. restoring
+/// would overwrite something the snapshot doesn't have. Files are compared by size and the content
+/// hash the manifest recorded; since restores don't keep modification times, those are only
+/// compared for entries recorded without a hash.
+fn differs_from(path: &Path, meta: &FileMetadata, parallel_threshold: u64) -> bool {
+    match fs::symlink_metadata(path) {
+        Ok(current) if current.len() != meta.file_size => true,
+        Ok(current)
+            if current.is_file() && meta.link_target.is_none() && meta.checksum.is_some() =>
+        {
+            snapshot::file_checksum(path, parallel_threshold).ok() != meta.checksum
+        }
+        Ok(current) => {
+            let modified = current
+                .modified()
+                .map(|time| {
+                    DateTime::<Local>::from(time)
+                        .format("%Y-%m-%d %H:%M:%S")
+                        .to_string()
+                })
+                .unwrap_or_default();
+            current.len() != meta.file_size || modified != meta.modified
+        }
+        Err(_) => false,
+    }
+}
+
+/// Returns the files in the target directory that aren't in `keep`, sorted. The repository
+/// folder and files matched by the target's ignore rules are left out.
+fn files_to_clean(target_path: &Path, keep: &HashSet<String>) -> io::Result<Vec<String>> {
+    let config = config::load_config(target_path);
+    let ignore_list = ignore::build_ignore_list(target_path, config.exclude_vcs)?;
+    let mut current = Vec::new();
+    snapshot::collect_metadata_recursive(
+        target_path,
+        &info::repo_folder_name(),
+        target_path,
+        &ignore_list,
+        &mut current,
+    )?;
+
+    let mut removed: Vec<String> = current
+        .into_iter()
+        .map(|meta| meta.relative_path)
+        .filter(|path| !keep.contains(path))
+        .collect();
+    removed.sort();
+    Ok(removed)
+}
+
+/// Deletes the given files from the target directory, then removes directories left empty.
+fn clean_target(target_path: &Path, removed: &[String]) -> io::Result<()> {
+    if removed.is_empty() {
+        return Ok(());
+    }
+
+    println!("Removed {} file(s) not in the snapshot:", removed.len());
+    for relative_path in removed {
+        let path = target_path.join(relative_path);
+        fs::remove_file(&path)?;
+        println!("  - {}", relative_path);
+
+        // Remove parent directories that are now empty; remove_dir fails on non-empty ones
+        let mut parent = path.parent();
+        while let Some(dir) = parent {
+            if dir == target_path || fs::remove_dir(dir).is_err() {
+                break;
+            }
+            parent = dir.parent();
+        }
+    }
+    Ok(())
+}
+
+/// Returns true if a file exists at the path and its contents differ from the snapshot's copy.
+/// Files whose size and modification time match the manifest are taken to be unchanged;
+/// otherwise same-sized files are compared by hash, so a file whose time changed only because
+/// an earlier restore rewrote it isn't a conflict.
+fn conflicts_with_snapshot(
+    path: &Path,
+    stored: &Path,
+    meta: &FileMetadata,
+    parallel_threshold: u64,
+) -> bool {
+    if !differs_from(path, meta, parallel_threshold) {
+        return false;
+    }
+    let same_size = fs::symlink_metadata(path)
+        .is_ok_and(|current| current.is_file() && current.len() == meta.file_size);
+    if !same_size || meta.link_target.is_some() {
+        return true;
+    }
+    match (
+        snapshot::file_checksum(path, parallel_threshold),
+        snapshot::stored_checksum(stored, meta, parallel_threshold),
+    ) {
+        (Ok(current), Ok(recorded)) => current != recorded,
+        _ => true,
+    }
+}
+
+/// Asks whether to overwrite a working file that differs from the snapshot, for
+/// `--on-conflict prompt`. Anything but "y" or "yes" keeps the file.
+fn ask_overwrite(relative_path: &str) -> io::Result<bool> {
+    print!(
+        "{} differs from the snapshot. Overwrite it? [y/N] ",
+        relative_path
+    );
+    io::stdout().flush()?;
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    Ok(matches!(input.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Renames each of the given files in the target directory to `<name>.bak` (or `<name>.bak.N`
+/// if that is taken) so the restore doesn't destroy them, for `--on-conflict backup`. Each
+/// rename is recorded in the journal, so a rollback can reverse it.
+fn back_up_conflicts(
+    target_path: &Path,
+    relative_paths: &[String],
+    journal: &mut RestoreJournal,
+) -> io::Result<()> {
+    if relative_paths.is_empty() {
+        return Ok(());
+    }
+
+    println!(
+        "Backing up {} file(s) that differ from the snapshot:",
+        relative_paths.len()
+    );
+    for relative_path in relative_paths {
+        let path = target_path.join(relative_path);
+        let mut backup = PathBuf::from(format!("{}.bak", path.display()));
+        let mut n = 1;
+        while fs::symlink_metadata(&backup).is_ok() {
+            backup = PathBuf::from(format!("{}.bak.{}", path.display(), n));
+            n += 1;
+        }
+        fs::rename(&path, &backup)?;
+        journal
+            .backed_up
+            .push((relative_path.clone(), backup.clone()));
+        println!(
+            "  - {} -> {}",
+            relative_path,
+            backup.file_name().unwrap_or_default().to_string_lossy()
+        );
+    }
+    Ok(())
+}
+
+/// Renames the files moved aside by `back_up_conflicts` back to their original names, replacing
+/// whatever the restore wrote there.
+fn undo_backups(target_path: &Path, journal: &RestoreJournal) -> io::Result<()> {
+    for (relative_path, backup) in journal.backed_up.iter().rev() {
+        let path = target_path.join(relative_path);
+        if fs::symlink_metadata(&path).is_ok_and(|m| !m.is_dir()) {
+            fs::remove_file(&path)?;
+        }
+        fs::rename(backup, &path)?;
+    }
+    Ok(())
+}
+
+/// Returns true if the file exists and its modification time is later than the recorded
+/// `modified` timestamp (both compared in the manifest's `%Y-%m-%d %H:%M:%S` format).
+fn modified_after(path: &Path, recorded: &str) -> bool {
+    // Snapshots taken with `--content-only` don't record modification times
+    if recorded.is_empty() {
+        return false;
+    }
+    match fs::metadata(path).and_then(|m| m.modified()) {
+        Ok(time) => {
+            let current = DateTime::<Local>::from(time)
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string();
+            current.as_str() > recorded
+        }
+        Err(_) => false,
+    }
+}
+
+/// Copies the given files from the snapshot's chain of folders (see `snapshot_chain`) into the
+/// target directory, decompressing files stored compressed, and records each file written so a failed restore can be reported
+/// and rolled back.
+fn restore_files<'a>(
+    target_dir: &Path,
+    chain: &[PathBuf],
+    manifest: &HashMap<String, FileMetadata>,
+    relative_paths: impl Iterator<Item = &'a String>,
+    buffer_size: usize,
+    written: &mut Vec<RestoredFile>,
+) -> io::Result<()> {
+    let canonical_target = fs::canonicalize(target_dir)?;
+    for relative_path in relative_paths {
+        let target_path = target_dir.join(relative_path);
+        let source_path = manifest::stored_file_dir(chain, relative_path).join(relative_path);
 
         // Create parent directories if they don't exist
         if let Some(parent) = target_path.parent() {
+            check_within_target(&canonical_target, parent, relative_path)?;
             fs::create_dir_all(parent)?;
         }
 
+        // Recreate stored symbolic links, replacing whatever is at the target path
+        let is_link = fs::symlink_metadata(&source_path)
+            .map(|m| m.file_type().is_symlink())
+            .unwrap_or(false);
+        if is_link {
+            let existed = fs::symlink_metadata(&target_path).is_ok();
+            if existed {
+                fs::remove_file(&target_path)?;
+            }
+            snapshot::copy_symlink(&source_path, &target_path)?;
+            written.push(RestoredFile {
+                relative_path: relative_path.clone(),
+                existed,
+            });
+            continue;
+        }
+
         // Copy the file from the snapshot to the working directory
         if source_path.exists() && source_path.is_file() {
-            fs::copy(&source_path, &target_path)?;
+            snapshot::injected_delay();
+            let existed = target_path.exists();
+            let copied = match manifest.get(relative_path) {
+                Some(meta) => {
+                    snapshot::extract_stored_file(&source_path, meta, &target_path, buffer_size)
+                }
+                None => snapshot::copy_file(&source_path, &target_path, buffer_size).map(|_| ()),
+            };
+            copied.map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("Failed to restore {}: {}", relative_path, e),
+                )
+            })?;
+            written.push(RestoredFile {
+                relative_path: relative_path.clone(),
+                existed,
+            });
         }
     }
-
-    println!("Snapshot {} restored successfully.", version);
     Ok(())
 }
+
+/// Reapplies the recorded extended attributes to the restored files. Attributes that can't be set
+/// (e.g. a `security.*` attribute without privileges) are reported but don't fail the restore.
+fn restore_xattrs(
+    target_dir: &Path,
+    manifest: &HashMap<String, FileMetadata>,
+    written: &[RestoredFile],
+) {
+    for file in written {
+        let xattrs = match manifest.get(&file.relative_path) {
+            Some(FileMetadata {
+                xattrs: Some(xattrs),
+                link_target: None,
+                ..
+            }) => xattrs,
+            _ => continue,
+        };
+        if let Err(e) = snapshot::apply_xattrs(&target_dir.join(&file.relative_path), xattrs) {
+            eprintln!("Warning: {}: {}", file.relative_path, e);
+        }
+    }
+}
+
+/// Reapplies the access and modification times recorded by `snapshot --preserve-times` to the
+/// restored files. Creation and inode change times are set by the operating system and can't be
+/// restored. Times that can't be set are reported but don't fail the restore.
+fn restore_times(
+    target_dir: &Path,
+    manifest: &HashMap<String, FileMetadata>,
+    written: &[RestoredFile],
+) {
+    for file in written {
+        let Some(times) = manifest
+            .get(&file.relative_path)
+            .and_then(|m| m.times.as_ref())
+        else {
+            continue;
+        };
+        let (Some(accessed), Some(modified)) = (
+            snapshot::parse_file_time(&times.accessed),
+            snapshot::parse_file_time(&times.modified),
+        ) else {
+            eprintln!("Warning: {}: invalid recorded times", file.relative_path);
+            continue;
+        };
+        let path = target_dir.join(&file.relative_path);
+        if let Err(e) = filetime::set_symlink_file_times(&path, accessed, modified) {
+            eprintln!("Warning: {}: {}", file.relative_path, e);
+        }
+    }
+}
+
+/// Reports the files written before a restore failed and, if a backup snapshot exists,
+/// rolls the working directory back to it (automatically with `rollback_on_error`,
+/// otherwise after confirmation). Returns the error to surface to the caller.
+fn handle_restore_failure(
+    base_path: &Path,
+    target_dir: &Path,
+    error: io::Error,
+    journal: &RestoreJournal,
+    backup_version: Option<&str>,
+    buffer_size: usize,
+    options: &RestoreOptions,
+) -> io::Error {
+    println!(
+        "Restore failed after writing {} file(s):",
+        journal.written.len()
+    );
+    for file in &journal.written {
+        println!("  - {}", file.relative_path);
+    }
+
+    let backup_version = match backup_version {
+        Some(v) => v,
+        None => {
+            println!("No backup snapshot was created, so these files can't be rolled back.");
+            return error;
+        }
+    };
+
+    let rollback = options.rollback_on_error || {
+        println!(
+            "Roll back the working directory to backup snapshot {}? (y/n)",
+            backup_version
+        );
+        let mut input = String::new();
+        io::stdin().read_line(&mut input).is_ok() && input.trim().eq_ignore_ascii_case("y")
+    };
+
+    if !rollback {
+        println!(
+            "Run 'snapsafe restore {}' to return to the pre-restore state.",
+            backup_version
+        );
+        return error;
+    }
+
+    match rollback_restore(base_path, target_dir, backup_version, buffer_size, journal) {
+        Ok(()) => {
+            println!("Rolled back to backup snapshot {}.", backup_version);
+            io::Error::new(error.kind(), format!("{} (changes rolled back)", error))
+        }
+        Err(rollback_error) => io::Error::new(
+            error.kind(),
+            format!("{} (rollback also failed: {})", error, rollback_error),
+        ),
+    }
+}
+
+/// Undoes a partial restore using the backup snapshot: overwritten files are copied back
+/// from the backup, files that didn't exist before the restore are removed, and files renamed
+/// by `--on-conflict backup` get their names back.
+fn rollback_restore(
+    base_path: &Path,
+    target_dir: &Path,
+    backup_version: &str,
+    buffer_size: usize,
+    journal: &RestoreJournal,
+) -> io::Result<()> {
+    let (backup_path, backup_manifest) =
+        manifest::load_snapshot_manifest(base_path, backup_version)?.ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::NotFound,
+                format!("Manifest for snapshot {} not found", backup_version),
+            )
+        })?;
+
+    for file in &journal.written {
+        let target_path = target_dir.join(&file.relative_path);
+        if let (true, Some(meta)) = (file.existed, backup_manifest.get(&file.relative_path)) {
+            snapshot::extract_stored_file(
+                &backup_path.join(&file.relative_path),
+                meta,
+                &target_path,
+                buffer_size,
+            )?;
+        } else if !file.existed && target_path.is_file() {
+            fs::remove_file(&target_path)?;
+        }
+    }
+    undo_backups(target_dir, journal)
+}
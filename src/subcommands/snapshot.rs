@@ -1,12 +1,140 @@
-use crate::constants::{IGNORE_FILE, MANIFEST_FILE, REPO_FOLDER, SNAPSHOTS_FOLDER};
+use crate::attributes::{self, Attributes};
+use crate::config;
+use crate::constants::{
+    CHECKSUMS_FILE, HASH_CHUNK_SIZE, IGNORE_FILE, NOTE_FILE, RESERVED_FILES, RETRY_BASE_DELAY_MS,
+    RETRY_MAX_DELAY_MS,
+};
+use crate::ignore::{self, build_ignore_list};
 use crate::info;
 use crate::manifest;
-use crate::models::{FileMetadata, SnapshotIndex};
-use chrono::{DateTime, Local};
-use std::collections::HashMap;
+use crate::models::{FileMetadata, FileTimes, SnapshotIndex};
+use crate::profile;
+use crate::subcommands::diff;
+use crate::watchdog;
+use chrono::{DateTime, Local, NaiveDateTime, SecondsFormat, Utc};
+use clap::ValueEnum;
+use filetime::FileTime;
+use flate2::read::GzDecoder;
+use flate2::Compression;
+use flate2::GzBuilder;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use std::io::{self, BufRead};
+use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+/// How files are compared with the baseline snapshot to decide whether they can be hard-linked.
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum CompareBy {
+    /// Size and modification time, or the SHA-256 content hash recorded in the baseline when only
+    /// the modification time changed (default)
+    #[default]
+    Mtime,
+    /// Size and BLAKE3 hash of the contents
+    Hash,
+}
+
+/// Optional behaviour switches for `create_snapshot`.
+#[derive(Debug, Default, Clone)]
+pub struct SnapshotOptions {
+    /// Only create the snapshot when the working directory differs from the latest snapshot.
+    pub if_changed: bool,
+    /// Exclude version control metadata directories in addition to the ignore list.
+    pub exclude_vcs: bool,
+    /// Snapshot every file, bypassing `.snapsafeignore` and the VCS exclusions.
+    /// The repository folder itself is always skipped.
+    pub no_ignore: bool,
+    /// Hard-link unchanged files against this snapshot instead of the latest one.
+    pub from: Option<String>,
+    /// How files are compared with the baseline snapshot.
+    pub compare_by: CompareBy,
+    /// Human-friendly name for the snapshot, which can be used in place of its version.
+    pub label: Option<String>,
+    /// Fail instead of warning when paths differ only by case.
+    pub strict_case: bool,
+    /// Retry a file copy or link this many times when it fails with a transient error.
+    pub retries: u32,
+    /// Report each retry on stderr.
+    pub verbose: bool,
+    /// Store hard-linked files in the working tree as independent copies instead of keeping
+    /// them linked to each other in the snapshot.
+    pub dereference_hardlinks: bool,
+    /// Leave zero-byte files out of the snapshot.
+    pub exclude_empty_files: bool,
+    /// Overrides the `outlier_factor` config key: warn about files this many times larger than
+    /// the typical file of the baseline snapshot.
+    pub outlier_factor: Option<f64>,
+    /// Leave the files flagged as size outliers out of the snapshot instead of only warning.
+    pub block_outliers: bool,
+    /// Overrides the `compress_threshold` config key: store files larger than this many bytes
+    /// gzip-compressed.
+    pub compress_threshold: Option<u64>,
+    /// Record each file's precise access, modification, and creation times in the manifest.
+    pub preserve_times: bool,
+    /// Use the given version verbatim (sanitized) as the version and folder name instead of
+    /// normalizing it to `vX.Y.Z.B`.
+    pub label_as_version: bool,
+    /// File (e.g. release notes) whose contents are stored with the snapshot as its note.
+    pub note_file: Option<PathBuf>,
+    /// Identify files by size and content hash only: every file is hashed and modification
+    /// times are left out of the manifest, so identical content gives an identical manifest.
+    pub content_only: bool,
+    /// Leave files unchanged since the baseline snapshot out of the new snapshot's folder
+    /// instead of hard-linking them; they are read from the baseline when needed.
+    pub incremental: bool,
+    /// Write a `SHA256SUMS` file listing the SHA-256 hash of each stored file into the
+    /// snapshot folder, for checking it with `sha256sum -c`.
+    pub checksum_manifest: bool,
+    /// Don't print progress messages; warnings and errors still go to stderr.
+    pub quiet: bool,
+}
+
+/// Settings shared by the workers that store files in a new snapshot.
+struct CopyContext<'a> {
+    /// The snapshot unchanged files are hard-linked against.
+    snapshot: Option<&'a (PathBuf, HashMap<String, FileMetadata>)>,
+    /// Folders the baseline snapshot's files are stored in, which include its incremental bases.
+    baseline_chain: Vec<PathBuf>,
+    /// Leave unchanged files out instead of hard-linking them.
+    incremental: bool,
+    /// How files are compared with that snapshot.
+    compare_by: CompareBy,
+    /// Files of at least this many bytes are hashed on multiple threads.
+    parallel_hash_threshold: u64,
+    /// Number of files stored at the same time.
+    jobs: usize,
+    /// Retries for a copy or link that fails with a transient error.
+    retries: u32,
+    /// Report each retry on stderr.
+    verbose: bool,
+    /// Don't print progress messages.
+    quiet: bool,
+    /// Store hard-linked working files as independent copies.
+    dereference_hardlinks: bool,
+    /// Leave zero-byte files out of the snapshot.
+    exclude_empty_files: bool,
+    /// Only hard-link a file whose content provably matches the baseline's copy.
+    concurrency_safe: bool,
+    /// Size of the buffer files are copied through.
+    buffer_size: usize,
+    /// Files larger than this multiple of the baseline's typical file size are flagged.
+    outlier_factor: Option<f64>,
+    /// Percentile of the baseline's file sizes that counts as the typical file size.
+    outlier_percentile: f64,
+    /// Leave flagged files out of the snapshot.
+    block_outliers: bool,
+    /// Files larger than this many bytes are stored compressed.
+    compress_threshold: Option<u64>,
+    /// Record precise file times.
+    preserve_times: bool,
+    /// Leave modification times out of the manifest.
+    content_only: bool,
+    /// Per-path attributes from `.snapsafeattributes`.
+    attributes: Attributes,
+}
 
 /// Creates a new snapshot using the current directory as the base.
 /// The new snapshot folder name is determined by the versioning scheme (using an optional tag
@@ -14,12 +142,28 @@ use std::path::{Path, PathBuf};
 /// if a file is unchanged compared to the previous snapshot (by size and modification time),
 /// a hard link is created instead of copying. Detailed file metadata is collected and written
 /// to a manifest file in the snapshot folder. The head manifest is updated with the new snapshot entry.
-pub fn create_snapshot(message: Option<String>, version: Option<String>) -> io::Result<()> {
+///
+/// Returns the head manifest entry of the new snapshot, or `Ok(None)` when `options.if_changed`
+/// is set and nothing changed, in which case no snapshot is created.
+pub fn create_snapshot(
+    message: Option<String>,
+    version: Option<String>,
+    options: &SnapshotOptions,
+) -> io::Result<Option<SnapshotIndex>> {
     let base_path = info::get_base_dir()?;
-    let ignore_list = read_ignore_list(&base_path)?;
+    let config = config::load_config(&base_path);
+    let mut ignore_list = Vec::new();
+    if options.no_ignore {
+        if !options.quiet {
+            println!("Ignore rules disabled: all files will be included in the snapshot.");
+        }
+    } else {
+        ignore_list = build_ignore_list(&base_path, options.exclude_vcs || config.exclude_vcs)?;
+    }
 
-    let repo_path = base_path.join(REPO_FOLDER);
-    let snapshots_path = repo_path.join(SNAPSHOTS_FOLDER);
+    let repo_folder = info::repo_folder_name();
+    let repo_path = info::get_repo_dir(&base_path);
+    let snapshots_path = info::get_snapshots_dir(&base_path);
 
     if !repo_path.exists() {
         return Err(io::Error::new(
@@ -27,52 +171,245 @@ pub fn create_snapshot(message: Option<String>, version: Option<String>) -> io::
             "Repository not initialized. Please run the init command first.",
         ));
     }
+    if let Some(name) = RESERVED_FILES.iter().find(|name| {
+        fs::symlink_metadata(base_path.join(name)).is_ok()
+            && !ignore::is_ignored(name, &ignore_list)
+    }) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "The working directory has a file named {}, which snapsafe reserves for snapshot metadata. Rename it or add it to {}. No snapshot was created.",
+                name, IGNORE_FILE
+            ),
+        ));
+    }
+
+    // Read the note up front so a missing file fails before anything is stored
+    let note = match options.note_file {
+        Some(ref path) => Some(fs::read(path).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Failed to read note file {:?}: {}", path, e),
+            )
+        })?),
+        None => None,
+    };
 
     // Load head manifest.
-    let mut head_manifest = manifest::load_head_manifest(&base_path)?;
-    // Determine new version string.
-    let new_version = info::get_next_version(&head_manifest, version.clone());
+    let head_manifest = manifest::load_head_manifest(&base_path)?;
+
+    // Load previous snapshot manifest (if any) using the head manifest.
+    let prev_snapshot = manifest::load_last_snapshot_manifest(&base_path, &head_manifest)?;
+
+    if options.if_changed {
+        if let Some((_, ref prev_manifest)) = prev_snapshot {
+            let mut current = Vec::new();
+            collect_metadata_recursive(
+                &base_path,
+                &repo_folder,
+                &base_path,
+                &ignore_list,
+                &mut current,
+            )?;
+            if options.exclude_empty_files {
+                current.retain(|meta| meta.file_size > 0 || meta.link_target.is_some());
+            }
+            hash_content_only_matches(&base_path, &mut current, prev_manifest, &config);
+            if !has_changes(&current, prev_manifest) {
+                return Ok(None);
+            }
+        }
+    }
 
-    // New snapshot folder is named by the version.
-    let snapshot_dir = snapshots_path.join(&new_version);
-    if snapshot_dir.exists() {
-        // If a specific version was provided, return an error
-        if version.is_some() {
+    // The snapshot files are hard-linked against becomes the new snapshot's parent.
+    let mut parent = prev_snapshot
+        .as_ref()
+        .and(head_manifest.last())
+        .map(|s| s.version.clone());
+
+    // Load the baseline for hard-linking when it isn't the latest snapshot.
+    let from_snapshot = match options.from {
+        Some(ref id) => {
+            let from_version = info::resolve_snapshot_id(Some(id.clone()), &head_manifest)?;
+            let snapshot = manifest::load_snapshot_manifest(&base_path, &from_version)?
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("Manifest for snapshot {} not found", from_version),
+                    )
+                })?;
+            if !options.quiet {
+                println!(
+                    "Hard-linking unchanged files against snapshot {}.",
+                    from_version
+                );
+            }
+            parent = Some(from_version);
+            Some(snapshot)
+        }
+        None => None,
+    };
+    let incremental = options.incremental && parent.is_some();
+    if options.incremental && !incremental && !options.quiet {
+        println!("No earlier snapshot to build on; storing every file.");
+    }
+
+    // Hashing large files uses the shared thread pool, sized by the jobs setting
+    let _ = rayon_core::ThreadPoolBuilder::new()
+        .num_threads(config.jobs)
+        .build_global();
+    let context = CopyContext {
+        snapshot: from_snapshot.as_ref().or(prev_snapshot.as_ref()),
+        baseline_chain: parent
+            .as_deref()
+            .map(|version| manifest::snapshot_chain(&base_path, &head_manifest, version))
+            .unwrap_or_default(),
+        incremental,
+        compare_by: if options.content_only {
+            CompareBy::Hash
+        } else {
+            options.compare_by
+        },
+        parallel_hash_threshold: if config.jobs > 1 {
+            config.parallel_hash_threshold
+        } else {
+            u64::MAX
+        },
+        jobs: config.jobs,
+        retries: options.retries,
+        verbose: options.verbose,
+        quiet: options.quiet,
+        dereference_hardlinks: options.dereference_hardlinks,
+        exclude_empty_files: options.exclude_empty_files,
+        concurrency_safe: config.snapshot_concurrency_safe,
+        buffer_size: config.copy_buffer_size,
+        outlier_factor: options
+            .outlier_factor
+            .or(config.outlier_factor)
+            .filter(|factor| *factor > 0.0),
+        outlier_percentile: config.outlier_percentile,
+        block_outliers: options.block_outliers,
+        compress_threshold: options.compress_threshold.or(config.compress_threshold),
+        attributes: Attributes::load(&base_path)?,
+        preserve_times: options.preserve_times,
+        content_only: options.content_only,
+    };
+
+    if let Some(ref label) = options.label {
+        if let Some(existing) = head_manifest
+            .iter()
+            .find(|s| s.label.as_deref() == Some(label.as_str()))
+        {
             return Err(io::Error::new(
                 io::ErrorKind::AlreadyExists,
                 format!(
-                    "A snapshot with version {} already exists. Please choose a different version.",
-                    new_version
+                    "Snapshot {} is already labelled '{}'. Labels must be unique.",
+                    existing.version, label
                 ),
             ));
         }
     }
-    fs::create_dir(&snapshot_dir)?;
 
-    if let Some(ref msg) = message {
+    // Determine new version string.
+    let mut new_version = match version {
+        Some(ref name) if options.label_as_version => {
+            let sanitized = info::sanitize_version(name)?;
+            if sanitized != *name && !options.quiet {
+                println!("Using version {} (sanitized from '{}')", sanitized, name);
+            }
+            sanitized
+        }
+        _ => info::get_next_version(&head_manifest, version.clone()),
+    };
+
+    // New snapshot folder is named by the version. Creating it claims the version, so a
+    // concurrent snapshot that computed the same version moves on to the next free one.
+    let snapshot_dir = loop {
+        let snapshot_dir = snapshots_path.join(&new_version);
+        match fs::create_dir(&snapshot_dir) {
+            Ok(()) => break snapshot_dir,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                // If a specific version was provided, return an error
+                let next = info::next_build_version(&new_version).filter(|_| version.is_none());
+                match next {
+                    Some(next) => new_version = next,
+                    None => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::AlreadyExists,
+                            format!(
+                                "A snapshot with version {} already exists. Please choose a different version.",
+                                new_version
+                            ),
+                        ))
+                    }
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    };
+    watchdog::track_partial(&snapshot_dir);
+
+    if let (Some(ref msg), false) = (&message, options.quiet) {
         println!("Snapshot message: {}", msg);
     }
 
-    // Load previous snapshot manifest (if any) using the head manifest.
-    let prev_snapshot = manifest::load_last_snapshot_manifest(&base_path, &head_manifest)?;
-
     // Prepare vector to collect detailed file metadata.
     let mut metadata_vec: Vec<FileMetadata> = Vec::new();
-    copy_or_link_recursive_with_metadata(
+    if let Err(e) = copy_or_link_with_metadata(
         &base_path,
         &snapshot_dir,
-        REPO_FOLDER,
+        &repo_folder,
         &base_path,
         &ignore_list,
-        &prev_snapshot,
+        &context,
         &mut metadata_vec,
-    )?;
+    ) {
+        // Don't leave a partial snapshot behind to block the next attempt
+        let _ = fs::remove_dir_all(&snapshot_dir);
+        return Err(e);
+    }
+
+    // Paths differing only by case can't coexist on case-insensitive filesystems.
+    let collisions = find_case_collisions(&metadata_vec);
+    if !collisions.is_empty() {
+        eprintln!(
+            "Warning: {} group(s) of paths differ only by case and would collide on case-insensitive filesystems:",
+            collisions.len()
+        );
+        for group in &collisions {
+            eprintln!("  - {}", group.join(", "));
+        }
+        if options.strict_case {
+            fs::remove_dir_all(&snapshot_dir)?;
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Paths differ only by case (--strict-case). No snapshot was created.",
+            ));
+        }
+    }
+
+    if options.checksum_manifest {
+        if metadata_vec
+            .iter()
+            .any(|m| m.relative_path == CHECKSUMS_FILE)
+        {
+            eprintln!(
+                "Warning: not writing {}: the snapshot has its own file with that name.",
+                CHECKSUMS_FILE
+            );
+        } else {
+            profile::time("checksum manifest", || {
+                write_checksum_manifest(&snapshot_dir, &metadata_vec, context.buffer_size)
+            })?;
+        }
+    }
 
     // Write the detailed manifest into the snapshot folder.
-    let manifest_path = snapshot_dir.join(MANIFEST_FILE);
-    let manifest_json = serde_json::to_string_pretty(&metadata_vec)
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-    fs::write(&manifest_path, manifest_json)?;
+    let manifest_write = profile::start("manifest write");
+    let manifest_checksum = manifest::save_snapshot_manifest(&snapshot_dir, &metadata_vec)?;
+    if let Some(ref note) = note {
+        fs::write(snapshot_dir.join(NOTE_FILE), note)?;
+    }
 
     // Create a new snapshot index entry.
     let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
@@ -81,48 +418,496 @@ pub fn create_snapshot(message: Option<String>, version: Option<String>) -> io::
         timestamp,
         message,
         metadata: None,
+        total_size: Some(metadata_vec.iter().map(|m| m.file_size).sum()),
+        base_dir: Some(base_path.to_string_lossy().to_string()),
+        label: options.label.clone(),
+        parent,
+        incremental,
+        manifest_checksum: Some(manifest_checksum),
+        amended_at: None,
     };
 
-    // Update the head manifest.
-    head_manifest.push(new_snapshot_index);
-    manifest::save_head_manifest(&base_path, &head_manifest)?;
+    // Update the head manifest, re-reading it in case a concurrent snapshot was recorded
+    // while this one was being stored.
+    watchdog::commit(&snapshot_dir, || {
+        let mut head_manifest = manifest::load_head_manifest(&base_path)?;
+        head_manifest.push(new_snapshot_index.clone());
+        manifest::save_head_manifest(&base_path, &head_manifest)
+    })?;
+    drop(manifest_write);
 
-    println!("Snapshot created successfully.");
-    Ok(())
+    if !options.quiet {
+        println!("Snapshot created successfully.");
+    }
+    Ok(Some(new_snapshot_index))
 }
 
-/// Reads the ignore list from the .snapsafeignore file in the base directory.
-/// Each non-empty, non-comment line is treated as a literal file or directory name to ignore.
-fn read_ignore_list(base: &Path) -> io::Result<Vec<String>> {
-    let ignore_path = base.join(IGNORE_FILE);
-    let mut ignore_list = Vec::new();
+/// Writes `SHA256SUMS` into the snapshot folder: one `<hash>  <path>` line per file stored in
+/// the folder, hashing the bytes on disk (so files stored compressed are listed with the hash of
+/// their compressed form). Symbolic links, and files an incremental snapshot left out, aren't
+/// listed.
+fn write_checksum_manifest(
+    snapshot_dir: &Path,
+    metadata_vec: &[FileMetadata],
+    buffer_size: usize,
+) -> io::Result<()> {
+    let mut paths: Vec<&str> = metadata_vec
+        .iter()
+        .filter(|meta| meta.link_target.is_none())
+        .map(|meta| meta.relative_path.as_str())
+        .collect();
+    paths.sort();
 
-    if ignore_path.exists() {
-        let file = fs::File::open(ignore_path)?;
-        let reader = io::BufReader::new(file);
-        for line_result in reader.lines() {
-            let line = line_result?;
-            let trimmed = line.trim();
-            if !trimmed.is_empty() && !trimmed.starts_with('#') {
-                ignore_list.push(trimmed.to_string());
-            }
+    let mut lines = String::new();
+    for path in paths {
+        let hash = match fs::File::open(snapshot_dir.join(path)) {
+            Ok(file) => reader_sha256(file, buffer_size)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        // sha256sum marks lines whose path has escaped backslashes or newlines with a backslash
+        let name = path.replace('\\', "/");
+        if name.contains('\n') {
+            lines.push_str(&format!("\\{}  {}\n", hash, name.replace('\n', "\\n")));
+        } else {
+            lines.push_str(&format!("{}  {}\n", hash, name));
         }
     }
-    Ok(ignore_list)
+    fs::write(snapshot_dir.join(CHECKSUMS_FILE), lines)
 }
 
-/// Recursively processes files and directories from src to dst, skipping entries that match skip_dir
-/// or appear in ignore_list. For each file, if a previous snapshot exists and the file is unchanged
-/// (based on size and modification time), an attempt is made to create a hard link from the previous
-/// snapshot's file; otherwise, the file is copied. Collected file metadata is appended to the metadata vector.
-fn copy_or_link_recursive_with_metadata(
+/// Returns the most recently modified file that a snapshot with these options would include,
+/// with its age in seconds, if it was modified less than `idle_secs` seconds ago. Used by
+/// `snapshot --only-if-idle` to avoid capturing a directory that is still being written.
+pub fn find_recent_change(
+    idle_secs: u64,
+    options: &SnapshotOptions,
+) -> io::Result<Option<(String, i64)>> {
+    let base_path = info::get_base_dir()?;
+    let ignore_list = if options.no_ignore {
+        Vec::new()
+    } else {
+        let exclude_vcs = options.exclude_vcs || config::load_config(&base_path).exclude_vcs;
+        build_ignore_list(&base_path, exclude_vcs)?
+    };
+    let mut current = Vec::new();
+    collect_metadata_recursive(
+        &base_path,
+        &info::repo_folder_name(),
+        &base_path,
+        &ignore_list,
+        &mut current,
+    )?;
+
+    let now = Local::now().naive_local();
+    let newest = current
+        .into_iter()
+        .filter_map(|meta| {
+            let modified =
+                NaiveDateTime::parse_from_str(&meta.modified, "%Y-%m-%d %H:%M:%S").ok()?;
+            Some((meta.relative_path, (now - modified).num_seconds()))
+        })
+        .min_by_key(|(_, age)| *age);
+    Ok(newest.filter(|(_, age)| *age < idle_secs as i64))
+}
+
+/// Groups the manifest paths that are identical apart from letter case, e.g. `ReadMe.txt` and
+/// `readme.txt`. Each group is sorted, and groups are returned in path order.
+pub fn find_case_collisions(metadata: &[FileMetadata]) -> Vec<Vec<String>> {
+    let mut by_lowercase: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for meta in metadata {
+        by_lowercase
+            .entry(meta.relative_path.to_lowercase())
+            .or_default()
+            .push(meta.relative_path.clone());
+    }
+    by_lowercase
+        .into_values()
+        .filter(|paths| paths.len() > 1)
+        .map(|mut paths| {
+            paths.sort();
+            paths
+        })
+        .collect()
+}
+
+/// Returns true if the collected working-directory metadata differs from the given manifest,
+/// i.e. a file was added, removed, or changed in size, modification time or permissions.
+fn has_changes(current: &[FileMetadata], prev_manifest: &HashMap<String, FileMetadata>) -> bool {
+    if current.len() != prev_manifest.len() {
+        return true;
+    }
+    current
+        .iter()
+        .any(|meta| match prev_manifest.get(&meta.relative_path) {
+            Some(prev) => diff::is_modified(prev, meta, &diff::DiffOptions::default()),
+            None => true,
+        })
+}
+
+/// Hashes the working files whose baseline entry has no modification time (`--content-only`)
+/// and the same size, since they can only be compared by content. Files that can't be read keep
+/// no hash and count as changed.
+fn hash_content_only_matches(
+    base_path: &Path,
+    current: &mut [FileMetadata],
+    prev_manifest: &HashMap<String, FileMetadata>,
+    config: &config::Config,
+) {
+    for meta in current.iter_mut().filter(|meta| meta.link_target.is_none()) {
+        let needs_hash = prev_manifest
+            .get(&meta.relative_path)
+            .is_some_and(|prev| prev.modified.is_empty() && prev.file_size == meta.file_size);
+        if needs_hash {
+            meta.checksum = file_checksum(
+                &base_path.join(&meta.relative_path),
+                config.parallel_hash_threshold,
+            )
+            .ok();
+        }
+    }
+}
+
+/// Stores the files under src in the snapshot folder dst, skipping entries that match skip_dir
+/// or appear in ignore_list. The directory tree is created first; the files are then stored by
+/// up to `context.jobs` worker threads (see `store_entry`). Collected file metadata is appended
+/// to the metadata vector in the order the files were found.
+fn copy_or_link_with_metadata(
     src: &Path,
     dst: &Path,
     skip_dir: &str,
     base: &Path,
     ignore_list: &Vec<String>,
-    prev_snapshot: &Option<(PathBuf, HashMap<String, FileMetadata>)>,
+    context: &CopyContext,
     metadata: &mut Vec<FileMetadata>,
+) -> io::Result<()> {
+    let traversal = profile::start("traversal");
+    let mut entries = Vec::new();
+    create_tree(src, dst, skip_dir, ignore_list, &mut entries)?;
+    if context.exclude_empty_files {
+        let before = entries.len();
+        entries.retain(|(path, _)| !is_empty_file(path));
+        if entries.len() < before && !context.quiet {
+            println!("Skipped {} empty file(s).", before - entries.len());
+        }
+    }
+    check_size_outliers(&mut entries, base, context)?;
+    // Read the times before any file is opened, since copying or hashing updates access times
+    let times: Vec<Option<FileTimes>> = entries
+        .iter()
+        .map(|(path, _)| context.preserve_times.then(|| read_times(path)).flatten())
+        .collect();
+
+    // Files sharing an inode with an earlier entry are linked to its copy once that is stored
+    let mut link_of = if context.dereference_hardlinks {
+        vec![None; entries.len()]
+    } else {
+        find_source_hard_links(&entries)?
+    };
+    // Files marked no-dedup get their own copy, and no other file is linked to them
+    let own_copy: Vec<bool> = entries
+        .iter()
+        .map(|(path, _)| {
+            let relative_path = path.strip_prefix(base).unwrap_or(path);
+            context
+                .attributes
+                .is_set(&relative_path.to_string_lossy(), attributes::NO_DEDUP)
+        })
+        .collect();
+    for index in 0..link_of.len() {
+        if own_copy[index] || link_of[index].is_some_and(|first| own_copy[first]) {
+            link_of[index] = None;
+        }
+    }
+    drop(traversal);
+    // Identical files are stored once too, unless every file should get its own copy
+    if !context.dereference_hardlinks {
+        let duplicates = find_duplicate_files(&entries, &mut link_of, &own_copy, context)?;
+        if duplicates > 0 && !context.quiet {
+            println!(
+                "Storing {} duplicate file(s) as links to identical files.",
+                duplicates
+            );
+        }
+    }
+    let _copying = profile::start("copying");
+    let primary: Vec<usize> = (0..entries.len())
+        .filter(|&index| link_of[index].is_none())
+        .collect();
+
+    let mut stored: Vec<Option<FileMetadata>> = vec![None; entries.len()];
+    let jobs = context.jobs.clamp(1, primary.len().max(1));
+    if jobs == 1 {
+        for &index in &primary {
+            let (path, dest_path) = &entries[index];
+            stored[index] = Some(store_entry(path, dest_path, base, context)?);
+        }
+    } else {
+        if !context.quiet {
+            println!("Using {} parallel jobs.", jobs);
+        }
+        // Workers take the next unclaimed entry until none are left
+        let next = AtomicUsize::new(0);
+        let mut results: Vec<(usize, io::Result<FileMetadata>)> = thread::scope(|scope| {
+            let workers: Vec<_> = (0..jobs)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut stored = Vec::new();
+                        while let Some(&index) = primary.get(next.fetch_add(1, Ordering::Relaxed)) {
+                            let (path, dest_path) = &entries[index];
+                            stored.push((index, store_entry(path, dest_path, base, context)));
+                        }
+                        stored
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().expect("snapshot worker thread panicked"))
+                .collect()
+        });
+        results.sort_by_key(|(index, _)| *index);
+        for (index, result) in results {
+            stored[index] = Some(result?);
+        }
+    }
+
+    for (index, first) in link_of.iter().enumerate() {
+        if let Some(first) = *first {
+            let (path, dest_path) = &entries[index];
+            let first_meta = stored[first].clone();
+            stored[index] = Some(store_source_hard_link(
+                path,
+                dest_path,
+                &entries[first].1,
+                first_meta.as_ref(),
+                base,
+                context,
+            )?);
+        }
+    }
+    for (meta, times) in stored.iter_mut().zip(times) {
+        if let Some(meta) = meta {
+            meta.times = times;
+            if context.content_only {
+                meta.modified.clear();
+            }
+        }
+    }
+    metadata.extend(stored.into_iter().flatten());
+    Ok(())
+}
+
+/// Reads the access, modification, and creation times of a file (not following symbolic links).
+fn read_times(path: &Path) -> Option<FileTimes> {
+    let meta = fs::symlink_metadata(path).ok()?;
+    Some(FileTimes {
+        accessed: format_file_time(FileTime::from_last_access_time(&meta)),
+        modified: format_file_time(FileTime::from_last_modification_time(&meta)),
+        created: FileTime::from_creation_time(&meta).map(format_file_time),
+    })
+}
+
+/// Formats a file time as an RFC 3339 string with nanoseconds.
+fn format_file_time(time: FileTime) -> String {
+    DateTime::<Utc>::from_timestamp(time.unix_seconds(), time.nanoseconds())
+        .unwrap_or_default()
+        .to_rfc3339_opts(SecondsFormat::Nanos, true)
+}
+
+/// Parses a time written by `format_file_time`.
+pub fn parse_file_time(value: &str) -> Option<FileTime> {
+    let time = DateTime::parse_from_rfc3339(value).ok()?;
+    Some(FileTime::from_unix_time(
+        time.timestamp(),
+        time.timestamp_subsec_nanos(),
+    ))
+}
+
+/// Warns about files far larger than is typical for the baseline snapshot, e.g. a build artifact
+/// accidentally left in a data directory. The typical size is the configured percentile (the
+/// median by default) of the baseline's file sizes; files larger than `outlier_factor` times that
+/// are flagged, unless the baseline already had them at that size. With `block_outliers`, the
+/// flagged files are left out of the snapshot.
+fn check_size_outliers(
+    entries: &mut Vec<(PathBuf, PathBuf)>,
+    base: &Path,
+    context: &CopyContext,
+) -> io::Result<()> {
+    let (Some(factor), Some((_, baseline))) = (context.outlier_factor, context.snapshot) else {
+        return Ok(());
+    };
+    let mut sizes: Vec<u64> = baseline
+        .values()
+        .filter(|meta| meta.link_target.is_none())
+        .map(|meta| meta.file_size)
+        .collect();
+    if sizes.is_empty() {
+        return Ok(());
+    }
+    sizes.sort_unstable();
+    let rank = (sizes.len() - 1) as f64 * context.outlier_percentile / 100.0;
+    let typical = sizes[rank.round() as usize].max(1);
+    let limit = (typical as f64 * factor) as u64;
+
+    let mut outliers = Vec::new();
+    for (index, (path, _)) in entries.iter().enumerate() {
+        let meta = fs::symlink_metadata(path)?;
+        if !meta.is_file() || meta.len() <= limit {
+            continue;
+        }
+        let relative_path = path.strip_prefix(base).unwrap_or(path).to_string_lossy();
+        if baseline
+            .get(relative_path.as_ref())
+            .is_some_and(|prev| prev.file_size > limit)
+        {
+            continue;
+        }
+        eprintln!(
+            "Warning: {} is {}, {:.0}x the typical file size of {} in the previous snapshot.",
+            relative_path,
+            info::format_size(meta.len()),
+            meta.len() as f64 / typical as f64,
+            info::format_size(typical)
+        );
+        outliers.push(index);
+    }
+
+    if context.block_outliers && !outliers.is_empty() {
+        let mut index = 0;
+        entries.retain(|_| {
+            index += 1;
+            !outliers.contains(&(index - 1))
+        });
+        if !context.quiet {
+            println!(
+                "Skipped {} unusually large file(s) (--block-outliers).",
+                outliers.len()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// For each entry, returns the index of an earlier entry that is the same file in the working
+/// tree (a hard link sharing its inode), or None if the entry is the first of its inode.
+#[cfg(unix)]
+fn find_source_hard_links(entries: &[(PathBuf, PathBuf)]) -> io::Result<Vec<Option<usize>>> {
+    use std::collections::hash_map::Entry;
+    use std::os::unix::fs::MetadataExt;
+
+    let mut first_by_inode = HashMap::new();
+    let mut link_of = Vec::with_capacity(entries.len());
+    for (index, (path, _)) in entries.iter().enumerate() {
+        let meta = fs::symlink_metadata(path)?;
+        if !meta.is_file() || meta.nlink() < 2 {
+            link_of.push(None);
+            continue;
+        }
+        match first_by_inode.entry((meta.dev(), meta.ino())) {
+            Entry::Occupied(first) => link_of.push(Some(*first.get())),
+            Entry::Vacant(slot) => {
+                slot.insert(index);
+                link_of.push(None);
+            }
+        }
+    }
+    Ok(link_of)
+}
+
+/// Inode information isn't available on this platform, so every file is stored on its own.
+#[cfg(not(unix))]
+fn find_source_hard_links(entries: &[(PathBuf, PathBuf)]) -> io::Result<Vec<Option<usize>>> {
+    Ok(vec![None; entries.len()])
+}
+
+/// Finds files with the same contents and permissions as an earlier entry and marks them in
+/// `link_of`, so their copies in the snapshot are hard-linked to that entry's copy instead of
+/// being stored again. Only non-empty files sharing their size with another file are hashed, and
+/// files marked in `own_copy` are left alone. Returns the number of duplicates found.
+fn find_duplicate_files(
+    entries: &[(PathBuf, PathBuf)],
+    link_of: &mut [Option<usize>],
+    own_copy: &[bool],
+    context: &CopyContext,
+) -> io::Result<usize> {
+    let mut by_size: BTreeMap<u64, Vec<(usize, fs::Metadata)>> = BTreeMap::new();
+    for (index, (path, _)) in entries.iter().enumerate() {
+        if link_of[index].is_some() || own_copy[index] {
+            continue;
+        }
+        let meta = fs::symlink_metadata(path)?;
+        if meta.is_file() && meta.len() > 0 {
+            by_size.entry(meta.len()).or_default().push((index, meta));
+        }
+    }
+
+    let mut duplicates = 0;
+    for candidates in by_size.into_values().filter(|group| group.len() > 1) {
+        let mut first_by_content: HashMap<(String, Option<u32>), usize> = HashMap::new();
+        for (index, meta) in candidates {
+            let checksum = profile::time("hashing", || {
+                file_checksum(&entries[index].0, context.parallel_hash_threshold)
+            })?;
+            match first_by_content.get(&(checksum.clone(), file_mode(&meta))) {
+                Some(&first) => {
+                    link_of[index] = Some(first);
+                    duplicates += 1;
+                }
+                None => {
+                    first_by_content.insert((checksum, file_mode(&meta)), index);
+                }
+            }
+        }
+    }
+    Ok(duplicates)
+}
+
+/// Stores a file that is a hard link to, or an identical copy of, an earlier file in the working
+/// tree as a hard link to that file's copy in the snapshot, so the two share one inode. If the
+/// link can't be created, the file is stored on its own.
+fn store_source_hard_link(
+    path: &Path,
+    dest_path: &Path,
+    first_dest_path: &Path,
+    first_meta: Option<&FileMetadata>,
+    base: &Path,
+    context: &CopyContext,
+) -> io::Result<FileMetadata> {
+    let mut file_meta = build_file_metadata(path, base)?;
+    file_meta.xattrs = read_xattrs(path);
+    if with_retries(context, &file_meta.relative_path, || {
+        fs::hard_link(first_dest_path, dest_path)
+    })
+    .is_err()
+    {
+        return store_entry(path, dest_path, base, context);
+    }
+    file_meta.checksum = first_meta.and_then(|meta| meta.checksum.clone());
+    file_meta.sha256 = first_meta.and_then(|meta| meta.sha256.clone());
+    file_meta.compressed_size = first_meta.and_then(|meta| meta.compressed_size);
+    // An identical copy shares the first file's stored copy and its modification time
+    if let Some(first_meta) = first_meta {
+        let stored_modified = first_meta
+            .stored_modified
+            .as_ref()
+            .unwrap_or(&first_meta.modified);
+        if !stored_modified.is_empty() && *stored_modified != file_meta.modified {
+            file_meta.stored_modified = Some(stored_modified.clone());
+        }
+    }
+    Ok(file_meta)
+}
+
+/// Recursively creates the directories under src in dst and collects the (source, destination)
+/// paths of the files and symbolic links to store, applying the same skip and ignore rules as
+/// `collect_metadata_recursive`.
+fn create_tree(
+    src: &Path,
+    dst: &Path,
+    skip_dir: &str,
+    ignore_list: &Vec<String>,
+    entries: &mut Vec<(PathBuf, PathBuf)>,
 ) -> io::Result<()> {
     for entry in fs::read_dir(src)? {
         let entry = entry?;
@@ -134,59 +919,651 @@ fn copy_or_link_recursive_with_metadata(
         if file_name_str == skip_dir {
             continue;
         }
-        if ignore_list.contains(&file_name_str.to_string()) {
+        if ignore::is_ignored(&file_name_str, ignore_list) {
             continue;
         }
 
         let dest_path = dst.join(&file_name);
 
-        if path.is_dir() {
+        if is_symlink(&entry)? || path.is_file() {
+            entries.push((path, dest_path));
+        } else if path.is_dir() {
             fs::create_dir_all(&dest_path)?;
-            copy_or_link_recursive_with_metadata(
-                &path,
-                &dest_path,
-                skip_dir,
-                base,
-                ignore_list,
-                prev_snapshot,
-                metadata,
-            )?;
-        } else if path.is_file() {
-            let meta = fs::metadata(&path)?;
-            let file_size = meta.len();
-            let modified_time: DateTime<Local> = meta
-                .modified()
-                .map(DateTime::<Local>::from)
-                .unwrap_or_else(|_| Local::now());
-            let modified_str = modified_time.format("%Y-%m-%d %H:%M:%S").to_string();
-            let relative_path = path
-                .strip_prefix(base)
-                .unwrap_or(&path)
-                .to_string_lossy()
-                .to_string();
-
-            let file_meta = FileMetadata {
-                relative_path: relative_path.clone(),
-                file_size,
-                modified: modified_str.clone(),
-            };
-
-            let mut used_hard_link = false;
-            if let Some((prev_snapshot_dir, prev_manifest)) = prev_snapshot {
-                if let Some(prev_meta) = prev_manifest.get(&relative_path) {
-                    if prev_meta.file_size == file_size && prev_meta.modified == modified_str {
-                        let prev_file_path = prev_snapshot_dir.join(&relative_path);
-                        if fs::hard_link(&prev_file_path, &dest_path).is_ok() {
-                            used_hard_link = true;
-                        }
+            create_tree(&path, &dest_path, skip_dir, ignore_list, entries)?;
+        }
+    }
+    Ok(())
+}
+
+/// Stores a single file in the snapshot and returns its manifest entry. If the baseline snapshot
+/// has the file unchanged (by size and either modification time or content hash), a hard link to
+/// the baseline's copy is created; otherwise, the file is copied. Symbolic links are recreated
+/// as links. The entry records the file's BLAKE3 and SHA-256 hashes, computed while copying or
+/// carried over from the baseline; later snapshots compare the SHA-256 to recognize the file
+/// after its modification time changes.
+fn store_entry(
+    path: &Path,
+    dest_path: &Path,
+    base: &Path,
+    context: &CopyContext,
+) -> io::Result<FileMetadata> {
+    let mut file_meta = build_file_metadata(path, base)?;
+    if file_meta.link_target.is_some() {
+        copy_symlink(path, dest_path)?;
+        return Ok(file_meta);
+    }
+    file_meta.xattrs = read_xattrs(path);
+
+    // A baseline entry without a modification time (`--content-only`) can only be matched by hash
+    let baseline_by_hash = context
+        .snapshot
+        .and_then(|(_, prev_manifest)| prev_manifest.get(&file_meta.relative_path))
+        .is_some_and(|prev_meta| prev_meta.modified.is_empty());
+    let dedup = !context
+        .attributes
+        .is_set(&file_meta.relative_path, attributes::NO_DEDUP);
+    // A file rewritten with the same contents (e.g. a regenerated build artifact) keeps its size
+    // but gets a new modification time; it's still unchanged if its SHA-256 matches the baseline's
+    let rewritten = dedup
+        && context
+            .snapshot
+            .and_then(|(_, prev_manifest)| prev_manifest.get(&file_meta.relative_path))
+            .is_some_and(|prev_meta| {
+                prev_meta.sha256.is_some()
+                    && prev_meta.file_size == file_meta.file_size
+                    && prev_meta.modified != file_meta.modified
+            });
+    if context.compare_by == CompareBy::Hash || baseline_by_hash {
+        file_meta.checksum = Some(profile::time("hashing", || {
+            file_checksum(path, context.parallel_hash_threshold)
+        })?);
+    }
+    if rewritten {
+        file_meta.sha256 = Some(profile::time("hashing", || {
+            reader_sha256(fs::File::open(path)?, context.buffer_size)
+        })?);
+    }
+    let relative_path = &file_meta.relative_path;
+
+    let mut used_hard_link = false;
+    let mut compressed_size = None;
+    if let Some((_, prev_manifest)) = context.snapshot.filter(|_| dedup) {
+        if let Some(prev_meta) = prev_manifest.get(relative_path) {
+            let prev_file_path = manifest::stored_file_dir(&context.baseline_chain, relative_path)
+                .join(relative_path);
+            // A hard link shares the baseline copy's permissions, so a mode change needs a copy
+            let unchanged = prev_meta.file_size == file_meta.file_size
+                && !diff::mode_changed(prev_meta, &file_meta)
+                && match context.compare_by {
+                    // Baselines written before hashes were recorded fall back to the mtime
+                    CompareBy::Mtime if !baseline_by_hash => {
+                        prev_meta.modified == file_meta.modified
+                            || (prev_meta.sha256.is_some() && prev_meta.sha256 == file_meta.sha256)
                     }
+                    _ => {
+                        let prev_checksum = match prev_meta.checksum {
+                            Some(ref checksum) => Some(checksum.clone()),
+                            None => profile::time("hashing", || {
+                                stored_checksum(
+                                    &prev_file_path,
+                                    prev_meta,
+                                    context.parallel_hash_threshold,
+                                )
+                            })
+                            .ok(),
+                        };
+                        prev_checksum.is_some() && prev_checksum == file_meta.checksum
+                    }
+                };
+            let unchanged = unchanged
+                && (!context.concurrency_safe
+                    || content_is_stable(
+                        path,
+                        base,
+                        &file_meta,
+                        &prev_file_path,
+                        prev_meta,
+                        context,
+                    ));
+            // An incremental snapshot leaves unchanged files to its baseline
+            if unchanged
+                && (context.incremental
+                    || with_retries(context, relative_path, || {
+                        fs::hard_link(&prev_file_path, dest_path)
+                    })
+                    .is_ok())
+            {
+                used_hard_link = true;
+                compressed_size = prev_meta.compressed_size;
+                if file_meta.checksum.is_none() {
+                    file_meta.checksum = prev_meta.checksum.clone();
+                }
+                if file_meta.sha256.is_none() {
+                    file_meta.sha256 = prev_meta.sha256.clone();
+                }
+                // The baseline's copy keeps the modification time it was stored with
+                let stored_modified = prev_meta
+                    .stored_modified
+                    .as_ref()
+                    .unwrap_or(&prev_meta.modified);
+                if !stored_modified.is_empty() && *stored_modified != file_meta.modified {
+                    file_meta.stored_modified = Some(stored_modified.clone());
                 }
             }
-            if !used_hard_link {
-                fs::copy(&path, &dest_path)?;
+        }
+    }
+    if !used_hard_link {
+        let compress = context
+            .attributes
+            .get(relative_path, attributes::COMPRESS)
+            .unwrap_or_else(|| {
+                context
+                    .compress_threshold
+                    .is_some_and(|threshold| file_meta.file_size > threshold)
+            });
+        let mut hashes = None;
+        with_retries(context, relative_path, || {
+            let (size, checksum, sha256) =
+                copy_preserving_mtime(path, dest_path, compress, context.buffer_size)?;
+            compressed_size = size;
+            hashes = Some((checksum, sha256));
+            Ok(())
+        })?;
+        if let Some((checksum, sha256)) = hashes {
+            file_meta.checksum = Some(checksum);
+            file_meta.sha256 = Some(sha256);
+        }
+    }
+    file_meta.compressed_size = compressed_size;
+    Ok(file_meta)
+}
+
+/// With the `snapshot_concurrency_safe` setting, confirms that a file judged unchanged really
+/// holds the baseline's content before it is hard-linked: its contents are hashed and compared
+/// with the baseline copy, and its size and modification time must not change while it is read.
+/// A file that another process is rewriting is then copied instead of linked to stale content.
+fn content_is_stable(
+    path: &Path,
+    base: &Path,
+    file_meta: &FileMetadata,
+    prev_file_path: &Path,
+    prev_meta: &FileMetadata,
+    context: &CopyContext,
+) -> bool {
+    let hash = |path: &Path| {
+        profile::time("hashing", || {
+            file_checksum(path, context.parallel_hash_threshold)
+        })
+        .ok()
+    };
+    let current = file_meta.checksum.clone().or_else(|| hash(path));
+    let baseline = prev_meta.checksum.clone().or_else(|| {
+        profile::time("hashing", || {
+            stored_checksum(prev_file_path, prev_meta, context.parallel_hash_threshold)
+        })
+        .ok()
+    });
+    let still_same = build_file_metadata(path, base).is_ok_and(|meta| {
+        meta.file_size == file_meta.file_size && meta.modified == file_meta.modified
+    });
+    current.is_some() && current == baseline && still_same
+}
+
+/// Runs a copy or link operation, retrying it with exponential backoff while it fails with a
+/// transient error (as seen on NFS/SMB mounts), up to the configured number of retries.
+fn with_retries(
+    context: &CopyContext,
+    relative_path: &str,
+    mut operation: impl FnMut() -> io::Result<()>,
+) -> io::Result<()> {
+    let mut delay = Duration::from_millis(RETRY_BASE_DELAY_MS);
+    let mut attempt = 0;
+    loop {
+        injected_delay();
+        let error = match injected_transient_error().and_then(|_| operation()) {
+            Ok(()) => return Ok(()),
+            Err(e) => e,
+        };
+        if attempt >= context.retries || !is_transient(&error) {
+            if attempt == 0 {
+                return Err(error);
             }
-            metadata.push(file_meta);
+            return Err(io::Error::new(
+                error.kind(),
+                format!(
+                    "{} (gave up on {} after {} retries)",
+                    error, relative_path, attempt
+                ),
+            ));
+        }
+        attempt += 1;
+        if context.verbose {
+            eprintln!(
+                "Retrying {} in {} ms after error: {} (retry {}/{})",
+                relative_path,
+                delay.as_millis(),
+                error,
+                attempt,
+                context.retries
+            );
+        }
+        thread::sleep(delay);
+        delay = (delay * 2).min(Duration::from_millis(RETRY_MAX_DELAY_MS));
+    }
+}
+
+/// Returns true for errors that may go away when the operation is retried.
+fn is_transient(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::WouldBlock
+            | io::ErrorKind::TimedOut
+            | io::ErrorKind::Interrupted
+            | io::ErrorKind::ResourceBusy
+            | io::ErrorKind::StaleNetworkFileHandle
+    )
+}
+
+/// Fails the first N copy or link attempts with a transient error when
+/// `SNAPSAFE_INJECT_TRANSIENT_ERRORS=N` is set, so retries can be tested.
+#[cfg(debug_assertions)]
+fn injected_transient_error() -> io::Result<()> {
+    static INJECTED: AtomicUsize = AtomicUsize::new(0);
+    let limit = std::env::var(crate::constants::INJECT_TRANSIENT_ERRORS_ENV)
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(0);
+    if INJECTED.fetch_add(1, Ordering::SeqCst) < limit {
+        Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "injected transient error",
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Error injection is only available in debug builds.
+#[cfg(not(debug_assertions))]
+fn injected_transient_error() -> io::Result<()> {
+    Ok(())
+}
+
+/// Sleeps before a file copy when `SNAPSAFE_INJECT_DELAY_MS=N` is set, so slow or hung storage
+/// can be simulated when testing `--timeout`.
+#[cfg(debug_assertions)]
+pub fn injected_delay() {
+    if let Some(ms) = std::env::var(crate::constants::INJECT_DELAY_ENV)
+        .ok()
+        .and_then(|ms| ms.parse().ok())
+    {
+        std::thread::sleep(std::time::Duration::from_millis(ms));
+    }
+}
+
+/// Delay injection is only available in debug builds.
+#[cfg(not(debug_assertions))]
+pub fn injected_delay() {}
+
+/// Returns true if the path is a regular file with no contents. Symbolic links are never empty.
+fn is_empty_file(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|meta| meta.is_file() && meta.len() == 0)
+}
+
+/// Returns true if the entry is a symbolic link that is stored as a link.
+/// Links are only preserved on Unix; on other platforms they are followed.
+fn is_symlink(entry: &fs::DirEntry) -> io::Result<bool> {
+    Ok(cfg!(unix) && entry.file_type()?.is_symlink())
+}
+
+/// Recreates the symbolic link at src as dst, pointing at the same target.
+#[cfg(unix)]
+pub fn copy_symlink(src: &Path, dst: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(src)?, dst)
+}
+
+/// Symbolic links aren't preserved on this platform, so the target is copied instead.
+#[cfg(not(unix))]
+pub fn copy_symlink(src: &Path, dst: &Path) -> io::Result<()> {
+    copy_preserving_mtime(src, dst, false, crate::constants::DEFAULT_COPY_BUFFER_SIZE).map(|_| ())
+}
+
+/// Reads the extended attributes of a file, or None if it has none or they can't be read.
+#[cfg(unix)]
+fn read_xattrs(path: &Path) -> Option<HashMap<String, String>> {
+    let mut xattrs = HashMap::new();
+    for name in xattr::list(path).ok()? {
+        let value = match xattr::get(path, &name) {
+            Ok(Some(value)) => value,
+            _ => continue,
+        };
+        if let (Some(name), Ok(value)) = (name.to_str(), String::from_utf8(value)) {
+            xattrs.insert(name.to_string(), value);
+        }
+    }
+    if xattrs.is_empty() {
+        None
+    } else {
+        Some(xattrs)
+    }
+}
+
+/// Extended attributes aren't supported on this platform.
+#[cfg(not(unix))]
+fn read_xattrs(_path: &Path) -> Option<HashMap<String, String>> {
+    None
+}
+
+/// Sets the given extended attributes on a file.
+#[cfg(unix)]
+pub fn apply_xattrs(path: &Path, xattrs: &HashMap<String, String>) -> io::Result<()> {
+    for (name, value) in xattrs {
+        xattr::set(path, name, value.as_bytes()).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Failed to set extended attribute {}: {}", name, e),
+            )
+        })?;
+    }
+    Ok(())
+}
+
+/// Extended attributes aren't supported on this platform, so there is nothing to set.
+#[cfg(not(unix))]
+pub fn apply_xattrs(_path: &Path, _xattrs: &HashMap<String, String>) -> io::Result<()> {
+    Ok(())
+}
+
+/// Copies a file, gzip-compressing it if `compress` is set, and carries over its modification
+/// time, so the stored copy matches the `modified` value recorded in the manifest. Returns the
+/// compressed size of a compressed copy and the BLAKE3 and SHA-256 hashes of the contents copied.
+fn copy_preserving_mtime(
+    src: &Path,
+    dst: &Path,
+    compress: bool,
+    buffer_size: usize,
+) -> io::Result<(Option<u64>, String, String)> {
+    let source = fs::File::open(src)?;
+    let permissions = source.metadata()?.permissions();
+    let mut reader = HashingReader {
+        inner: source,
+        hasher: blake3::Hasher::new(),
+        sha256: Sha256::new(),
+    };
+    let compressed_size = if compress {
+        let mut encoder = GzBuilder::new()
+            .extra(COMPRESSED_MARKER.to_vec())
+            .write(fs::File::create(dst)?, Compression::default());
+        copy_stream(&mut reader, &mut encoder, buffer_size)?;
+        encoder.finish()?;
+        fs::set_permissions(dst, permissions)?;
+        Some(fs::metadata(dst)?.len())
+    } else {
+        copy_stream(&mut reader, fs::File::create(dst)?, buffer_size)?;
+        fs::set_permissions(dst, permissions)?;
+        None
+    };
+    let mtime = FileTime::from_last_modification_time(&fs::metadata(src)?);
+    filetime::set_file_mtime(dst, mtime)?;
+    Ok((
+        compressed_size,
+        reader.hasher.finalize().to_hex().to_string(),
+        hex(&reader.sha256.finalize()),
+    ))
+}
+
+/// Extra field written into the gzip header of files stored compressed (an RFC 1952 subfield
+/// with ID "Ss" and no data), which tells them apart from `.gz` files stored as they are.
+const COMPRESSED_MARKER: [u8; 4] = *b"Ss\0\0";
+
+/// Tells from its gzip header whether a file in a snapshot folder is stored compressed: Some(true)
+/// for files compressed by snapsafe and Some(false) for files stored as they are. None for a
+/// gzip stream with the bare header written before compressed files were marked, which can't be
+/// told apart from a `.gz` file stored as it is.
+pub fn stored_compression(stored: &Path) -> io::Result<Option<bool>> {
+    let mut header = Vec::with_capacity(16);
+    fs::File::open(stored)?.take(16).read_to_end(&mut header)?;
+    if !header.starts_with(&[0x1f, 0x8b, 8]) {
+        return Ok(Some(false));
+    }
+    // FEXTRA flag, then XLEN (4, little-endian) and the marker subfield after the fixed header
+    if header.len() == 16 && header[3] == 4 && header[10..12] == [4, 0] {
+        return Ok(Some(header[12..] == COMPRESSED_MARKER));
+    }
+    // No flags, no modification time, and an unknown operating system, as written unmarked
+    let unmarked = header.len() >= 10 && header[3..8] == [0; 5] && header[9] == 255;
+    Ok(if unmarked { None } else { Some(false) })
+}
+
+/// Reader that hashes everything read through it with BLAKE3 and SHA-256, so a file's hashes are
+/// computed while it is copied rather than in a second pass.
+struct HashingReader<R> {
+    inner: R,
+    hasher: blake3::Hasher,
+    sha256: Sha256,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        self.sha256.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+/// Computes the SHA-256 hash of everything read from reader, through a buffer of the given size,
+/// as a lowercase hex string.
+fn reader_sha256(mut reader: impl Read, buffer_size: usize) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; buffer_size.max(1)];
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hex(&hasher.finalize()))
+}
+
+/// Formats a digest as a lowercase hex string.
+fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Copies a file's contents and permissions through a buffer of the given size, replacing the
+/// destination's contents. Returns the number of bytes copied. The buffer size is the
+/// `copy_buffer_size` setting, so throughput can be tuned for the storage involved.
+pub fn copy_file(src: &Path, dst: &Path, buffer_size: usize) -> io::Result<u64> {
+    let reader = fs::File::open(src)?;
+    let permissions = reader.metadata()?.permissions();
+    let copied = copy_stream(reader, fs::File::create(dst)?, buffer_size)?;
+    fs::set_permissions(dst, permissions)?;
+    Ok(copied)
+}
+
+/// Writes a file stored in a snapshot to dst with its original contents, decompressing it if it
+/// was stored compressed.
+pub fn extract_stored_file(
+    stored: &Path,
+    meta: &FileMetadata,
+    dst: &Path,
+    buffer_size: usize,
+) -> io::Result<()> {
+    if meta.compressed_size.is_none() {
+        return copy_file(stored, dst, buffer_size).map(|_| ());
+    }
+    let reader = fs::File::open(stored)?;
+    let permissions = reader.metadata()?.permissions();
+    copy_stream(GzDecoder::new(reader), fs::File::create(dst)?, buffer_size)?;
+    fs::set_permissions(dst, permissions)
+}
+
+/// Opens a file stored in a snapshot for reading its original contents, decompressing it on the
+/// fly if it was stored compressed.
+pub fn open_stored_file(stored: &Path, meta: &FileMetadata) -> io::Result<Box<dyn Read>> {
+    let file = fs::File::open(stored)?;
+    Ok(match meta.compressed_size {
+        Some(_) => Box::new(GzDecoder::new(file)),
+        None => Box::new(file),
+    })
+}
+
+/// Copies everything from reader to writer through a buffer of the given size and returns the
+/// number of bytes copied.
+fn copy_stream(
+    mut reader: impl Read,
+    mut writer: impl Write,
+    buffer_size: usize,
+) -> io::Result<u64> {
+    let mut buffer = vec![0; buffer_size.max(1)];
+    let mut copied = 0;
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        writer.write_all(&buffer[..read])?;
+        copied += read as u64;
+    }
+    writer.flush()?;
+    Ok(copied)
+}
+
+/// Recursively collects metadata for the files under src without copying anything,
+/// applying the same skip and ignore rules as `create_tree`.
+pub fn collect_metadata_recursive(
+    src: &Path,
+    skip_dir: &str,
+    base: &Path,
+    ignore_list: &Vec<String>,
+    metadata: &mut Vec<FileMetadata>,
+) -> io::Result<()> {
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let path = entry.path();
+        let file_name = entry.file_name();
+        let file_name_str = file_name.to_string_lossy();
+
+        if file_name_str == skip_dir {
+            continue;
+        }
+        if ignore::is_ignored(&file_name_str, ignore_list) {
+            continue;
+        }
+
+        if is_symlink(&entry)? || path.is_file() {
+            metadata.push(build_file_metadata(&path, base)?);
+        } else if path.is_dir() {
+            collect_metadata_recursive(&path, skip_dir, base, ignore_list, metadata)?;
         }
     }
     Ok(())
 }
+
+/// Builds the manifest entry (relative path, size, and modification time) for a single file.
+/// Symbolic links are described by the link itself (on Unix) and record their target.
+pub fn build_file_metadata(path: &Path, base: &Path) -> io::Result<FileMetadata> {
+    let link_meta = fs::symlink_metadata(path)?;
+    let link_target = if cfg!(unix) && link_meta.file_type().is_symlink() {
+        Some(fs::read_link(path)?.to_string_lossy().to_string())
+    } else {
+        None
+    };
+    let meta = if link_target.is_some() {
+        link_meta
+    } else {
+        fs::metadata(path)?
+    };
+    let modified_time: DateTime<Local> = meta
+        .modified()
+        .map(DateTime::<Local>::from)
+        .unwrap_or_else(|_| Local::now());
+    let relative_path = path
+        .strip_prefix(base)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .to_string();
+
+    Ok(FileMetadata {
+        relative_path,
+        file_size: meta.len(),
+        modified: modified_time.format("%Y-%m-%d %H:%M:%S").to_string(),
+        checksum: None,
+        sha256: None,
+        mode: if link_target.is_some() {
+            None
+        } else {
+            file_mode(&meta)
+        },
+        link_target,
+        xattrs: None,
+        times: None,
+        compressed_size: None,
+        stored_modified: None,
+    })
+}
+
+/// Returns the permission bits of a file on Unix.
+#[cfg(unix)]
+fn file_mode(meta: &fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(meta.permissions().mode() & 0o7777)
+}
+
+/// Permission bits aren't recorded on other platforms.
+#[cfg(not(unix))]
+fn file_mode(_meta: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+/// Computes the BLAKE3 hash of a file's contents as a lowercase hex string.
+/// The file is read in fixed-size chunks so memory use stays bounded; files of at least
+/// `parallel_threshold` bytes have each chunk hashed on multiple threads.
+pub fn file_checksum(path: &Path, parallel_threshold: u64) -> io::Result<String> {
+    let file = fs::File::open(path)?;
+    let parallel = file.metadata()?.len() >= parallel_threshold;
+    reader_checksum(file, parallel)
+}
+
+/// Computes the BLAKE3 hash of the original contents of a file stored in a snapshot, which
+/// differ from the bytes on disk when the file was stored compressed.
+pub fn stored_checksum(
+    stored: &Path,
+    meta: &FileMetadata,
+    parallel_threshold: u64,
+) -> io::Result<String> {
+    match meta.compressed_size {
+        Some(_) => reader_checksum(
+            open_stored_file(stored, meta)?,
+            meta.file_size >= parallel_threshold,
+        ),
+        None => file_checksum(stored, parallel_threshold),
+    }
+}
+
+/// Hashes everything read from reader, in fixed-size chunks that are each hashed on multiple
+/// threads when `parallel` is set.
+fn reader_checksum(mut reader: impl Read, parallel: bool) -> io::Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    let mut buffer = vec![0; HASH_CHUNK_SIZE];
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        if parallel {
+            hasher.update_rayon(&buffer[..read]);
+        } else {
+            hasher.update(&buffer[..read]);
+        }
+    }
+    Ok(hasher.finalize().to_hex().to_string())
+}
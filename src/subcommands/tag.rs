@@ -1,104 +1,159 @@
 use std::io;
 
 use crate::info;
-use crate::manifest::{load_head_manifest, save_head_manifest};
+use crate::manifest::{load_head_manifest, save_head_manifest, save_snapshot_meta};
 use crate::models::SnapshotMetadata;
 
-/// Add, remove, or list tags for snapshots
+/// Add, remove, or list tags for snapshots.
+/// With `raw`, the list is printed one tag per line without decoration, for scripts.
+/// The snapshot ID `all` applies the change to every snapshot, after a confirmation unless
+/// `yes` is set.
 pub fn manage_tags(
     snapshot_id: Option<String>,
     add: Option<Vec<String>>,
     remove: Option<Vec<String>>,
     list: bool,
+    raw: bool,
+    yes: bool,
 ) -> io::Result<()> {
     let base_path = info::get_base_dir()?;
     let mut head_manifest = load_head_manifest(&base_path)?;
 
-    let actual_id = info::resolve_snapshot_id(snapshot_id, &head_manifest)?;
-
-    // Find the snapshot in the head manifest
-    let snapshot_index = head_manifest
-        .iter()
-        .position(|s| s.version == actual_id || s.version.starts_with(&actual_id))
-        .ok_or_else(|| {
-            io::Error::new(
-                io::ErrorKind::NotFound,
-                format!("Snapshot {} not found", actual_id),
-            )
-        })?;
+    // Find the snapshot(s) in the head manifest
+    let all = snapshot_id
+        .as_deref()
+        .is_some_and(|id| id.eq_ignore_ascii_case(info::ALL_SNAPSHOTS_ID));
+    let positions = info::resolve_snapshot_positions(snapshot_id, &head_manifest)?;
 
     // Add tags
     if let Some(ref tags) = add {
-        // Use ref to avoid moving tags
-        // Reference to the snapshot
-        let snapshot = &mut head_manifest[snapshot_index];
-
-        // Initialize metadata if it doesn't exist
-        if snapshot.metadata.is_none() {
-            snapshot.metadata = Some(SnapshotMetadata::default());
+        let change = format!("add tag(s) {}", tags.join(", "));
+        if all && !info::confirm_bulk_change(&change, positions.len(), yes)? {
+            return Ok(());
         }
 
-        let metadata = snapshot.metadata.as_mut().unwrap();
-
-        for tag in tags {
-            if !metadata.tags.contains(tag) {
-                metadata.tags.push(tag.clone());
-                println!("Added tag '{}' to snapshot {}", tag, snapshot.version);
-            } else {
-                println!(
-                    "Tag '{}' already exists for snapshot {}",
-                    tag, snapshot.version
-                );
+        for &position in &positions {
+            let snapshot = &mut head_manifest[position];
+            let metadata = snapshot
+                .metadata
+                .get_or_insert_with(SnapshotMetadata::default);
+
+            for tag in tags {
+                if !metadata.tags.contains(tag) {
+                    metadata.tags.push(tag.clone());
+                    println!("Added tag '{}' to snapshot {}", tag, snapshot.version);
+                } else {
+                    println!(
+                        "Tag '{}' already exists for snapshot {}",
+                        tag, snapshot.version
+                    );
+                }
             }
         }
 
         // Save the updated manifest
         save_head_manifest(&base_path, &head_manifest)?;
+        for &position in &positions {
+            save_snapshot_meta(&base_path, &head_manifest[position])?;
+        }
     }
     // Remove tags
     else if let Some(ref tags) = remove {
-        // Use ref to avoid moving tags
-        // Reference to the snapshot
-        let snapshot = &mut head_manifest[snapshot_index];
-
-        // Initialize metadata if it doesn't exist
-        if snapshot.metadata.is_none() {
-            snapshot.metadata = Some(SnapshotMetadata::default());
+        let change = format!("remove tag(s) {}", tags.join(", "));
+        if all && !info::confirm_bulk_change(&change, positions.len(), yes)? {
+            return Ok(());
         }
 
-        let metadata = snapshot.metadata.as_mut().unwrap();
-
-        for tag in tags {
-            if let Some(pos) = metadata.tags.iter().position(|t| t == tag) {
-                metadata.tags.remove(pos);
-                println!("Removed tag '{}' from snapshot {}", tag, snapshot.version);
-            } else {
-                println!("Tag '{}' not found for snapshot {}", tag, snapshot.version);
+        for &position in &positions {
+            let snapshot = &mut head_manifest[position];
+            let metadata = snapshot
+                .metadata
+                .get_or_insert_with(SnapshotMetadata::default);
+
+            for tag in tags {
+                if let Some(pos) = metadata.tags.iter().position(|t| t == tag) {
+                    metadata.tags.remove(pos);
+                    println!("Removed tag '{}' from snapshot {}", tag, snapshot.version);
+                } else {
+                    println!("Tag '{}' not found for snapshot {}", tag, snapshot.version);
+                }
             }
         }
 
         // Save the updated manifest
         save_head_manifest(&base_path, &head_manifest)?;
+        for &position in &positions {
+            save_snapshot_meta(&base_path, &head_manifest[position])?;
+        }
     }
     // List tags
     else if list || (add.is_none() && remove.is_none()) {
-        // Use a separate binding for the snapshot to avoid borrow conflicts
-        let snapshot = &head_manifest[snapshot_index];
+        for &position in &positions {
+            let snapshot = &head_manifest[position];
+
+            if raw {
+                for tag in snapshot.metadata.iter().flat_map(|metadata| &metadata.tags) {
+                    println!("{}", tag);
+                }
+                continue;
+            }
 
-        println!("Tags for snapshot {}:", snapshot.version);
+            println!("Tags for snapshot {}:", snapshot.version);
 
-        if let Some(ref metadata) = snapshot.metadata {
-            if metadata.tags.is_empty() {
-                println!("  No tags");
+            if let Some(ref metadata) = snapshot.metadata {
+                if metadata.tags.is_empty() {
+                    println!("  No tags");
+                } else {
+                    for tag in &metadata.tags {
+                        println!("  - {}", tag);
+                    }
+                }
             } else {
-                for tag in &metadata.tags {
-                    println!("  - {}", tag);
+                println!("  No metadata available");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Moves tags to a snapshot: each tag is removed from every snapshot currently holding it
+/// and added to the given snapshot, saving the head manifest once.
+pub fn move_tags(snapshot_id: Option<String>, tags: &[String]) -> io::Result<()> {
+    let base_path = info::get_base_dir()?;
+    let mut head_manifest = load_head_manifest(&base_path)?;
+    let actual_id = info::resolve_snapshot_id(snapshot_id, &head_manifest)?;
+
+    let mut changed = Vec::new();
+    for (position, snapshot) in head_manifest.iter_mut().enumerate() {
+        if snapshot.version == actual_id {
+            changed.push(position);
+            let metadata = snapshot
+                .metadata
+                .get_or_insert_with(SnapshotMetadata::default);
+            for tag in tags {
+                if !metadata.tags.contains(tag) {
+                    metadata.tags.push(tag.clone());
                 }
             }
-        } else {
-            println!("  No metadata available");
+        } else if let Some(ref mut metadata) = snapshot.metadata {
+            let before = metadata.tags.len();
+            metadata.tags.retain(|t| !tags.contains(t));
+            if metadata.tags.len() != before {
+                changed.push(position);
+                println!(
+                    "Removed tag(s) {} from snapshot {}",
+                    tags.join(", "),
+                    snapshot.version
+                );
+            }
         }
     }
 
+    save_head_manifest(&base_path, &head_manifest)?;
+    for position in changed {
+        save_snapshot_meta(&base_path, &head_manifest[position])?;
+    }
+    println!("Moved tag(s) {} to snapshot {}", tags.join(", "), actual_id);
     Ok(())
 }
@@ -1,97 +1,581 @@
+use chrono::{DateTime, Local, NaiveDateTime};
+use clap::ValueEnum;
+use serde::Serialize;
 use std::fs;
-use std::io;
-use std::path::Path;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::SystemTime;
 
-use crate::constants::{MANIFEST_FILE, REPO_FOLDER, SNAPSHOTS_FOLDER};
+use crate::config;
+use crate::constants::{MANIFEST_FILE, SNAPSHOT_META_FILE};
 use crate::info;
-use crate::manifest::load_head_manifest;
-use crate::models::FileMetadata;
+use crate::manifest::{self, load_head_manifest, save_head_manifest};
+use crate::models::{FileMetadata, SnapshotIndex, VerificationRecord};
+use crate::profile;
+use crate::storage::{self, IoLimiter};
+use crate::subcommands::prune::parse_duration;
+use crate::watchdog;
 
-/// Verify the integrity of snapshots
-pub fn verify_snapshots(snapshot_id: Option<String>) -> io::Result<()> {
+/// Optional checks performed by `verify_snapshots` on top of the existence and size checks.
+#[derive(Debug, Default, Clone)]
+pub struct VerifyOptions {
+    /// Check that files unchanged since the baseline snapshot (its parent) still share its inode.
+    pub check_links: bool,
+    /// Stop at the first snapshot that fails verification instead of checking the rest.
+    pub fail_fast: bool,
+    /// Compare each stored file's modification time with the one recorded in the manifest.
+    pub check_mtime: bool,
+    /// Treat modification time mismatches as failures instead of warnings.
+    pub strict: bool,
+    /// Print the results as JSON instead of a human-readable report.
+    pub json: bool,
+    /// Print only a single status line, e.g. `OK verified=3 failed=0`, for monitoring probes.
+    pub oneline: bool,
+    /// List every problem file in the failure details instead of the first few.
+    pub show_all: bool,
+    /// Highlight the results with ANSI colors.
+    pub color: bool,
+    /// Skip snapshots that passed verification within this duration (e.g. "1h") and haven't
+    /// changed since. Snapshots that pass are recorded for later runs; without this option
+    /// `verify` never writes to the repository.
+    pub skip_recent: Option<String>,
+    /// Compare each snapshot's manifest with the checksum recorded in the head manifest when
+    /// the snapshot was created, failing snapshots whose manifest was modified since.
+    pub manifest_hash: bool,
+    /// Largest number of stored files read at once, separate from the number of jobs. Defaults
+    /// to one on a spinning disk and one per job otherwise.
+    pub io_limit: Option<usize>,
+}
+
+/// When `verify` colors its output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum ColorChoice {
+    /// When printing to a terminal and `NO_COLOR` isn't set (default)
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Returns whether output should be colored.
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                std::env::var_os("NO_COLOR").is_none() && io::stdout().is_terminal()
+            }
+        }
+    }
+}
+
+/// Number of files listed per problem in the failure details unless `show_all` is set.
+const FAILURE_LIST_LIMIT: usize = 5;
+
+/// Largest difference (in seconds) between a stored file's modification time and the manifest
+/// that still counts as a match, allowing for filesystems with coarse timestamps (e.g. FAT).
+const MTIME_TOLERANCE_SECS: i64 = 2;
+
+/// Verification outcome of one snapshot, as reported by `verify --json`.
+#[derive(Serialize)]
+struct SnapshotReport {
+    version: String,
+    /// "ok", "failed", "error" (the snapshot couldn't be checked), "skipped" (`--fail-fast`), or
+    /// "recent" (passed recently enough for `--skip-recent`).
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// When a "recent" snapshot last passed verification.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    verified_at: Option<String>,
+    missing_files: usize,
+    corrupt_files: usize,
+    unshared_links: Vec<String>,
+    mtime_mismatches: Vec<String>,
+    /// "ok", "mismatch", or "unrecorded" (the snapshot has no recorded checksum), when checked
+    /// with `--manifest-hash`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    manifest_hash: Option<&'static str>,
+    /// Paths behind `missing_files` and `corrupt_files`, listed in the failure details.
+    #[serde(skip)]
+    missing: Vec<String>,
+    #[serde(skip)]
+    corrupt: Vec<String>,
+}
+
+impl SnapshotReport {
+    fn new(version: &str, status: &'static str) -> Self {
+        SnapshotReport {
+            version: version.to_string(),
+            status,
+            error: None,
+            verified_at: None,
+            missing_files: 0,
+            corrupt_files: 0,
+            unshared_links: Vec::new(),
+            mtime_mismatches: Vec::new(),
+            manifest_hash: None,
+            missing: Vec::new(),
+            corrupt: Vec::new(),
+        }
+    }
+}
+
+/// The full report printed by `verify --json`.
+#[derive(Serialize)]
+struct VerifyReport {
+    snapshots: Vec<SnapshotReport>,
+    verified: usize,
+    succeeded: usize,
+    failed: usize,
+    skipped: usize,
+    recent: usize,
+}
+
+/// Verify the integrity of snapshots.
+/// Returns false if any snapshot failed verification; errors mean the repository couldn't be read.
+pub fn verify_snapshots(snapshot_id: Option<String>, options: &VerifyOptions) -> io::Result<bool> {
     let base_path = info::get_base_dir()?;
     let head_manifest = load_head_manifest(&base_path)?;
+    let skip_recent = options
+        .skip_recent
+        .as_deref()
+        .map(parse_duration)
+        .transpose()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    // The JSON and one-line outputs replace the human-readable report
+    let report_each = !options.json && !options.oneline;
 
-    if head_manifest.is_empty() {
+    if head_manifest.is_empty() && report_each {
         println!("No snapshots found to verify.");
-        return Ok(());
+        return Ok(true);
     }
 
     let snapshots_to_verify = if let Some(id) = snapshot_id {
         // Find the specific snapshot
-        let snapshot = head_manifest
+        let version = info::resolve_snapshot_id(Some(id), &head_manifest)?;
+        head_manifest
             .iter()
-            .find(|s| s.version == id || s.version.starts_with(&id));
-
-        match snapshot {
-            Some(s) => vec![s.clone()],
-            None => {
-                return Err(io::Error::new(
-                    io::ErrorKind::NotFound,
-                    format!("Snapshot {} not found", id),
-                ));
-            }
-        }
+            .filter(|s| s.version == version)
+            .cloned()
+            .collect()
     } else {
         // Verify all snapshots
-        head_manifest
+        head_manifest.clone()
     };
 
-    println!("Verifying {} snapshot(s)...", snapshots_to_verify.len());
+    if report_each {
+        println!("Verifying {} snapshot(s)...", snapshots_to_verify.len());
+    }
 
+    let jobs = config::load_config(&base_path).jobs;
+    let io_limit = match options.io_limit {
+        Some(limit) => limit,
+        None => {
+            let limit = storage::default_io_limit(&info::get_snapshots_dir(&base_path), jobs);
+            if limit < jobs && report_each {
+                println!("Snapshots are on a spinning disk; reading one file at a time (change this with --io-limit).");
+            }
+            limit
+        }
+    };
+    let workers = Workers {
+        jobs,
+        limiter: IoLimiter::new(io_limit),
+    };
+
+    let mut reports = Vec::new();
     let mut success_count = 0;
     let mut error_count = 0;
+    let mut recent_count = 0;
+    let mut passed = Vec::new();
 
     for snapshot in &snapshots_to_verify {
-        print!("Verifying snapshot {}: ", snapshot.version);
-
-        match verify_single_snapshot(&base_path, &snapshot.version) {
-            Ok(result) => {
-                if result.success {
-                    println!("✅ OK");
-                    success_count += 1;
-                } else {
-                    println!("❌ FAILED");
-                    println!("  Missing files: {}", result.missing_files);
-                    println!("  Corrupt files: {}", result.corrupt_files);
-                    error_count += 1;
+        if options.fail_fast && error_count > 0 {
+            reports.push(SnapshotReport::new(&snapshot.version, "skipped"));
+            continue;
+        }
+
+        if let Some(window) = skip_recent {
+            if let Some(record) =
+                recent_verification(&base_path, &snapshot.version, window, options)
+            {
+                let report = SnapshotReport {
+                    verified_at: Some(record.verified_at),
+                    ..SnapshotReport::new(&snapshot.version, "recent")
+                };
+                recent_count += 1;
+                if report_each {
+                    print_report(&report, options);
                 }
+                reports.push(report);
+                continue;
             }
-            Err(e) => {
-                println!("❌ ERROR: {}", e);
-                error_count += 1;
+        }
+
+        let previous = baseline_version(&head_manifest, snapshot);
+        let started = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+        let fingerprint = match skip_recent {
+            Some(_) => {
+                snapshot_fingerprint(&info::get_snapshots_dir(&base_path).join(&snapshot.version))
+                    .ok()
             }
+            None => None,
+        };
+        let chain = manifest::snapshot_chain(&base_path, &head_manifest, &snapshot.version);
+        let report =
+            match verify_single_snapshot(&base_path, snapshot, &chain, previous, options, &workers)
+            {
+                Ok(result) => {
+                    if let (true, Some(fingerprint)) = (result.success, fingerprint) {
+                        passed.push((
+                            snapshot.version.clone(),
+                            VerificationRecord {
+                                verified_at: started,
+                                check_links: options.check_links,
+                                check_mtime: options.check_mtime
+                                    && result.mtime_mismatches.is_empty(),
+                                manifest_hash: result.manifest_hash == Some("ok"),
+                                fingerprint,
+                            },
+                        ));
+                    }
+                    SnapshotReport {
+                        status: if result.success { "ok" } else { "failed" },
+                        missing_files: result.missing_files.len(),
+                        corrupt_files: result.corrupt_files.len(),
+                        unshared_links: result.unshared_links,
+                        mtime_mismatches: result.mtime_mismatches,
+                        manifest_hash: result.manifest_hash,
+                        missing: result.missing_files,
+                        corrupt: result.corrupt_files,
+                        ..SnapshotReport::new(&snapshot.version, "")
+                    }
+                }
+                Err(e) => SnapshotReport {
+                    error: Some(e.to_string()),
+                    ..SnapshotReport::new(&snapshot.version, "error")
+                },
+            };
+        if report.status == "ok" {
+            success_count += 1;
+        } else {
+            error_count += 1;
+        }
+        if report_each {
+            print_report(&report, options);
         }
+        reports.push(report);
     }
 
-    println!("\nVerification complete:");
-    println!("  Verified: {}", snapshots_to_verify.len());
-    println!("  Success: {}", success_count);
-    println!("  Failed: {}", error_count);
+    if let Err(e) = watchdog::critical(|| record_verifications(&base_path, passed)) {
+        eprintln!("Warning: couldn't record the verification results: {}", e);
+    }
 
-    if error_count > 0 {
-        return Err(io::Error::new(
-            io::ErrorKind::Other,
-            format!("{} snapshot(s) failed verification", error_count),
-        ));
+    let verified = success_count + error_count;
+    let skipped = snapshots_to_verify.len() - verified - recent_count;
+    if options.json {
+        let report = VerifyReport {
+            snapshots: reports,
+            verified,
+            succeeded: success_count,
+            failed: error_count,
+            skipped,
+            recent: recent_count,
+        };
+        let json = serde_json::to_string_pretty(&report).map_err(io::Error::other)?;
+        println!("{}", json);
+    } else if options.oneline {
+        let mut line = format!(
+            "{} verified={} failed={}",
+            if error_count == 0 { "OK" } else { "FAILED" },
+            verified,
+            error_count
+        );
+        if skipped > 0 {
+            line.push_str(&format!(" skipped={}", skipped));
+        }
+        if recent_count > 0 {
+            line.push_str(&format!(" recent={}", recent_count));
+        }
+        println!("{}", line);
+    } else {
+        print_failures(&reports, options);
+        println!("\nVerification complete:");
+        println!("  Verified: {}", verified);
+        println!("   Success: {}", paint(success_count, GREEN, options.color));
+        let failed = if error_count > 0 {
+            paint(error_count, RED, options.color)
+        } else {
+            error_count.to_string()
+        };
+        println!("    Failed: {}", failed);
+        if skipped > 0 {
+            println!("   Skipped: {} (stopped at the first failure)", skipped);
+        }
+        if recent_count > 0 {
+            println!(
+                "    Recent: {} (verified recently and unchanged)",
+                recent_count
+            );
+        }
+    }
+
+    Ok(error_count == 0)
+}
+
+/// Returns the last verification of a snapshot if it passed within the window, covered at least
+/// the checks requested now, and no file in the snapshot folder changed since it started.
+fn recent_verification(
+    base_path: &Path,
+    version: &str,
+    window: chrono::Duration,
+    options: &VerifyOptions,
+) -> Option<VerificationRecord> {
+    let snapshot_path = info::get_snapshots_dir(base_path).join(version);
+    let record = manifest::load_snapshot_meta(&snapshot_path)
+        .ok()??
+        .last_verified?;
+    if (options.check_links && !record.check_links)
+        || (options.check_mtime && !record.check_mtime)
+        || (options.manifest_hash && !record.manifest_hash)
+    {
+        return None;
+    }
+    let verified_at = NaiveDateTime::parse_from_str(&record.verified_at, "%Y-%m-%d %H:%M:%S")
+        .ok()?
+        .and_local_timezone(Local)
+        .single()?;
+    if Local::now() - verified_at > window {
+        return None;
+    }
+    match snapshot_fingerprint(&snapshot_path) {
+        Ok(fingerprint) if fingerprint == record.fingerprint => Some(record),
+        _ => None,
     }
+}
 
+/// Hashes the path, size, and modification time of every entry in a snapshot folder (the
+/// manifest included, the snapshot's metadata file excluded), so any change to a stored file
+/// can be detected without comparing timestamps with the clock.
+fn snapshot_fingerprint(snapshot_path: &Path) -> io::Result<String> {
+    let mut entries = Vec::new();
+    collect_fingerprint_entries(snapshot_path, snapshot_path, &mut entries)?;
+    entries.sort();
+    let mut hasher = blake3::Hasher::new();
+    for entry in entries {
+        hasher.update(entry.as_bytes());
+        hasher.update(b"\0");
+    }
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Adds a "path size mtime" line for each entry under dir to entries.
+fn collect_fingerprint_entries(
+    root: &Path,
+    dir: &Path,
+    entries: &mut Vec<String>,
+) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let relative = path.strip_prefix(root).unwrap_or(&path);
+        if relative.to_string_lossy().starts_with(SNAPSHOT_META_FILE) {
+            continue;
+        }
+        let meta = path.symlink_metadata()?;
+        let mtime = meta
+            .modified()?
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        entries.push(format!("{} {} {}", relative.display(), meta.len(), mtime));
+        if meta.is_dir() {
+            collect_fingerprint_entries(root, &path, entries)?;
+        }
+    }
     Ok(())
 }
 
+/// Saves the verifications that passed in the head manifest and each snapshot's metadata file.
+fn record_verifications(
+    base_path: &Path,
+    passed: Vec<(String, VerificationRecord)>,
+) -> io::Result<()> {
+    if passed.is_empty() {
+        return Ok(());
+    }
+    let mut head_manifest = load_head_manifest(base_path)?;
+    for (version, record) in passed {
+        if let Some(snapshot) = head_manifest.iter_mut().find(|s| s.version == version) {
+            snapshot
+                .metadata
+                .get_or_insert_with(Default::default)
+                .last_verified = Some(record);
+            manifest::save_snapshot_meta(base_path, snapshot)?;
+        }
+    }
+    save_head_manifest(base_path, &head_manifest)
+}
+
+const GREEN: &str = "32";
+const YELLOW: &str = "33";
+const RED: &str = "31";
+
+/// Wraps text in the given ANSI color code when color is enabled.
+fn paint(text: impl std::fmt::Display, code: &str, color: bool) -> String {
+    if color {
+        format!("\x1b[{}m{}\x1b[0m", code, text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Prints the human-readable result of verifying one snapshot. The details of failures are
+/// printed together by `print_failures` once every snapshot has been checked.
+fn print_report(report: &SnapshotReport, options: &VerifyOptions) {
+    print!("Verifying snapshot {}: ", report.version);
+    match report.status {
+        "ok" if report.mtime_mismatches.is_empty()
+            && report.manifest_hash != Some("unrecorded") =>
+        {
+            println!("{}", paint("✅ OK", GREEN, options.color))
+        }
+        "ok" => {
+            println!("{}", paint("✅ OK (with warnings)", YELLOW, options.color));
+            print_files(
+                "  Modification time mismatches",
+                &report.mtime_mismatches,
+                options,
+            );
+            if report.manifest_hash == Some("unrecorded") {
+                println!("  No manifest checksum recorded; the manifest couldn't be checked");
+            }
+        }
+        "recent" => println!(
+            "{}",
+            paint(
+                format!(
+                    "✅ OK (verified at {}, skipped)",
+                    report.verified_at.as_deref().unwrap_or_default()
+                ),
+                GREEN,
+                options.color
+            )
+        ),
+        "failed" => println!("{}", paint("❌ FAILED", RED, options.color)),
+        _ => println!("{}", paint("❌ ERROR", RED, options.color)),
+    }
+}
+
+/// Prints a "Failures" section with the problems found in every snapshot that failed or
+/// couldn't be checked, so they don't scroll off in a long run.
+fn print_failures(reports: &[SnapshotReport], options: &VerifyOptions) {
+    let failures: Vec<&SnapshotReport> = reports
+        .iter()
+        .filter(|r| r.status == "failed" || r.status == "error")
+        .collect();
+    if failures.is_empty() {
+        return;
+    }
+
+    println!("\n{}", paint("Failures:", RED, options.color));
+    for report in failures {
+        if let Some(ref error) = report.error {
+            println!("  {}: {}", report.version, error);
+            continue;
+        }
+        println!("  {}:", report.version);
+        if report.manifest_hash == Some("mismatch") {
+            println!("    Manifest mismatch: modified after the snapshot was created");
+        }
+        print_files("    Missing files", &report.missing, options);
+        print_files("    Corrupt files", &report.corrupt, options);
+        print_files("    Unshared hard links", &report.unshared_links, options);
+        print_files(
+            "    Modification time mismatches",
+            &report.mtime_mismatches,
+            options,
+        );
+    }
+}
+
+/// Prints a labelled count and the files behind it, listing at most `FAILURE_LIST_LIMIT` of them
+/// unless `show_all` is set. Nothing is printed for an empty list.
+fn print_files(label: &str, files: &[String], options: &VerifyOptions) {
+    if files.is_empty() {
+        return;
+    }
+    let indent = " ".repeat(label.len() - label.trim_start().len());
+    println!("{}: {}", label, files.len());
+    let shown = if options.show_all {
+        files.len()
+    } else {
+        FAILURE_LIST_LIMIT
+    };
+    for path in files.iter().take(shown) {
+        println!("{}  - {}", indent, path);
+    }
+    if files.len() > shown {
+        println!(
+            "{}  ... and {} more (use --show-all to list them)",
+            indent,
+            files.len() - shown
+        );
+    }
+}
+
+/// Returns the version of the snapshot the given one was hard-linked against: its recorded
+/// parent (e.g. the one given with `snapshot --from`), or for snapshots that don't record one,
+/// the snapshot created right before it. None if there is none or the parent was removed.
+fn baseline_version<'a>(
+    head_manifest: &'a [SnapshotIndex],
+    snapshot: &SnapshotIndex,
+) -> Option<&'a str> {
+    if let Some(ref parent) = snapshot.parent {
+        return head_manifest
+            .iter()
+            .find(|s| &s.version == parent)
+            .map(|s| s.version.as_str());
+    }
+    let position = head_manifest
+        .iter()
+        .position(|s| s.version == snapshot.version)?;
+    if position == 0 {
+        None
+    } else {
+        Some(head_manifest[position - 1].version.as_str())
+    }
+}
+
 /// Result of verifying a single snapshot
 struct VerificationResult {
     success: bool,
-    missing_files: usize,
-    corrupt_files: usize,
+    missing_files: Vec<String>,
+    corrupt_files: Vec<String>,
+    /// Files that are identical to the baseline snapshot but no longer share its inode.
+    unshared_links: Vec<String>,
+    /// Files whose modification time differs from the manifest.
+    mtime_mismatches: Vec<String>,
+    /// Outcome of the `--manifest-hash` check, if it was requested.
+    manifest_hash: Option<&'static str>,
 }
 
-/// Verify a single snapshot
-fn verify_single_snapshot(base_path: &Path, version: &str) -> io::Result<VerificationResult> {
-    let snapshot_path = base_path
-        .join(REPO_FOLDER)
-        .join(SNAPSHOTS_FOLDER)
-        .join(version);
+/// Verify a single snapshot. Files an incremental snapshot left out are checked in the folders
+/// of the snapshots it builds on, the rest of `chain`.
+fn verify_single_snapshot(
+    base_path: &Path,
+    snapshot: &SnapshotIndex,
+    chain: &[PathBuf],
+    previous: Option<&str>,
+    options: &VerifyOptions,
+    workers: &Workers,
+) -> io::Result<VerificationResult> {
+    let version = &snapshot.version;
+    let snapshot_path = info::get_snapshots_dir(base_path).join(version);
 
     if !snapshot_path.exists() {
         return Err(io::Error::new(
@@ -108,42 +592,223 @@ fn verify_single_snapshot(base_path: &Path, version: &str) -> io::Result<Verific
         ));
     }
 
+    let manifest_hash = if options.manifest_hash {
+        match snapshot.manifest_checksum {
+            Some(ref recorded) if *recorded == manifest::manifest_checksum(&snapshot_path)? => {
+                Some("ok")
+            }
+            Some(_) => Some("mismatch"),
+            None => Some("unrecorded"),
+        }
+    } else {
+        None
+    };
+
     // Load the snapshot manifest
-    let manifest_content = fs::read_to_string(&manifest_path)?;
-    let metadata_vec: Vec<FileMetadata> = serde_json::from_str(&manifest_content)
-        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let manifest_read = profile::start("manifest read");
+    let metadata_vec: Vec<FileMetadata> = manifest::read_manifest_entries(&manifest_path)?;
+    drop(manifest_read);
+    let file_checks = profile::start("file checks");
 
-    let mut missing_files = 0;
-    let mut corrupt_files = 0;
+    let mut missing_files = Vec::new();
+    let mut corrupt_files = Vec::new();
+    let mut mtime_mismatches = Vec::new();
 
     // Verify each file in the manifest
-    for meta in &metadata_vec {
-        let file_path = snapshot_path.join(&meta.relative_path);
-
-        if !file_path.exists() {
-            missing_files += 1;
-            continue;
+    for (meta, check) in
+        metadata_vec
+            .iter()
+            .zip(check_files(&metadata_vec, chain, options, workers))
+    {
+        if check.missing {
+            missing_files.push(meta.relative_path.clone());
         }
-
-        let actual_meta = match fs::metadata(&file_path) {
-            Ok(m) => m,
-            Err(_) => {
-                corrupt_files += 1;
-                continue;
-            }
-        };
-
-        // Check file size
-        if actual_meta.len() != meta.file_size {
-            corrupt_files += 1;
+        if check.corrupt {
+            corrupt_files.push(meta.relative_path.clone());
+        }
+        if check.mtime_mismatch {
+            mtime_mismatches.push(meta.relative_path.clone());
         }
     }
+    missing_files.sort();
+    corrupt_files.sort();
+    mtime_mismatches.sort();
+    drop(file_checks);
+
+    let unshared_links = match (options.check_links, previous) {
+        (true, Some(prev)) => profile::time("link checks", || {
+            find_unshared_links(base_path, &snapshot_path, &metadata_vec, prev)
+        })?,
+        _ => Vec::new(),
+    };
 
-    let success = missing_files == 0 && corrupt_files == 0;
+    let success = missing_files.is_empty()
+        && corrupt_files.is_empty()
+        && unshared_links.is_empty()
+        && manifest_hash != Some("mismatch")
+        && (!options.strict || mtime_mismatches.is_empty());
 
     Ok(VerificationResult {
         success,
         missing_files,
         corrupt_files,
+        unshared_links,
+        mtime_mismatches,
+        manifest_hash,
     })
 }
+
+/// How many files are checked at once, and how many of those checks may read storage at a time.
+struct Workers {
+    jobs: usize,
+    limiter: IoLimiter,
+}
+
+/// Problems found with one stored file.
+#[derive(Default)]
+struct FileCheck {
+    missing: bool,
+    corrupt: bool,
+    mtime_mismatch: bool,
+}
+
+/// Checks the stored copy of each file in the manifest, on up to `jobs` threads, and returns
+/// the results in manifest order.
+fn check_files(
+    metadata_vec: &[FileMetadata],
+    chain: &[PathBuf],
+    options: &VerifyOptions,
+    workers: &Workers,
+) -> Vec<FileCheck> {
+    let jobs = workers.jobs.clamp(1, metadata_vec.len().max(1));
+    if jobs == 1 {
+        return metadata_vec
+            .iter()
+            .map(|meta| check_file(meta, chain, options, &workers.limiter))
+            .collect();
+    }
+
+    // Workers take the next unchecked file until none are left
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<(usize, FileCheck)> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..jobs)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut checked = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(meta) = metadata_vec.get(index) else {
+                            break;
+                        };
+                        checked.push((index, check_file(meta, chain, options, &workers.limiter)));
+                    }
+                    checked
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("verify worker thread panicked"))
+            .collect()
+    });
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, check)| check).collect()
+}
+
+/// Checks that one file's stored copy exists with the recorded size and, with `check_mtime`,
+/// modification time (that of the baseline's copy for files linked after a rewrite).
+fn check_file(
+    meta: &FileMetadata,
+    chain: &[PathBuf],
+    options: &VerifyOptions,
+    limiter: &IoLimiter,
+) -> FileCheck {
+    // Stored symbolic links may dangle, so don't follow them
+    let stored = {
+        let _permit = limiter.acquire();
+        let dir = manifest::stored_file_dir(chain, &meta.relative_path);
+        fs::symlink_metadata(dir.join(&meta.relative_path))
+    };
+    let Ok(actual_meta) = stored else {
+        return FileCheck {
+            missing: true,
+            ..FileCheck::default()
+        };
+    };
+
+    FileCheck {
+        missing: false,
+        // Files stored compressed take their compressed size on disk
+        corrupt: actual_meta.len() != meta.compressed_size.unwrap_or(meta.file_size),
+        mtime_mismatch: options.check_mtime
+            && meta.link_target.is_none()
+            && !meta.modified.is_empty()
+            && !mtime_matches(
+                &actual_meta,
+                meta.stored_modified.as_ref().unwrap_or(&meta.modified),
+            ),
+    }
+}
+
+/// Returns true if the file's modification time is within `MTIME_TOLERANCE_SECS` of the
+/// recorded `modified` timestamp. Unreadable or unparseable times count as a mismatch.
+fn mtime_matches(actual: &fs::Metadata, recorded: &str) -> bool {
+    let recorded = match NaiveDateTime::parse_from_str(recorded, "%Y-%m-%d %H:%M:%S") {
+        Ok(time) => time,
+        Err(_) => return false,
+    };
+    match actual.modified() {
+        Ok(time) => {
+            let actual = DateTime::<Local>::from(time).naive_local();
+            (actual - recorded).num_seconds().abs() <= MTIME_TOLERANCE_SECS
+        }
+        Err(_) => false,
+    }
+}
+
+/// Returns the files of a snapshot that are unchanged since the baseline snapshot (same size,
+/// mode, and hash or modification time, so they should have been hard-linked) but no longer
+/// share its inode.
+#[cfg(unix)]
+fn find_unshared_links(
+    base_path: &Path,
+    snapshot_path: &Path,
+    metadata_vec: &[FileMetadata],
+    previous: &str,
+) -> io::Result<Vec<String>> {
+    use crate::subcommands::diff::{self, DiffOptions};
+    use std::os::unix::fs::MetadataExt;
+
+    let (prev_path, prev_manifest) = match manifest::load_snapshot_manifest(base_path, previous)? {
+        Some(snap) => snap,
+        None => return Ok(Vec::new()),
+    };
+
+    let mut unshared = Vec::new();
+    for meta in metadata_vec.iter().filter(|m| m.link_target.is_none()) {
+        let prev_meta = match prev_manifest.get(&meta.relative_path) {
+            Some(p) if !diff::is_modified(p, meta, &DiffOptions::default()) => p,
+            _ => continue,
+        };
+        let current = fs::metadata(snapshot_path.join(&meta.relative_path));
+        let prior = fs::metadata(prev_path.join(&prev_meta.relative_path));
+        if let (Ok(current), Ok(prior)) = (current, prior) {
+            if current.dev() != prior.dev() || current.ino() != prior.ino() {
+                unshared.push(meta.relative_path.clone());
+            }
+        }
+    }
+    unshared.sort();
+    Ok(unshared)
+}
+
+/// Inode information isn't available on this platform, so hard-link sharing can't be checked.
+#[cfg(not(unix))]
+fn find_unshared_links(
+    _base_path: &Path,
+    _snapshot_path: &Path,
+    _metadata_vec: &[FileMetadata],
+    _previous: &str,
+) -> io::Result<Vec<String>> {
+    Ok(Vec::new())
+}
@@ -0,0 +1,83 @@
+//! Time limit for the global `--timeout` flag.
+//!
+//! A hung read or write on network storage can block a command forever. When a limit is set, a
+//! background thread waits for it to pass, removes any partially written snapshot, and exits
+//! with `EXIT_TIMED_OUT`. Commands mark the steps that must not be cut short (such as saving
+//! the head manifest) with `commit` or `critical`, so the repository is never left half-updated.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use crate::constants::EXIT_TIMED_OUT;
+
+/// What to clean up and report when the limit passes.
+struct State {
+    /// Snapshot folders that were created but aren't in the head manifest yet.
+    partial: Vec<PathBuf>,
+    /// Extra advice printed with the timeout error.
+    note: Option<String>,
+}
+
+static STATE: Mutex<State> = Mutex::new(State {
+    partial: Vec::new(),
+    note: None,
+});
+
+/// Starts the watchdog for a command that must finish within `secs` seconds.
+pub fn start(command: &'static str, secs: u64) {
+    thread::spawn(move || {
+        thread::sleep(Duration::from_secs(secs));
+        // Waits for any critical section in progress to finish
+        let state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+        eprintln!(
+            "Error: {} timed out after {} second(s) and was aborted.",
+            command, secs
+        );
+        for dir in &state.partial {
+            if fs::remove_dir_all(dir).is_ok() {
+                eprintln!("Removed the partial snapshot {}.", dir.display());
+            } else {
+                eprintln!(
+                    "Couldn't remove the partial snapshot {}; once the storage responds, delete that folder by hand ('snapsafe check' reports it as not in the head manifest).",
+                    dir.display()
+                );
+            }
+        }
+        if let Some(ref note) = state.note {
+            eprintln!("{}", note);
+        }
+        process::exit(EXIT_TIMED_OUT);
+    });
+}
+
+/// Registers a snapshot folder that is being written, so it's removed if the limit passes
+/// before `commit` is called for it.
+pub fn track_partial(dir: &Path) {
+    let mut state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    state.partial.push(dir.to_path_buf());
+}
+
+/// Runs the step that makes the snapshot folder part of the repository. The watchdog can't
+/// abort while it runs, and the folder is no longer removed on timeout once it succeeds.
+pub fn commit<T>(dir: &Path, step: impl FnOnce() -> std::io::Result<T>) -> std::io::Result<T> {
+    let mut state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    let result = step()?;
+    state.partial.retain(|partial| partial != dir);
+    Ok(result)
+}
+
+/// Runs a step that the watchdog must not interrupt, such as writing a repository file.
+pub fn critical<T>(step: impl FnOnce() -> T) -> T {
+    let _state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    step()
+}
+
+/// Sets advice printed with the timeout error, e.g. how to undo a partial restore.
+pub fn set_note(note: String) {
+    let mut state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    state.note = Some(note);
+}
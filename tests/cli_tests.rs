@@ -1,6 +1,7 @@
 use assert_cmd::Command;
 use predicates::prelude::*;
 use std::fs;
+use std::path::{Path, PathBuf};
 use tempfile::TempDir;
 
 // Helper function to set up a test environment
@@ -35,6 +36,83 @@ fn setup_test_env() -> TempDir {
     temp_dir
 }
 
+// Helper function to recursively copy a directory
+fn copy_dir(src: &Path, dst: &Path) {
+    fs::create_dir_all(dst).unwrap();
+    for entry in fs::read_dir(src).unwrap() {
+        let entry = entry.unwrap();
+        let target = dst.join(entry.file_name());
+        if entry.path().is_dir() {
+            copy_dir(&entry.path(), &target);
+        } else {
+            fs::copy(entry.path(), target).unwrap();
+        }
+    }
+}
+
+// Helper function to collect every file under a directory with its contents, for detecting writes
+fn read_tree(dir: &Path) -> Vec<(PathBuf, Vec<u8>)> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            files.extend(read_tree(&path));
+        } else {
+            files.push((path.clone(), fs::read(&path).unwrap()));
+        }
+    }
+    files.sort();
+    files
+}
+
+// Helper function to set the read-only flag on a directory tree
+fn set_tree_readonly(dir: &Path, readonly: bool) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            set_tree_readonly(&path, readonly);
+        }
+        let mut permissions = fs::metadata(&path).unwrap().permissions();
+        permissions.set_readonly(readonly);
+        fs::set_permissions(&path, permissions).unwrap();
+    }
+}
+
+// Helper function to build a snapsafe command running in the given directory
+fn snapsafe(dir: &Path) -> Command {
+    let mut cmd = Command::cargo_bin("snapsafe").unwrap();
+    cmd.current_dir(dir);
+    cmd
+}
+
+// Helper function to set up a test environment with an initialized repository
+fn setup_initialized_env() -> TempDir {
+    let temp_dir = setup_test_env();
+    snapsafe(temp_dir.path()).arg("init").assert().success();
+    temp_dir
+}
+
+// Helper function to write an archive holding a link `v1/evil` to outside, followed by a file
+// written through it, `v1/evil/f`
+#[cfg(unix)]
+fn write_link_escape_archive(archive_path: &Path, outside: &Path) {
+    let file = fs::File::create(archive_path).unwrap();
+    let encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+    let mut link = tar::Header::new_gnu();
+    link.set_entry_type(tar::EntryType::Symlink);
+    link.set_size(0);
+    link.set_mode(0o777);
+    builder.append_link(&mut link, "v1/evil", outside).unwrap();
+    let mut header = tar::Header::new_gnu();
+    header.set_size(5);
+    header.set_mode(0o644);
+    builder
+        .append_data(&mut header, "v1/evil/f", &b"owned"[..])
+        .unwrap();
+    builder.into_inner().unwrap().finish().unwrap();
+}
+
 #[test]
 fn test_init_command() {
     let temp_dir = setup_test_env();
@@ -51,6 +129,101 @@ fn test_init_command() {
         .exists());
 }
 
+#[test]
+fn test_init_with_external_store() {
+    let temp_dir = setup_test_env();
+    let temp_path = temp_dir.path();
+    let store_dir = TempDir::new().unwrap();
+    let store = store_dir.path().join("store");
+
+    snapsafe(temp_path)
+        .args(["init", "--store"])
+        .arg(&store)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Snapshots will be stored in"));
+    snapsafe(temp_path)
+        .args(["snapshot", "-m", "External"])
+        .assert()
+        .success();
+
+    assert!(store.join("v1.0.0.0").join("file1.txt").exists());
+    assert!(store.join("v1.0.0.0").join("manifest.json").exists());
+    assert!(!temp_path
+        .join(".snapsafe")
+        .join("snapshots")
+        .join("v1.0.0.0")
+        .exists());
+
+    // Snapshots in the store are found by the other commands
+    snapsafe(temp_path).arg("verify").assert().success();
+    fs::write(temp_path.join("file1.txt"), "Changed content").unwrap();
+    snapsafe(temp_path)
+        .args(["restore", "v1.0.0.0"])
+        .write_stdin("\n")
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(temp_path.join("file1.txt")).unwrap(),
+        "File 1 content"
+    );
+
+    // The store can't be put inside the directory being snapshotted
+    let other = setup_test_env();
+    snapsafe(other.path())
+        .args(["init", "--store", "inside"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("must be outside"));
+}
+
+#[test]
+fn test_init_repairs_head_manifest() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+    let head_manifest = temp_path.join(".snapsafe").join("head_manifest.json");
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    // A missing head manifest is recreated; the snapshot folder is kept and reported
+    fs::remove_file(&head_manifest).unwrap();
+    snapsafe(temp_path)
+        .arg("init")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Initialized head manifest"))
+        .stdout(predicate::str::contains("not listed in the head manifest"))
+        .stdout(predicate::str::contains("  - v1.0.0.0"));
+    assert_eq!(fs::read_to_string(&head_manifest).unwrap(), "[]");
+    assert!(temp_path.join(".snapsafe/snapshots/v1.0.0.0").exists());
+
+    // A corrupt one is only reset with --force
+    fs::write(&head_manifest, "{not json").unwrap();
+    snapsafe(temp_path)
+        .arg("init")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("init --force"));
+    snapsafe(temp_path)
+        .args(["init", "--force"])
+        .write_stdin("n\n")
+        .assert()
+        .failure();
+    assert_eq!(fs::read_to_string(&head_manifest).unwrap(), "{not json");
+
+    snapsafe(temp_path)
+        .args(["init", "--force"])
+        .write_stdin("y\n")
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(&head_manifest).unwrap(), "[]");
+    assert_eq!(
+        fs::read_to_string(temp_path.join(".snapsafe/head_manifest.json.bak")).unwrap(),
+        "{not json"
+    );
+    assert!(temp_path.join(".snapsafe/snapshots/v1.0.0.0").exists());
+}
+
 #[test]
 fn test_snapshot_and_list() {
     let temp_dir = setup_test_env();
@@ -170,3 +343,4620 @@ fn test_tag_and_metadata() {
         .stdout(predicate::str::contains("test-tag"))
         .stdout(predicate::str::contains("test-key=test-value"));
 }
+
+#[test]
+fn test_snapshot_concurrency_safe_copies_rewritten_files() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+    let file1 = temp_path.join("file1.txt");
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    // A rewrite that keeps the size and modification time, as a concurrent writer caught
+    // mid-update can, looks unchanged to the size and mtime comparison
+    let mtime = filetime::FileTime::from_last_modification_time(&fs::metadata(&file1).unwrap());
+    fs::write(&file1, "File 1 CONTENT").unwrap();
+    filetime::set_file_mtime(&file1, mtime).unwrap();
+
+    let stored = |version: &str| {
+        fs::read_to_string(
+            temp_path
+                .join(".snapsafe/snapshots")
+                .join(version)
+                .join("file1.txt"),
+        )
+        .unwrap()
+    };
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    assert_eq!(stored("v1.0.0.1"), "File 1 content");
+
+    snapsafe(temp_path)
+        .args(["config", "--set", "snapshot_concurrency_safe", "true"])
+        .assert()
+        .success();
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    assert_eq!(stored("v1.0.0.2"), "File 1 CONTENT");
+    // Files whose content does match are still linked
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let inode = |version: &str| {
+            fs::metadata(
+                temp_path
+                    .join(".snapsafe/snapshots")
+                    .join(version)
+                    .join("file2.txt"),
+            )
+            .unwrap()
+            .ino()
+        };
+        assert_eq!(inode("v1.0.0.1"), inode("v1.0.0.2"));
+    }
+}
+
+#[test]
+fn test_snapshot_profile_prints_phase_timings() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    let output = snapsafe(temp_path)
+        .args(["snapshot", "--profile"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let output = String::from_utf8(output).unwrap();
+    let profile = &output[output.find("Profile:").expect("no profile printed")..];
+    let timing = |phase: &str| -> f64 {
+        let line = profile
+            .lines()
+            .find(|line| line.trim_start().starts_with(phase))
+            .unwrap_or_else(|| panic!("no {} timing", phase));
+        line.trim_end_matches(" ms")
+            .split_whitespace()
+            .last()
+            .unwrap()
+            .parse()
+            .unwrap()
+    };
+    assert!(timing("traversal") > 0.0);
+    assert!(timing("manifest write") > 0.0);
+
+    // Nothing is printed without the flag
+    snapsafe(temp_path)
+        .arg("snapshot")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Profile:").not());
+}
+
+#[test]
+fn test_snapshot_only_if_idle() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    // Age every included file; the ignored ones stay fresh and don't count
+    let hour_ago = filetime::FileTime::from_unix_time(chrono::Local::now().timestamp() - 3600, 0);
+    for (path, _) in read_tree(temp_path) {
+        let rel = path
+            .strip_prefix(temp_path)
+            .unwrap()
+            .to_string_lossy()
+            .into_owned();
+        if !rel.starts_with(".snapsafe/") && !rel.starts_with("ignored") {
+            filetime::set_file_mtime(&path, hour_ago).unwrap();
+        }
+    }
+    snapsafe(temp_path)
+        .args(["snapshot", "--only-if-idle", "60"])
+        .assert()
+        .success();
+
+    fs::write(temp_path.join("file1.txt"), "Still being written").unwrap();
+    snapsafe(temp_path)
+        .args(["snapshot", "--only-if-idle", "60"])
+        .assert()
+        .code(12)
+        .stdout(predicate::str::contains("Skipping snapshot: file1.txt"));
+    assert!(!temp_path.join(".snapsafe/snapshots/v1.0.0.1").exists());
+}
+
+#[test]
+fn test_snapshot_if_changed_exit_code() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path)
+        .args(["snapshot", "-m", "First snapshot"])
+        .assert()
+        .success();
+
+    // Nothing changed, so no snapshot is created and the documented exit code is returned
+    snapsafe(temp_path)
+        .args(["snapshot", "--if-changed"])
+        .assert()
+        .code(10)
+        .stdout(predicate::str::contains("No changes"));
+
+    snapsafe(temp_path)
+        .args(["snapshot", "--if-changed", "--quiet-on-no-change"])
+        .assert()
+        .code(10)
+        .stdout(predicate::str::is_empty());
+
+    snapsafe(temp_path)
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("v1.0.0.1").not());
+
+    // A new file means a snapshot is taken
+    fs::write(temp_path.join("new_file.txt"), "New content").unwrap();
+    snapsafe(temp_path)
+        .args(["snapshot", "--if-changed"])
+        .assert()
+        .success();
+
+    snapsafe(temp_path)
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("v1.0.0.1"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_verify_check_links_detects_broken_hard_link() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    snapsafe(temp_path)
+        .args(["verify", "--check-links"])
+        .assert()
+        .success();
+
+    // Replace the hard-linked file with an identical, independent copy
+    let stored = temp_path
+        .join(".snapsafe")
+        .join("snapshots")
+        .join("v1.0.0.1")
+        .join("file2.txt");
+    let copy = temp_path.join("copy.tmp");
+    fs::copy(&stored, &copy).unwrap();
+    fs::remove_file(&stored).unwrap();
+    fs::rename(&copy, &stored).unwrap();
+
+    // Plain verification can't tell the difference
+    snapsafe(temp_path).arg("verify").assert().success();
+
+    snapsafe(temp_path)
+        .args(["verify", "--check-links"])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("Unshared hard links: 1"))
+        .stdout(predicate::str::contains("file2.txt"));
+}
+
+#[test]
+fn test_verify_skip_recent() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    snapsafe(temp_path)
+        .args(["verify", "--skip-recent", "1h"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Verified: 1"));
+
+    snapsafe(temp_path)
+        .args(["verify", "--skip-recent", "1h"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("skipped"))
+        .stdout(predicate::str::contains("Verified: 0"))
+        .stdout(predicate::str::contains("Recent: 1"));
+
+    // Stricter checks than the recorded verification aren't skipped
+    snapsafe(temp_path)
+        .args(["verify", "--skip-recent", "1h", "--check-links"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Verified: 1"));
+
+    // Modifying a stored file invalidates the cached result
+    let stored = temp_path.join(".snapsafe/snapshots/v1.0.0.0/file1.txt");
+    fs::write(&stored, "Tampered").unwrap();
+    snapsafe(temp_path)
+        .args(["verify", "--skip-recent", "1h"])
+        .assert()
+        .code(11)
+        .stdout(predicate::str::contains("Verified: 1"));
+}
+
+#[test]
+fn test_verify_fail_fast_stops_at_first_failure() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    // Break the first snapshot only
+    let stored = temp_path
+        .join(".snapsafe")
+        .join("snapshots")
+        .join("v1.0.0.0")
+        .join("file1.txt");
+    fs::remove_file(&stored).unwrap();
+
+    snapsafe(temp_path)
+        .args(["verify", "--fail-fast"])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("Verified: 1"))
+        .stdout(predicate::str::contains("Skipped: 2"))
+        .stdout(predicate::str::contains("Verifying snapshot v1.0.0.1").not());
+
+    snapsafe(temp_path)
+        .arg("verify")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("Verified: 3"));
+}
+
+#[test]
+fn test_verify_groups_failures_at_the_end() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+    for i in 0..6 {
+        fs::write(temp_path.join(format!("extra{}.txt", i)), "extra").unwrap();
+    }
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    // Six missing files in the first snapshot, a corrupt one in the last. The corrupt file is
+    // replaced rather than edited, since its inode is shared with the other snapshots.
+    let snapshots = temp_path.join(".snapsafe").join("snapshots");
+    for i in 0..6 {
+        fs::remove_file(snapshots.join(format!("v1.0.0.0/extra{}.txt", i))).unwrap();
+    }
+    let corrupt = snapshots.join("v1.0.0.2").join("file1.txt");
+    fs::remove_file(&corrupt).unwrap();
+    fs::write(&corrupt, "tampered").unwrap();
+
+    let output = snapsafe(temp_path)
+        .args(["verify", "--color", "never"])
+        .assert()
+        .failure()
+        .get_output()
+        .stdout
+        .clone();
+    let output = String::from_utf8(output).unwrap();
+
+    let failures = output.find("Failures:").expect("no failures section");
+    let last_result = output.rfind("Verifying snapshot").unwrap();
+    assert!(failures > last_result);
+    let section = &output[failures..];
+    assert!(section.contains("v1.0.0.0:\n    Missing files: 6"));
+    assert!(section.contains("... and 1 more (use --show-all to list them)"));
+    assert!(section.contains("v1.0.0.2:\n    Corrupt files: 1\n      - file1.txt"));
+    assert!(!section.contains("v1.0.0.1"));
+    assert!(!output.contains('\x1b'));
+
+    snapsafe(temp_path)
+        .args(["verify", "--show-all"])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("- extra5.txt"))
+        .stdout(predicate::str::contains("more (use --show-all").not());
+}
+
+#[test]
+fn test_verify_json_reports_each_snapshot() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    fs::write(temp_path.join("file1.txt"), "Second version of file 1").unwrap();
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    // Corrupt the second snapshot only
+    let stored = temp_path.join(".snapsafe/snapshots/v1.0.0.1/file1.txt");
+    fs::remove_file(&stored).unwrap();
+    fs::write(&stored, "short").unwrap();
+
+    let output = snapsafe(temp_path)
+        .args(["verify", "--json"])
+        .assert()
+        .code(11)
+        .get_output()
+        .stdout
+        .clone();
+    let report: serde_json::Value = serde_json::from_slice(&output).unwrap();
+    assert_eq!(report["snapshots"][0]["version"], "v1.0.0.0");
+    assert_eq!(report["snapshots"][0]["status"], "ok");
+    assert_eq!(report["snapshots"][1]["version"], "v1.0.0.1");
+    assert_eq!(report["snapshots"][1]["status"], "failed");
+    assert_eq!(report["snapshots"][1]["corrupt_files"], 1);
+    assert_eq!(report["succeeded"], 1);
+    assert_eq!(report["failed"], 1);
+
+    // An unreadable repository is a different kind of failure
+    fs::write(temp_path.join(".snapsafe/head_manifest.json"), "not json").unwrap();
+    snapsafe(temp_path)
+        .args(["verify", "--json"])
+        .assert()
+        .code(1);
+}
+
+#[test]
+fn test_restore_rollback_on_error() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    // Change the working tree, and turn file2.txt into a directory so restoring it fails
+    fs::write(temp_path.join("file1.txt"), "Changed content").unwrap();
+    fs::write(temp_path.join("new_file.txt"), "New content").unwrap();
+    fs::remove_file(temp_path.join("file2.txt")).unwrap();
+    fs::create_dir(temp_path.join("file2.txt")).unwrap();
+    fs::write(temp_path.join("file2.txt").join("inner.txt"), "Inner").unwrap();
+
+    snapsafe(temp_path)
+        .args(["restore", "v1.0.0.0", "--rollback-on-error"])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "Rolled back to backup snapshot v1.0.0.1",
+        ))
+        .stderr(predicate::str::contains("file2.txt"));
+
+    // The working tree is back to its pre-restore state
+    assert_eq!(
+        fs::read_to_string(temp_path.join("file1.txt")).unwrap(),
+        "Changed content"
+    );
+    assert_eq!(
+        fs::read_to_string(temp_path.join("new_file.txt")).unwrap(),
+        "New content"
+    );
+    assert!(temp_path.join("file2.txt").join("inner.txt").exists());
+}
+
+#[test]
+fn test_restore_failure_without_backup_reports_written_files() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    fs::remove_file(temp_path.join("file2.txt")).unwrap();
+    fs::create_dir(temp_path.join("file2.txt")).unwrap();
+
+    snapsafe(temp_path)
+        .args(["restore", "v1.0.0.0", "--no-backup"])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("Restore failed after writing"))
+        .stdout(predicate::str::contains("No backup snapshot was created"));
+}
+
+#[test]
+fn test_typed_metadata_range_query() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    fs::write(temp_path.join("file1.txt"), "Modified content").unwrap();
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    snapsafe(temp_path)
+        .args([
+            "meta",
+            "v1.0.0.0",
+            "--set",
+            "build_number",
+            "9",
+            "--type",
+            "int",
+        ])
+        .assert()
+        .success();
+    snapsafe(temp_path)
+        .args([
+            "meta",
+            "v1.0.0.1",
+            "--set",
+            "build_number",
+            "42",
+            "--type",
+            "int",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("build_number = 42 (int)"));
+
+    // Compared as numbers: "9" would sort after "40" as a string
+    snapsafe(temp_path)
+        .args(["find", "--meta-gt", "build_number", "40"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("v1.0.0.1"))
+        .stdout(predicate::str::contains("v1.0.0.0").not());
+
+    snapsafe(temp_path)
+        .args([
+            "meta",
+            "v1.0.0.0",
+            "--set",
+            "build_number",
+            "abc",
+            "--type",
+            "int",
+        ])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_snapshot_exclude_vcs() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+    let config_home = TempDir::new().unwrap();
+
+    fs::create_dir(temp_path.join(".svn")).unwrap();
+    fs::write(temp_path.join(".svn").join("entries"), "svn metadata").unwrap();
+    fs::create_dir(temp_path.join(".hg")).unwrap();
+    fs::write(temp_path.join(".hg").join("store"), "hg metadata").unwrap();
+
+    snapsafe(temp_path)
+        .env("XDG_CONFIG_HOME", config_home.path())
+        .arg("snapshot")
+        .assert()
+        .success();
+    snapsafe(temp_path)
+        .env("XDG_CONFIG_HOME", config_home.path())
+        .args(["snapshot", "--exclude-vcs"])
+        .assert()
+        .success();
+
+    let snapshots = temp_path.join(".snapsafe").join("snapshots");
+    assert!(snapshots
+        .join("v1.0.0.0")
+        .join(".svn")
+        .join("entries")
+        .exists());
+    assert!(!snapshots.join("v1.0.0.1").join(".svn").exists());
+    assert!(!snapshots.join("v1.0.0.1").join(".hg").exists());
+    assert!(snapshots.join("v1.0.0.1").join("file1.txt").exists());
+
+    // The config toggle has the same effect without the flag
+    snapsafe(temp_path)
+        .env("XDG_CONFIG_HOME", config_home.path())
+        .args(["config", "--set", "exclude_vcs", "true"])
+        .assert()
+        .success();
+    snapsafe(temp_path)
+        .env("XDG_CONFIG_HOME", config_home.path())
+        .arg("snapshot")
+        .assert()
+        .success();
+    assert!(!snapshots.join("v1.0.0.2").join(".svn").exists());
+
+    snapsafe(temp_path)
+        .env("XDG_CONFIG_HOME", config_home.path())
+        .args(["config", "--set", "exclude_vcs", "maybe"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_check_fix_manifest() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    let snapshot_dir = temp_path
+        .join(".snapsafe")
+        .join("snapshots")
+        .join("v1.0.0.0");
+    fs::remove_file(snapshot_dir.join("manifest.json")).unwrap();
+
+    snapsafe(temp_path)
+        .args(["verify", "v1.0.0.0"])
+        .assert()
+        .failure();
+    snapsafe(temp_path)
+        .arg("check")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("manifest is missing"));
+
+    snapsafe(temp_path)
+        .args(["fsck", "--fix-manifest", "v1.0.0.0"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Rebuilt manifest for snapshot v1.0.0.0 (4 files)",
+        ));
+
+    snapsafe(temp_path)
+        .args(["verify", "v1.0.0.0"])
+        .assert()
+        .success();
+
+    // An unchanged tree still hard-links against the rebuilt manifest
+    snapsafe(temp_path)
+        .args(["snapshot", "--if-changed"])
+        .assert()
+        .code(10);
+}
+
+#[test]
+fn test_list_columns() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path)
+        .args(["snapshot", "-m", "Hidden message", "--tags", "release"])
+        .assert()
+        .success();
+
+    let output = snapsafe(temp_path)
+        .args(["list", "--columns", "tags,version,size"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let output = String::from_utf8(output).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    let header: Vec<&str> = lines[0].split_whitespace().collect();
+    assert_eq!(header, vec!["Tags", "Version", "Size"]);
+    let row: Vec<&str> = lines[2].split_whitespace().collect();
+    assert_eq!(row[..2], ["release", "v1.0.0.0"]);
+    assert!(!output.contains("Timestamp"));
+    assert!(!output.contains("Hidden message"));
+
+    snapsafe(temp_path)
+        .args(["list", "--columns", "version,owner"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid value 'owner'"));
+}
+
+#[test]
+fn test_list_show_size() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    fs::write(temp_path.join("large.bin"), vec![0u8; 2 * 1024 * 1024]).unwrap();
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    snapsafe(temp_path)
+        .args(["list", "--show-size"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Size"))
+        .stdout(predicate::str::contains("2.0 MiB"));
+
+    // Snapshots without a cached size fall back to the manifest
+    let head_path = temp_path.join(".snapsafe").join("head_manifest.json");
+    let head = fs::read_to_string(&head_path).unwrap();
+    fs::write(&head_path, head.replace("\"total_size\"", "\"unused\"")).unwrap();
+    snapsafe(temp_path)
+        .args(["list", "--show-size"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("2.0 MiB"));
+}
+
+#[test]
+fn test_snapshot_no_ignore() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    snapsafe(temp_path)
+        .args(["snapshot", "--no-ignore"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Ignore rules disabled"));
+
+    let snapshots = temp_path.join(".snapsafe").join("snapshots");
+    assert!(!snapshots.join("v1.0.0.0").join("ignored_file.txt").exists());
+    assert!(snapshots.join("v1.0.0.1").join("ignored_file.txt").exists());
+    assert!(snapshots
+        .join("v1.0.0.1")
+        .join("ignored_dir")
+        .join("ignored.txt")
+        .exists());
+    // The repository itself is never captured
+    assert!(!snapshots.join("v1.0.0.1").join(".snapsafe").exists());
+}
+
+#[test]
+fn test_search_index_tracks_tags() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    snapsafe(temp_path)
+        .args(["tag", "v1.0.0.1", "--add", "production"])
+        .assert()
+        .success();
+
+    let index_path = temp_path.join(".snapsafe").join("index.json");
+    let index = fs::read_to_string(&index_path).unwrap();
+    assert!(index.contains("production"));
+
+    snapsafe(temp_path)
+        .args(["find", "--tag", "production"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("v1.0.0.1"))
+        .stdout(predicate::str::contains("v1.0.0.0").not());
+    snapsafe(temp_path)
+        .args(["list", "--tag", "production"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("v1.0.0.1"))
+        .stdout(predicate::str::contains("v1.0.0.0").not());
+
+    // Queries are answered from the index, so a stale index is visible until check rebuilds it
+    fs::write(
+        &index_path,
+        r#"{"tags":{"production":["v1.0.0.0"]},"meta_keys":{}}"#,
+    )
+    .unwrap();
+    snapsafe(temp_path)
+        .args(["find", "--tag", "production"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("v1.0.0.0"));
+
+    snapsafe(temp_path)
+        .arg("check")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Rebuilt the stale search index"));
+    snapsafe(temp_path)
+        .args(["find", "--tag", "production"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("v1.0.0.1"))
+        .stdout(predicate::str::contains("v1.0.0.0").not());
+}
+
+#[test]
+fn test_restore_refuses_paths_escaping_the_target() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    // Tamper with the manifest so an entry points above the restore target
+    let snapshots = temp_path.join(".snapsafe").join("snapshots");
+    fs::write(snapshots.join("escaped.txt"), "outside").unwrap();
+    let manifest_path = snapshots.join("v1.0.0.0").join("manifest.json");
+    let mut manifest: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&manifest_path).unwrap()).unwrap();
+    let mut entry = manifest[0].clone();
+    entry["relative_path"] = serde_json::json!("../escaped.txt");
+    manifest.as_array_mut().unwrap().push(entry);
+    fs::write(&manifest_path, manifest.to_string()).unwrap();
+
+    let target = temp_path.join("checkout");
+    snapsafe(temp_path)
+        .args(["restore", "v1.0.0.0", "--target"])
+        .arg(&target)
+        .write_stdin("\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "the path escapes the target directory",
+        ));
+    assert!(!temp_path.join("escaped.txt").exists());
+    assert!(!target.join("file1.txt").exists());
+}
+
+#[test]
+fn test_restore_keep_newer() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    // Edit both files after the snapshot, but only file1.txt gets a later modification time
+    fs::write(temp_path.join("file1.txt"), "Edited later").unwrap();
+    let later =
+        filetime::FileTime::from_unix_time(filetime::FileTime::now().unix_seconds() + 3600, 0);
+    filetime::set_file_mtime(temp_path.join("file1.txt"), later).unwrap();
+    fs::write(temp_path.join("file2.txt"), "Older edit").unwrap();
+    let earlier = filetime::FileTime::from_unix_time(0, 0);
+    filetime::set_file_mtime(temp_path.join("file2.txt"), earlier).unwrap();
+
+    snapsafe(temp_path)
+        .args(["restore", "v1.0.0.0", "--no-backup", "--keep-newer"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Kept 1 file(s)"))
+        .stdout(predicate::str::contains("file1.txt"));
+
+    assert_eq!(
+        fs::read_to_string(temp_path.join("file1.txt")).unwrap(),
+        "Edited later"
+    );
+    assert_eq!(
+        fs::read_to_string(temp_path.join("file2.txt")).unwrap(),
+        "File 2 content"
+    );
+}
+
+#[test]
+fn test_unknown_snapshot_id_lists_available_snapshots() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    snapsafe(temp_path)
+        .args(["snapshot", "--label", "release"])
+        .assert()
+        .success();
+
+    snapsafe(temp_path)
+        .args(["restore", "v9.9.9.9", "--yes"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Snapshot v9.9.9.9 not found. Available snapshots: v1.0.0.0, v1.0.0.1 (release)",
+        ));
+
+    // Larger repositories get the closest matches instead of the full list
+    for _ in 0..10 {
+        snapsafe(temp_path).arg("snapshot").assert().success();
+    }
+    snapsafe(temp_path)
+        .args(["verify", "v1.0.0.1x"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Closest matches: v1.0.0.1 (release)",
+        ))
+        .stderr(predicate::str::contains("12 snapshots in total"));
+}
+
+#[test]
+fn test_restore_dry_run_matches_restore() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    fs::write(temp_path.join("file1.txt"), "Edited after the snapshot").unwrap();
+    fs::remove_file(temp_path.join("file2.txt")).unwrap();
+    fs::write(temp_path.join("extra.txt"), "Not in the snapshot").unwrap();
+    let before = read_tree(temp_path);
+
+    snapsafe(temp_path)
+        .args(["restore", "v1.0.0.0", "--clean", "--dry-run"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("create     file2.txt"))
+        .stdout(predicate::str::contains("overwrite  file1.txt"))
+        .stdout(predicate::str::contains("delete     extra.txt"))
+        .stdout(predicate::str::contains(
+            "would create 1, overwrite 1 and delete 1 file(s); 2 already match",
+        ));
+    assert_eq!(read_tree(temp_path), before);
+
+    snapsafe(temp_path)
+        .args(["restore", "v1.0.0.0", "--clean", "--yes", "--no-backup"])
+        .assert()
+        .success();
+    assert!(!temp_path.join("extra.txt").exists());
+    assert_eq!(
+        fs::read_to_string(temp_path.join("file1.txt")).unwrap(),
+        "File 1 content"
+    );
+    assert_eq!(
+        fs::read_to_string(temp_path.join("file2.txt")).unwrap(),
+        "File 2 content"
+    );
+}
+
+#[test]
+fn test_custom_repo_dir_from_env() {
+    let temp_dir = setup_test_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path)
+        .env("SNAPSAFE_DIR", ".snap2")
+        .arg("init")
+        .assert()
+        .success();
+    snapsafe(temp_path)
+        .env("SNAPSAFE_DIR", ".snap2")
+        .args(["snapshot", "-m", "Second repo"])
+        .assert()
+        .success();
+
+    let snapshot_dir = temp_path.join(".snap2").join("snapshots").join("v1.0.0.0");
+    assert!(snapshot_dir.join("file1.txt").exists());
+    assert!(!snapshot_dir.join(".snap2").exists());
+    assert!(!temp_path.join(".snapsafe").exists());
+
+    snapsafe(temp_path)
+        .env("SNAPSAFE_DIR", ".snap2")
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Second repo"));
+
+    // The default repository is untouched and empty
+    snapsafe(temp_path)
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No snapshots found."));
+}
+
+#[test]
+fn test_diff_meta() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path)
+        .args([
+            "snapshot", "--tags", "stable", "beta", "--meta", "env", "staging",
+        ])
+        .assert()
+        .success();
+    snapsafe(temp_path)
+        .args([
+            "snapshot",
+            "--tags",
+            "stable",
+            "--meta",
+            "env",
+            "production",
+        ])
+        .assert()
+        .success();
+
+    snapsafe(temp_path)
+        .args(["diff", "v1.0.0.0", "v1.0.0.1", "--meta"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Removed Tags:"))
+        .stdout(predicate::str::contains("beta"))
+        .stdout(predicate::str::contains("~ env: staging -> production"))
+        .stdout(predicate::str::contains("Added Tags:").not())
+        .stdout(predicate::str::contains("stable").not());
+}
+
+#[test]
+fn test_snapshot_retag_moves_tag() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    for _ in 0..3 {
+        snapsafe(temp_path)
+            .args(["snapshot", "--retag", "stable"])
+            .assert()
+            .success();
+    }
+
+    snapsafe(temp_path)
+        .args(["find", "--tag", "stable"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("v1.0.0.2"))
+        .stdout(predicate::str::contains("v1.0.0.0").not())
+        .stdout(predicate::str::contains("v1.0.0.1").not());
+}
+
+#[test]
+fn test_snapshot_rejects_bad_meta_before_creating() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path)
+        .args(["snapshot", "--meta", "a", "1", "extra"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("unexpected argument 'extra'"));
+    snapsafe(temp_path)
+        .args(["snapshot", "--meta", "", "1"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Metadata keys can't be empty"));
+    snapsafe(temp_path)
+        .args(["snapshot", "--tags", "nightly", ""])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Tags can't be empty"));
+    snapsafe(temp_path)
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No snapshots found"));
+
+    // Repeating --meta adds each pair
+    snapsafe(temp_path)
+        .args(["snapshot", "--meta", "a", "1", "--meta", "b", "2"])
+        .assert()
+        .success()
+        .stderr(predicate::str::is_empty());
+    snapsafe(temp_path)
+        .args(["meta", "v1.0.0.0", "--list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("a = 1"))
+        .stdout(predicate::str::contains("b = 2"));
+}
+
+#[test]
+fn test_snapshot_meta_json() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path)
+        .args(["snapshot", "--meta-json", r#"{"a":"1","b":"2"}"#])
+        .args(["--meta", "c", "3"])
+        .assert()
+        .success();
+    snapsafe(temp_path)
+        .args(["meta", "v1.0.0.0", "--list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("a = 1"))
+        .stdout(predicate::str::contains("b = 2"))
+        .stdout(predicate::str::contains("c = 3"));
+
+    // Nested or non-string values are rejected without creating a snapshot
+    for json in [
+        r#"{"a":{"nested":"1"}}"#,
+        r#"{"a":1}"#,
+        r#"["a"]"#,
+        "not json",
+    ] {
+        snapsafe(temp_path)
+            .args(["snapshot", "--meta-json", json])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("--meta-json"));
+    }
+    snapsafe(temp_path)
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("v1.0.0.1").not());
+}
+
+#[test]
+fn test_snapshot_inherit_tags() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path)
+        .args(["snapshot", "--tags", "nightly"])
+        .assert()
+        .success();
+    snapsafe(temp_path)
+        .args(["snapshot", "--inherit-tags", "--tags", "build-2"])
+        .assert()
+        .success();
+
+    snapsafe(temp_path)
+        .args(["tag", "v1.0.0.1", "--list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("nightly"))
+        .stdout(predicate::str::contains("build-2"));
+
+    // Without the flag, the next snapshot starts untagged
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    snapsafe(temp_path)
+        .args(["find", "--tag", "nightly"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("v1.0.0.2").not());
+}
+
+#[test]
+fn test_info_churn_ranks_files() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    // file1.txt changes in every snapshot; file2.txt never does
+    for i in 0..4 {
+        fs::write(temp_path.join("file1.txt"), format!("Revision {:03}", i)).unwrap();
+        let mtime = filetime::FileTime::from_unix_time(1_000_000 + i * 60, 0);
+        filetime::set_file_mtime(temp_path.join("file1.txt"), mtime).unwrap();
+        snapsafe(temp_path).arg("snapshot").assert().success();
+    }
+
+    let output = snapsafe(temp_path)
+        .args(["info", "--churn"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("across 4 snapshot(s)"))
+        .get_output()
+        .stdout
+        .clone();
+    let output = String::from_utf8(output).unwrap();
+    let (churned, stable) = output.split_once("Most stable files:").unwrap();
+
+    let churned: Vec<&str> = churned
+        .lines()
+        .filter(|l| l.contains("change(s)"))
+        .collect();
+    assert_eq!(churned.len(), 1);
+    assert!(churned[0].contains("3 change(s)  file1.txt"));
+
+    let stable: Vec<&str> = stable.lines().filter(|l| !l.is_empty()).collect();
+    assert!(stable
+        .iter()
+        .any(|l| l.contains("4 snapshot(s) unchanged  file2.txt")));
+    assert!(stable
+        .last()
+        .unwrap()
+        .contains("1 snapshot(s) unchanged  file1.txt"));
+}
+
+#[test]
+fn test_restore_original_location() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    snapsafe(temp_path)
+        .arg("info")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "Location:   {}",
+            temp_path.display()
+        )));
+
+    // Restore from a copy of the repository living in another directory
+    let other_dir = TempDir::new().unwrap();
+    copy_dir(
+        &temp_path.join(".snapsafe"),
+        &other_dir.path().join(".snapsafe"),
+    );
+    fs::remove_file(temp_path.join("file1.txt")).unwrap();
+
+    snapsafe(other_dir.path())
+        .args(["restore", "v1.0.0.0", "--original-location"])
+        .write_stdin("\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Skipping backup snapshot"));
+
+    assert!(temp_path.join("file1.txt").exists());
+    assert!(!other_dir.path().join("file1.txt").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_snapshot_preserves_source_hard_links() {
+    use std::os::unix::fs::MetadataExt;
+
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    fs::write(temp_path.join("shared.bin"), "shared contents").unwrap();
+    fs::hard_link(
+        temp_path.join("shared.bin"),
+        temp_path.join("subdir").join("alias.bin"),
+    )
+    .unwrap();
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    fs::write(temp_path.join("shared.bin"), "shared contents, edited").unwrap();
+    snapsafe(temp_path)
+        .args(["snapshot", "--dereference-hardlinks"])
+        .assert()
+        .success();
+
+    let snapshots = temp_path.join(".snapsafe").join("snapshots");
+    let inode = |version: &str, file: &str| {
+        fs::metadata(snapshots.join(version).join(file))
+            .unwrap()
+            .ino()
+    };
+    assert_eq!(
+        inode("v1.0.0.0", "shared.bin"),
+        inode("v1.0.0.0", "subdir/alias.bin")
+    );
+    assert_ne!(
+        inode("v1.0.0.1", "shared.bin"),
+        inode("v1.0.0.1", "subdir/alias.bin")
+    );
+    assert_eq!(
+        fs::read_to_string(snapshots.join("v1.0.0.1/subdir/alias.bin")).unwrap(),
+        "shared contents, edited"
+    );
+
+    let manifest = fs::read_to_string(snapshots.join("v1.0.0.0/manifest.json")).unwrap();
+    assert!(manifest.contains("alias.bin"));
+    snapsafe(temp_path).arg("verify").assert().success();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_snapshot_from_compare_by_hash_links_after_reset() {
+    use std::os::unix::fs::MetadataExt;
+
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    fs::write(temp_path.join("file1.txt"), "Changed content").unwrap();
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    // Reset the working tree to the first snapshot; restored files get fresh mtimes
+    snapsafe(temp_path)
+        .args(["restore", "v1.0.0.0", "--no-backup"])
+        .write_stdin("\n")
+        .assert()
+        .success();
+    let past = filetime::FileTime::from_unix_time(1_000_000_000, 0);
+    for file in ["file1.txt", "file2.txt", "subdir/file3.txt"] {
+        filetime::set_file_mtime(temp_path.join(file), past).unwrap();
+    }
+
+    snapsafe(temp_path)
+        .args(["snapshot", "--from", "v1.0.0.0", "--compare-by", "hash"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Hard-linking unchanged files against snapshot v1.0.0.0",
+        ));
+
+    let snapshots = temp_path.join(".snapsafe").join("snapshots");
+    for file in ["file1.txt", "file2.txt", "subdir/file3.txt"] {
+        let old = fs::metadata(snapshots.join("v1.0.0.0").join(file)).unwrap();
+        let new = fs::metadata(snapshots.join("v1.0.0.2").join(file)).unwrap();
+        assert_eq!(old.ino(), new.ino(), "{} should be hard-linked", file);
+    }
+
+    let manifest = fs::read_to_string(snapshots.join("v1.0.0.2").join("manifest.json")).unwrap();
+    assert!(manifest.contains("\"checksum\""));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_export_symlink_with_and_without_dereference() {
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+    std::os::unix::fs::symlink("file1.txt", temp_path.join("link.txt")).unwrap();
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    snapsafe(temp_path)
+        .args(["export", "v1.0.0.0", "-o", "links.tar.gz"])
+        .assert()
+        .success();
+    snapsafe(temp_path)
+        .args(["export", "v1.0.0.0", "-o", "deref.tar.gz", "--dereference"])
+        .assert()
+        .success();
+
+    // Returns the entry type and contents of link.txt in the archive
+    let read_link_entry = |name: &str| {
+        let file = fs::File::open(temp_path.join(name)).unwrap();
+        let mut archive = tar::Archive::new(GzDecoder::new(file));
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            if entry.path().unwrap().ends_with("v1.0.0.0/link.txt") {
+                let kind = entry.header().entry_type();
+                let mut contents = String::new();
+                entry.read_to_string(&mut contents).unwrap();
+                return (kind, contents);
+            }
+        }
+        panic!("link.txt not found in {}", name);
+    };
+
+    let (kind, _) = read_link_entry("links.tar.gz");
+    assert!(kind.is_symlink());
+
+    let (kind, contents) = read_link_entry("deref.tar.gz");
+    assert!(kind.is_file());
+    assert_eq!(contents, "File 1 content");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_config_edit_rejects_invalid_value() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path)
+        .args(["config", "--set", "exclude_vcs", "true"])
+        .assert()
+        .success();
+    let config_path = temp_path.join(".snapsafe").join("config.json");
+    let original = fs::read_to_string(&config_path).unwrap();
+
+    // A fake editor that writes an invalid value into the file it is given
+    let editor = temp_path.join("editor.sh");
+    fs::write(
+        &editor,
+        "#!/bin/sh\necho '{\"exclude_vcs\": \"maybe\"}' > \"$1\"\n",
+    )
+    .unwrap();
+    fs::set_permissions(&editor, fs::Permissions::from_mode(0o755)).unwrap();
+
+    snapsafe(temp_path)
+        .args(["config", "--edit"])
+        .env("EDITOR", &editor)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid value 'maybe'"));
+
+    assert_eq!(fs::read_to_string(&config_path).unwrap(), original);
+}
+
+#[test]
+fn test_config_validate_reports_bad_entries() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    let config_path = temp_path.join(".snapsafe").join("config.json");
+    let contents = "{\"compression\": \"zip\", \"exclude_vcs\": \"true\", \"jobs\": \"0\"}";
+    fs::write(&config_path, contents).unwrap();
+
+    snapsafe(temp_path)
+        .args(["config", "--validate"])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("Unknown config key 'compression'"))
+        .stdout(predicate::str::contains(
+            "Invalid value '0' for config key 'jobs'",
+        ))
+        .stdout(predicate::str::contains("key 'exclude_vcs'").not())
+        .stderr(predicate::str::contains("2 invalid setting(s)"));
+    assert_eq!(fs::read_to_string(&config_path).unwrap(), contents);
+
+    fs::write(&config_path, "{\"exclude_vcs\": \"true\"}").unwrap();
+    snapsafe(temp_path)
+        .args(["config", "--validate"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("is valid"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_restore_preserve_xattrs() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+    let file1 = temp_path.join("file1.txt");
+
+    // Not every filesystem supports user extended attributes
+    if xattr::set(&file1, "user.snapsafe.test", b"kept").is_err() {
+        return;
+    }
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    fs::remove_file(&file1).unwrap();
+    snapsafe(temp_path)
+        .args(["restore", "latest", "--yes", "--preserve-xattrs"])
+        .assert()
+        .success();
+
+    assert_eq!(fs::read_to_string(&file1).unwrap(), "File 1 content");
+    assert_eq!(
+        xattr::get(&file1, "user.snapsafe.test").unwrap(),
+        Some(b"kept".to_vec())
+    );
+}
+
+#[test]
+fn test_parallel_hash_threshold_matches_sequential_hash() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    // Larger than one read chunk so the hash spans several updates
+    let contents: Vec<u8> = (0..3 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+    fs::write(temp_path.join("large.bin"), &contents).unwrap();
+    let expected = blake3::hash(&contents).to_hex().to_string();
+
+    for threshold in ["0", "18446744073709551615"] {
+        snapsafe(temp_path)
+            .args(["config", "--set", "parallel_hash_threshold", threshold])
+            .assert()
+            .success();
+        snapsafe(temp_path)
+            .args(["snapshot", "--compare-by", "hash"])
+            .assert()
+            .success();
+    }
+
+    let snapshots = temp_path.join(".snapsafe").join("snapshots");
+    for version in ["v1.0.0.0", "v1.0.0.1"] {
+        let manifest = fs::read_to_string(snapshots.join(version).join("manifest.json")).unwrap();
+        assert!(
+            manifest.contains(&expected),
+            "{} has the wrong hash",
+            version
+        );
+    }
+}
+
+#[test]
+fn test_list_and_info_age() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    // Backdate the snapshot by an hour
+    let head_path = temp_path.join(".snapsafe").join("head_manifest.json");
+    let mut head: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&head_path).unwrap()).unwrap();
+    let an_hour_ago = (chrono::Local::now() - chrono::Duration::minutes(61))
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string();
+    head[0]["timestamp"] = serde_json::Value::String(an_hour_ago.clone());
+    fs::write(&head_path, serde_json::to_string_pretty(&head).unwrap()).unwrap();
+
+    snapsafe(temp_path)
+        .args(["list", "--age"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1 hour ago"))
+        .stdout(predicate::str::contains(&an_hour_ago).not());
+
+    snapsafe(temp_path)
+        .args(["info", "--age"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "1 hour ago ({})",
+            an_hour_ago
+        )));
+}
+
+#[test]
+fn test_snapshot_label_resolves_in_info() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path)
+        .args(["snapshot", "--label", "release-candidate"])
+        .assert()
+        .success();
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    snapsafe(temp_path)
+        .args(["info", "release-candidate"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Version:    v1.0.0.0"))
+        .stdout(predicate::str::contains("Label:      release-candidate"));
+
+    snapsafe(temp_path)
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Label"))
+        .stdout(predicate::str::contains("release-candidate"));
+
+    // Labels are unique
+    snapsafe(temp_path)
+        .args(["snapshot", "--label", "release-candidate"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("already labelled"));
+}
+
+#[test]
+fn test_prune_keep_last_per_tag() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    let tags: [&[&str]; 5] = [&["alpha"], &["beta"], &["alpha", "beta"], &[], &["beta"]];
+    for (i, snapshot_tags) in tags.iter().enumerate() {
+        snapsafe(temp_path).arg("snapshot").assert().success();
+        if !snapshot_tags.is_empty() {
+            let version = format!("v1.0.0.{}", i);
+            snapsafe(temp_path)
+                .args(["tag", &version, "--add"])
+                .args(snapshot_tags.iter())
+                .assert()
+                .success();
+        }
+    }
+
+    // alpha keeps v1.0.0.2 (which beta alone would drop), beta keeps v1.0.0.4, and the
+    // untagged v1.0.0.3 isn't touched without other criteria
+    snapsafe(temp_path)
+        .args(["prune", "--keep-last-per-tag", "1", "--yes"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Pruned 2 snapshots."));
+
+    let snapshots = temp_path.join(".snapsafe/snapshots");
+    for version in ["v1.0.0.0", "v1.0.0.1"] {
+        assert!(!snapshots.join(version).exists(), "{} kept", version);
+    }
+    for version in ["v1.0.0.2", "v1.0.0.3", "v1.0.0.4"] {
+        assert!(snapshots.join(version).exists(), "{} pruned", version);
+    }
+}
+
+#[test]
+fn test_prune_dry_run_json_matches_human_plan() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    fs::write(temp_path.join("file1.txt"), "Changed content!").unwrap();
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    let human = snapsafe(temp_path)
+        .args(["prune", "--keep-last", "1", "--dry-run"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let human = String::from_utf8(human).unwrap();
+    let listed: Vec<&str> = human
+        .lines()
+        .filter_map(|line| line.strip_prefix("  - "))
+        .map(|line| line.split(' ').next().unwrap())
+        .collect();
+
+    let output = snapsafe(temp_path)
+        .args(["prune", "--keep-last", "1", "--dry-run", "--json"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let plan: serde_json::Value = serde_json::from_slice(&output).unwrap();
+    let planned: Vec<&str> = plan["snapshots"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|s| s["version"].as_str().unwrap())
+        .collect();
+
+    assert_eq!(planned, listed);
+    assert_eq!(planned, vec!["v1.0.0.0", "v1.0.0.1"]);
+    assert!(plan["snapshots"][0]["timestamp"].is_string());
+    // Nothing was deleted
+    assert!(temp_path.join(".snapsafe/snapshots/v1.0.0.0").exists());
+
+    #[cfg(unix)]
+    {
+        // Only the old file1.txt is unique to the pruned snapshots; the rest is hard-linked
+        assert_eq!(
+            plan["snapshots"][0]["reclaimable_bytes"],
+            "File 1 content".len()
+        );
+        assert_eq!(plan["snapshots"][1]["reclaimable_bytes"], 0);
+        assert_eq!(plan["total_reclaimable_bytes"], "File 1 content".len());
+    }
+}
+
+#[test]
+fn test_snapshot_flags_size_outliers() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    // 100x the median file size of the previous snapshot (14 bytes)
+    fs::write(temp_path.join("huge.bin"), vec![0u8; 1400]).unwrap();
+    fs::write(temp_path.join("normal.txt"), "A normal file").unwrap();
+    snapsafe(temp_path)
+        .args(["snapshot", "--outlier-factor", "50"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Warning: huge.bin is"))
+        .stderr(predicate::str::contains("100x the typical file size"))
+        .stderr(predicate::str::contains("normal.txt").not());
+    assert!(temp_path
+        .join(".snapsafe/snapshots/v1.0.0.1/huge.bin")
+        .exists());
+
+    // A file that was already that large isn't flagged again; new outliers are blocked
+    fs::write(temp_path.join("huge2.bin"), vec![0u8; 1400]).unwrap();
+    snapsafe(temp_path)
+        .args(["config", "--set", "outlier_factor", "50"])
+        .assert()
+        .success();
+    snapsafe(temp_path)
+        .args(["snapshot", "--block-outliers"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("huge2.bin"))
+        .stderr(predicate::str::contains("Warning: huge.bin is").not())
+        .stdout(predicate::str::contains(
+            "Skipped 1 unusually large file(s)",
+        ));
+    let snapshot = temp_path.join(".snapsafe/snapshots/v1.0.0.2");
+    assert!(snapshot.join("huge.bin").exists());
+    assert!(!snapshot.join("huge2.bin").exists());
+
+    // --quiet leaves out the progress line but still warns
+    fs::write(temp_path.join("huge3.bin"), vec![0u8; 1400]).unwrap();
+    snapsafe(temp_path)
+        .args(["snapshot", "--block-outliers", "--quiet"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("huge3.bin"))
+        .stdout(predicate::str::contains("Skipped").not());
+    assert!(!temp_path
+        .join(".snapsafe/snapshots/v1.0.0.3/huge3.bin")
+        .exists());
+}
+
+#[test]
+fn test_snapshot_compress_threshold() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+    let large: Vec<u8> = b"compressible line of text\n".repeat(400);
+    fs::write(temp_path.join("large.txt"), &large).unwrap();
+
+    snapsafe(temp_path)
+        .args(["snapshot", "--compress-threshold", "1000"])
+        .assert()
+        .success();
+    let snapshot = temp_path.join(".snapsafe/snapshots/v1.0.0.0");
+    // Small files are stored as is, large ones gzip-compressed
+    assert_eq!(
+        fs::read(snapshot.join("file1.txt")).unwrap(),
+        b"File 1 content"
+    );
+    let stored = fs::read(snapshot.join("large.txt")).unwrap();
+    assert_eq!(&stored[..2], &[0x1f, 0x8b]);
+    assert!(stored.len() < large.len());
+    let manifest = fs::read_to_string(snapshot.join("manifest.json")).unwrap();
+    assert!(manifest.contains("compressed_size"));
+
+    // Unchanged files are still hard-linked by the next snapshot, compressed or not
+    snapsafe(temp_path)
+        .args(["config", "--set", "compress_threshold", "1000"])
+        .assert()
+        .success();
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let next = temp_path.join(".snapsafe/snapshots/v1.0.0.1");
+        for file in ["file1.txt", "large.txt"] {
+            assert_eq!(
+                fs::metadata(snapshot.join(file)).unwrap().ino(),
+                fs::metadata(next.join(file)).unwrap().ino()
+            );
+        }
+    }
+
+    snapsafe(temp_path)
+        .args(["verify", "v1.0.0.0"])
+        .assert()
+        .success();
+    snapsafe(temp_path)
+        .args(["diff", "v1.0.0.0", "--self"])
+        .assert()
+        .success();
+
+    // Restore and export hand back the original contents
+    fs::write(temp_path.join("large.txt"), "changed").unwrap();
+    snapsafe(temp_path)
+        .args(["restore", "v1.0.0.0", "--yes"])
+        .assert()
+        .success();
+    assert_eq!(fs::read(temp_path.join("large.txt")).unwrap(), large);
+
+    let archive = temp_path.join("export.tar.gz");
+    snapsafe(temp_path)
+        .args(["export", "v1.0.0.0", "--output"])
+        .arg(&archive)
+        .assert()
+        .success();
+    let target = temp_path.join("extracted");
+    snapsafe(temp_path)
+        .args(["restore", "--from-archive"])
+        .arg(&archive)
+        .arg("--target")
+        .arg(&target)
+        .arg("--yes")
+        .assert()
+        .success();
+    assert_eq!(fs::read(target.join("large.txt")).unwrap(), large);
+}
+
+#[test]
+fn test_meta_and_tag_list_raw() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path)
+        .args(["snapshot", "--meta", "env", "prod", "--meta", "build", "42"])
+        .args(["--tags", "nightly", "stable"])
+        .assert()
+        .success();
+
+    snapsafe(temp_path)
+        .args(["meta", "latest", "--list", "--raw"])
+        .assert()
+        .success()
+        .stdout("build=42\nenv=prod\n");
+    snapsafe(temp_path)
+        .args(["tag", "latest", "--list", "--raw"])
+        .assert()
+        .success()
+        .stdout("nightly\nstable\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_diff_reports_mode_changes() {
+    use std::os::unix::fs::PermissionsExt;
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+    let script = temp_path.join("file1.txt");
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o644)).unwrap();
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    // Only the executable bit changes; size and modification time stay the same
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+    snapsafe(temp_path)
+        .args(["snapshot", "--if-changed"])
+        .assert()
+        .success();
+    snapsafe(temp_path)
+        .args(["diff", "v1.0.0.0", "v1.0.0.1"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("file1.txt (mode 644 -> 755)"))
+        .stdout(predicate::str::contains("file2.txt").not());
+
+    // The new snapshot keeps its own copy with the new permissions
+    let stored = temp_path.join(".snapsafe/snapshots/v1.0.0.1/file1.txt");
+    assert_eq!(
+        fs::metadata(stored).unwrap().permissions().mode() & 0o777,
+        0o755
+    );
+}
+
+#[test]
+fn test_history_shows_file_timeline() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+    let notes = temp_path.join("notes.txt");
+
+    fs::write(&notes, "first line\nsecond line\n").unwrap();
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    fs::write(&notes, "first line\nchanged line\n").unwrap();
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    fs::remove_file(&notes).unwrap();
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    let output = snapsafe(temp_path)
+        .args(["history", "./notes.txt", "--text"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let output = String::from_utf8(output).unwrap();
+    let status = |version: &str| {
+        output
+            .lines()
+            .find(|line| line.starts_with(version))
+            .and_then(|line| line.split_whitespace().last())
+            .unwrap()
+            .to_string()
+    };
+    assert_eq!(status("v1.0.0.0"), "added");
+    assert_eq!(status("v1.0.0.1"), "unchanged");
+    assert_eq!(status("v1.0.0.2"), "modified");
+    assert_eq!(status("v1.0.0.3"), "removed");
+    assert!(output.contains("    - second line\n    + changed line"));
+    assert!(!output.contains("first line"));
+
+    snapsafe(temp_path)
+        .args(["history", "missing.txt"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "missing.txt isn't in any snapshot",
+        ));
+}
+
+#[test]
+fn test_preserve_times_round_trip() {
+    use filetime::FileTime;
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+    let file1 = temp_path.join("file1.txt");
+    let accessed = FileTime::from_unix_time(1_600_000_000, 123_456_789);
+    let modified = FileTime::from_unix_time(1_500_000_000, 987_654_321);
+    filetime::set_file_times(&file1, accessed, modified).unwrap();
+
+    snapsafe(temp_path)
+        .args(["snapshot", "--preserve-times"])
+        .assert()
+        .success();
+    let manifest =
+        fs::read_to_string(temp_path.join(".snapsafe/snapshots/v1.0.0.0/manifest.json")).unwrap();
+    assert!(manifest.contains("2020-09-13T12:26:40.123456789Z"));
+
+    fs::write(&file1, "Changed content").unwrap();
+    snapsafe(temp_path)
+        .args(["restore", "v1.0.0.0", "--yes"])
+        .assert()
+        .success();
+    let meta = fs::metadata(&file1).unwrap();
+    assert_eq!(fs::read_to_string(&file1).unwrap(), "File 1 content");
+    assert_eq!(FileTime::from_last_modification_time(&meta), modified);
+    assert_eq!(FileTime::from_last_access_time(&meta), accessed);
+}
+
+#[test]
+fn test_prune_before_date() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+    for _ in 0..3 {
+        snapsafe(temp_path).arg("snapshot").assert().success();
+    }
+
+    // Date the snapshots on either side of 2024-01-01
+    let head_path = temp_path.join(".snapsafe").join("head_manifest.json");
+    let mut head: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&head_path).unwrap()).unwrap();
+    for (index, timestamp) in [
+        "2023-06-01 10:00:00",
+        "2023-12-31 23:59:59",
+        "2024-01-01 00:00:00",
+    ]
+    .iter()
+    .enumerate()
+    {
+        head[index]["timestamp"] = serde_json::Value::String(timestamp.to_string());
+    }
+    fs::write(&head_path, serde_json::to_string_pretty(&head).unwrap()).unwrap();
+
+    snapsafe(temp_path)
+        .args(["prune", "--before", "2024-01-01", "--dry-run"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("v1.0.0.0 (2023-06-01 10:00:00)"))
+        .stdout(predicate::str::contains("v1.0.0.1 (2023-12-31 23:59:59)"))
+        .stdout(predicate::str::contains("v1.0.0.2").not())
+        .stdout(predicate::str::contains(
+            "Dry run - no snapshots were deleted.",
+        ));
+    assert!(temp_path.join(".snapsafe/snapshots/v1.0.0.0").exists());
+
+    snapsafe(temp_path)
+        .args(["prune", "--before", "01/01/2024", "--dry-run"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid date: 01/01/2024"));
+}
+
+#[test]
+fn test_snapshot_label_as_version() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+    let snapshots = temp_path.join(".snapsafe/snapshots");
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    snapsafe(temp_path)
+        .args(["snapshot", "-v", "release-2024-q1", "--label-as-version"])
+        .assert()
+        .success();
+    assert!(snapshots.join("release-2024-q1").is_dir());
+    snapsafe(temp_path)
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("release-2024-q1"));
+
+    // Unsafe characters are replaced, and auto-increment skips the named snapshot
+    snapsafe(temp_path)
+        .args(["snapshot", "-v", "../hotfix 2", "--label-as-version"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Using version hotfix-2"));
+    assert!(snapshots.join("hotfix-2").is_dir());
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    assert!(snapshots.join("v1.0.0.1").is_dir());
+
+    snapsafe(temp_path)
+        .args(["snapshot", "-v", "release-2024-q1", "--label-as-version"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("already exists"));
+}
+
+#[test]
+fn test_manifests_in_mixed_formats() {
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    fs::write(temp_path.join("file2.txt"), "File 2 has changed").unwrap();
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    let read_json = |path: &Path| -> serde_json::Value {
+        serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+    };
+    let to_json_lines = |value: &serde_json::Value| -> String {
+        value
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| format!("{}\n", entry))
+            .collect()
+    };
+    let snapshots = temp_path.join(".snapsafe/snapshots");
+
+    // v1.0.0.0: gzip-compressed compact JSON; v1.0.0.1 and the head manifest: JSON Lines
+    let first = snapshots.join("v1.0.0.0/manifest.json");
+    let compact = read_json(&first).to_string();
+    let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(compact.as_bytes()).unwrap();
+    fs::write(&first, encoder.finish().unwrap()).unwrap();
+    let second = snapshots.join("v1.0.0.1/manifest.json");
+    fs::write(&second, to_json_lines(&read_json(&second))).unwrap();
+    let head = temp_path.join(".snapsafe/head_manifest.json");
+    fs::write(&head, to_json_lines(&read_json(&head))).unwrap();
+
+    snapsafe(temp_path)
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("v1.0.0.1"));
+    snapsafe(temp_path).arg("verify").assert().success();
+    snapsafe(temp_path)
+        .args(["diff", "v1.0.0.0", "v1.0.0.1"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("file2.txt"));
+    snapsafe(temp_path)
+        .args(["restore", "v1.0.0.0", "--yes"])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(temp_path.join("file2.txt")).unwrap(),
+        "File 2 content"
+    );
+
+    fs::write(&second, "not a manifest").unwrap();
+    snapsafe(temp_path)
+        .args(["diff", "v1.0.0.0", "v1.0.0.1"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("unrecognized manifest format"));
+}
+
+#[test]
+fn test_manifest_is_deterministic() {
+    use filetime::FileTime;
+    let mtime = FileTime::from_unix_time(1_700_000_000, 0);
+    let files = ["b.txt", "a.txt", "dir/z.txt", "dir/c.txt", "A.txt"];
+    // Create the same tree on two "machines", writing the files in opposite orders
+    let trees: Vec<TempDir> = [files.to_vec(), files.iter().rev().copied().collect()]
+        .into_iter()
+        .map(|order| {
+            let temp_dir = TempDir::new().unwrap();
+            fs::create_dir(temp_dir.path().join("dir")).unwrap();
+            for name in order {
+                let path = temp_dir.path().join(name);
+                fs::write(&path, name).unwrap();
+                filetime::set_file_mtime(&path, mtime).unwrap();
+            }
+            snapsafe(temp_dir.path()).arg("init").assert().success();
+            snapsafe(temp_dir.path()).arg("snapshot").assert().success();
+            temp_dir
+        })
+        .collect();
+
+    let manifest = |dir: &TempDir| {
+        fs::read(
+            dir.path()
+                .join(".snapsafe/snapshots/v1.0.0.0/manifest.json"),
+        )
+        .unwrap()
+    };
+    assert_eq!(manifest(&trees[0]), manifest(&trees[1]));
+    let text = String::from_utf8(manifest(&trees[0])).unwrap();
+    let positions: Vec<usize> = ["A.txt", "a.txt", "b.txt", "dir/c.txt", "dir/z.txt"]
+        .iter()
+        .map(|name| text.find(&format!("\"{}\"", name)).unwrap())
+        .collect();
+    assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+}
+
+#[test]
+fn test_restore_exclude_keeps_working_copy() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+    let config_dir = temp_path.join("config");
+    fs::create_dir(&config_dir).unwrap();
+    fs::write(config_dir.join("local.toml"), "snapshot = true").unwrap();
+    fs::write(config_dir.join("app.toml"), "name = 'app'").unwrap();
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    fs::write(config_dir.join("local.toml"), "mine = true").unwrap();
+    fs::write(config_dir.join("app.toml"), "name = 'edited'").unwrap();
+    fs::write(config_dir.join("extra.toml"), "local only").unwrap();
+    snapsafe(temp_path)
+        .args(["restore", "v1.0.0.0", "--yes", "--clean"])
+        .args([
+            "--exclude",
+            "config/local.toml",
+            "--exclude",
+            "config/extra.*",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Excluding 1 file(s)"));
+
+    assert_eq!(
+        fs::read_to_string(config_dir.join("local.toml")).unwrap(),
+        "mine = true"
+    );
+    assert_eq!(
+        fs::read_to_string(config_dir.join("app.toml")).unwrap(),
+        "name = 'app'"
+    );
+    // Excluded files aren't deleted by --clean either
+    assert!(config_dir.join("extra.toml").exists());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_config_paths() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+    let config_home = TempDir::new().unwrap();
+    let global = config_home.path().join("snapsafe").join("config.json");
+    let repo = temp_path.join(".snapsafe").join("config.json");
+    let paths = || {
+        let mut cmd = snapsafe(temp_path);
+        cmd.env("XDG_CONFIG_HOME", config_home.path())
+            .args(["config", "--paths"]);
+        cmd
+    };
+
+    paths()
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "Global config:     {:?} (not found)",
+            global
+        )))
+        .stdout(predicate::str::contains(format!(
+            "Repository config: {:?} (not found)",
+            repo
+        )));
+
+    fs::create_dir_all(global.parent().unwrap()).unwrap();
+    fs::write(&global, "{}").unwrap();
+    snapsafe(temp_path)
+        .args(["config", "--set", "jobs", "2"])
+        .assert()
+        .success();
+    paths()
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!("{:?} (exists)", global)))
+        .stdout(predicate::str::contains(format!("{:?} (exists)", repo)));
+}
+
+#[test]
+fn test_snapshot_deduplicates_identical_files() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+    let contents: Vec<u8> = (0..10 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+    fs::write(temp_path.join("asset1.bin"), &contents).unwrap();
+    fs::create_dir(temp_path.join("copy")).unwrap();
+    fs::write(temp_path.join("copy/asset2.bin"), &contents).unwrap();
+    let mut different = contents.clone();
+    different[0] = 255;
+    fs::write(temp_path.join("asset3.bin"), &different).unwrap();
+
+    snapsafe(temp_path)
+        .arg("snapshot")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Storing 1 duplicate file(s)"));
+    let snapshot = temp_path.join(".snapsafe/snapshots/v1.0.0.0");
+    assert_eq!(
+        fs::read(snapshot.join("copy/asset2.bin")).unwrap(),
+        contents
+    );
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let inode = |path: &str| fs::metadata(snapshot.join(path)).unwrap().ino();
+        assert_eq!(inode("asset1.bin"), inode("copy/asset2.bin"));
+        assert_ne!(inode("asset1.bin"), inode("asset3.bin"));
+        assert_eq!(
+            fs::metadata(snapshot.join("asset1.bin")).unwrap().nlink(),
+            2
+        );
+    }
+    snapsafe(temp_path).arg("verify").assert().success();
+}
+
+#[test]
+fn test_verify_manifest_hash_detects_edited_manifest() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    snapsafe(temp_path)
+        .args(["verify", "--manifest-hash"])
+        .assert()
+        .success();
+
+    // An edit that leaves the stored files consistent with the manifest
+    let manifest_path = temp_path.join(".snapsafe/snapshots/v1.0.0.0/manifest.json");
+    let mut entries: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&manifest_path).unwrap()).unwrap();
+    entries[0]["modified"] = serde_json::json!("2000-01-01 00:00:00");
+    fs::write(
+        &manifest_path,
+        serde_json::to_string_pretty(&entries).unwrap(),
+    )
+    .unwrap();
+
+    snapsafe(temp_path).arg("verify").assert().success();
+    snapsafe(temp_path)
+        .args(["verify", "--manifest-hash"])
+        .assert()
+        .code(11)
+        .stdout(predicate::str::contains(
+            "Manifest mismatch: modified after the snapshot was created",
+        ));
+
+    // Rebuilding the manifest records a new checksum
+    snapsafe(temp_path)
+        .args(["check", "--fix-manifest", "v1.0.0.0"])
+        .assert()
+        .success();
+    snapsafe(temp_path)
+        .args(["verify", "--manifest-hash"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_restore_on_conflict_policies() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    let conflict = |content: &str| fs::write(temp_path.join("file1.txt"), content).unwrap();
+    let restore = |policy: &str| {
+        let mut cmd = snapsafe(temp_path);
+        cmd.args([
+            "restore",
+            "v1.0.0.0",
+            "--yes",
+            "--no-backup",
+            "--on-conflict",
+            policy,
+        ]);
+        cmd
+    };
+
+    conflict("Edited in the working copy");
+    restore("skip")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Skipped 1 file(s) that differ from the snapshot",
+        ));
+    assert_eq!(
+        fs::read_to_string(temp_path.join("file1.txt")).unwrap(),
+        "Edited in the working copy"
+    );
+
+    restore("prompt").write_stdin("n\n").assert().success();
+    assert_eq!(
+        fs::read_to_string(temp_path.join("file1.txt")).unwrap(),
+        "Edited in the working copy"
+    );
+    restore("prompt")
+        .write_stdin("y\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "file1.txt differs from the snapshot",
+        ));
+    assert_eq!(
+        fs::read_to_string(temp_path.join("file1.txt")).unwrap(),
+        "File 1 content"
+    );
+
+    conflict("Edited in the working copy");
+    restore("backup").assert().success();
+    restore("backup").assert().success();
+    assert_eq!(
+        fs::read_to_string(temp_path.join("file1.txt")).unwrap(),
+        "File 1 content"
+    );
+    assert_eq!(
+        fs::read_to_string(temp_path.join("file1.txt.bak")).unwrap(),
+        "Edited in the working copy"
+    );
+    // Files matching the snapshot aren't conflicts, so the second restore backed up nothing
+    assert!(!temp_path.join("file1.txt.bak.1").exists());
+    assert!(!temp_path.join("file2.txt.bak").exists());
+
+    conflict("Edited in the working copy");
+    restore("overwrite").assert().success();
+    assert_eq!(
+        fs::read_to_string(temp_path.join("file1.txt")).unwrap(),
+        "File 1 content"
+    );
+}
+
+#[test]
+fn test_list_and_log_paging() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    // Piped output isn't paged
+    for command in ["list", "log"] {
+        snapsafe(temp_path)
+            .arg(command)
+            .env("PAGER", "sed s/^/paged:/")
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("v1.0.0.0"))
+            .stdout(predicate::str::contains("paged:").not());
+    }
+
+    snapsafe(temp_path)
+        .args(["list", "--page"])
+        .env("PAGER", "sed s/^/paged:/")
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("paged:Version"));
+    snapsafe(temp_path)
+        .args(["log", "--page"])
+        .env("PAGER", "sed s/^/paged:/")
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("paged:v1.0.0.0"));
+    snapsafe(temp_path)
+        .args(["log", "--page", "--no-page"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_snapshot_note_file() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+    let notes_dir = TempDir::new().unwrap();
+    let notes = notes_dir.path().join("RELEASE.md");
+    fs::write(&notes, "# Release 2.1\n\n- Faster restores\n").unwrap();
+
+    snapsafe(temp_path)
+        .args(["snapshot", "--note-file"])
+        .arg(&notes)
+        .assert()
+        .success();
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    snapsafe(temp_path)
+        .args(["info", "v1.0.0.0", "--note"])
+        .assert()
+        .success()
+        .stdout("# Release 2.1\n\n- Faster restores\n");
+    snapsafe(temp_path)
+        .args(["info", "v1.0.0.0"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Note:       attached"));
+    snapsafe(temp_path)
+        .args(["info", "v1.0.0.1", "--note"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Snapshot v1.0.0.1 has no note"));
+
+    // The note isn't part of the snapshotted tree
+    snapsafe(temp_path)
+        .args(["diff", "--self", "v1.0.0.0"])
+        .assert()
+        .success();
+    snapsafe(temp_path)
+        .args(["restore", "v1.0.0.0", "--yes", "--no-backup", "--clean"])
+        .assert()
+        .success();
+    assert!(!temp_path.join(".snapsafe_note.md").exists());
+
+    snapsafe(temp_path)
+        .args(["snapshot", "--note-file", "missing.md"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Failed to read note file"));
+    assert!(!temp_path.join(".snapsafe/snapshots/v1.0.0.2").exists());
+}
+
+#[test]
+fn test_check_repair_head_rebuilds_head_manifest() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+    snapsafe(temp_path)
+        .args(["snapshot", "--tags", "stable"])
+        .assert()
+        .success();
+    fs::write(temp_path.join("file1.txt"), "File 1 changed").unwrap();
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    let head_path = temp_path.join(".snapsafe/head_manifest.json");
+    fs::remove_file(&head_path).unwrap();
+    snapsafe(temp_path)
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No snapshots found."));
+
+    snapsafe(temp_path)
+        .args(["check", "--repair-head"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Rebuilt the head manifest: 2 snapshot(s), 2 recovered",
+        ));
+    snapsafe(temp_path)
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("v1.0.0.0"))
+        .stdout(predicate::str::contains("v1.0.0.1"));
+    snapsafe(temp_path)
+        .args(["list", "--tag", "stable"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("v1.0.0.0"))
+        .stdout(predicate::str::contains("v1.0.0.1").not());
+    snapsafe(temp_path)
+        .args(["restore", "v1.0.0.0", "--yes", "--no-backup"])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(temp_path.join("file1.txt")).unwrap(),
+        "File 1 content"
+    );
+
+    // A corrupted head manifest is rebuilt too
+    fs::write(&head_path, "{ not json").unwrap();
+    snapsafe(temp_path)
+        .args(["check", "--repair-head"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("The head manifest is unreadable"));
+    snapsafe(temp_path)
+        .args(["info", "v1.0.0.1"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_diff_working_applies_ignore_list() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    snapsafe(temp_path)
+        .args(["diff", "v1.0.0.0", "working"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "No differences found between snapshot v1.0.0.0 and the working directory.",
+        ));
+
+    // Ignored output (by name, at any depth) never shows up; other new files do
+    fs::write(temp_path.join("ignored_file.txt"), "generated").unwrap();
+    fs::create_dir_all(temp_path.join("subdir/ignored_dir")).unwrap();
+    fs::write(temp_path.join("subdir/ignored_dir/out.o"), "generated").unwrap();
+    fs::write(temp_path.join("new.txt"), "new").unwrap();
+    fs::write(temp_path.join("file1.txt"), "File 1 has changed").unwrap();
+    snapsafe(temp_path)
+        .args(["diff", "v1.0.0.0", "working"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("new.txt"))
+        .stdout(predicate::str::contains("Updated Files:"))
+        .stdout(predicate::str::contains("file1.txt"))
+        .stdout(predicate::str::contains("ignored").not());
+
+    // The working directory matches what a snapshot records
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    snapsafe(temp_path)
+        .args(["diff", "v1.0.0.1", "working"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No differences found"));
+}
+
+#[test]
+fn test_snapshot_content_only_ignores_mtimes() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+    snapsafe(temp_path)
+        .args(["snapshot", "--content-only"])
+        .assert()
+        .success();
+
+    // Same bytes, different modification times
+    let past = filetime::FileTime::from_unix_time(1_000_000_000, 0);
+    for file in ["file1.txt", "file2.txt", "subdir/file3.txt"] {
+        filetime::set_file_mtime(temp_path.join(file), past).unwrap();
+    }
+    snapsafe(temp_path)
+        .args(["snapshot", "--content-only", "--if-changed"])
+        .assert()
+        .code(10);
+    snapsafe(temp_path)
+        .args(["snapshot", "--content-only"])
+        .assert()
+        .success();
+
+    let snapshots = temp_path.join(".snapsafe/snapshots");
+    let manifest1 = fs::read_to_string(snapshots.join("v1.0.0.0/manifest.json")).unwrap();
+    let manifest2 = fs::read_to_string(snapshots.join("v1.0.0.1/manifest.json")).unwrap();
+    assert_eq!(manifest1, manifest2);
+    assert!(!manifest1.contains("\"modified\""));
+    snapsafe(temp_path)
+        .args(["diff", "v1.0.0.0", "v1.0.0.1"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No differences found"));
+
+    // A change that keeps the size is still found by hash
+    fs::write(temp_path.join("file1.txt"), "File 1 CONTENT").unwrap();
+    filetime::set_file_mtime(temp_path.join("file1.txt"), past).unwrap();
+    snapsafe(temp_path)
+        .args(["snapshot", "--content-only"])
+        .assert()
+        .success();
+    snapsafe(temp_path)
+        .args(["diff", "v1.0.0.1", "v1.0.0.2"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("file1.txt"))
+        .stdout(predicate::str::contains("file2.txt").not());
+    snapsafe(temp_path)
+        .args(["verify", "--check-links", "--check-mtime"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_timeout_aborts_slow_snapshot_and_removes_partial_state() {
+    let temp_dir = setup_initialized_env();
+    let head_manifest = temp_dir.path().join(".snapsafe").join("head_manifest.json");
+    let before = fs::read_to_string(&head_manifest).unwrap();
+
+    snapsafe(temp_dir.path())
+        .args(["snapshot", "--timeout", "1", "-m", "Slow"])
+        .env("SNAPSAFE_INJECT_DELAY_MS", "800")
+        .assert()
+        .code(13)
+        .stderr(predicate::str::contains(
+            "snapshot timed out after 1 second(s)",
+        ))
+        .stderr(predicate::str::contains("Removed the partial snapshot"));
+
+    assert!(!temp_dir
+        .path()
+        .join(".snapsafe/snapshots/v1.0.0.0")
+        .exists());
+    assert_eq!(fs::read_to_string(&head_manifest).unwrap(), before);
+
+    // A run that finishes in time isn't affected
+    snapsafe(temp_dir.path())
+        .args(["snapshot", "--timeout", "60", "-m", "Fast"])
+        .assert()
+        .success();
+    assert!(temp_dir
+        .path()
+        .join(".snapsafe/snapshots/v1.0.0.0")
+        .exists());
+}
+
+#[test]
+fn test_tag_all_applies_to_every_snapshot() {
+    let temp_dir = setup_initialized_env();
+    for message in ["First", "Second", "Third"] {
+        snapsafe(temp_dir.path())
+            .args(["snapshot", "-m", message])
+            .assert()
+            .success();
+    }
+
+    // Declining the confirmation changes nothing
+    snapsafe(temp_dir.path())
+        .args(["tag", "all", "--add", "migrated"])
+        .write_stdin("n\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "This will add tag(s) migrated on all 3 snapshot(s).",
+        ))
+        .stdout(predicate::str::contains("Cancelled"));
+    snapsafe(temp_dir.path())
+        .args(["find", "--tag", "migrated"])
+        .assert()
+        .stdout(predicate::str::contains("v1.0.0.0").not());
+
+    snapsafe(temp_dir.path())
+        .args(["tag", "all", "--add", "migrated"])
+        .write_stdin("y\n")
+        .assert()
+        .success();
+    let head: serde_json::Value = serde_json::from_str(
+        &fs::read_to_string(temp_dir.path().join(".snapsafe/head_manifest.json")).unwrap(),
+    )
+    .unwrap();
+    let snapshots = head.as_array().unwrap();
+    assert_eq!(snapshots.len(), 3);
+    for snapshot in snapshots {
+        assert!(snapshot["metadata"]["tags"]
+            .as_array()
+            .unwrap()
+            .contains(&serde_json::json!("migrated")));
+    }
+
+    snapsafe(temp_dir.path())
+        .args(["meta", "all", "--set", "owner", "ops", "--yes"])
+        .assert()
+        .success();
+    snapsafe(temp_dir.path())
+        .args(["meta", "v1.0.0.1", "--list", "--raw"])
+        .assert()
+        .stdout("owner=ops\n");
+}
+
+#[test]
+fn test_incremental_snapshot_stores_only_changed_files() {
+    let temp_dir = setup_initialized_env();
+    snapsafe(temp_dir.path())
+        .args(["snapshot", "-m", "Full"])
+        .assert()
+        .success();
+
+    fs::write(temp_dir.path().join("file2.txt"), "File 2 content, edited").unwrap();
+    fs::write(temp_dir.path().join("new.txt"), "New file").unwrap();
+    snapsafe(temp_dir.path())
+        .args(["snapshot", "--incremental", "-m", "Changes"])
+        .assert()
+        .success();
+    let incremental = temp_dir.path().join(".snapsafe/snapshots/v1.0.0.1");
+    assert!(incremental.join("file2.txt").exists());
+    assert!(incremental.join("new.txt").exists());
+    assert!(!incremental.join("file1.txt").exists());
+    assert!(!incremental.join("subdir/file3.txt").exists());
+
+    // A second incremental snapshot builds on the first, so the chain has three links
+    fs::write(temp_dir.path().join("new.txt"), "New file, edited").unwrap();
+    snapsafe(temp_dir.path())
+        .args(["snapshot", "--incremental", "-m", "More changes"])
+        .assert()
+        .success();
+    assert!(!temp_dir
+        .path()
+        .join(".snapsafe/snapshots/v1.0.0.2/file2.txt")
+        .exists());
+    snapsafe(temp_dir.path())
+        .args(["verify", "v1.0.0.2"])
+        .assert()
+        .success();
+
+    // Bases of a kept incremental snapshot can't be pruned
+    snapsafe(temp_dir.path())
+        .args(["prune", "--keep-last", "1", "--yes"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Keeping 2 snapshot(s) that incremental snapshots build on.",
+        ));
+
+    fs::remove_file(temp_dir.path().join("file1.txt")).unwrap();
+    fs::remove_dir_all(temp_dir.path().join("subdir")).unwrap();
+    fs::write(temp_dir.path().join("file2.txt"), "Scratch").unwrap();
+    snapsafe(temp_dir.path())
+        .args(["restore", "v1.0.0.2", "--yes"])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("file1.txt")).unwrap(),
+        "File 1 content"
+    );
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("subdir/file3.txt")).unwrap(),
+        "File 3 content"
+    );
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("file2.txt")).unwrap(),
+        "File 2 content, edited"
+    );
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("new.txt")).unwrap(),
+        "New file, edited"
+    );
+}
+
+#[test]
+fn test_verify_io_limit_with_parallel_jobs() {
+    let temp_dir = setup_initialized_env();
+    for i in 0..20 {
+        fs::write(temp_dir.path().join(format!("extra{}.txt", i)), "extra").unwrap();
+    }
+    snapsafe(temp_dir.path())
+        .args(["snapshot", "-m", "Many files"])
+        .assert()
+        .success();
+
+    for io_limit in ["1", "4"] {
+        snapsafe(temp_dir.path())
+            .args(["verify", "--jobs", "4", "--io-limit", io_limit])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("OK"));
+    }
+
+    fs::remove_file(
+        temp_dir
+            .path()
+            .join(".snapsafe/snapshots/v1.0.0.0/extra7.txt"),
+    )
+    .unwrap();
+    snapsafe(temp_dir.path())
+        .args(["verify", "--jobs", "4", "--io-limit", "1", "--show-all"])
+        .assert()
+        .code(11)
+        .stdout(predicate::str::contains("extra7.txt"));
+
+    snapsafe(temp_dir.path())
+        .args(["verify", "--io-limit", "0"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_export_carries_snapshot_index_to_import() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+    snapsafe(temp_path)
+        .args([
+            "snapshot",
+            "-m",
+            "Quarterly close",
+            "--label",
+            "q3",
+            "--tags",
+            "finance",
+        ])
+        .assert()
+        .success();
+    snapsafe(temp_path)
+        .args(["meta", "q3", "--set", "approved_by", "cfo"])
+        .assert()
+        .success();
+    let head: serde_json::Value = serde_json::from_str(
+        &fs::read_to_string(temp_path.join(".snapsafe/head_manifest.json")).unwrap(),
+    )
+    .unwrap();
+    let timestamp = head[0]["timestamp"].as_str().unwrap().to_string();
+    let archive = temp_path.join("q3.tar.gz");
+    snapsafe(temp_path)
+        .args(["export", "q3", "-o"])
+        .arg(&archive)
+        .assert()
+        .success();
+
+    let offsite = setup_initialized_env();
+    snapsafe(offsite.path())
+        .arg("import")
+        .arg(&archive)
+        .assert()
+        .success();
+    let imported: serde_json::Value = serde_json::from_str(
+        &fs::read_to_string(offsite.path().join(".snapsafe/head_manifest.json")).unwrap(),
+    )
+    .unwrap();
+    let entry = &imported[0];
+    assert_eq!(entry["version"], "v1.0.0.0");
+    assert_eq!(entry["timestamp"], timestamp.as_str());
+    assert_eq!(entry["message"], "Quarterly close");
+    assert_eq!(entry["label"], "q3");
+    assert_eq!(entry["metadata"]["tags"][0], "finance");
+    assert_eq!(entry["metadata"]["custom"]["approved_by"], "cfo");
+    assert!(!offsite
+        .path()
+        .join(".snapsafe/snapshots/v1.0.0.0/.snapsafe_index.json")
+        .exists());
+    snapsafe(offsite.path()).arg("verify").assert().success();
+
+    // A label already taken in the importing repository is dropped
+    let taken = setup_initialized_env();
+    snapsafe(taken.path())
+        .args(["snapshot", "--version", "v2.0.0.0", "--label", "q3"])
+        .assert()
+        .success();
+    snapsafe(taken.path())
+        .arg("import")
+        .arg(&archive)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Dropping label 'q3'"));
+
+    // Restoring the archive directly doesn't write the index as a working file
+    let target = TempDir::new().unwrap();
+    snapsafe(temp_path)
+        .args(["restore", "--from-archive"])
+        .arg(&archive)
+        .arg("--target")
+        .arg(target.path())
+        .arg("--yes")
+        .assert()
+        .success();
+    assert!(target.path().join("file1.txt").exists());
+    assert!(!target.path().join(".snapsafe_index.json").exists());
+}
+
+#[test]
+fn test_concurrent_snapshots_get_distinct_versions() {
+    let temp_dir = setup_initialized_env();
+    let snapshots = temp_dir.path().join(".snapsafe/snapshots");
+
+    // Both runs read the empty head manifest and compute v1.0.0.0; the slower one has to
+    // move on when it finds the version's folder already created
+    let start = |delay: &str| {
+        std::process::Command::new(assert_cmd::cargo::cargo_bin("snapsafe"))
+            .args(["snapshot", "-m", "Racing"])
+            .env("SNAPSAFE_INJECT_DELAY_MS", delay)
+            .current_dir(temp_dir.path())
+            .stdout(std::process::Stdio::null())
+            .spawn()
+            .unwrap()
+    };
+    let mut fast = start("100");
+    let mut slow = start("400");
+    assert!(fast.wait().unwrap().success());
+    assert!(slow.wait().unwrap().success());
+
+    let head: serde_json::Value = serde_json::from_str(
+        &fs::read_to_string(temp_dir.path().join(".snapsafe/head_manifest.json")).unwrap(),
+    )
+    .unwrap();
+    let mut versions: Vec<&str> = head
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|s| s["version"].as_str().unwrap())
+        .collect();
+    versions.sort();
+    assert_eq!(versions, ["v1.0.0.0", "v1.0.0.1"]);
+    assert!(snapshots.join("v1.0.0.1/file1.txt").exists());
+
+    // A folder claimed by a run that hasn't recorded its snapshot yet is skipped too
+    fs::create_dir(snapshots.join("v1.0.0.2")).unwrap();
+    snapsafe(temp_dir.path())
+        .args(["snapshot", "-m", "Next"])
+        .assert()
+        .success();
+    assert!(snapshots.join("v1.0.0.3/manifest.json").exists());
+
+    // An explicitly requested version that is taken is still an error
+    snapsafe(temp_dir.path())
+        .args(["snapshot", "--version", "v1.0.0.2"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("already exists"));
+}
+
+#[test]
+fn test_restore_subtree_by_path() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+    fs::create_dir_all(temp_path.join("assets/icons")).unwrap();
+    fs::write(temp_path.join("assets/logo.svg"), "<svg/>").unwrap();
+    fs::write(temp_path.join("assets/icons/home.svg"), "<svg>home</svg>").unwrap();
+    fs::write(temp_path.join("assets-old.txt"), "Not under assets/").unwrap();
+    snapsafe(temp_path)
+        .args(["snapshot", "-m", "With assets"])
+        .assert()
+        .success();
+
+    fs::remove_dir_all(temp_path.join("assets")).unwrap();
+    fs::write(temp_path.join("file1.txt"), "Edited after the snapshot").unwrap();
+    fs::write(temp_path.join("assets-old.txt"), "Edited too").unwrap();
+
+    snapsafe(temp_path)
+        .args(["restore", "v1.0.0.0", "./assets/", "--yes", "--no-backup"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Restoring only assets (2 file(s)).",
+        ));
+    assert_eq!(
+        fs::read_to_string(temp_path.join("assets/icons/home.svg")).unwrap(),
+        "<svg>home</svg>"
+    );
+    assert!(temp_path.join("assets/logo.svg").exists());
+    // Files outside the subtree, including ones sharing its name as a prefix, are untouched
+    assert_eq!(
+        fs::read_to_string(temp_path.join("file1.txt")).unwrap(),
+        "Edited after the snapshot"
+    );
+    assert_eq!(
+        fs::read_to_string(temp_path.join("assets-old.txt")).unwrap(),
+        "Edited too"
+    );
+
+    // --clean only deletes files under the subtree
+    fs::write(temp_path.join("assets/stray.png"), "stray").unwrap();
+    fs::write(temp_path.join("notes.txt"), "Outside").unwrap();
+    snapsafe(temp_path)
+        .args([
+            "restore",
+            "v1.0.0.0",
+            "assets",
+            "--clean",
+            "--yes",
+            "--no-backup",
+        ])
+        .assert()
+        .success();
+    assert!(!temp_path.join("assets/stray.png").exists());
+    assert!(temp_path.join("notes.txt").exists());
+
+    snapsafe(temp_path)
+        .args(["restore", "v1.0.0.0", "missing/", "--yes"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("has no files under missing"));
+}
+
+#[test]
+fn test_checksum_manifest_checks_with_sha256sum() {
+    let temp_dir = setup_initialized_env();
+    snapsafe(temp_dir.path())
+        .args(["snapshot", "--checksum-manifest", "-m", "With sums"])
+        .assert()
+        .success();
+    let snapshot_dir = temp_dir.path().join(".snapsafe/snapshots/v1.0.0.0");
+    let sums = fs::read_to_string(snapshot_dir.join("SHA256SUMS")).unwrap();
+    assert!(sums.contains("  file1.txt\n"));
+    assert!(sums.contains("  subdir/file3.txt\n"));
+    assert!(!sums.contains("manifest.json"));
+
+    // Checked by the system tool where it's installed
+    let check = || {
+        std::process::Command::new("sha256sum")
+            .args(["-c", "--quiet", "SHA256SUMS"])
+            .current_dir(&snapshot_dir)
+            .output()
+    };
+    let Ok(output) = check() else {
+        return;
+    };
+    assert!(output.status.success());
+    fs::write(snapshot_dir.join("file1.txt"), "Corrupted").unwrap();
+    assert!(!check().unwrap().status.success());
+
+    // Only snapshots taken with the flag get one
+    snapsafe(temp_dir.path())
+        .args(["snapshot", "-m", "Without sums"])
+        .assert()
+        .success();
+    assert!(!temp_dir
+        .path()
+        .join(".snapsafe/snapshots/v1.0.0.1/SHA256SUMS")
+        .exists());
+}
+
+#[test]
+fn test_info_reports_shared_and_unique_data() {
+    let temp_dir = setup_initialized_env();
+    fs::write(temp_dir.path().join("large.bin"), vec![7u8; 100_000]).unwrap();
+    snapsafe(temp_dir.path())
+        .args(["snapshot", "-m", "First"])
+        .assert()
+        .success();
+    fs::write(temp_dir.path().join("file1.txt"), "Edited").unwrap();
+    snapsafe(temp_dir.path())
+        .args(["snapshot", "-m", "Second"])
+        .assert()
+        .success();
+
+    snapsafe(temp_dir.path())
+        .args(["info", "v1.0.0.1"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Shared files:      4 of 5 (80%) shared with v1.0.0.0",
+        ))
+        .stdout(predicate::str::contains(
+            "Unique data:       6 B added by this snapshot (1 file(s))",
+        ));
+    snapsafe(temp_dir.path())
+        .args(["info", "v1.0.0.0"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Shared files:      0 of"))
+        .stdout(predicate::str::contains("KiB added by this snapshot"));
+}
+
+#[test]
+fn test_snapshot_print_version_quiet() {
+    let temp_dir = setup_initialized_env();
+    snapsafe(temp_dir.path())
+        .args(["snapshot", "-m", "First"])
+        .assert()
+        .success();
+    snapsafe(temp_dir.path())
+        .args(["snapshot", "--print-version", "-q", "-m", "Second"])
+        .assert()
+        .success()
+        .stdout("v1.0.0.1\n");
+
+    // Without -q the version is still the last line
+    let output = snapsafe(temp_dir.path())
+        .args(["snapshot", "--print-version", "-m", "Third"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Snapshot created successfully."));
+    assert_eq!(stdout.lines().last(), Some("v1.0.0.2"));
+}
+
+#[test]
+fn test_amend_recaptures_latest_snapshot() {
+    let temp_dir = setup_initialized_env();
+    snapsafe(temp_dir.path())
+        .args(["snapshot", "-m", "First"])
+        .assert()
+        .success();
+    snapsafe(temp_dir.path())
+        .args(["snapshot", "-m", "Release", "--tags", "release"])
+        .assert()
+        .success();
+    let head: serde_json::Value = serde_json::from_str(
+        &fs::read_to_string(temp_dir.path().join(".snapsafe/head_manifest.json")).unwrap(),
+    )
+    .unwrap();
+    let created = head[1]["timestamp"].clone();
+
+    fs::write(temp_dir.path().join("forgotten.txt"), "Forgotten file").unwrap();
+    snapsafe(temp_dir.path())
+        .arg("amend")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Amended snapshot v1.0.0.1"));
+
+    let head: serde_json::Value = serde_json::from_str(
+        &fs::read_to_string(temp_dir.path().join(".snapsafe/head_manifest.json")).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(head.as_array().unwrap().len(), 2);
+    assert_eq!(head[1]["version"], "v1.0.0.1");
+    assert_eq!(head[1]["timestamp"], created);
+    assert_eq!(head[1]["message"], "Release");
+    assert_eq!(head[1]["metadata"]["tags"][0], "release");
+    assert!(head[1]["amended_at"].is_string());
+    assert_eq!(
+        fs::read_to_string(
+            temp_dir
+                .path()
+                .join(".snapsafe/snapshots/v1.0.0.1/forgotten.txt")
+        )
+        .unwrap(),
+        "Forgotten file"
+    );
+    assert!(!temp_dir.path().join(".snapsafe/snapshots/.amend").exists());
+
+    // Only the latest snapshot can be amended
+    snapsafe(temp_dir.path())
+        .args(["amend", "v1.0.0.0"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("1 later snapshot(s)"));
+}
+
+#[test]
+fn test_diff_word_diff_marks_changed_word() {
+    let temp_dir = setup_initialized_env();
+    let long_line = "The quick brown fox jumps over the lazy dog while the farmer watches from the porch and the sun sets behind the hills.";
+    fs::write(
+        temp_dir.path().join("notes.md"),
+        format!("# Notes\n{}\n", long_line),
+    )
+    .unwrap();
+    snapsafe(temp_dir.path())
+        .args(["snapshot", "-m", "First"])
+        .assert()
+        .success();
+    fs::write(
+        temp_dir.path().join("notes.md"),
+        format!("# Notes\n{}\n", long_line.replace("lazy", "sleepy")),
+    )
+    .unwrap();
+    snapsafe(temp_dir.path())
+        .args(["snapshot", "-m", "Second"])
+        .assert()
+        .success();
+
+    let output = snapsafe(temp_dir.path())
+        .args(["diff", "v1.0.0.0", "v1.0.0.1", "--word-diff"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Word Diff: notes.md"));
+    assert!(stdout.contains("@@ line 2 @@"));
+    assert!(stdout.contains(
+        "The quick brown fox jumps over the [-lazy-]{+sleepy+} dog while the farmer watches"
+    ));
+    assert_eq!(stdout.matches("[-").count(), 1);
+    assert_eq!(stdout.matches("{+").count(), 1);
+}
+
+#[test]
+fn test_prune_dry_run_show_kept() {
+    let temp_dir = setup_initialized_env();
+    for i in 0..4 {
+        fs::write(temp_dir.path().join("file1.txt"), format!("Version {}", i)).unwrap();
+        snapsafe(temp_dir.path())
+            .args(["snapshot", "-m", &format!("Snapshot {}", i)])
+            .assert()
+            .success();
+    }
+
+    let output = snapsafe(temp_dir.path())
+        .args(["prune", "--keep-last", "2", "--dry-run", "--show-kept"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("The following 2 snapshot(s) will be kept:"));
+    let kept: Vec<&str> = stdout
+        .lines()
+        .filter_map(|line| line.strip_prefix("  + "))
+        .map(|line| line.split(' ').next().unwrap())
+        .collect();
+    assert_eq!(kept, ["v1.0.0.2", "v1.0.0.3"]);
+    assert!(stdout.contains("just now"));
+
+    // The JSON plan lists the survivors too
+    let output = snapsafe(temp_dir.path())
+        .args([
+            "prune",
+            "--keep-last",
+            "2",
+            "--dry-run",
+            "--show-kept",
+            "--json",
+        ])
+        .output()
+        .unwrap();
+    let plan: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(plan["kept"][0]["version"], "v1.0.0.2");
+    assert_eq!(plan["kept"][1]["version"], "v1.0.0.3");
+    assert_eq!(plan["snapshots"].as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn test_config_env_var_overrides_config_files() {
+    let temp_dir = setup_initialized_env();
+    snapsafe(temp_dir.path())
+        .args(["config", "--set", "jobs", "4"])
+        .assert()
+        .success();
+    snapsafe(temp_dir.path())
+        .arg("snapshot")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Using 4 parallel jobs."));
+
+    // The environment takes precedence over the repository config
+    snapsafe(temp_dir.path())
+        .env("SNAPSAFE_JOBS", "1")
+        .arg("snapshot")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("parallel jobs").not());
+    snapsafe(temp_dir.path())
+        .env("SNAPSAFE_JOBS", "1")
+        .args(["config", "--paths"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("jobs (from SNAPSAFE_JOBS)"));
+
+    // The --jobs flag still wins, and invalid values are ignored with a warning
+    snapsafe(temp_dir.path())
+        .env("SNAPSAFE_JOBS", "1")
+        .args(["snapshot", "--jobs", "3"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Using 3 parallel jobs."));
+    snapsafe(temp_dir.path())
+        .env("SNAPSAFE_JOBS", "zero")
+        .arg("snapshot")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Using 4 parallel jobs."))
+        .stderr(predicate::str::contains(
+            "ignoring invalid value 'zero' of SNAPSAFE_JOBS",
+        ));
+}
+
+#[test]
+fn test_snapsafeattributes_compress_and_no_dedup() {
+    let temp_dir = setup_initialized_env();
+    fs::write(
+        temp_dir.path().join(".snapsafeattributes"),
+        "# Per-path options\n*.csv compress\nkeep/* no-dedup\n",
+    )
+    .unwrap();
+    fs::write(temp_dir.path().join("data.csv"), "a,b\n1,2\n").unwrap();
+    fs::create_dir(temp_dir.path().join("reports")).unwrap();
+    fs::write(temp_dir.path().join("reports/q1.csv"), "q,total\n1,10\n").unwrap();
+    fs::create_dir(temp_dir.path().join("keep")).unwrap();
+    fs::write(temp_dir.path().join("keep/state.db"), "State").unwrap();
+    snapsafe(temp_dir.path())
+        .args(["snapshot", "-m", "First"])
+        .assert()
+        .success();
+
+    let manifest: serde_json::Value = serde_json::from_str(
+        &fs::read_to_string(
+            temp_dir
+                .path()
+                .join(".snapsafe/snapshots/v1.0.0.0/manifest.json"),
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let compressed: Vec<&str> = manifest
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|entry| !entry["compressed_size"].is_null())
+        .map(|entry| entry["relative_path"].as_str().unwrap())
+        .collect();
+    assert_eq!(compressed, ["data.csv", "reports/q1.csv"]);
+
+    // Compressed files are restored as they were, and no-dedup files aren't hard-linked
+    snapsafe(temp_dir.path())
+        .args(["snapshot", "-m", "Second"])
+        .assert()
+        .success();
+    fs::remove_file(temp_dir.path().join("data.csv")).unwrap();
+    snapsafe(temp_dir.path())
+        .args(["restore", "v1.0.0.1", "--yes", "--no-backup"])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("data.csv")).unwrap(),
+        "a,b\n1,2\n"
+    );
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let snapshots = temp_dir.path().join(".snapsafe/snapshots");
+        let inode = |path: &str| fs::metadata(snapshots.join(path)).unwrap().ino();
+        assert_ne!(
+            inode("v1.0.0.0/keep/state.db"),
+            inode("v1.0.0.1/keep/state.db")
+        );
+        assert_eq!(inode("v1.0.0.0/data.csv"), inode("v1.0.0.1/data.csv"));
+    }
+}
+
+#[test]
+fn test_verify_oneline_status() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    snapsafe(temp_path)
+        .args(["verify", "--oneline"])
+        .assert()
+        .success()
+        .stdout("OK verified=2 failed=0\n");
+
+    // Break the first snapshot only
+    let stored = temp_path
+        .join(".snapsafe")
+        .join("snapshots")
+        .join("v1.0.0.0")
+        .join("file1.txt");
+    fs::remove_file(&stored).unwrap();
+
+    snapsafe(temp_path)
+        .args(["verify", "--oneline"])
+        .assert()
+        .code(11)
+        .stdout("FAILED verified=2 failed=1\n");
+}
+
+#[test]
+fn test_init_name_shown_by_about_and_info() {
+    let temp_dir = setup_test_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path)
+        .args([
+            "init",
+            "--name",
+            "web-frontend",
+            "--description",
+            "Nightly builds",
+        ])
+        .assert()
+        .success();
+    snapsafe(temp_path)
+        .arg("about")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Name:        web-frontend"))
+        .stdout(predicate::str::contains("Description: Nightly builds"))
+        .stdout(predicate::str::contains("Count:       0"));
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    snapsafe(temp_path)
+        .arg("info")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Repository: web-frontend"));
+
+    // Running init again keeps the description and can remove the name
+    snapsafe(temp_path)
+        .args(["init", "--name", ""])
+        .assert()
+        .success();
+    snapsafe(temp_path)
+        .arg("about")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("web-frontend").not())
+        .stdout(predicate::str::contains("Description: Nightly builds"))
+        .stdout(predicate::str::contains("Count:       1 (latest v1.0.0.0"));
+}
+
+#[test]
+fn test_snapshot_links_touched_files_by_content_hash() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    // Touch every file, and rewrite one with different contents of the same size
+    let later =
+        filetime::FileTime::from_unix_time(filetime::FileTime::now().unix_seconds() + 3600, 0);
+    for file in ["file1.txt", "file2.txt", "subdir/file3.txt"] {
+        filetime::set_file_mtime(temp_path.join(file), later).unwrap();
+    }
+    fs::write(temp_path.join("file2.txt"), "File 2 CONTENT").unwrap();
+    filetime::set_file_mtime(temp_path.join("file2.txt"), later).unwrap();
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    snapsafe(temp_path)
+        .arg("info")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Unique data:       14 B added by this snapshot (1 file(s))",
+        ));
+
+    // The touched files keep their new modification time in the manifest
+    let manifest =
+        fs::read_to_string(temp_path.join(".snapsafe/snapshots/v1.0.0.1/manifest.json")).unwrap();
+    let modified = chrono::DateTime::<chrono::Local>::from(
+        fs::metadata(temp_path.join("file1.txt"))
+            .unwrap()
+            .modified()
+            .unwrap(),
+    )
+    .format("%Y-%m-%d %H:%M:%S")
+    .to_string();
+    assert!(manifest.contains(&modified));
+
+    // With nothing changed but the times, the next snapshot adds nothing
+    let even_later =
+        filetime::FileTime::from_unix_time(filetime::FileTime::now().unix_seconds() + 7200, 0);
+    for file in ["file1.txt", "file2.txt", "subdir/file3.txt"] {
+        filetime::set_file_mtime(temp_path.join(file), even_later).unwrap();
+    }
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    snapsafe(temp_path)
+        .arg("info")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Unique data:       0 B added by this snapshot (0 file(s))",
+        ));
+}
+
+#[test]
+fn test_fix_manifest_handles_compressed_and_incremental_snapshots() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+    let large: Vec<u8> = b"compressible line of text\n".repeat(400);
+    fs::write(temp_path.join("large.txt"), &large).unwrap();
+
+    snapsafe(temp_path)
+        .args(["snapshot", "--compress-threshold", "1000"])
+        .assert()
+        .success();
+    let snapshots = temp_path.join(".snapsafe/snapshots");
+    fs::remove_file(snapshots.join("v1.0.0.0/manifest.json")).unwrap();
+    snapsafe(temp_path)
+        .args(["check", "--fix-manifest", "v1.0.0.0"])
+        .assert()
+        .success();
+
+    // The rebuilt manifest records the compressed file with its original size
+    let manifest = fs::read_to_string(snapshots.join("v1.0.0.0/manifest.json")).unwrap();
+    assert!(manifest.contains("\"compressed_size\""));
+    assert!(manifest.contains(&format!("\"file_size\": {}", large.len())));
+    let target = temp_path.join("checkout");
+    snapsafe(temp_path)
+        .args(["restore", "v1.0.0.0", "--target"])
+        .arg(&target)
+        .assert()
+        .success();
+    assert_eq!(fs::read(target.join("large.txt")).unwrap(), large);
+
+    // An incremental snapshot keeps its unchanged files in its parent, so it isn't rebuilt
+    fs::write(temp_path.join("file1.txt"), "changed").unwrap();
+    snapsafe(temp_path)
+        .args(["snapshot", "--incremental"])
+        .assert()
+        .success();
+    fs::remove_file(snapshots.join("v1.0.0.1/manifest.json")).unwrap();
+    snapsafe(temp_path)
+        .args(["check", "--fix-manifest", "v1.0.0.1"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("v1.0.0.1 is incremental"));
+    assert!(!snapshots.join("v1.0.0.1/manifest.json").exists());
+}
+
+#[test]
+fn test_fix_manifest_records_content_hashes() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+    let large: Vec<u8> = b"compressible line of text\n".repeat(400);
+    fs::write(temp_path.join("large.txt"), &large).unwrap();
+
+    snapsafe(temp_path)
+        .args(["snapshot", "--compress-threshold", "1000"])
+        .assert()
+        .success();
+    let manifest_path = temp_path.join(".snapsafe/snapshots/v1.0.0.0/manifest.json");
+    let original: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&manifest_path).unwrap()).unwrap();
+    fs::remove_file(&manifest_path).unwrap();
+    snapsafe(temp_path)
+        .args(["check", "--fix-manifest", "v1.0.0.0"])
+        .assert()
+        .success();
+
+    // Every file gets the hash of its original contents, compressed or not
+    let rebuilt: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&manifest_path).unwrap()).unwrap();
+    let hashes = |manifest: &serde_json::Value| {
+        manifest
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| (entry["relative_path"].clone(), entry["checksum"].clone()))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(hashes(&rebuilt).len(), 5);
+    assert!(hashes(&rebuilt).iter().all(|(_, hash)| hash.is_string()));
+    assert_eq!(hashes(&rebuilt), hashes(&original));
+}
+
+#[test]
+fn test_restore_from_archive_rejects_unsupported_guards() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    for flags in [
+        &["--force"][..],
+        &["--confirm-overwrite-threshold", "5"],
+        &["--preserve-xattrs"],
+        &["--on-conflict", "backup"],
+    ] {
+        snapsafe(temp_path)
+            .args(["restore", "--from-archive", "backup.tar.gz"])
+            .args(flags)
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("cannot be used with"));
+    }
+}
+
+#[test]
+fn test_restore_rollback_reverses_conflict_backups() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    // file1.txt conflicts and is backed up; replacing subdir with a file makes the restore fail
+    fs::write(temp_path.join("file1.txt"), "Changed content").unwrap();
+    fs::remove_dir_all(temp_path.join("subdir")).unwrap();
+    fs::write(temp_path.join("subdir"), "Not a directory").unwrap();
+
+    snapsafe(temp_path)
+        .args([
+            "restore",
+            "v1.0.0.0",
+            "--yes",
+            "--rollback-on-error",
+            "--on-conflict",
+            "backup",
+        ])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("file1.txt -> file1.txt.bak"))
+        .stdout(predicate::str::contains(
+            "Rolled back to backup snapshot v1.0.0.1",
+        ));
+
+    // The backed-up file has its name back and no .bak file is left over
+    assert_eq!(
+        fs::read_to_string(temp_path.join("file1.txt")).unwrap(),
+        "Changed content"
+    );
+    assert!(!temp_path.join("file1.txt.bak").exists());
+    assert_eq!(
+        fs::read_to_string(temp_path.join("subdir")).unwrap(),
+        "Not a directory"
+    );
+}
+
+#[test]
+fn test_verify_check_links_uses_the_from_baseline() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    fs::write(temp_path.join("file2.txt"), "Changed content").unwrap();
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    // Linked against v1.0.0.0, so file2.txt is a new copy rather than a link to v1.0.0.1's
+    snapsafe(temp_path)
+        .args(["snapshot", "--from", "v1.0.0.0"])
+        .assert()
+        .success();
+    snapsafe(temp_path)
+        .args(["verify", "v1.0.0.2", "--check-links"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Unshared hard links").not());
+
+    // A broken link to the --from baseline is still reported
+    let stored = temp_path.join(".snapsafe/snapshots/v1.0.0.2/file1.txt");
+    let copy = temp_path.join("copy.tmp");
+    fs::copy(&stored, &copy).unwrap();
+    fs::remove_file(&stored).unwrap();
+    fs::rename(&copy, &stored).unwrap();
+    snapsafe(temp_path)
+        .args(["verify", "v1.0.0.2", "--check-links"])
+        .assert()
+        .code(11)
+        .stdout(predicate::str::contains("file1.txt"))
+        .stdout(predicate::str::contains("file2.txt").not());
+}
+
+#[test]
+fn test_diff_resolves_snapshot_ids() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path)
+        .args(["snapshot", "--label", "base"])
+        .assert()
+        .success();
+    fs::write(temp_path.join("file1.txt"), "Changed content").unwrap();
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    // Labels and "latest" work like in other commands
+    snapsafe(temp_path)
+        .args(["diff", "base", "latest"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("file1.txt"));
+
+    // An unknown ID lists the snapshots that exist
+    snapsafe(temp_path)
+        .args(["diff", "v9.9", "v1.0.0.0"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Snapshot v9.9 not found"))
+        .stderr(predicate::str::contains(
+            "Available snapshots: v1.0.0.0 (base), v1.0.0.1",
+        ));
+}
+
+#[test]
+fn test_snapshot_rejects_reserved_metadata_file_names() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+    fs::write(temp_path.join(".snapsafe_meta.json"), "user data").unwrap();
+
+    snapsafe(temp_path)
+        .args(["snapshot", "--tags", "release"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "has a file named .snapsafe_meta.json, which snapsafe reserves",
+        ));
+    assert!(!temp_path.join(".snapsafe/snapshots/v1.0.0.0").exists());
+
+    // Ignoring the file lets the rest be snapshotted, with the metadata intact
+    let mut ignore = fs::read_to_string(temp_path.join(".snapsafeignore")).unwrap();
+    ignore.push_str("\n.snapsafe_meta.json\n");
+    fs::write(temp_path.join(".snapsafeignore"), ignore).unwrap();
+    snapsafe(temp_path)
+        .args(["snapshot", "--tags", "release"])
+        .assert()
+        .success();
+    let meta =
+        fs::read_to_string(temp_path.join(".snapsafe/snapshots/v1.0.0.0/.snapsafe_meta.json"))
+            .unwrap();
+    assert!(meta.contains("release"));
+    assert_eq!(
+        fs::read_to_string(temp_path.join(".snapsafe_meta.json")).unwrap(),
+        "user data"
+    );
+}
+
+#[test]
+fn test_snapshot_rejects_reserved_note_file_name() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+    fs::write(temp_path.join(".snapsafe_note.md"), "my notes").unwrap();
+    fs::write(temp_path.join("release.md"), "release notes").unwrap();
+
+    snapsafe(temp_path)
+        .args(["snapshot", "--note-file", "release.md"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "has a file named .snapsafe_note.md, which snapsafe reserves",
+        ));
+    assert!(!temp_path.join(".snapsafe/snapshots/v1.0.0.0").exists());
+
+    let mut ignore = fs::read_to_string(temp_path.join(".snapsafeignore")).unwrap();
+    ignore.push_str("\n.snapsafe_note.md\n");
+    fs::write(temp_path.join(".snapsafeignore"), ignore).unwrap();
+    snapsafe(temp_path)
+        .args(["snapshot", "--note-file", "release.md"])
+        .assert()
+        .success();
+    snapsafe(temp_path)
+        .args(["info", "v1.0.0.0", "--note"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("release notes"))
+        .stdout(predicate::str::contains("my notes").not());
+}
+
+#[test]
+fn test_verify_check_mtime_accepts_files_linked_after_a_rewrite() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    for hours in [1, 2] {
+        let later = filetime::FileTime::from_unix_time(
+            filetime::FileTime::now().unix_seconds() + hours * 3600,
+            0,
+        );
+        filetime::set_file_mtime(temp_path.join("file1.txt"), later).unwrap();
+        snapsafe(temp_path).arg("snapshot").assert().success();
+    }
+
+    // The later snapshots link the first copy, which keeps its original modification time
+    let manifest =
+        fs::read_to_string(temp_path.join(".snapsafe/snapshots/v1.0.0.2/manifest.json")).unwrap();
+    assert!(manifest.contains("stored_modified"));
+    snapsafe(temp_path)
+        .args(["verify", "--check-mtime", "--strict"])
+        .assert()
+        .success();
+
+    // A stored copy touched after the fact is still reported
+    let stored = temp_path.join(".snapsafe/snapshots/v1.0.0.0/file1.txt");
+    filetime::set_file_mtime(
+        &stored,
+        filetime::FileTime::from_unix_time(2_000_000_000, 0),
+    )
+    .unwrap();
+    snapsafe(temp_path)
+        .args(["verify", "v1.0.0.2", "--check-mtime", "--strict"])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("Modification time mismatches: 1"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_restore_from_archive_refuses_writes_through_links() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+    let outside = TempDir::new().unwrap();
+    let archive = temp_path.join("evil.tar.gz");
+    write_link_escape_archive(&archive, outside.path());
+
+    snapsafe(temp_path)
+        .args([
+            "restore",
+            "--from-archive",
+            "evil.tar.gz",
+            "--target",
+            "out",
+            "--yes",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("escapes the target directory"));
+    assert!(!outside.path().join("f").exists());
+
+    // Entries can't land in the repository folder either
+    let file = fs::File::create(&archive).unwrap();
+    let encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+    let mut header = tar::Header::new_gnu();
+    header.set_size(5);
+    header.set_mode(0o644);
+    builder
+        .append_data(
+            &mut header,
+            "v1/.snapsafe/head_manifest.json",
+            &b"owned"[..],
+        )
+        .unwrap();
+    builder.into_inner().unwrap().finish().unwrap();
+    snapsafe(temp_path)
+        .args(["restore", "--from-archive", "evil.tar.gz", "--yes"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("inside the .snapsafe folder"));
+    assert_ne!(
+        fs::read_to_string(temp_path.join(".snapsafe/head_manifest.json")).unwrap(),
+        "owned"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_import_refuses_writes_through_links() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+    let outside = TempDir::new().unwrap();
+    write_link_escape_archive(&temp_path.join("evil.tar.gz"), outside.path());
+
+    snapsafe(temp_path)
+        .args(["import", "evil.tar.gz"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Failed to import v1/evil/f"));
+    assert!(!outside.path().join("f").exists());
+    assert!(!temp_path.join(".snapsafe/snapshots/.import").exists());
+    assert!(!temp_path.join(".snapsafe/snapshots/v1").exists());
+
+    // Hard links may only point at other files in the archive
+    fs::write(outside.path().join("secret"), "secret").unwrap();
+    let file = fs::File::create(temp_path.join("evil.tar.gz")).unwrap();
+    let encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+    let mut link = tar::Header::new_gnu();
+    link.set_entry_type(tar::EntryType::Link);
+    link.set_size(0);
+    builder
+        .append_link(&mut link, "v1/secret", outside.path().join("secret"))
+        .unwrap();
+    builder.into_inner().unwrap().finish().unwrap();
+    snapsafe(temp_path)
+        .args(["import", "evil.tar.gz"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("escapes the target directory"));
+    assert!(!temp_path.join(".snapsafe/snapshots/.import").exists());
+}
+
+#[test]
+fn test_snapshot_records_sha256_and_falls_back_to_mtime_without_it() {
+    use sha2::{Digest, Sha256};
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    let manifest_path = temp_path.join(".snapsafe/snapshots/v1.0.0.0/manifest.json");
+    let entries: Vec<serde_json::Value> =
+        serde_json::from_str(&fs::read_to_string(&manifest_path).unwrap()).unwrap();
+    let file1 = entries
+        .iter()
+        .find(|e| e["relative_path"] == "file1.txt")
+        .unwrap();
+    let expected: String = Sha256::digest(fs::read(temp_path.join("file1.txt")).unwrap())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    assert_eq!(file1["sha256"], expected.as_str());
+
+    // A baseline without SHA-256 hashes (as written by older versions) is compared by mtime, so
+    // touched files are copied again
+    let stripped: Vec<serde_json::Value> = entries
+        .into_iter()
+        .map(|mut e| {
+            e.as_object_mut().unwrap().remove("sha256");
+            e
+        })
+        .collect();
+    fs::write(&manifest_path, serde_json::to_string(&stripped).unwrap()).unwrap();
+    let later =
+        filetime::FileTime::from_unix_time(filetime::FileTime::now().unix_seconds() + 3600, 0);
+    filetime::set_file_mtime(temp_path.join("file1.txt"), later).unwrap();
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    snapsafe(temp_path)
+        .arg("info")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Unique data:       14 B added by this snapshot (1 file(s))",
+        ));
+}
+
+#[test]
+fn test_restore_threshold_ignores_files_only_touched() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    // Restores don't keep modification times, so an unchanged tree may carry new ones
+    let later =
+        filetime::FileTime::from_unix_time(filetime::FileTime::now().unix_seconds() + 3600, 0);
+    for file in ["file1.txt", "file2.txt", "subdir/file3.txt"] {
+        filetime::set_file_mtime(temp_path.join(file), later).unwrap();
+    }
+    snapsafe(temp_path)
+        .args(["restore", "v1.0.0.0", "--dry-run"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("overwrite 0"));
+    snapsafe(temp_path)
+        .args(["restore", "v1.0.0.0", "--yes", "--no-backup"])
+        .args(["--confirm-overwrite-threshold", "1"])
+        .assert()
+        .success();
+
+    // A same-sized edit still counts
+    fs::write(temp_path.join("file1.txt"), "File 1 CONTENT").unwrap();
+    fs::write(temp_path.join("file2.txt"), "File 2 CONTENT").unwrap();
+    snapsafe(temp_path)
+        .args(["restore", "v1.0.0.0", "--yes", "--no-backup"])
+        .args(["--confirm-overwrite-threshold", "1"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("more than the limit of 1"));
+}
+
+#[test]
+fn test_jobs_controls_parallel_snapshot() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path)
+        .args(["--jobs", "1", "snapshot"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("parallel jobs").not());
+
+    snapsafe(temp_path)
+        .args(["snapshot", "--jobs", "3"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Using 3 parallel jobs."));
+
+    // The config key is used when the flag isn't given
+    snapsafe(temp_path)
+        .args(["config", "--set", "jobs", "1"])
+        .assert()
+        .success();
+    snapsafe(temp_path)
+        .arg("snapshot")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("parallel jobs").not());
+
+    // Parallel and sequential snapshots record the same files
+    let snapshots = temp_path.join(".snapsafe").join("snapshots");
+    for version in ["v1.0.0.0", "v1.0.0.1", "v1.0.0.2"] {
+        for file in ["file1.txt", "file2.txt", "subdir/file3.txt"] {
+            assert!(snapshots.join(version).join(file).exists());
+        }
+    }
+    snapsafe(temp_path).arg("verify").assert().success();
+}
+
+#[test]
+fn test_buffer_size_copies_files_intact() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+    let contents: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+    fs::write(temp_path.join("large.bin"), &contents).unwrap();
+
+    // A buffer much smaller than the file (and not a divisor of its size) still copies it whole
+    snapsafe(temp_path)
+        .args(["snapshot", "--buffer-size", "7"])
+        .assert()
+        .success();
+    snapsafe(temp_path).arg("verify").assert().success();
+
+    fs::write(temp_path.join("large.bin"), "changed").unwrap();
+    snapsafe(temp_path)
+        .args(["config", "--set", "copy_buffer_size", "4096"])
+        .assert()
+        .success();
+    snapsafe(temp_path)
+        .args(["restore", "v1.0.0.0", "--yes", "--no-backup"])
+        .assert()
+        .success();
+    assert_eq!(fs::read(temp_path.join("large.bin")).unwrap(), contents);
+
+    snapsafe(temp_path)
+        .args(["config", "--set", "copy_buffer_size", "0"])
+        .assert()
+        .failure();
+    snapsafe(temp_path)
+        .args(["snapshot", "--buffer-size", "0"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_incremental_export_and_import() {
+    use flate2::read::GzDecoder;
+
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+    let archives = TempDir::new().unwrap();
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    fs::write(temp_path.join("file1.txt"), "Second version of file 1").unwrap();
+    fs::write(temp_path.join("subdir").join("new.txt"), "new file").unwrap();
+    snapsafe(temp_path)
+        .args(["snapshot", "-m", "second"])
+        .assert()
+        .success();
+
+    let full = archives.path().join("full.tar.gz");
+    let incremental = archives.path().join("incremental.tar.gz");
+    snapsafe(temp_path)
+        .args(["export", "v1.0.0.0", "-o"])
+        .arg(&full)
+        .assert()
+        .success();
+    snapsafe(temp_path)
+        .args(["export", "v1.0.0.1", "--since", "v1.0.0.0", "-o"])
+        .arg(&incremental)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Exported 2 file(s)"));
+
+    let file = fs::File::open(&incremental).unwrap();
+    let mut names: Vec<String> = tar::Archive::new(GzDecoder::new(file))
+        .entries()
+        .unwrap()
+        .map(|entry| entry.unwrap().path().unwrap().to_string_lossy().to_string())
+        .collect();
+    names.sort();
+    assert_eq!(
+        names,
+        [
+            "v1.0.0.1/file1.txt",
+            "v1.0.0.1/incremental.json",
+            "v1.0.0.1/manifest.json",
+            "v1.0.0.1/subdir/new.txt",
+        ]
+    );
+
+    // Incremental archives can't be restored on their own
+    let checkout = TempDir::new().unwrap();
+    snapsafe(temp_path)
+        .args(["restore", "--from-archive"])
+        .arg(&incremental)
+        .arg("--target")
+        .arg(checkout.path())
+        .write_stdin("\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("snapsafe import"));
+
+    // The base has to be imported before the increment
+    let offsite = setup_initialized_env();
+    snapsafe(offsite.path())
+        .arg("import")
+        .arg(&incremental)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Import v1.0.0.0 first"));
+    snapsafe(offsite.path())
+        .arg("import")
+        .arg(&full)
+        .assert()
+        .success();
+    snapsafe(offsite.path())
+        .arg("import")
+        .arg(&incremental)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Imported snapshot v1.0.0.1"));
+
+    let imported = offsite.path().join(".snapsafe/snapshots/v1.0.0.1");
+    for file in [
+        "file1.txt",
+        "file2.txt",
+        "subdir/file3.txt",
+        "subdir/new.txt",
+    ] {
+        assert_eq!(
+            fs::read(imported.join(file)).unwrap(),
+            fs::read(temp_path.join(".snapsafe/snapshots/v1.0.0.1").join(file)).unwrap(),
+            "{} differs",
+            file
+        );
+    }
+    assert!(!imported.join("incremental.json").exists());
+    assert!(!offsite.path().join(".snapsafe/snapshots/.import").exists());
+    snapsafe(offsite.path())
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("second"));
+    snapsafe(offsite.path()).arg("verify").assert().success();
+    snapsafe(offsite.path()).arg("check").assert().success();
+}
+
+#[test]
+fn test_tags_and_metadata_survive_export_and_import() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path)
+        .args(["snapshot", "--tags", "release", "stable"])
+        .assert()
+        .success();
+    snapsafe(temp_path)
+        .args(["meta", "v1.0.0.0", "--set", "ran_by", "ci"])
+        .assert()
+        .success();
+    snapsafe(temp_path)
+        .args(["tag", "v1.0.0.0", "--remove", "stable"])
+        .assert()
+        .success();
+    let archive = temp_path.join(".snapsafe/exports/v1.0.0.0.tar.gz");
+    snapsafe(temp_path)
+        .args(["export", "v1.0.0.0"])
+        .assert()
+        .success();
+    assert!(!temp_path.join("v1.0.0.0.tar.gz").exists());
+
+    let offsite = setup_initialized_env();
+    snapsafe(offsite.path())
+        .arg("import")
+        .arg(&archive)
+        .assert()
+        .success();
+    snapsafe(offsite.path())
+        .args(["tag", "v1.0.0.0", "--list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("release"))
+        .stdout(predicate::str::contains("stable").not());
+    snapsafe(offsite.path())
+        .args(["meta", "v1.0.0.0", "--list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("ran_by = ci"));
+
+    // If the head manifest loses them, check restores them from the snapshot folder
+    let head_manifest = offsite.path().join(".snapsafe/head_manifest.json");
+    let mut entries: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&head_manifest).unwrap()).unwrap();
+    entries[0]["metadata"] = serde_json::Value::Null;
+    fs::write(&head_manifest, entries.to_string()).unwrap();
+    snapsafe(offsite.path())
+        .arg("check")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Restored the tags and metadata of v1.0.0.0",
+        ));
+    snapsafe(offsite.path())
+        .args(["find", "--tag", "release"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("v1.0.0.0"));
+
+    // The metadata file isn't restored as a working file
+    snapsafe(offsite.path())
+        .args(["restore", "v1.0.0.0", "--yes"])
+        .assert()
+        .success();
+    assert!(!offsite.path().join(".snapsafe_meta.json").exists());
+}
+
+#[test]
+fn test_restore_from_archive_into_clean_target() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    snapsafe(temp_path)
+        .args(["export", "v1.0.0.0", "-o", "backup.tar.gz"])
+        .assert()
+        .success();
+
+    let target_dir = TempDir::new().unwrap();
+    let target = target_dir.path().join("restored");
+    snapsafe(temp_path)
+        .args(["restore", "--from-archive", "backup.tar.gz", "--target"])
+        .arg(&target)
+        .write_stdin("\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Restored 4 file(s)"));
+
+    for file in [
+        "file1.txt",
+        "file2.txt",
+        "subdir/file3.txt",
+        ".snapsafeignore",
+    ] {
+        assert_eq!(
+            fs::read(target.join(file)).unwrap(),
+            fs::read(temp_path.join(file)).unwrap(),
+            "{} differs",
+            file
+        );
+    }
+    assert!(!target.join("manifest.json").exists());
+    assert!(!target.join("ignored_file.txt").exists());
+
+    // --clean removes files the snapshot doesn't have
+    fs::create_dir(target.join("extra")).unwrap();
+    fs::write(target.join("extra").join("stray.txt"), "stray").unwrap();
+    snapsafe(temp_path)
+        .args(["restore", "v1.0.0.0", "--clean", "--target"])
+        .arg(&target)
+        .write_stdin("\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Removed 1 file(s) not in the snapshot",
+        ));
+    assert!(!target.join("extra").exists());
+    assert!(target.join("file1.txt").exists());
+}
+
+#[test]
+fn test_disable_backup_config_prevents_restore_backups() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    snapsafe(temp_path)
+        .args(["config", "--set", "disable_backup", "true"])
+        .assert()
+        .success();
+
+    fs::write(temp_path.join("file1.txt"), "Edited after the snapshot").unwrap();
+    snapsafe(temp_path)
+        .args(["restore", "v1.0.0.0"])
+        .write_stdin("\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "backups are disabled by the disable_backup setting",
+        ));
+
+    let head = fs::read_to_string(temp_path.join(".snapsafe/head_manifest.json")).unwrap();
+    let head: Vec<serde_json::Value> = serde_json::from_str(&head).unwrap();
+    assert_eq!(head.len(), 1);
+    assert_eq!(
+        fs::read_to_string(temp_path.join("file1.txt")).unwrap(),
+        "File 1 content"
+    );
+
+    snapsafe(temp_path)
+        .args(["config", "--set", "disable_backup", "yes"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_restore_over_threshold_requires_force() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    // A small change restores under --yes
+    fs::write(temp_path.join("file1.txt"), "Edited after the snapshot").unwrap();
+    snapsafe(temp_path)
+        .args(["restore", "v1.0.0.0", "--yes", "--no-backup"])
+        .args(["--confirm-overwrite-threshold", "2"])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(temp_path.join("file1.txt")).unwrap(),
+        "File 1 content"
+    );
+
+    // Deleting three files plus an overwrite is over the threshold
+    fs::write(temp_path.join("file2.txt"), "Edited after the snapshot").unwrap();
+    for name in ["new1.txt", "new2.txt", "new3.txt"] {
+        fs::write(temp_path.join(name), "new").unwrap();
+    }
+    snapsafe(temp_path)
+        .args(["restore", "v1.0.0.0", "--clean", "--yes", "--no-backup"])
+        .args(["--confirm-overwrite-threshold", "2"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "delete 3 file(s), more than the limit of 2",
+        ))
+        .stderr(predicate::str::contains("--force"));
+    assert!(temp_path.join("new1.txt").exists());
+    assert_eq!(
+        fs::read_to_string(temp_path.join("file2.txt")).unwrap(),
+        "Edited after the snapshot"
+    );
+
+    // Without --yes the user has to type "yes"
+    snapsafe(temp_path)
+        .args(["restore", "v1.0.0.0", "--clean", "--no-backup"])
+        .args(["--confirm-overwrite-threshold", "2"])
+        .write_stdin("\n\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Restore cancelled"));
+    assert!(temp_path.join("new1.txt").exists());
+
+    snapsafe(temp_path)
+        .args([
+            "restore",
+            "v1.0.0.0",
+            "--clean",
+            "--yes",
+            "--force",
+            "--no-backup",
+        ])
+        .args(["--confirm-overwrite-threshold", "2"])
+        .assert()
+        .success();
+    assert!(!temp_path.join("new1.txt").exists());
+    assert_eq!(
+        fs::read_to_string(temp_path.join("file2.txt")).unwrap(),
+        "File 2 content"
+    );
+}
+
+#[test]
+fn test_snapshot_retries_transient_errors() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    // Without retries the first transient error aborts the snapshot
+    snapsafe(temp_path)
+        .env("SNAPSAFE_INJECT_TRANSIENT_ERRORS", "2")
+        .arg("snapshot")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("injected transient error"));
+    assert!(!temp_path.join(".snapsafe/snapshots/v1.0.0.0").exists());
+
+    snapsafe(temp_path)
+        .env("SNAPSAFE_INJECT_TRANSIENT_ERRORS", "2")
+        .args(["snapshot", "--retries", "3", "--verbose"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Retrying"))
+        .stderr(predicate::str::contains("(retry 2/3)"));
+    let latest = temp_path.join(".snapsafe/snapshots/v1.0.0.0");
+    assert_eq!(
+        fs::read_to_string(latest.join("file1.txt")).unwrap(),
+        "File 1 content"
+    );
+    snapsafe(temp_path).arg("verify").assert().success();
+}
+
+#[test]
+fn test_snapshot_empty_files() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    fs::write(temp_path.join("empty.marker"), "").unwrap();
+    fs::write(temp_path.join("subdir").join(".keep"), "").unwrap();
+
+    // Empty files are stored like any other, including when hashing
+    snapsafe(temp_path)
+        .args(["snapshot", "--compare-by", "hash"])
+        .assert()
+        .success();
+    let snapshots = temp_path.join(".snapsafe").join("snapshots");
+    assert!(snapshots.join("v1.0.0.0/empty.marker").exists());
+    assert!(snapshots.join("v1.0.0.0/subdir/.keep").exists());
+    snapsafe(temp_path)
+        .args(["snapshot", "--compare-by", "hash"])
+        .assert()
+        .success();
+    snapsafe(temp_path).arg("verify").assert().success();
+
+    snapsafe(temp_path)
+        .args(["snapshot", "--exclude-empty-files"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Skipped 2 empty file(s)."));
+    assert!(!snapshots.join("v1.0.0.2/empty.marker").exists());
+    assert!(!snapshots.join("v1.0.0.2/subdir/.keep").exists());
+    assert!(snapshots.join("v1.0.0.2/file1.txt").exists());
+    let manifest = fs::read_to_string(snapshots.join("v1.0.0.2/manifest.json")).unwrap();
+    assert!(!manifest.contains("empty.marker"));
+
+    // Excluded empty files don't count as changes
+    snapsafe(temp_path)
+        .args(["snapshot", "--exclude-empty-files", "--if-changed"])
+        .assert()
+        .code(10);
+}
+
+#[test]
+fn test_log_graph_shows_parents() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path)
+        .args(["snapshot", "-m", "first"])
+        .assert()
+        .success();
+    fs::write(temp_path.join("file1.txt"), "Second version of file 1").unwrap();
+    snapsafe(temp_path)
+        .args(["snapshot", "-m", "second"])
+        .assert()
+        .success();
+    snapsafe(temp_path)
+        .args(["snapshot", "--from", "v1.0.0.0", "-m", "branched"])
+        .assert()
+        .success();
+
+    let head: serde_json::Value = serde_json::from_str(
+        &fs::read_to_string(temp_path.join(".snapsafe/head_manifest.json")).unwrap(),
+    )
+    .unwrap();
+    assert!(head[0].get("parent").is_none());
+    assert_eq!(head[1]["parent"], "v1.0.0.0");
+    assert_eq!(head[2]["parent"], "v1.0.0.0");
+
+    let output = snapsafe(temp_path)
+        .args(["log", "--graph"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let lines: Vec<String> = String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .map(|l| l.split("  ").next().unwrap().to_string())
+        .collect();
+    assert_eq!(lines, ["* v1.0.0.0", "├─* v1.0.0.1", "* v1.0.0.2"]);
+
+    snapsafe(temp_path)
+        .arg("log")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("(parent v1.0.0.0)"))
+        .stdout(predicate::str::contains("(root)"));
+}
+
+#[test]
+fn test_snapshot_warns_about_case_collisions() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    fs::write(temp_path.join("ReadMe.txt"), "upper").unwrap();
+    // On case-insensitive filesystems the two names are the same file
+    fs::write(temp_path.join("readme.txt"), "lower").unwrap();
+    if fs::read_to_string(temp_path.join("ReadMe.txt")).unwrap() != "upper" {
+        return;
+    }
+
+    snapsafe(temp_path)
+        .args(["snapshot", "--strict-case"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("ReadMe.txt, readme.txt"));
+    assert!(!temp_path.join(".snapsafe/snapshots/v1.0.0.0").exists());
+
+    snapsafe(temp_path)
+        .arg("snapshot")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("differ only by case"))
+        .stderr(predicate::str::contains("ReadMe.txt, readme.txt"));
+
+    snapsafe(temp_path)
+        .arg("check")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "v1.0.0.0: paths differ only by case: ReadMe.txt, readme.txt",
+        ));
+}
+
+#[test]
+fn test_diff_ignore_glob() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    fs::write(temp_path.join("app.log"), "first run").unwrap();
+    fs::write(temp_path.join("subdir").join("debug.log"), "first run").unwrap();
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    fs::write(temp_path.join("app.log"), "second run!").unwrap();
+    fs::write(temp_path.join("subdir").join("debug.log"), "second run!").unwrap();
+    fs::write(temp_path.join("subdir").join("new.log"), "new").unwrap();
+    fs::write(temp_path.join("file2.txt"), "File 2 has changed").unwrap();
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    snapsafe(temp_path)
+        .args(["diff", "v1.0.0.0", "v1.0.0.1"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("debug.log"));
+
+    snapsafe(temp_path)
+        .args(["diff", "v1.0.0.0", "v1.0.0.1", "--ignore", "**/*.log"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("file2.txt"))
+        .stdout(predicate::str::contains(".log").not())
+        .stdout(predicate::str::contains("Added Files").not());
+
+    snapsafe(temp_path)
+        .args(["diff", "v1.0.0.0", "v1.0.0.1", "--ignore", "["])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid glob pattern"));
+}
+
+#[test]
+fn test_diff_self_names_tampered_files() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    snapsafe(temp_path)
+        .args(["diff", "--self", "v1.0.0.0"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No differences found"));
+
+    let stored = temp_path.join(".snapsafe/snapshots/v1.0.0.0");
+    fs::write(stored.join("file1.txt"), "Tampered").unwrap();
+    fs::remove_file(stored.join("file2.txt")).unwrap();
+    fs::write(stored.join("subdir").join("planted.txt"), "Planted").unwrap();
+
+    snapsafe(temp_path)
+        .args(["diff", "--self", "v1.0.0.0"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "file1.txt (size: recorded 14 bytes, found 8 bytes)",
+        ))
+        .stdout(predicate::str::contains("Missing Files:"))
+        .stdout(predicate::str::contains("file2.txt"))
+        .stdout(predicate::str::contains("Untracked Files:"))
+        .stdout(predicate::str::contains("planted.txt"))
+        .stdout(predicate::str::contains("subdir/file3.txt").not());
+}
+
+#[test]
+fn test_diff_size_only() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    // Same size, different content and modification time
+    fs::write(temp_path.join("file1.txt"), "File 1 CONTENT").unwrap();
+    filetime::set_file_mtime(
+        temp_path.join("file1.txt"),
+        filetime::FileTime::from_unix_time(1_000_000_000, 0),
+    )
+    .unwrap();
+    fs::write(temp_path.join("file2.txt"), "File 2 grew longer").unwrap();
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    snapsafe(temp_path)
+        .args(["diff", "v1.0.0.0", "v1.0.0.1"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("file1.txt"))
+        .stdout(predicate::str::contains("file2.txt"));
+
+    snapsafe(temp_path)
+        .args(["diff", "v1.0.0.0", "v1.0.0.1", "--size-only"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("file2.txt"))
+        .stdout(predicate::str::contains("file1.txt").not());
+}
+
+#[test]
+fn test_read_only_commands_on_read_only_repo() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path)
+        .args(["snapshot", "--tags", "release"])
+        .assert()
+        .success();
+    fs::write(temp_path.join("file2.txt"), "File 2 has changed").unwrap();
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    let repo = temp_path.join(".snapsafe");
+    let before = read_tree(&repo);
+    set_tree_readonly(&repo, true);
+
+    let commands: &[&[&str]] = &[
+        &["list"],
+        &["list", "--tag", "release", "--show-size"],
+        &["diff", "v1.0.0.0", "v1.0.0.1"],
+        &["info", "v1.0.0.0"],
+        &["verify"],
+        &["log", "--graph"],
+        &["find", "--tag", "release"],
+    ];
+    let results: Vec<_> = commands
+        .iter()
+        .map(|args| snapsafe(temp_path).args(*args).output().unwrap())
+        .collect();
+
+    set_tree_readonly(&repo, false);
+    for (args, output) in commands.iter().zip(results) {
+        assert!(
+            output.status.success(),
+            "{:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    assert_eq!(
+        read_tree(&repo),
+        before,
+        "a read-only command wrote to the repo"
+    );
+}
+
+#[test]
+fn test_info_compare_shows_deltas() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    fs::write(temp_path.join("big.bin"), vec![0u8; 2048]).unwrap();
+    fs::write(temp_path.join("notes.txt"), "more notes").unwrap();
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    let output = snapsafe(temp_path)
+        .args(["info", "v1.0.0.0", "--compare", "latest"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("Comparing v1.0.0.0 with v1.0.0.1"));
+    let row = |name: &str| {
+        output
+            .lines()
+            .find(|line| line.starts_with(name))
+            .unwrap_or_else(|| panic!("no {} row", name))
+            .to_string()
+    };
+    assert!(row("Total files").ends_with("+2"));
+    assert!(row("Total size").ends_with("+2.0 KiB"));
+    assert!(row("bin ").ends_with("+1"));
+    assert!(row("txt ").ends_with("+1"));
+
+    snapsafe(temp_path)
+        .args(["info", "latest", "--compare", "v1.0.0.0"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("-2\n"));
+}
+
+#[test]
+fn test_diff_compares_hashes_only_when_both_snapshots_have_them() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    // Manifests written by older versions recorded no hashes
+    let manifest_path = temp_path.join(".snapsafe/snapshots/v1.0.0.0/manifest.json");
+    let mut manifest: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&manifest_path).unwrap()).unwrap();
+    for entry in manifest.as_array_mut().unwrap() {
+        entry.as_object_mut().unwrap().remove("checksum");
+    }
+    fs::write(&manifest_path, manifest.to_string()).unwrap();
+    snapsafe(temp_path)
+        .args(["snapshot", "--compare-by", "hash"])
+        .assert()
+        .success();
+    snapsafe(temp_path)
+        .args(["diff", "v1.0.0.0", "v1.0.0.1"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No differences found"));
+
+    // Same contents, new modification time
+    filetime::set_file_mtime(
+        temp_path.join("file1.txt"),
+        filetime::FileTime::from_unix_time(1_000_000_000, 0),
+    )
+    .unwrap();
+    snapsafe(temp_path)
+        .args(["snapshot", "--compare-by", "hash"])
+        .assert()
+        .success();
+
+    // Both sides have hashes, so the touched file isn't reported
+    snapsafe(temp_path)
+        .args(["diff", "v1.0.0.1", "v1.0.0.2"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No differences found"));
+
+    // The older snapshot has no hashes, so the modification time decides
+    snapsafe(temp_path)
+        .args(["diff", "v1.0.0.0", "v1.0.0.2"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("file1.txt"));
+}
+
+#[test]
+fn test_sizes_use_human_readable_units() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    fs::write(temp_path.join("big.bin"), vec![0u8; 1536]).unwrap();
+    snapsafe(temp_path).arg("snapshot").assert().success();
+
+    snapsafe(temp_path)
+        .arg("info")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Largest file:      1.5 KiB (big.bin)",
+        ))
+        .stdout(predicate::str::contains(" MB)").not());
+
+    // Pretend the snapshot is much larger
+    let head_path = temp_path.join(".snapsafe").join("head_manifest.json");
+    let mut head: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&head_path).unwrap()).unwrap();
+    head[0]["total_size"] = serde_json::json!(5u64 * 1024 * 1024 * 1024 + 512 * 1024 * 1024);
+    fs::write(&head_path, serde_json::to_string_pretty(&head).unwrap()).unwrap();
+
+    snapsafe(temp_path)
+        .args(["list", "--show-size"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("5.5 GiB"));
+}
+
+#[test]
+fn test_snapshot_prune_after_keep_last() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    for _ in 0..4 {
+        snapsafe(temp_path).arg("snapshot").assert().success();
+    }
+    snapsafe(temp_path)
+        .args(["snapshot", "--prune-after-keep-last", "3", "--yes"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Pruned 2 snapshots."));
+
+    let snapshots: Vec<_> = fs::read_dir(temp_path.join(".snapsafe").join("snapshots"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .collect();
+    assert_eq!(snapshots.len(), 3);
+    assert!(snapshots.contains(&"v1.0.0.4".to_string()));
+
+    snapsafe(temp_path)
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("v1.0.0.1").not())
+        .stdout(predicate::str::contains("v1.0.0.2"));
+}
+
+#[test]
+fn test_verify_check_mtime_strict() {
+    let temp_dir = setup_initialized_env();
+    let temp_path = temp_dir.path();
+
+    snapsafe(temp_path).arg("snapshot").assert().success();
+    snapsafe(temp_path)
+        .args(["verify", "--check-mtime", "--strict"])
+        .assert()
+        .success();
+
+    // Touch a stored file
+    let stored = temp_path.join(".snapsafe/snapshots/v1.0.0.0/file1.txt");
+    let later = filetime::FileTime::from_unix_time(2_000_000_000, 0);
+    filetime::set_file_mtime(&stored, later).unwrap();
+
+    snapsafe(temp_path)
+        .args(["verify", "--check-mtime"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("OK (with warnings)"))
+        .stdout(predicate::str::contains("file1.txt"));
+
+    snapsafe(temp_path)
+        .args(["verify", "--check-mtime", "--strict"])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("Modification time mismatches: 1"))
+        .stdout(predicate::str::contains("file1.txt"));
+}